@@ -1,10 +1,29 @@
+use crate::filesystem::{FileSystem, RealFileSystem, SharedFileSystem};
+use crate::hash;
+use crate::journal::{Journal, JournalAction};
+use crate::Error;
 use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDateTime, TimeZone};
 use filetime::FileTime;
-use log::{debug, error, warn};
-use std::fmt::{Display, Formatter};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fmt::{Debug, Display, Formatter};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf, Prefix};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// File name prefix of temporary files PhotoSort creates in the target directory
+pub const TEMPORARY_FILE_PREFIX: &str = ".photosort-tmp-";
+
+/// Paths longer than this (in characters) are accessed in extended-length form on Windows, leaving room for the
+/// temporary files of copies below the `MAX_PATH` limit of 260 characters
+pub const LONG_PATH_THRESHOLD: usize = 240;
 
 /// `ActualAction` is an enumeration that defines the different types of actions that can be performed on a file.
 ///
@@ -15,13 +34,16 @@ use std::str::FromStr;
 /// * `Hardlink` - Represents the action of creating a hard link to a file.
 /// * `RelativeSymlink` - Represents the action of creating a relative symbolic link to a file.
 /// * `AbsoluteSymlink` - Represents the action of creating an absolute symbolic link to a file.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// * `Reflink` - Represents the action of creating a copy-on-write clone (reflink) of a file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ActualAction {
     Move,
     Copy,
     Hardlink,
     RelativeSymlink,
     AbsoluteSymlink,
+    Reflink,
 }
 
 impl Display for ActualAction {
@@ -32,6 +54,7 @@ impl Display for ActualAction {
             ActualAction::Hardlink => write!(f, "Hardlink"),
             ActualAction::RelativeSymlink => write!(f, "RelSymlink"),
             ActualAction::AbsoluteSymlink => write!(f, "AbsSymlink"),
+            ActualAction::Reflink => write!(f, "Reflink"),
         }
     }
 }
@@ -71,11 +94,402 @@ impl FromStr for ActualAction {
             "relsym" => Ok(ActualAction::RelativeSymlink), // Alias for "RelativeSymlink"
             "absolute_symlink" => Ok(ActualAction::AbsoluteSymlink),
             "abssym" => Ok(ActualAction::AbsoluteSymlink), // Alias for "AbsoluteSymlink"
+            "reflink" => Ok(ActualAction::Reflink),
+            "clone" => Ok(ActualAction::Reflink), // Alias for "Reflink"
             _ => Err(anyhow::anyhow!("Invalid action mode")),
         }
     }
 }
 
+/// `ReflinkFallback` defines what happens if a reflink can not be created, e.g. because the file system
+/// does not support copy-on-write clones or source and target are on different file systems.
+///
+/// # Variants
+/// * `Copy` - The file is copied instead
+/// * `Error` - The action fails with an error
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ReflinkFallback {
+    #[default]
+    Copy,
+    Error,
+}
+
+impl FromStr for ReflinkFallback {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "copy" => Ok(ReflinkFallback::Copy),
+            "error" => Ok(ReflinkFallback::Error),
+            _ => Err(anyhow::anyhow!("Invalid reflink fallback")),
+        }
+    }
+}
+
+/// `HardlinkFallback` defines what happens if a hardlink can not be created, e.g. because source and target are on
+/// different file systems.
+///
+/// # Variants
+/// * `Copy` - The file is copied instead
+/// * `Symlink` - An absolute symlink is created instead
+/// * `Error` - The action fails with an error
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum HardlinkFallback {
+    #[default]
+    Copy,
+    Symlink,
+    Error,
+}
+
+impl FromStr for HardlinkFallback {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "copy" => Ok(HardlinkFallback::Copy),
+            "symlink" => Ok(HardlinkFallback::Symlink),
+            "error" => Ok(HardlinkFallback::Error),
+            _ => Err(anyhow::anyhow!("Invalid hardlink fallback")),
+        }
+    }
+}
+
+/// `SymlinkFallback` defines what happens if a symlink can not be created because the user lacks the privilege
+/// to create symlinks (on Windows, unless Developer Mode is enabled or the program runs as administrator).
+///
+/// # Variants
+/// * `Error` - The action fails with an error
+/// * `Hardlink` - A hardlink is created instead
+/// * `Copy` - The file is copied instead
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SymlinkFallback {
+    #[default]
+    Error,
+    Hardlink,
+    Copy,
+}
+
+impl FromStr for SymlinkFallback {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(SymlinkFallback::Error),
+            "hardlink" => Ok(SymlinkFallback::Hardlink),
+            "copy" => Ok(SymlinkFallback::Copy),
+            _ => Err(anyhow::anyhow!("Invalid symlink fallback")),
+        }
+    }
+}
+
+/// `SymlinkPrivilege` remembers whether the user lacks the privilege to create symlinks. It is set once creating a
+/// symlink failed because of missing privileges, symlinks are not attempted afterward. Clones share the same state,
+/// e.g. for all files of a run.
+#[derive(Clone, Default)]
+pub struct SymlinkPrivilege(Arc<AtomicBool>);
+
+impl SymlinkPrivilege {
+    /// Creates a new state, symlinks are attempted until creating one fails because of missing privileges.
+    pub fn new() -> SymlinkPrivilege {
+        SymlinkPrivilege::default()
+    }
+
+    /// Checks if creating a symlink failed because of missing privileges.
+    pub fn is_missing(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Records that the privilege is missing, returns `true` if this was not known before.
+    fn set_missing(&self) -> bool {
+        !self.0.swap(true, Ordering::Relaxed)
+    }
+}
+
+impl Debug for SymlinkPrivilege {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SymlinkPrivilege(missing: {})", self.is_missing())
+    }
+}
+
+impl PartialEq for SymlinkPrivilege {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SymlinkPrivilege {}
+
+/// `ActionReport` collects what happened while actions were performed besides the actions themselves, e.g. for a
+/// single file and its sidecars. Clones share the same report.
+#[derive(Clone, Default)]
+pub struct ActionReport(Arc<Mutex<ReportedEvents>>);
+
+/// `ReportedEvents` holds the events collected by an `ActionReport`.
+///
+/// # Fields
+/// * `hardlink_fallbacks` - The number of hardlinks that were substituted by a copy or symlink (see `HardlinkFallback`).
+/// * `symlink_fallbacks` - The number of symlinks that were substituted by a hardlink or copy (see `SymlinkFallback`).
+/// * `undeleted_sources` - The sources (paired with their targets) of moves across file systems that were copied but
+///   could not be deleted. These files exist twice and have to be cleaned up manually.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportedEvents {
+    pub hardlink_fallbacks: usize,
+    pub symlink_fallbacks: usize,
+    pub undeleted_sources: Vec<(PathBuf, PathBuf)>,
+}
+
+impl ActionReport {
+    /// Creates a new empty report.
+    pub fn new() -> ActionReport {
+        ActionReport::default()
+    }
+
+    /// Returns the events reported so far.
+    pub fn events(&self) -> ReportedEvents {
+        self.0
+            .lock()
+            .map(|events| events.clone())
+            .unwrap_or_default()
+    }
+
+    fn update(&self, update: impl FnOnce(&mut ReportedEvents)) {
+        if let Ok(mut events) = self.0.lock() {
+            update(&mut events);
+        }
+    }
+}
+
+impl Debug for ActionReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ActionReport({:?})", self.events())
+    }
+}
+
+impl PartialEq for ActionReport {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ActionReport {}
+
+/// Records an event in the report of the options, if any.
+fn report(options: &ActionOptions, update: impl FnOnce(&mut ReportedEvents)) {
+    if let Some(report) = &options.report {
+        report.update(update);
+    }
+}
+
+/// `CopyProgress` is a callback that receives the progress of copying a file: the source file, the number of bytes
+/// copied so far and the total number of bytes. It is called once before the first chunk and after every chunk.
+#[derive(Clone)]
+pub struct CopyProgress(Arc<CopyProgressFn>);
+
+type CopyProgressFn = dyn Fn(&Path, u64, u64) + Send + Sync;
+
+impl CopyProgress {
+    /// Creates a new `CopyProgress` from a callback.
+    pub fn new<F: Fn(&Path, u64, u64) + Send + Sync + 'static>(callback: F) -> CopyProgress {
+        CopyProgress(Arc::new(callback))
+    }
+
+    fn report(&self, source: &Path, copied: u64, total: u64) {
+        (self.0)(source, copied, total)
+    }
+}
+
+impl Debug for CopyProgress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CopyProgress")
+    }
+}
+
+impl PartialEq for CopyProgress {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CopyProgress {}
+
+/// `BandwidthLimiter` limits the throughput of file copies using a token bucket. Clones share the same bucket, so the
+/// limit holds in aggregate when files are copied by multiple threads.
+#[derive(Clone)]
+pub struct BandwidthLimiter(Arc<Mutex<TokenBucket>>);
+
+struct TokenBucket {
+    bytes_per_second: u64,
+    available: f64,
+    last_refill: Instant,
+}
+
+impl BandwidthLimiter {
+    /// Creates a new `BandwidthLimiter` allowing the given number of bytes per second.
+    pub fn new(bytes_per_second: u64) -> BandwidthLimiter {
+        BandwidthLimiter(Arc::new(Mutex::new(TokenBucket {
+            bytes_per_second: bytes_per_second.max(1),
+            available: 0.0,
+            last_refill: Instant::now(),
+        })))
+    }
+
+    /// The number of bytes per second.
+    pub fn bytes_per_second(&self) -> u64 {
+        self.0
+            .lock()
+            .map(|bucket| bucket.bytes_per_second)
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Takes the given number of bytes from the bucket, blocking until they are available.
+    fn acquire(&self, bytes: u64) {
+        let wait = {
+            let Ok(mut bucket) = self.0.lock() else {
+                return;
+            };
+            let now = Instant::now();
+            let rate = bucket.bytes_per_second as f64;
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            // allow bursts of at most one second
+            bucket.available = (bucket.available + elapsed * rate).min(rate);
+            bucket.last_refill = now;
+            bucket.available -= bytes as f64;
+
+            if bucket.available < 0.0 {
+                Duration::from_secs_f64(-bucket.available / rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+impl Debug for BandwidthLimiter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BandwidthLimiter({} B/s)", self.bytes_per_second())
+    }
+}
+
+impl PartialEq for BandwidthLimiter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for BandwidthLimiter {}
+
+/// `CancellationToken` asks a running operation to stop, e.g. when the user presses Ctrl-C. Clones share the same
+/// state, so a token can be cancelled from another thread (or a signal handler) while a run is in progress.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a new token that is not cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancels the token, operations checking it stop at the next opportunity.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Checks if the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Debug for CancellationToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CancellationToken({})", self.is_cancelled())
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancellationToken {}
+
+/// Parses a byte size like `512K`, `40M` or `1G` (binary units, an optional `B`, `iB` and `/s` suffix is ignored).
+///
+/// # Errors
+///
+/// This function will return an error if the string is not a valid size.
+pub fn parse_byte_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let s = s.strip_suffix("/s").unwrap_or(s);
+    let s = s
+        .strip_suffix("iB")
+        .or_else(|| s.strip_suffix('B'))
+        .unwrap_or(s);
+    let (number, unit) = match s.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((index, _)) => s.split_at(index),
+        None => (s, ""),
+    };
+
+    let factor: u64 = match unit.to_uppercase().as_str() {
+        "" => 1,
+        "K" => 1024,
+        "M" => 1024 * 1024,
+        "G" => 1024 * 1024 * 1024,
+        _ => return Err(anyhow!("Invalid size unit: {:?}", unit)),
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid size: {:?}", s))?;
+    if !number.is_finite() || number <= 0.0 {
+        return Err(anyhow!("Size must be positive: {:?}", s));
+    }
+
+    Ok((number * factor as f64) as u64)
+}
+
+/// `ActionOptions` holds the options that influence how an action is performed.
+///
+/// # Fields
+/// * `mkdir` - Mkdir subfolders on the way, in dry-run mode no subfolders are created.
+/// * `reflink_fallback` - What to do if a reflink can not be created.
+/// * `hardlink_fallback` - What to do if a hardlink can not be created.
+/// * `symlink_fallback` - What to do if a symlink can not be created because of missing privileges.
+/// * `symlink_privilege` - Whether the privilege to create symlinks is known to be missing, share it between the files of a
+///   run so that the missing privilege is explained once and symlinks are not attempted afterward.
+/// * `overwrite` - Replace an existing target file atomically instead of failing.
+/// * `verify` - Verify the content of copied files (also when moving across file systems) by comparing checksums.
+/// * `use_trash` - Move files to the trash of the operating system instead of deleting them permanently.
+/// * `copy_progress` - A callback receiving the progress of copied files. If set, files are copied in chunks.
+/// * `bandwidth_limiter` - Limits the throughput of file copies (renames are not affected). If set, files are copied in chunks.
+/// * `journal` - A journal the intent of moves across file systems is recorded in before the file is copied, so that an interrupted move can be reverted.
+/// * `cancel` - A token that aborts chunked copies between two chunks, the partial copy is removed.
+/// * `report` - A report substituted hardlinks and symlinks and undeleted sources of moves are recorded in.
+/// * `file_system` - The file system the action is performed on, the local file system if not set. Fallbacks,
+///   verification, the trash and chunked copies are only supported by the local file system.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ActionOptions {
+    pub mkdir: bool,
+    pub reflink_fallback: ReflinkFallback,
+    pub hardlink_fallback: HardlinkFallback,
+    pub symlink_fallback: SymlinkFallback,
+    pub symlink_privilege: SymlinkPrivilege,
+    pub overwrite: bool,
+    pub verify: bool,
+    pub use_trash: bool,
+    pub copy_progress: Option<CopyProgress>,
+    pub bandwidth_limiter: Option<BandwidthLimiter>,
+    pub journal: Option<Arc<Journal>>,
+    pub cancel: Option<CancellationToken>,
+    pub report: Option<ActionReport>,
+    pub file_system: Option<SharedFileSystem>,
+}
+
 /// Performs the specified action on the source file and target file.
 ///
 /// # Arguments
@@ -83,11 +497,11 @@ impl FromStr for ActualAction {
 /// * `source` - A PathBuf reference to the source file.
 /// * `target` - A PathBuf reference to the target file.
 /// * `action` - An ActionMode reference specifying the action to be performed.
-/// * `mkdir` - Mkdir subfolders on the way, in dry-run mode no subfolders are created.
+/// * `options` - An ActionOptions reference specifying how the action is performed.
 ///
 /// # Returns
 ///
-/// * `std::io::Result<()>` - An IO Result indicating the success or failure of the operation.
+/// * `Result<(), Error>` - A Result indicating the success or failure of the operation.
 ///
 /// # Actions
 ///
@@ -98,137 +512,1477 @@ impl FromStr for ActualAction {
 ///    * `ActualAction::Hardlink` - Creates a hard link at the target location pointing to the source file.
 ///    * `ActualAction::RelativeSymlink` - Creates a relative symbolic link at the target location pointing to the source file.
 ///    * `ActualAction::AbsoluteSymlink` - Creates an absolute symbolic link at the target location pointing to the source file.
+///    * `ActualAction::Reflink` - Creates a copy-on-write clone of the source file at the target location.
+///
+/// If `options.overwrite` is set, an existing target file is replaced atomically: the action is performed
+/// on a temporary file next to the target, which is then renamed to the target.
 ///
 /// # Errors
 ///
 /// This function will return an error if:
 ///
-/// * The target file already exists.
-/// * An error occurred during the file operation.
+/// * The target file already exists (and `options.overwrite` is not set) (`Error::TargetExists`).
+/// * The target subfolder does not exist (and `options.mkdir` is not set) (`Error::DirectoryNotFound`).
+/// * The target file is the same file as the source file (when overwriting).
+/// * A chunked copy was aborted by `options.cancel` (`Error::Cancelled`).
+/// * An error occurred during the file operation (`Error::Io`).
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(source = %source.display(), target = %target.display(), action = ?action))
+)]
 pub fn file_action(
-    source: &PathBuf,
-    target: &PathBuf,
+    source: &Path,
+    target: &Path,
     action: &ActionMode,
-    mkdir: bool,
-) -> Result<()> {
-    error_file_exists(target)
-        .map_err(|e| anyhow!("Target file already exists: {:?} - {:?}", target, e))?;
-
-    // check if parent folder exists
-    if let Some(parent) = target.parent() {
-        if !parent.exists() {
-            if !mkdir {
-                return Err(anyhow!(
-                    "Target subfolder does not exist. Use --mkdir to create it: {:?}",
-                    parent
-                ));
-            }
+    options: &ActionOptions,
+) -> Result<(), Error> {
+    if let Some(file_system) = &options.file_system {
+        return file_system_action(source, target, action, options, &**file_system);
+    }
 
-            if matches!(action, ActionMode::DryRun(_)) {
-                error!("[Mkdir] {:?}", parent);
-            } else {
-                fs::create_dir_all(parent).map_err(|e| {
-                    anyhow!("Failed to create target subfolder: {:?} - {:?}", parent, e)
-                })?;
-            }
+    let (source, target) = &extended_length_paths(source, target);
+    let replace = options.overwrite && entry_exists(target);
+    // replacing a symlink never loses data, even if it points at the source
+    if replace && !is_symlink(target) {
+        let same_file = is_same_file(source, target).map_err(|e| Error::io(target, e))?;
+        if same_file {
+            return Err(Error::Other(anyhow!(
+                "Refusing to overwrite target file {:?}, it is the same file as the source {:?}",
+                target,
+                source
+            )));
         }
+    } else if !replace && entry_exists(target) {
+        return Err(Error::TargetExists(target.clone()));
     }
 
+    create_parent(target, action, options, &RealFileSystem)?;
+
     let result = match action {
-        ActionMode::Execute(ActualAction::Move) => move_file(source, target),
-        ActionMode::Execute(ActualAction::Copy) => copy_file(source, target),
-        ActionMode::Execute(ActualAction::Hardlink) => hardlink_file(source, target),
-        ActionMode::Execute(ActualAction::RelativeSymlink) => relative_symlink_file(source, target),
-        ActionMode::Execute(ActualAction::AbsoluteSymlink) => absolute_symlink_file(source, target),
-        ActionMode::DryRun(action) => dry_run(source, target, action),
+        ActionMode::Execute(action) if replace => replace_file(source, target, action, options),
+        ActionMode::Execute(action) => execute(source, target, action, options),
+        ActionMode::DryRun(action) => dry_run(source, target, action, replace),
     };
 
-    match result {
-        Ok(_) => Ok(()),
-        Err(e) => Err(anyhow!("Failed to perform action: {:?}", e)),
+    result.map_err(|e| {
+        let cancelled = options
+            .cancel
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled);
+        if cancelled && e.kind() == std::io::ErrorKind::Interrupted {
+            Error::Cancelled(source.clone())
+        } else {
+            Error::io(target, e)
+        }
+    })
+}
+
+/// Performs an action like [`file_action`] on another file system than the local one.
+fn file_system_action(
+    source: &Path,
+    target: &Path,
+    action: &ActionMode,
+    options: &ActionOptions,
+    file_system: &dyn FileSystem,
+) -> Result<(), Error> {
+    let replace = options.overwrite && file_system.exists(target);
+    if replace {
+        let same_file = match (
+            file_system.canonicalize(source),
+            file_system.canonicalize(target),
+        ) {
+            (Ok(source), Ok(target)) => source == target,
+            _ => false,
+        };
+        if same_file
+            && !file_system
+                .symlink_metadata(target)
+                .is_ok_and(|m| m.is_symlink())
+        {
+            return Err(Error::Other(anyhow!(
+                "Refusing to overwrite target file {:?}, it is the same file as the source {:?}",
+                target,
+                source
+            )));
+        }
+    } else if file_system.exists(target) {
+        return Err(Error::TargetExists(target.to_path_buf()));
+    }
+
+    create_parent(target, action, options, file_system)?;
+
+    let action = match action {
+        ActionMode::Execute(action) => action,
+        ActionMode::DryRun(action) => {
+            return dry_run(
+                &source.to_path_buf(),
+                &target.to_path_buf(),
+                action,
+                replace,
+            )
+            .map_err(|e| Error::io(target, e));
+        }
+    };
+    if replace {
+        file_system
+            .remove_file(target)
+            .map_err(|e| Error::io(target, e))?;
     }
+    let result = match action {
+        ActualAction::Move => file_system.rename(source, target),
+        ActualAction::Copy | ActualAction::Reflink => file_system.copy(source, target).map(|_| ()),
+        ActualAction::Hardlink => file_system.hard_link(source, target),
+        ActualAction::RelativeSymlink => {
+            let original = file_system.canonicalize(source).and_then(|source| {
+                let directory = target.parent().unwrap_or(Path::new(""));
+                let directory = file_system.canonicalize(directory)?;
+                relative_path(&directory, &source).ok_or_else(|| {
+                    std::io::Error::other("No relative path from the target to the source")
+                })
+            });
+            original.and_then(|original| file_system.symlink(&original, target))
+        }
+        ActualAction::AbsoluteSymlink => file_system
+            .canonicalize(source)
+            .and_then(|source| file_system.symlink(&source, target)),
+    };
+    result.map_err(|e| Error::io(target, e))
 }
 
-fn dry_run(source: &PathBuf, target: &PathBuf, action: &ActualAction) -> std::io::Result<()> {
-    error!("[{}] {:?} -> {:?}", action, source, target);
-    Ok(())
+/// Checks that the parent directory of the target exists. A missing directory is created if `options.mkdir` is set,
+/// in dry-run mode this is only logged.
+fn create_parent(
+    target: &Path,
+    action: &ActionMode,
+    options: &ActionOptions,
+    file_system: &dyn FileSystem,
+) -> Result<(), Error> {
+    let Some(parent) = target.parent() else {
+        return Ok(());
+    };
+    if parent.as_os_str().is_empty() || file_system.metadata(parent).is_ok() {
+        return Ok(());
+    }
+    if !options.mkdir {
+        return Err(Error::DirectoryNotFound(parent.to_path_buf()));
+    }
+
+    if matches!(action, ActionMode::DryRun(_)) {
+        info!("[Mkdir] {:?}", parent);
+        Ok(())
+    } else {
+        file_system
+            .create_dir_all(parent)
+            .map_err(|e| Error::io(parent, e))
+    }
+}
+
+fn execute(
+    source: &PathBuf,
+    target: &PathBuf,
+    action: &ActualAction,
+    options: &ActionOptions,
+) -> std::io::Result<()> {
+    match action {
+        ActualAction::Move => move_file(source, target, options),
+        ActualAction::Copy => copy_file(source, target, options),
+        ActualAction::Hardlink => hardlink_file(source, target, options),
+        ActualAction::RelativeSymlink => relative_symlink_file(source, target, options),
+        ActualAction::AbsoluteSymlink => absolute_symlink_file(source, target, options),
+        ActualAction::Reflink => reflink_file(source, target, options),
+    }
 }
 
-fn error_file_exists(target: &Path) -> std::io::Result<()> {
-    if target.exists() {
-        Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            "Target file already exists",
-        ))
+fn dry_run(
+    source: &PathBuf,
+    target: &PathBuf,
+    action: &ActualAction,
+    replace: bool,
+) -> std::io::Result<()> {
+    if replace {
+        info!("[{}] {:?} -> {:?} (overwrite)", action, source, target);
     } else {
-        Ok(())
+        info!("[{}] {:?} -> {:?}", action, source, target);
     }
+    Ok(())
 }
 
-fn copy_file(source: &PathBuf, target: &PathBuf) -> std::io::Result<()> {
-    debug!("Copying {:?} -> {:?}", source, target);
+/// Performs the action on a temporary file next to the target and renames it onto the target afterward,
+/// so that the target is replaced atomically. With `options.use_trash`, the old target is moved to the trash once
+/// the temporary file is complete, and restored from the trash if the temporary file can not be renamed.
+fn replace_file(
+    source: &PathBuf,
+    target: &PathBuf,
+    action: &ActualAction,
+    options: &ActionOptions,
+) -> std::io::Result<()> {
+    debug!("Replacing {:?} -> {:?}", source, target);
 
-    let metadata = fs::metadata(source)?;
-    let result = fs::copy(source, target)?;
+    let temporary = temporary_path(target);
+    let result = execute(source, &temporary, action, options).and_then(|_| {
+        if !options.use_trash {
+            return fs::rename(&temporary, target);
+        }
+        remove_file(target, options)?;
+        fs::rename(&temporary, target).inspect_err(|err| {
+            warn!(
+                "Renaming temporary file failed, restoring the trashed target: {:?} for file {:?}",
+                err, target
+            );
+            if let Err(err) = restore_from_trash(target) {
+                error!("Failed to restore {:?} from the trash: {}", target, err);
+            }
+        })
+    });
+    if result.is_err() && fs::symlink_metadata(&temporary).is_ok() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
 
-    if metadata.len() != result {
-        let _ = fs::remove_file(target);
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "File copy failed",
-        ));
+/// Returns a unique temporary file path in the same directory as the target.
+fn temporary_path(target: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    target.with_file_name(format!(
+        "{}{}-{}",
+        TEMPORARY_FILE_PREFIX,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Minimum age of temporary files of other processes before they are considered stale
+const STALE_TEMPORARY_FILE_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Removes temporary files left behind by crashed runs in the directory of the target. Only files of other
+/// processes that were not modified within the last hour are removed, each directory is checked once.
+fn remove_stale_temporary_files(target: &Path) {
+    static CHECKED: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+    let directory = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if let Ok(mut checked) = CHECKED.lock() {
+        if !checked
+            .get_or_insert_with(HashSet::new)
+            .insert(directory.to_path_buf())
+        {
+            return;
+        }
     }
 
-    let mtime = FileTime::from_last_modification_time(&metadata);
-    let atime = FileTime::from_last_access_time(&metadata);
+    let own_prefix = format!("{}{}-", TEMPORARY_FILE_PREFIX, std::process::id());
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !name.starts_with(TEMPORARY_FILE_PREFIX) || name.starts_with(&own_prefix) {
+            continue;
+        }
 
-    filetime::set_file_times(target, atime, mtime)?;
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= STALE_TEMPORARY_FILE_AGE);
+        if stale {
+            warn!(
+                "Removing stale temporary file of an interrupted run: {:?}",
+                entry.path()
+            );
+            if let Err(err) = fs::remove_file(entry.path()) {
+                warn!("Failed to remove {:?}: {}", entry.path(), err);
+            }
+        }
+    }
+}
 
-    Ok(())
+/// Checks if there is a directory entry at the path. In contrast to [`Path::exists`], symlinks are not
+/// followed, so dangling symlinks are detected as well.
+pub fn entry_exists(path: &Path) -> bool {
+    fs::symlink_metadata(extended_length_path(path)).is_ok()
 }
 
-fn move_file(source: &PathBuf, target: &PathBuf) -> std::io::Result<()> {
-    debug!("Moving {:?} -> {:?}", source, target);
+/// Checks if there is a directory entry whose name only differs in case from the file name of the path, e.g.
+/// `IMG_1.JPG` for `IMG_1.jpg`. The exact name counts as well.
+pub fn entry_exists_ignore_case(path: &Path) -> bool {
+    if entry_exists(path) {
+        return true;
+    }
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return false;
+    };
+    let name = name.to_string_lossy().to_lowercase();
+    let Ok(entries) = fs::read_dir(parent) else {
+        return false;
+    };
+    entries
+        .flatten()
+        .any(|entry| entry.file_name().to_string_lossy().to_lowercase() == name)
+}
 
-    let result = fs::rename(source, target);
-    if let Err(err) = result {
-        warn!(
-            "Renaming file failed, falling back to cut/paste: {:?} for file {:?} -> {:?}",
-            err, source, target
-        );
-        copy_file(source, target)?;
-        fs::remove_file(source)
-    } else {
-        Ok(())
+/// Checks if a directory is on a case-insensitive file system (e.g. APFS or NTFS with their default settings),
+/// without writing to it: an entry of the directory (or the directory itself, if it is empty) is looked up with
+/// the case of its name swapped. A missing directory is probed at its nearest existing ancestor.
+pub fn is_case_insensitive_dir(dir: &Path) -> bool {
+    let Some(dir) = dir.ancestors().find(|dir| dir.exists()) else {
+        return false;
+    };
+    let swap_case = |name: &str| -> String {
+        name.chars()
+            .map(|c| {
+                if c.is_lowercase() {
+                    c.to_uppercase().next().unwrap_or(c)
+                } else {
+                    c.to_lowercase().next().unwrap_or(c)
+                }
+            })
+            .collect()
+    };
+
+    let entries = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| !is_symlink(path));
+    for path in entries.chain(std::iter::once(dir.to_path_buf())) {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let swapped = swap_case(name);
+        if swapped == name {
+            continue;
+        }
+        return is_same_file(&path, &path.with_file_name(swapped)).unwrap_or(false);
     }
+    false
 }
 
-fn hardlink_file(source: &PathBuf, target: &PathBuf) -> std::io::Result<()> {
-    debug!("Creating hardlink {:?} -> {:?}", source, target);
+/// File system types (as listed in `/proc/self/mountinfo`) with the file name restrictions of Windows
+#[cfg(target_os = "linux")]
+const WINDOWS_FILE_SYSTEMS: &[&str] = &[
+    "vfat", "msdos", "exfat", "ntfs", "ntfs3", "fuseblk", "cifs", "smb3", "smbfs",
+];
 
-    let result = fs::hard_link(source, target);
-    if let Err(_err) = result {
-        error!(
-            "Creating hardlink failed, falling back to copy: {:?} for file {:?} -> {:?}",
-            _err, source, target
-        );
-        copy_file(source, target)
+/// Checks if a directory is on a file system with the file name restrictions of Windows (e.g. FAT, exFAT or NTFS):
+/// on Windows always, on Linux the file system type of the mount containing the directory is looked up. A missing
+/// directory is looked up at its nearest existing ancestor. On other systems, this can not be detected.
+pub fn is_windows_file_system(dir: &Path) -> bool {
+    #[cfg(windows)]
+    {
+        let _ = dir;
+        true
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let Some(dir) = dir.ancestors().find_map(|dir| fs::canonicalize(dir).ok()) else {
+            return false;
+        };
+        let Ok(mountinfo) = fs::read_to_string("/proc/self/mountinfo") else {
+            return false;
+        };
+        // `<id> <parent> <major:minor> <root> <mount point> <options> [<optional fields>] - <type> <source> ...`
+        mountinfo
+            .lines()
+            .filter_map(|line| {
+                let (mount, fs_type) = line.split_once(" - ")?;
+                let mount_point = mount.split(' ').nth(4)?.replace("\\040", " ");
+                let fs_type = fs_type.split(' ').next()?;
+                Some((PathBuf::from(mount_point), fs_type))
+            })
+            .filter(|(mount_point, _)| dir.starts_with(mount_point))
+            .max_by_key(|(mount_point, _)| mount_point.components().count())
+            .is_some_and(|(_, fs_type)| WINDOWS_FILE_SYSTEMS.contains(&fs_type))
+    }
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        let _ = dir;
+        false
+    }
+}
+
+/// The length of the absolute form of a path in characters, as counted by the `MAX_PATH` limit of Windows.
+pub fn path_length(path: &Path) -> usize {
+    std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .as_os_str()
+        .to_string_lossy()
+        .chars()
+        .count()
+}
+
+/// Converts a path longer than `LONG_PATH_THRESHOLD` to the extended-length form (`\\?\C:\...` or
+/// `\\?\UNC\server\share\...`) on Windows, which is not limited by `MAX_PATH`. Other paths, and all paths on
+/// other systems, are returned unchanged.
+pub fn extended_length_path(path: &Path) -> PathBuf {
+    if cfg!(windows) && path_length(path) > LONG_PATH_THRESHOLD {
+        to_extended_length(path)
     } else {
-        Ok(())
+        path.to_path_buf()
     }
 }
 
-fn relative_symlink_file(source: &PathBuf, target: &PathBuf) -> std::io::Result<()> {
-    debug!("Creating symlink {:?} -> {:?}", source, target);
+/// Converts the source and the target of an action to the extended-length form on Windows (see
+/// [`extended_length_path`]), both if one of them is long, so that relative paths between them can still be
+/// computed.
+pub fn extended_length_paths(source: &Path, target: &Path) -> (PathBuf, PathBuf) {
+    if cfg!(windows)
+        && (path_length(source) > LONG_PATH_THRESHOLD || path_length(target) > LONG_PATH_THRESHOLD)
+    {
+        (to_extended_length(source), to_extended_length(target))
+    } else {
+        (source.to_path_buf(), target.to_path_buf())
+    }
+}
 
-    symlink::symlink_file(source, target)?;
+/// Converts a path to the extended-length form, `absolute` resolves `..` and separators as required by it.
+fn to_extended_length(path: &Path) -> PathBuf {
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    let Some(absolute_str) = absolute.to_str() else {
+        return absolute;
+    };
+    if absolute_str.starts_with(r"\\?\") {
+        absolute
+    } else if let Some(unc) = absolute_str.strip_prefix(r"\\") {
+        PathBuf::from(format!(r"\\?\UNC\{}", unc))
+    } else {
+        PathBuf::from(format!(r"\\?\{}", absolute_str))
+    }
+}
 
-    Ok(())
+/// Removes the extended-length prefix `fs::canonicalize` adds on Windows, unless the path is too long for the
+/// regular form.
+fn strip_extended_length_prefix(path: PathBuf) -> PathBuf {
+    let Some(path_str) = path.to_str() else {
+        return path;
+    };
+    let stripped = if let Some(unc) = path_str.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", unc)
+    } else if let Some(disk) = path_str.strip_prefix(r"\\?\") {
+        disk.to_string()
+    } else {
+        return path;
+    };
+    if stripped.chars().count() > LONG_PATH_THRESHOLD {
+        return path;
+    }
+    PathBuf::from(stripped)
 }
 
-fn absolute_symlink_file(source: &PathBuf, target: &PathBuf) -> std::io::Result<()> {
-    debug!("Creating symlink {:?} -> {:?}", source, target);
-    let source = fs::canonicalize(source)?;
+/// Checks if the path is a symlink (which may be dangling).
+pub fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(extended_length_path(path))
+        .is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
 
-    relative_symlink_file(&source, target)
+/// Checks if both paths refer to the same file, following symlinks.
+///
+/// # Errors
+///
+/// This function will return an error if the metadata of one of the files can not be read.
+pub fn is_same_file(a: &Path, b: &Path) -> std::io::Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let a = fs::metadata(a)?;
+        let b = fs::metadata(b)?;
+        Ok(a.dev() == b.dev() && a.ino() == b.ino())
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(
+            fs::canonicalize(extended_length_path(a))?
+                == fs::canonicalize(extended_length_path(b))?,
+        )
+    }
+}
+
+/// Copies a file to a temporary file next to the target, which is renamed to the target only after the copy
+/// was checked, so that an interrupted copy never leaves a truncated target file.
+fn copy_file(source: &PathBuf, target: &PathBuf, options: &ActionOptions) -> std::io::Result<()> {
+    debug!("Copying {:?} -> {:?}", source, target);
+
+    remove_stale_temporary_files(target);
+
+    let temporary = temporary_path(target);
+    let result = copy_to_temporary(source, &temporary, options)
+        .and_then(|_| persist_temporary(&temporary, target));
+    if result.is_err() && fs::symlink_metadata(&temporary).is_ok() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Moves a complete temporary file to the target without replacing a target that was created in the meantime.
+/// The temporary file is hard linked to the target, which fails if the target exists. On file systems without hard
+/// links, it is renamed after checking that the target is still absent.
+fn persist_temporary(temporary: &Path, target: &Path) -> std::io::Result<()> {
+    match fs::hard_link(temporary, target) {
+        Ok(()) => {
+            if let Err(err) = fs::remove_file(temporary) {
+                warn!("Failed to remove temporary file {:?}: {}", temporary, err);
+            }
+            Ok(())
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Err(err),
+        Err(err) => {
+            debug!(
+                "Hard linking temporary file failed, renaming it instead: {:?} for file {:?}",
+                err, target
+            );
+            if entry_exists(target) {
+                return Err(std::io::ErrorKind::AlreadyExists.into());
+            }
+            fs::rename(temporary, target)
+        }
+    }
+}
+
+fn copy_to_temporary(
+    source: &PathBuf,
+    target: &PathBuf,
+    options: &ActionOptions,
+) -> std::io::Result<()> {
+    let metadata = fs::metadata(source)?;
+    let result = if options.copy_progress.is_some() || options.bandwidth_limiter.is_some() {
+        copy_chunked(source, target, &metadata, options)
+    } else {
+        fs::copy(source, target)
+    }?;
+
+    if metadata.len() != result {
+        return Err(std::io::Error::other("File copy failed"));
+    }
+
+    if options.verify {
+        verify_copy(source, target)?;
+    }
+
+    copy_file_times(&metadata, target)
+}
+
+/// Size of the chunks of chunked copies
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Copies a file chunk by chunk, reporting the progress after every chunk and applying the bandwidth limit.
+/// Returns the number of copied bytes, or an `Interrupted` error if the copy was cancelled.
+fn copy_chunked(
+    source: &Path,
+    target: &Path,
+    metadata: &fs::Metadata,
+    options: &ActionOptions,
+) -> std::io::Result<u64> {
+    let total = metadata.len();
+    let mut reader = fs::File::open(source)?;
+    let mut writer = fs::File::create(target)?;
+    // smaller chunks keep slow limits smooth
+    let chunk_size = match &options.bandwidth_limiter {
+        Some(limiter) => {
+            (limiter.bytes_per_second() / 8).clamp(4096, COPY_CHUNK_SIZE as u64) as usize
+        }
+        None => COPY_CHUNK_SIZE,
+    };
+    let mut buffer = vec![0; chunk_size];
+    let mut copied = 0;
+
+    if let Some(progress) = &options.copy_progress {
+        progress.report(source, copied, total);
+    }
+    loop {
+        if options
+            .cancel
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "Copy cancelled",
+            ));
+        }
+        let read = read_chunk(&mut reader, &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        if let Some(limiter) = &options.bandwidth_limiter {
+            limiter.acquire(read as u64);
+        }
+        writer.write_all(&buffer[..read])?;
+        copied += read as u64;
+        if let Some(progress) = &options.copy_progress {
+            progress.report(source, copied, total);
+        }
+    }
+    writer.flush()?;
+    fs::set_permissions(target, metadata.permissions())?;
+
+    Ok(copied)
+}
+
+/// Compares the checksums of source and copied target file, removes the target if they differ.
+fn verify_copy(source: &PathBuf, target: &PathBuf) -> std::io::Result<()> {
+    let result = hash::hash_file(source).and_then(|source_hash| {
+        let target_hash = hash::hash_file(target)?;
+        if source_hash == target_hash {
+            debug!(
+                "Verified copy {:?} -> {:?} ({})",
+                source, target, target_hash
+            );
+            Ok(())
+        } else {
+            Err(std::io::Error::other(format!(
+                "Verification failed, checksum of {:?} ({}) differs from {:?} ({})",
+                target, target_hash, source, source_hash
+            )))
+        }
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(target);
+    }
+    result
+}
+
+fn copy_file_times(metadata: &fs::Metadata, target: &PathBuf) -> std::io::Result<()> {
+    let mtime = FileTime::from_last_modification_time(metadata);
+    let atime = FileTime::from_last_access_time(metadata);
+
+    filetime::set_file_times(target, atime, mtime)
+}
+
+fn reflink_file(
+    source: &PathBuf,
+    target: &PathBuf,
+    options: &ActionOptions,
+) -> std::io::Result<()> {
+    debug!("Creating reflink {:?} -> {:?}", source, target);
+
+    let metadata = fs::metadata(source)?;
+    let result = reflink_copy::reflink(source, target);
+    match result {
+        Ok(_) => copy_file_times(&metadata, target),
+        Err(err) => match options.reflink_fallback {
+            ReflinkFallback::Copy => {
+                warn!(
+                    "Creating reflink failed, falling back to copy: {:?} for file {:?} -> {:?}",
+                    err, source, target
+                );
+                copy_file(source, target, options)
+            }
+            ReflinkFallback::Error => Err(err),
+        },
+    }
+}
+
+/// Moves a file. If it can not be renamed (e.g. source and target are on different file systems), it is copied
+/// and verified, then the source is deleted. If the source can not be deleted, the move still succeeds, the source
+/// is recorded in the report of the options (see [`ReportedEvents::undeleted_sources`]).
+fn move_file(source: &PathBuf, target: &PathBuf, options: &ActionOptions) -> std::io::Result<()> {
+    debug!("Moving {:?} -> {:?}", source, target);
+
+    let result = fs::rename(source, target);
+    let Err(err) = result else {
+        return Ok(());
+    };
+    warn!(
+        "Renaming file failed, falling back to cut/paste: {:?} for file {:?} -> {:?}",
+        err, source, target
+    );
+
+    if let Some(journal) = &options.journal {
+        if let Err(err) = journal.record(JournalAction::MoveStarted, source, target, &Ok(())) {
+            warn!("Failed to record move of {:?} in journal: {}", source, err);
+        }
+    }
+
+    // the source is deleted afterward, so the copy is always verified
+    let copy_options = ActionOptions {
+        verify: true,
+        ..options.clone()
+    };
+    copy_file(source, target, &copy_options)?;
+    remove_moved_source(source, target, options);
+    Ok(())
+}
+
+/// Number of attempts to delete the source of a move across file systems
+const REMOVE_SOURCE_ATTEMPTS: u32 = 3;
+
+/// Deletes the source of a move across file systems after it was copied. Deletion is retried, as network shares
+/// may fail transiently. A source that can not be deleted is recorded in the report of the options.
+fn remove_moved_source(source: &PathBuf, target: &PathBuf, options: &ActionOptions) {
+    let mut attempt = 1;
+    let err = loop {
+        match remove_file(source, options) {
+            Ok(()) => return,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                warn!(
+                    "Source of move disappeared before it was deleted: {:?}",
+                    source
+                );
+                return;
+            }
+            Err(err) if attempt >= REMOVE_SOURCE_ATTEMPTS => break err,
+            Err(err) => {
+                debug!(
+                    "Deleting {:?} failed (attempt {}): {}",
+                    source, attempt, err
+                );
+                std::thread::sleep(Duration::from_millis(500 * u64::from(attempt)));
+                attempt += 1;
+            }
+        }
+    };
+
+    let reason = match err.kind() {
+        std::io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+        _ => err.to_string(),
+    };
+    warn!(
+        "Copied {:?} -> {:?} but could not delete the source ({}), the file exists twice",
+        source, target, reason
+    );
+    report(options, |events| {
+        events
+            .undeleted_sources
+            .push((source.clone(), target.clone()))
+    });
+}
+
+fn hardlink_file(
+    source: &PathBuf,
+    target: &PathBuf,
+    options: &ActionOptions,
+) -> std::io::Result<()> {
+    debug!("Creating hardlink {:?} -> {:?}", source, target);
+
+    let result = fs::hard_link(source, target);
+    let Err(err) = result else {
+        return Ok(());
+    };
+
+    match options.hardlink_fallback {
+        HardlinkFallback::Copy => {
+            warn!(
+                "Creating hardlink failed, falling back to copy: {:?} for file {:?} -> {:?}",
+                err, source, target
+            );
+            report(options, |events| events.hardlink_fallbacks += 1);
+            copy_file(source, target, options)
+        }
+        HardlinkFallback::Symlink => {
+            warn!(
+                "Creating hardlink failed, falling back to symlink: {:?} for file {:?} -> {:?}",
+                err, source, target
+            );
+            report(options, |events| events.hardlink_fallbacks += 1);
+            let options = ActionOptions {
+                // a failing hardlink must not be substituted by another hardlink
+                symlink_fallback: match options.symlink_fallback {
+                    SymlinkFallback::Hardlink => SymlinkFallback::Copy,
+                    fallback => fallback,
+                },
+                ..options.clone()
+            };
+            absolute_symlink_file(source, target, &options)
+        }
+        HardlinkFallback::Error => {
+            let target_dir = match target.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            Err(std::io::Error::new(
+                err.kind(),
+                format!(
+                    "Creating hardlink failed: {} (source file system: {:?}, target file system: {:?})",
+                    err,
+                    mount_point(source),
+                    mount_point(target_dir)
+                ),
+            ))
+        }
+    }
+}
+
+/// Returns the mount point of the file system a path is located on, or the path itself if it can not be determined.
+fn mount_point(path: &Path) -> PathBuf {
+    let Ok(path) = fs::canonicalize(path) else {
+        return path.to_path_buf();
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let Ok(device) = fs::metadata(&path).map(|metadata| metadata.dev()) else {
+            return path;
+        };
+
+        let mut mount_point = path.as_path();
+        while let Some(parent) = mount_point.parent() {
+            match fs::metadata(parent) {
+                Ok(metadata) if metadata.dev() == device => mount_point = parent,
+                _ => break,
+            }
+        }
+        mount_point.to_path_buf()
+    }
+
+    #[cfg(not(unix))]
+    {
+        path.ancestors()
+            .last()
+            .map(Path::to_path_buf)
+            .unwrap_or(path)
+    }
+}
+
+/// Checks if a file is moved to a target on another file system, where a move copies the file (see
+/// [`ActualAction::Move`]). The target does not need to exist yet, its nearest existing parent directory is checked.
+pub fn crosses_file_systems(source: &Path, target: &Path) -> bool {
+    let Some(target_dir) = target.ancestors().skip(1).find(|dir| dir.exists()) else {
+        return false;
+    };
+    mount_point(source) != mount_point(target_dir)
+}
+
+fn relative_symlink_file(
+    source: &PathBuf,
+    target: &PathBuf,
+    options: &ActionOptions,
+) -> std::io::Result<()> {
+    if options.symlink_privilege.is_missing() {
+        return symlink_fallback(source, target, options);
+    }
+
+    let canonical_source = fs::canonicalize(source)?;
+    let parent = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let parent = fs::canonicalize(parent)?;
+
+    let link = match relative_path(&parent, &canonical_source) {
+        Some(link) => link,
+        None => {
+            warn!(
+                "No relative path from {:?} to {:?}, creating an absolute symlink instead",
+                parent, canonical_source
+            );
+            canonical_source
+        }
+    };
+
+    create_symlink(&link, source, target, options)
+}
+
+fn absolute_symlink_file(
+    source: &PathBuf,
+    target: &PathBuf,
+    options: &ActionOptions,
+) -> std::io::Result<()> {
+    if options.symlink_privilege.is_missing() {
+        return symlink_fallback(source, target, options);
+    }
+
+    let link = strip_extended_length_prefix(fs::canonicalize(source)?);
+    create_symlink(&link, source, target, options)
+}
+
+/// Creates a symlink pointing at `link`. If the user lacks the privilege to create symlinks, this is explained
+/// once and the `SymlinkFallback` is applied to this and all following files.
+fn create_symlink(
+    link: &Path,
+    source: &PathBuf,
+    target: &PathBuf,
+    options: &ActionOptions,
+) -> std::io::Result<()> {
+    debug!("Creating symlink {:?} -> {:?}", link, target);
+    let result = symlink::symlink_file(link, target);
+    match result {
+        Err(err) if is_privilege_not_held(&err) => {
+            if options.symlink_privilege.set_missing() {
+                error!(
+                    "Creating symlinks requires a privilege the current user does not hold. On Windows, enable \
+                    Developer Mode or run PhotoSort as administrator. Applying --symlink-fallback ({:?}) to all \
+                    remaining files.",
+                    options.symlink_fallback
+                );
+            }
+            symlink_fallback(source, target, options)
+        }
+        result => result,
+    }
+}
+
+fn symlink_fallback(
+    source: &PathBuf,
+    target: &PathBuf,
+    options: &ActionOptions,
+) -> std::io::Result<()> {
+    match options.symlink_fallback {
+        SymlinkFallback::Error => Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "Creating symlinks is not permitted for the current user",
+        )),
+        SymlinkFallback::Hardlink => {
+            debug!(
+                "Creating hardlink instead of symlink {:?} -> {:?}",
+                source, target
+            );
+            report(options, |events| events.symlink_fallbacks += 1);
+            fs::hard_link(source, target)
+        }
+        SymlinkFallback::Copy => {
+            debug!(
+                "Copying instead of creating symlink {:?} -> {:?}",
+                source, target
+            );
+            report(options, |events| events.symlink_fallbacks += 1);
+            copy_file(source, target, options)
+        }
+    }
+}
+
+/// Checks if the error is caused by a missing privilege (`ERROR_PRIVILEGE_NOT_HELD` on Windows).
+fn is_privilege_not_held(err: &std::io::Error) -> bool {
+    /// Windows error code `ERROR_PRIVILEGE_NOT_HELD`
+    const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+    cfg!(windows) && err.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD)
+}
+
+/// Computes the relative path from a directory to a path, e.g. `../2020/IMG.jpg` from `/photos/2021` to
+/// `/photos/2020/IMG.jpg`. Both paths must be absolute and normalized (see [`fs::canonicalize`]).
+///
+/// # Arguments
+///
+/// * `from` - The directory the relative path starts at.
+/// * `to` - The path the relative path points to.
+///
+/// # Returns
+///
+/// * `Option<PathBuf>` - The relative path, `None` if the paths have no common root (e.g. different drives on Windows).
+pub fn relative_path(from: &Path, to: &Path) -> Option<PathBuf> {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| ComponentKey::from(**a) == ComponentKey::from(**b))
+        .count();
+    let has_common_root = matches!(
+        from.first().zip(to.first()),
+        Some((a, b)) if ComponentKey::from(*a) == ComponentKey::from(*b)
+    );
+    if !has_common_root {
+        return None;
+    }
+
+    let mut result = PathBuf::new();
+    for _ in common..from.len() {
+        result.push("..");
+    }
+    for component in &to[common..] {
+        result.push(component);
+    }
+    Some(result)
+}
+
+/// A path component compared by [`relative_path`]. The prefixes of Windows paths are compared by their drive or
+/// share, so that e.g. `C:\photos`, `c:\photos` and `\\?\C:\photos` have a common root.
+#[derive(PartialEq)]
+enum ComponentKey<'a> {
+    Drive(u8),
+    Share(&'a OsStr, &'a OsStr),
+    Other(Component<'a>),
+}
+
+impl<'a> From<Component<'a>> for ComponentKey<'a> {
+    fn from(component: Component<'a>) -> Self {
+        let Component::Prefix(prefix) = component else {
+            return ComponentKey::Other(component);
+        };
+        match prefix.kind() {
+            Prefix::Disk(drive) | Prefix::VerbatimDisk(drive) => {
+                ComponentKey::Drive(drive.to_ascii_uppercase())
+            }
+            Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                ComponentKey::Share(server, share)
+            }
+            _ => ComponentKey::Other(component),
+        }
+    }
+}
+
+/// Checks if two files have identical content. The file sizes are compared first, then the content is
+/// compared chunk by chunk.
+///
+/// # Arguments
+///
+/// * `a` - The first file.
+/// * `b` - The second file.
+///
+/// # Errors
+///
+/// This function will return an error if one of the files can not be read.
+pub fn is_identical_file(a: &Path, b: &Path) -> std::io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    if is_same_file(a, b)? {
+        return Ok(true);
+    }
+
+    is_identical_content(fs::File::open(a)?, fs::File::open(b)?)
+}
+
+/// Checks if two files of a file system have identical content, like [`is_identical_file`].
+///
+/// # Arguments
+///
+/// * `file_system` - The file system of the files.
+/// * `a` - The first file.
+/// * `b` - The second file.
+///
+/// # Errors
+///
+/// This function will return an error if one of the files can not be read.
+pub fn is_identical_file_on(
+    file_system: &dyn FileSystem,
+    a: &Path,
+    b: &Path,
+) -> std::io::Result<bool> {
+    if file_system.metadata(a)?.len != file_system.metadata(b)?.len {
+        return Ok(false);
+    }
+    if file_system.canonicalize(a)? == file_system.canonicalize(b)? {
+        return Ok(true);
+    }
+
+    is_identical_content(file_system.open(a)?, file_system.open(b)?)
+}
+
+/// Compares two readers chunk by chunk.
+fn is_identical_content<A: Read, B: Read>(a: A, b: B) -> std::io::Result<bool> {
+    const CHUNK_SIZE: usize = 64 * 1024;
+    let mut a = std::io::BufReader::with_capacity(CHUNK_SIZE, a);
+    let mut b = std::io::BufReader::with_capacity(CHUNK_SIZE, b);
+    let mut a_chunk = vec![0u8; CHUNK_SIZE];
+    let mut b_chunk = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let a_read = read_chunk(&mut a, &mut a_chunk)?;
+        let b_read = read_chunk(&mut b, &mut b_chunk)?;
+        if a_read != b_read || a_chunk[..a_read] != b_chunk[..b_read] {
+            return Ok(false);
+        }
+        if a_read == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fills the buffer as far as possible, returns the number of bytes read (0 at the end of the file).
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+/// Deletes a source file that is not needed anymore, e.g. because an identical copy already exists
+/// in the target directory. If `options.use_trash` is set, the file is moved to the trash instead.
+///
+/// # Arguments
+///
+/// * `path` - The file to delete.
+/// * `action` - An ActionMode reference, in dry-run mode the file is not deleted but the deletion is printed.
+/// * `options` - An ActionOptions reference specifying how the file is deleted.
+///
+/// # Errors
+///
+/// This function will return an error if the file could not be deleted.
+pub fn delete_file(path: &Path, action: &ActionMode, options: &ActionOptions) -> Result<()> {
+    match action {
+        ActionMode::DryRun(_) if options.use_trash => {
+            info!("[Trash] {:?}", path);
+            Ok(())
+        }
+        ActionMode::DryRun(_) => {
+            info!("[Delete] {:?}", path);
+            Ok(())
+        }
+        ActionMode::Execute(_) => remove_file(path, options)
+            .map_err(|e| anyhow!("Failed to delete file: {:?} - {:?}", path, e)),
+    }
+}
+
+/// Deletes a file, moving it to the trash if `options.use_trash` is set and the platform supports it.
+fn remove_file(path: &Path, options: &ActionOptions) -> std::io::Result<()> {
+    if options.use_trash {
+        #[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
+        {
+            debug!("Moving to trash {:?}", path);
+            return trash::delete(path).map_err(std::io::Error::other);
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+        warn!(
+            "Trash is not supported on this platform, deleting {:?}",
+            path
+        );
+    }
+
+    debug!("Deleting {:?}", path);
+    fs::remove_file(path)
+}
+
+/// Restores the most recently trashed file of a path from the trash of the operating system.
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn restore_from_trash(path: &Path) -> std::io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    // the trash records canonical paths
+    let parent = fs::canonicalize(parent)?;
+    let item = trash::os_limited::list()
+        .map_err(std::io::Error::other)?
+        .into_iter()
+        .filter(|item| {
+            path.file_name() == Some(item.name.as_os_str())
+                && fs::canonicalize(&item.original_parent).is_ok_and(|original| original == parent)
+        })
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "File not found in the trash")
+        })?;
+    trash::os_limited::restore_all([item]).map_err(std::io::Error::other)
+}
+
+/// Restores the most recently trashed file of a path, which is not supported on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn restore_from_trash(_path: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Restoring files from the trash is not supported on this platform",
+    ))
+}
+
+/// Checks if the modification time of the target file can be set for an action without touching another file,
+/// i.e. the action is not a symlink action.
+pub fn supports_set_mtime(action: ActualAction) -> bool {
+    !matches!(
+        action,
+        ActualAction::RelativeSymlink | ActualAction::AbsoluteSymlink
+    )
+}
+
+/// Converts a date in local time to a `FileTime`. Ambiguous dates (daylight saving time changes) use the earlier
+/// time, dates that do not exist in local time are interpreted as UTC.
+pub fn local_file_time(date: NaiveDateTime) -> FileTime {
+    let timestamp = match Local.from_local_datetime(&date).earliest() {
+        Some(date) => date.timestamp(),
+        None => date.and_utc().timestamp(),
+    };
+    FileTime::from_unix_time(timestamp, date.and_utc().timestamp_subsec_nanos())
+}
+
+/// Sets the modification time of a file to a date (in local time).
+///
+/// # Arguments
+///
+/// * `path` - The file.
+/// * `date` - The new modification time.
+/// * `action` - An ActionMode reference, in dry-run mode the modification time is not changed but printed.
+///
+/// # Errors
+///
+/// This function will return an error if the modification time could not be set.
+pub fn set_modification_time(path: &Path, date: NaiveDateTime, action: &ActionMode) -> Result<()> {
+    match action {
+        ActionMode::DryRun(_) => {
+            info!("[Mtime] {:?} {}", path, date);
+            Ok(())
+        }
+        ActionMode::Execute(_) => {
+            debug!("Setting modification time of {:?} to {}", path, date);
+            filetime::set_file_mtime(extended_length_path(path), local_file_time(date))
+                .map_err(|e| anyhow!("Failed to set modification time: {:?} - {:?}", path, e))
+        }
+    }
+}
+
+/// Names of files operating systems and file managers create in folders (e.g. `.DS_Store`, `Thumbs.db`).
+pub const LITTER_FILES: &[&str] = &[".DS_Store", "._.DS_Store", "Thumbs.db", "desktop.ini"];
+
+/// Names of directories NAS systems create in folders, e.g. `@eaDir` holding the thumbnails of a Synology NAS.
+pub const LITTER_DIRS: &[&str] = &["@eaDir"];
+
+/// `PruneOptions` holds the options that influence which directories are removed by [`prune_empty_dirs`].
+///
+/// # Fields
+/// * `include_root` - Also remove the root directory itself if it is empty.
+/// * `prune_litter` - Delete litter files (see [`LITTER_FILES`]), so that directories only containing litter are removed, too.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct PruneOptions {
+    pub include_root: bool,
+    pub prune_litter: bool,
+}
+
+/// Removes empty directories below a directory, bottom-up. Symlinks are not followed.
+///
+/// # Arguments
+///
+/// * `directory` - The root directory.
+/// * `removed_files` - Files that are considered absent, e.g. files that a dry-run would have moved away.
+/// * `action` - An ActionMode reference, in dry-run mode no directory is removed.
+/// * `options` - A PruneOptions reference specifying which directories are removed.
+///
+/// # Returns
+///
+/// * `Vec<PathBuf>` - The removed directories (or the directories that would be removed in dry-run mode), children first.
+///
+/// # Errors
+///
+/// This function will return an error if the root directory can not be read. Errors in subdirectories are logged,
+/// the affected directories are kept.
+pub fn prune_empty_dirs(
+    directory: &Path,
+    removed_files: &HashSet<PathBuf>,
+    action: &ActionMode,
+    options: &PruneOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut pruned = Vec::new();
+    prune_dir(directory, true, removed_files, action, options, &mut pruned)
+        .map_err(|e| anyhow!("Failed to prune directory: {:?} - {:?}", directory, e))?;
+    Ok(pruned)
+}
+
+/// Prunes a directory and its subdirectories, returns if the directory was (or would be) removed.
+fn prune_dir(
+    directory: &Path,
+    is_root: bool,
+    removed_files: &HashSet<PathBuf>,
+    action: &ActionMode,
+    options: &PruneOptions,
+    pruned: &mut Vec<PathBuf>,
+) -> std::io::Result<bool> {
+    let mut empty = true;
+    let mut litter = Vec::new();
+
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            match prune_dir(&path, false, removed_files, action, options, pruned) {
+                Ok(true) => {}
+                Ok(false) => empty = false,
+                Err(err) => {
+                    warn!("Failed to prune directory {:?}: {}", path, err);
+                    empty = false;
+                }
+            }
+        } else if removed_files.contains(&path) {
+            continue;
+        } else if options.prune_litter
+            && file_type.is_file()
+            && LITTER_FILES.iter().any(|name| entry.file_name() == *name)
+        {
+            litter.push(path);
+        } else {
+            empty = false;
+        }
+    }
+
+    if !empty || (is_root && !options.include_root) {
+        return Ok(false);
+    }
+
+    if let ActionMode::Execute(_) = action {
+        for file in &litter {
+            debug!("Deleting litter file {:?}", file);
+            fs::remove_file(file)?;
+        }
+        debug!("Removing empty directory {:?}", directory);
+        fs::remove_dir(directory)?;
+    }
+    pruned.push(directory.to_path_buf());
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths() {
+        let cases = [
+            (
+                "/photos/2021",
+                "/photos/2020/IMG.jpg",
+                Some("../2020/IMG.jpg"),
+            ),
+            ("/photos", "/photos/2020/IMG.jpg", Some("2020/IMG.jpg")),
+            ("/photos/2020", "/photos/2020/IMG.jpg", Some("IMG.jpg")),
+            (
+                "/target/2020/07",
+                "/source/a/b/IMG.jpg",
+                Some("../../../source/a/b/IMG.jpg"),
+            ),
+            ("/", "/source/IMG.jpg", Some("source/IMG.jpg")),
+            ("relative", "/source/IMG.jpg", None),
+        ];
+        for (from, to, expected) in cases {
+            assert_eq!(
+                relative_path(Path::new(from), Path::new(to)),
+                expected.map(PathBuf::from),
+                "relative path from {:?} to {:?}",
+                from,
+                to
+            );
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn relative_windows_paths() {
+        let cases = [
+            (
+                r"C:\photos\2021",
+                r"C:\photos\2020\IMG.jpg",
+                Some(r"..\2020\IMG.jpg"),
+            ),
+            (r"C:\", r"C:\source\IMG.jpg", Some(r"source\IMG.jpg")),
+            (
+                r"c:\target",
+                r"C:\source\IMG.jpg",
+                Some(r"..\source\IMG.jpg"),
+            ),
+            (r"C:\target", r"D:\source\IMG.jpg", None),
+            (r"C:target", r"D:source\IMG.jpg", None),
+            (
+                r"\\?\C:\target\2020",
+                r"\\?\C:\source\IMG.jpg",
+                Some(r"..\..\source\IMG.jpg"),
+            ),
+            (
+                r"\\?\C:\target",
+                r"C:\source\IMG.jpg",
+                Some(r"..\source\IMG.jpg"),
+            ),
+            (r"\\?\C:\target", r"\\?\D:\source\IMG.jpg", None),
+            (
+                r"\\server\share\target",
+                r"\\?\UNC\server\share\source\IMG.jpg",
+                Some(r"..\source\IMG.jpg"),
+            ),
+            (r"\\server\share\target", r"\\server\other\IMG.jpg", None),
+            (r"\target", r"C:\source\IMG.jpg", None),
+        ];
+        for (from, to, expected) in cases {
+            assert_eq!(
+                relative_path(Path::new(from), Path::new(to)),
+                expected.map(PathBuf::from),
+                "relative path from {:?} to {:?}",
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn identical_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, content: &[u8]| {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path
+        };
+        // larger than a chunk, so that the comparison reads several chunks
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut changed = content.clone();
+        *changed.last_mut().unwrap() ^= 1;
+
+        let a = file("a", &content);
+        let b = file("b", &content);
+        let c = file("c", &changed);
+        let d = file("d", &content[..content.len() - 1]);
+        let empty = file("empty", b"");
+        let also_empty = file("also_empty", b"");
+
+        assert!(is_identical_file(&a, &b).unwrap());
+        assert!(is_identical_file(&a, &a).unwrap());
+        assert!(!is_identical_file(&a, &c).unwrap());
+        assert!(!is_identical_file(&a, &d).unwrap());
+        assert!(is_identical_file(&empty, &also_empty).unwrap());
+        assert!(is_identical_file(&a, &dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn copies_never_replace_a_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.jpg");
+        let target = dir.path().join("target.jpg");
+        fs::write(&source, b"new").unwrap();
+        // created after the target was checked, e.g. by another process
+        fs::write(&target, b"existing").unwrap();
+
+        let err = copy_file(&source, &target, &ActionOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&target).unwrap(), b"existing");
+        // the temporary copy is removed
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        let copy = dir.path().join("copy.jpg");
+        copy_file(&source, &copy, &ActionOptions::default()).unwrap();
+        assert_eq!(fs::read(&copy).unwrap(), b"new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn missing_symlink_privilege_is_shared_per_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.jpg");
+        fs::write(&source, b"photo").unwrap();
+        let mode = ActionMode::Execute(ActualAction::AbsoluteSymlink);
+
+        let privilege = SymlinkPrivilege::new();
+        let run = |privilege: &SymlinkPrivilege, name: &str| {
+            let report = ActionReport::new();
+            let options = ActionOptions {
+                symlink_fallback: SymlinkFallback::Copy,
+                symlink_privilege: privilege.clone(),
+                report: Some(report.clone()),
+                ..ActionOptions::default()
+            };
+            let target = dir.path().join(name);
+            file_action(&source, &target, &mode, &options).unwrap();
+            (is_symlink(&target), report.events().symlink_fallbacks)
+        };
+        assert_eq!(run(&privilege, "first.jpg"), (true, 0));
+
+        // once the privilege is known to be missing, the files of the run are copied
+        assert!(privilege.set_missing());
+        assert!(!privilege.clone().set_missing());
+        assert_eq!(run(&privilege, "second.jpg"), (false, 1));
+        assert_eq!(fs::read(dir.path().join("second.jpg")).unwrap(), b"photo");
+
+        // another run starts over
+        assert_eq!(run(&SymlinkPrivilege::new(), "third.jpg"), (true, 0));
+    }
 }
@@ -1,14 +1,79 @@
 pub mod exif2date;
+pub mod exiftool;
 pub mod filename2date;
+pub mod gpmf;
 pub mod name_formatters;
+pub mod sniff;
+pub mod takeout;
 #[cfg(feature = "video")]
 pub mod video2date;
 
-use anyhow::Result;
-use chrono::NaiveDateTime;
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDateTime, TimeDelta, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
 
 use crate::analysis::filename2date::FileNameToDateTransformer;
 
+/// `MediaInfo` holds additional information about a media file, gathered while analyzing it.
+///
+/// # Fields
+/// * `duration` - The duration of a video file, `None` for images or if unknown.
+/// * `vertical_resolution` - The vertical resolution (height in pixels) of a video file, `None` for images or if unknown.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MediaInfo {
+    pub duration: Option<Duration>,
+    pub vertical_resolution: Option<u32>,
+}
+
+/// `DateSource` is an enumeration that defines where the date of a file was derived from.
+///
+/// # Variants
+///
+/// * `Exif` - The date was read from the Exif data of an image or the metadata of a video.
+/// * `Name` - The date was parsed from the file name.
+/// * `Exiftool` - The date was read from the exiftool JSON dump (see `metadata_json`).
+/// * `Takeout` - The date was read from the Google Takeout metadata file (`IMG_1234.jpg.json`) of the file.
+/// * `None` - No date could be derived.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateSource {
+    Exif,
+    Name,
+    Exiftool,
+    Takeout,
+    #[default]
+    None,
+}
+
+impl Display for DateSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DateSource::Exif => write!(f, "exif"),
+            DateSource::Name => write!(f, "name"),
+            DateSource::Exiftool => write!(f, "exiftool"),
+            DateSource::Takeout => write!(f, "takeout"),
+            DateSource::None => write!(f, "none"),
+        }
+    }
+}
+
+/// `FileAnalysis` is the result of analyzing a file.
+///
+/// # Fields
+/// * `date` - The date derived from the file, if any.
+/// * `date_source` - Where the date was derived from.
+/// * `name` - The transformed name of the file (the date part removed).
+/// * `media_info` - The `MediaInfo` of the file, empty for images.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileAnalysis {
+    pub date: Option<NaiveDateTime>,
+    pub date_source: DateSource,
+    pub name: String,
+    pub media_info: MediaInfo,
+}
+
 /// This function tries to retrieve a file creation date and time from a file name.
 ///
 /// The function accepts a list of `NameTransformer` instances that are used to match and transform the file name into a datetime.
@@ -51,3 +116,153 @@ pub fn get_name_time(
 
     Ok(None)
 }
+
+/// Parses a duration like `24h`, `90m`, `30s` or `2d` (a number without unit is in seconds).
+///
+/// # Errors
+///
+/// This function will return an error if the string is not a valid, non-negative duration.
+pub fn parse_duration(s: &str) -> Result<TimeDelta> {
+    let s = s.trim();
+    let (number, unit) = match s.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((index, _)) => s.split_at(index),
+        None => (s, ""),
+    };
+
+    let factor: i64 = match unit.to_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(anyhow!("Invalid duration unit: {:?}", unit)),
+    };
+    let number: i64 = number
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration: {:?}", s))?;
+    if number < 0 {
+        return Err(anyhow!("Duration must not be negative: {:?}", s));
+    }
+
+    number
+        .checked_mul(factor)
+        .and_then(TimeDelta::try_seconds)
+        .ok_or_else(|| anyhow!("Duration is too long: {:?}", s))
+}
+
+/// Parses a time offset like `+01:00`, `-00:30:15` (`[+-]HH:MM[:SS]`) or a number of seconds like `3600` or `-90`.
+///
+/// # Errors
+///
+/// This function will return an error if the string is not a valid offset.
+pub fn parse_time_offset(s: &str) -> Result<TimeDelta> {
+    let s = s.trim();
+    let (sign, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let invalid = || {
+        anyhow!(
+            "Invalid time offset: {:?}, expected [+-]HH:MM[:SS] or seconds",
+            s
+        )
+    };
+
+    let parts: Vec<&str> = unsigned.split(':').collect();
+    let seconds = match parts.as_slice() {
+        [seconds] => seconds.parse::<i64>().map_err(|_| invalid())?,
+        [hours, minutes] | [hours, minutes, _] => {
+            let hours = hours.parse::<i64>().map_err(|_| invalid())?;
+            let minutes = minutes.parse::<i64>().map_err(|_| invalid())?;
+            let seconds = match parts.get(2) {
+                Some(seconds) => seconds.parse::<i64>().map_err(|_| invalid())?,
+                None => 0,
+            };
+            if !(0..60).contains(&minutes) || !(0..60).contains(&seconds) {
+                return Err(invalid());
+            }
+            hours
+                .checked_mul(3600)
+                .and_then(|hours| hours.checked_add(minutes * 60 + seconds))
+                .ok_or_else(invalid)?
+        }
+        _ => return Err(invalid()),
+    };
+
+    TimeDelta::try_seconds(sign * seconds).ok_or_else(invalid)
+}
+
+/// Formats a time offset as `+HH:MM:SS` or `-HH:MM:SS`, see [`parse_time_offset`].
+pub fn format_time_offset(offset: TimeDelta) -> String {
+    let sign = if offset < TimeDelta::zero() { '-' } else { '+' };
+    let seconds = offset.num_seconds().unsigned_abs();
+    format!(
+        "{}{:02}:{:02}:{:02}",
+        sign,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Floors a date to a multiple of a number of seconds since the Unix epoch, e.g. `12:34:56` to `12:34:50` for 10
+/// seconds or to `12:34:00` for 60 seconds. Fractions of seconds are dropped.
+///
+/// # Arguments
+///
+/// * `date` - The date to floor.
+/// * `seconds` - The number of seconds, 0 and 1 only drop the fraction of the seconds.
+pub fn floor_date(date: NaiveDateTime, seconds: u32) -> NaiveDateTime {
+    let timestamp = date.and_utc().timestamp();
+    let floored = timestamp - timestamp.rem_euclid(i64::from(seconds.max(1)));
+    date.checked_sub_signed(TimeDelta::seconds(timestamp - floored))
+        .and_then(|date| date.with_nanosecond(0))
+        .unwrap_or(date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_offsets() {
+        let cases = [
+            ("+01:00", Some(3600)),
+            ("01:00", Some(3600)),
+            ("-00:30:15", Some(-1815)),
+            ("+25:00", Some(90000)),
+            ("3600", Some(3600)),
+            ("-90", Some(-90)),
+            (" +00:00 ", Some(0)),
+            ("01:60", None),
+            ("01:00:60", None),
+            ("1h", None),
+            ("01:00:00:00", None),
+            ("", None),
+        ];
+        for (offset, seconds) in cases {
+            assert_eq!(
+                parse_time_offset(offset).ok(),
+                seconds.map(TimeDelta::seconds),
+                "parsing {:?}",
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn formatted_time_offsets_parse_again() {
+        for seconds in [0, 59, 3600, -1815, 90000, -90] {
+            let offset = TimeDelta::seconds(seconds);
+            let formatted = format_time_offset(offset);
+            assert_eq!(
+                parse_time_offset(&formatted).unwrap(),
+                offset,
+                "{}",
+                formatted
+            );
+        }
+        assert_eq!(format_time_offset(TimeDelta::seconds(-1815)), "-00:30:15");
+        assert_eq!(format_time_offset(TimeDelta::seconds(90000)), "+25:00:00");
+    }
+}
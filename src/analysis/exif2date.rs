@@ -33,3 +33,76 @@ pub fn get_exif_time(file: &File) -> anyhow::Result<Option<NaiveDateTime>> {
         })
         .transpose()?)
 }
+
+/// Header of the maker note Apple devices write to the EXIF data of their photos
+const APPLE_MAKER_NOTE_HEADER: &[u8] = b"Apple iOS\0";
+/// Offset of the IFD of the Apple maker note, after the header, the version and the byte order (`MM`)
+const APPLE_MAKER_NOTE_IFD_OFFSET: usize = 14;
+/// Tag of the content identifier in the Apple maker note, it links the photo of a Live Photo to its video
+const APPLE_CONTENT_IDENTIFIER_TAG: u16 = 0x0011;
+
+/// This function retrieves the content identifier of a Live Photo from the Apple maker note in the EXIF data of a
+/// file. The video of the Live Photo carries the same identifier in its metadata.
+///
+/// # Arguments
+///
+/// * `file` - A reference to a `File` object.
+///
+/// # Returns
+///
+/// * `Result<Option<String>>` - The content identifier, or `None` if the file has no Apple maker note or the maker
+///   note has no content identifier.
+///
+/// # Errors
+///
+/// This function will return an error if the file could not be read or the EXIF data could not be read from the
+/// file.
+pub fn get_exif_content_identifier(file: &File) -> anyhow::Result<Option<String>> {
+    let mut bufreader = std::io::BufReader::new(file);
+    let exifreader = exif::Reader::new();
+    let exif = exifreader.read_from_container(&mut bufreader)?;
+    let Some(field) = exif.get_field(exif::Tag::MakerNote, exif::In::PRIMARY) else {
+        return Ok(None);
+    };
+    let exif::Value::Undefined(maker_note, _) = &field.value else {
+        return Ok(None);
+    };
+    Ok(find_apple_content_identifier(maker_note))
+}
+
+/// Reads the content identifier from an Apple maker note (a big-endian IFD, offsets are relative to the start of
+/// the maker note).
+fn find_apple_content_identifier(maker_note: &[u8]) -> Option<String> {
+    if !maker_note.starts_with(APPLE_MAKER_NOTE_HEADER) {
+        return None;
+    }
+    let read_u16 = |offset: usize| {
+        maker_note
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let read_u32 = |offset: usize| {
+        maker_note
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    let entries = read_u16(APPLE_MAKER_NOTE_IFD_OFFSET)?;
+    (0..usize::from(entries))
+        .map(|index| APPLE_MAKER_NOTE_IFD_OFFSET + 2 + index * 12)
+        .find(|entry| read_u16(*entry) == Some(APPLE_CONTENT_IDENTIFIER_TAG))
+        .and_then(|entry| {
+            let count = read_u32(entry + 4)? as usize;
+            // values of up to 4 bytes are stored in the entry itself
+            let offset = if count <= 4 {
+                entry + 8
+            } else {
+                read_u32(entry + 8)? as usize
+            };
+            let value = maker_note.get(offset..offset.checked_add(count)?)?;
+            let value = String::from_utf8_lossy(value)
+                .trim_end_matches('\0')
+                .to_string();
+            Some(value).filter(|value| !value.is_empty())
+        })
+}
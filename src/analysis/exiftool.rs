@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use log::debug;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
+
+/// The tags the date is read from, in order of preference.
+const DATE_TAGS: &[&str] = &["DateTimeOriginal", "CreateDate"];
+
+/// `ExiftoolDates` holds the dates of a JSON dump of exiftool (`exiftool -json -r <dir>`), an array with an object
+/// per file. The files are identified by their `SourceFile`, the date is read from `DateTimeOriginal` or
+/// `CreateDate` (also with group names, `-G`).
+///
+/// Absolute source files match the scanned files with the same absolute path (also after resolving symlinks).
+/// Relative source files are relative to the directory exiftool was run in, which is not known: they match the
+/// scanned files whose path ends with them, the longest match wins.
+#[derive(Debug, Clone, Default)]
+pub struct ExiftoolDates {
+    dates: HashMap<PathBuf, NaiveDateTime>,
+}
+
+impl ExiftoolDates {
+    /// Loads the dates of a JSON dump of exiftool. Files without (valid) date are left out.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be read or is not a JSON array.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ExiftoolDates> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| anyhow!("Failed to open exiftool JSON: {:?} - {:?}", path, e))?;
+        let value: serde_json::Value = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| anyhow!("Failed to parse exiftool JSON: {:?} - {}", path, e))?;
+        let entries = value
+            .as_array()
+            .ok_or_else(|| anyhow!("The exiftool JSON is not an array: {:?}", path))?;
+
+        let mut dates = HashMap::new();
+        for entry in entries {
+            let Some(source) = entry["SourceFile"].as_str() else {
+                continue;
+            };
+            match entry_date(entry) {
+                Some(date) => {
+                    dates.insert(normalize(Path::new(source)), date);
+                }
+                None => debug!("No date in the exiftool JSON for {:?}", source),
+            }
+        }
+        debug!(
+            "Loaded the dates of {} files from exiftool JSON {:?}",
+            dates.len(),
+            path
+        );
+        Ok(ExiftoolDates { dates })
+    }
+
+    /// The number of files with a date.
+    pub fn len(&self) -> usize {
+        self.dates.len()
+    }
+
+    /// Checks if no file has a date.
+    pub fn is_empty(&self) -> bool {
+        self.dates.is_empty()
+    }
+
+    /// Looks up the date of a scanned file.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<NaiveDateTime>` - The date, `None` if the file is not in the JSON or has no date.
+    pub fn date(&self, path: &Path) -> Option<NaiveDateTime> {
+        let absolute = normalize(&std::path::absolute(path).ok()?);
+        if let Some(date) = self.dates.get(&absolute) {
+            return Some(*date);
+        }
+        if let Some(date) = std::fs::canonicalize(path)
+            .ok()
+            .and_then(|canonical| self.dates.get(&canonical))
+        {
+            return Some(*date);
+        }
+
+        // relative source files, the longest suffix of the path first
+        let components: Vec<_> = absolute.components().collect();
+        (1..components.len()).find_map(|start| {
+            let suffix: PathBuf = components[start..].iter().collect();
+            self.dates.get(&suffix).copied()
+        })
+    }
+}
+
+/// Reads the date of an entry of the JSON dump, from the first tag of `DATE_TAGS` with a valid date.
+fn entry_date(entry: &serde_json::Value) -> Option<NaiveDateTime> {
+    let object = entry.as_object()?;
+    DATE_TAGS.iter().find_map(|tag| {
+        object
+            .iter()
+            .filter(|(key, _)| {
+                key.as_str() == *tag || key.rsplit_once(':').is_some_and(|(_, name)| name == *tag)
+            })
+            .find_map(|(_, value)| value.as_str().and_then(parse_date))
+    })
+}
+
+/// Parses a date as exiftool writes it, `2020:01:02 03:04:05`, optionally followed by sub-seconds and a time zone
+/// (`2020:01:02 03:04:05.12+02:00` or `...Z`). Like Exif dates, the local time is used, the time zone is ignored.
+/// Dates exiftool writes for unset tags (`0000:00:00 00:00:00`) are invalid.
+pub fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    let date = value.trim().get(..19)?;
+    NaiveDateTime::parse_from_str(date, "%Y:%m:%d %H:%M:%S").ok()
+}
+
+/// Normalizes a path lexically, without accessing the file system: `.` is removed and `..` removes the preceding
+/// component. Leading `..` of relative paths are dropped, they are matched as a suffix anyway.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,158 @@
+use chrono::NaiveDateTime;
+
+/// Key of the GPS UTC time entry in a GPMF stream
+const KEY_GPSU: &[u8; 4] = b"GPSU";
+/// Type of a nested GPMF container
+const TYPE_NESTED: u8 = 0;
+/// Type of a GPMF UTC date time string (`yymmddhhmmss.sss`)
+const TYPE_UTC_DATE: u8 = b'U';
+
+/// This function searches a GPMF (GoPro Metadata Format) payload for the first GPS UTC time
+/// sample (`GPSU`).
+///
+/// A GPMF payload is a sequence of KLV entries. Each entry starts with a four character key,
+/// followed by a one byte type, a one byte sample size and a two byte (big-endian) repeat count.
+/// The data (sample size * repeat count bytes) is padded to a multiple of four bytes. Entries of
+/// type `0` contain nested entries.
+///
+/// # Arguments
+/// * `data` - The raw payload of a GPMF stream packet.
+///
+/// # Returns
+/// * `Some(NaiveDateTime)` - The first GPS time found in the payload.
+/// * `None` - If the payload is malformed or does not contain a GPS time.
+pub fn find_gps_time(data: &[u8]) -> Option<NaiveDateTime> {
+    let mut offset = 0;
+    while offset + 8 <= data.len() {
+        let key = &data[offset..offset + 4];
+        let data_type = data[offset + 4];
+        let sample_size = data[offset + 5] as usize;
+        let repeat = u16::from_be_bytes([data[offset + 6], data[offset + 7]]) as usize;
+
+        let length = sample_size * repeat;
+        let start = offset + 8;
+        let end = start + length;
+        if end > data.len() {
+            return None;
+        }
+        let value = &data[start..end];
+
+        if data_type == TYPE_NESTED {
+            if let Some(time) = find_gps_time(value) {
+                return Some(time);
+            }
+        } else if key == KEY_GPSU && data_type == TYPE_UTC_DATE {
+            // an entry without samples (repeat count 0) has no time, the next entry may have one
+            if let Some(sample) = value.get(..sample_size).filter(|sample| !sample.is_empty()) {
+                let sample = String::from_utf8_lossy(sample);
+                if let Ok(time) = NaiveDateTime::parse_from_str(sample.trim(), "%y%m%d%H%M%S%.f") {
+                    return Some(time);
+                }
+            }
+        }
+
+        // data is padded to 32bit alignment
+        offset = start + length.div_ceil(4) * 4;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// Encodes a KLV entry, the data is padded to a multiple of four bytes.
+    fn klv(key: &[u8; 4], data_type: u8, sample_size: u8, repeat: u16, data: &[u8]) -> Vec<u8> {
+        let mut entry = key.to_vec();
+        entry.push(data_type);
+        entry.push(sample_size);
+        entry.extend_from_slice(&repeat.to_be_bytes());
+        entry.extend_from_slice(data);
+        entry.resize(entry.len().div_ceil(4) * 4, 0);
+        entry
+    }
+
+    fn gpsu(time: &str) -> Vec<u8> {
+        klv(
+            KEY_GPSU,
+            TYPE_UTC_DATE,
+            time.len() as u8,
+            1,
+            time.as_bytes(),
+        )
+    }
+
+    fn nested(key: &[u8; 4], entries: &[Vec<u8>]) -> Vec<u8> {
+        let data = entries.concat();
+        klv(key, TYPE_NESTED, 1, data.len() as u16, &data)
+    }
+
+    fn expected() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 7, 14)
+            .unwrap()
+            .and_hms_milli_opt(10, 20, 30, 450)
+            .unwrap()
+    }
+
+    #[test]
+    fn finds_nested_gps_time() {
+        let payload = nested(
+            b"DEVC",
+            &[
+                klv(b"DVID", b'L', 4, 1, &[0, 0, 0, 1]),
+                nested(
+                    b"STRM",
+                    &[klv(b"STNM", b'c', 3, 1, b"GPS"), gpsu("230714102030.450")],
+                ),
+            ],
+        );
+        assert_eq!(find_gps_time(&payload), Some(expected()));
+    }
+
+    /// A GPMF payload laid out like the telemetry of a GoPro camera: a device with its ID and name, and a GPS stream
+    /// with timestamps, name, fix, time, precision, units, scales and two `GPS5` samples. Unlike the payloads built
+    /// above, nested containers have a sample size of four.
+    const CAMERA_PAYLOAD: &[u8] = include_bytes!("testdata/gopro_gps.gpmf");
+
+    #[test]
+    fn finds_gps_time_in_camera_payload() {
+        assert_eq!(find_gps_time(CAMERA_PAYLOAD), Some(expected()));
+
+        // the same payload without a GPS time
+        let position = CAMERA_PAYLOAD
+            .windows(4)
+            .position(|key| key == KEY_GPSU)
+            .unwrap();
+        let mut payload = CAMERA_PAYLOAD.to_vec();
+        payload[position..position + 4].copy_from_slice(b"GPSX");
+        assert_eq!(find_gps_time(&payload), None);
+    }
+
+    #[test]
+    fn truncated_payload_has_no_time() {
+        let payload = gpsu("230714102030.450");
+        for length in 0..payload.len() - 4 {
+            assert_eq!(find_gps_time(&payload[..length]), None);
+        }
+        // the length of the nested container exceeds the payload
+        let mut payload = nested(b"DEVC", &[gpsu("230714102030.450")]);
+        payload.truncate(payload.len() - 4);
+        assert_eq!(find_gps_time(&payload), None);
+    }
+
+    #[test]
+    fn zero_repeat_entry_is_skipped() {
+        let empty = klv(KEY_GPSU, TYPE_UTC_DATE, 16, 0, &[]);
+        assert_eq!(find_gps_time(&empty), None);
+
+        let payload = [empty, gpsu("230714102030.450")].concat();
+        assert_eq!(find_gps_time(&payload), Some(expected()));
+    }
+
+    #[test]
+    fn invalid_time_is_ignored() {
+        assert_eq!(find_gps_time(&gpsu("not a date time!")), None);
+        assert_eq!(find_gps_time(&[]), None);
+    }
+}
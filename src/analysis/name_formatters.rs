@@ -1,6 +1,8 @@
+use crate::locale::DateLocale;
 use anyhow::Result;
 use chrono::NaiveDateTime;
 use regex::Regex;
+use std::time::Duration;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum FileType {
@@ -9,15 +11,21 @@ pub enum FileType {
     None,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NameFormatterInvocationInfo<'a> {
     pub date: &'a Option<NaiveDateTime>,
     pub date_string: &'a str,
     pub date_default_format: &'a str,
+    pub nodate_placeholder: &'a str,
+    pub locale: &'a DateLocale,
     pub file_type: &'a FileType,
     pub cleaned_name: &'a str,
     pub duplicate_counter: Option<u32>,
     pub extension: String,
+    pub duration: Option<Duration>,
+    pub vertical_resolution: Option<u32>,
+    pub relative_dir: String,
+    pub source_label: &'a str,
 }
 
 pub trait NameFormatter {
@@ -27,6 +35,124 @@ pub trait NameFormatter {
         matched: regex::Captures,
         invocation_info: &NameFormatterInvocationInfo,
     ) -> Result<String>;
+    /// Returns true if the replacement text is a relative path whose `/` separators are kept, so that it can span
+    /// several directories of the target path. The separators of all other replacement texts are removed.
+    fn spans_directories(&self) -> bool {
+        false
+    }
+    /// The name of the command, e.g. `date`, used to list the available commands. Defaults to the first word of the
+    /// argument template.
+    fn name(&self) -> &str {
+        let template = self.argument_template().as_str();
+        let template = template.trim_start_matches(['^', '(']);
+        let end = template
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(template.len());
+        &template[..end]
+    }
+    /// A short description of the syntax of the command, e.g. `{date} or {date?FORMAT}`. Defaults to the name in
+    /// braces.
+    fn syntax_help(&self) -> String {
+        format!("{{{}}}", self.name())
+    }
+}
+
+/// `FormatterRegistry` holds the name formatters of an `Analyzer`, in the order they are tried: the first formatter
+/// whose argument template matches a command formats it.
+#[derive(Default)]
+pub struct FormatterRegistry {
+    formatters: Vec<Box<dyn NameFormatter + Send + Sync>>,
+}
+
+impl FormatterRegistry {
+    /// Adds a formatter, it is tried after the formatters added before.
+    pub fn register(&mut self, formatter: Box<dyn NameFormatter + Send + Sync>) {
+        self.formatters.push(formatter);
+    }
+
+    /// The registered formatters, in the order they are tried.
+    pub fn iter(&self) -> impl Iterator<Item = &(dyn NameFormatter + Send + Sync)> {
+        self.formatters.iter().map(|formatter| formatter.as_ref())
+    }
+
+    /// The names of the registered commands, without duplicates.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for formatter in self.iter() {
+            if !names.contains(&formatter.name()) {
+                names.push(formatter.name());
+            }
+        }
+        names
+    }
+
+    /// Finds the formatter of a command (without modifier, e.g. `date?%Y`).
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(&dyn NameFormatter, regex::Captures)>` - The first formatter whose argument template matches the
+    ///   command and the captures of its template, `None` if no formatter handles the command.
+    pub fn find<'a, 'c>(
+        &'a self,
+        command: &'c str,
+    ) -> Option<(&'a (dyn NameFormatter + Send + Sync), regex::Captures<'c>)> {
+        self.iter().find_map(|formatter| {
+            formatter
+                .argument_template()
+                .captures(command)
+                .map(|captures| (formatter, captures))
+        })
+    }
+
+    /// Suggests the registered command closest to an unknown command (without modifier, e.g. `dte?%Y`), e.g. `date`
+    /// for `dte`. `None` if no command is similar.
+    pub fn suggest(&self, command: &str) -> Option<&str> {
+        let word = command.split('?').next().unwrap_or(command);
+        self.names()
+            .into_iter()
+            .map(|name| (edit_distance(word, name), name))
+            .filter(|(distance, name)| *distance <= (name.chars().count() / 3).max(1))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, name)| name)
+    }
+
+    /// Describes a command no formatter is registered for, with the closest command (see [`suggest`](#method.suggest)),
+    /// e.g. `unknown command {dte}, did you mean {date}?`.
+    pub fn unknown_command(&self, command: &str) -> String {
+        match self.suggest(command) {
+            Some(name) => format!(
+                "unknown command {{{}}}, did you mean {{{}}}?",
+                command, name
+            ),
+            None => format!("unknown command {{{}}}", command),
+        }
+    }
+
+    /// Lists the registered commands, e.g. `available commands: {name}, {dup}, {date}`.
+    pub fn available_commands(&self) -> String {
+        let names: Vec<String> = self
+            .names()
+            .iter()
+            .map(|name| format!("{{{}}}", name))
+            .collect();
+        format!("available commands: {}", names.join(", "))
+    }
+}
+
+/// The Levenshtein distance of two strings: the number of inserted, removed or replaced characters that turn one
+/// into the other.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a != *b);
+            current[j + 1] = replace.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
 }
 
 mod date;
@@ -39,3 +165,11 @@ mod file_type;
 pub use file_type::*;
 mod extension;
 pub use extension::*;
+mod duration;
+pub use duration::*;
+mod resolution;
+pub use resolution::*;
+mod relpath;
+pub use relpath::*;
+mod source;
+pub use source::*;
@@ -1,5 +1,6 @@
 use crate::analysis::name_formatters::{NameFormatter, NameFormatterInvocationInfo};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
 use lazy_static::lazy_static;
 use regex::Regex;
 
@@ -16,6 +17,12 @@ impl NameFormatter for FormatDate {
     fn argument_template(&self) -> &Regex {
         &DATE_FORMAT
     }
+    fn name(&self) -> &str {
+        "date"
+    }
+    fn syntax_help(&self) -> String {
+        "{date} or {date?FORMAT} with a chrono format, e.g. {date?%Y-%m}, alias {d}".to_string()
+    }
     fn replacement_text(
         &self,
         capture: regex::Captures<'_>,
@@ -24,8 +31,24 @@ impl NameFormatter for FormatDate {
         let format_string = capture
             .get(3)
             .map_or(invocation_info.date_default_format, |m| m.as_str());
-        Ok(invocation_info.date.map_or("NODATE".to_string(), |x| {
-            x.format(format_string).to_string()
-        }))
+        check_date_format(format_string)?;
+        Ok(invocation_info
+            .date
+            .map_or(invocation_info.nodate_placeholder.to_string(), |x| {
+                invocation_info.locale.format(&x, format_string)
+            }))
+    }
+}
+
+/// Checks that a date format is a valid chrono format string, formatting a date with an invalid format
+/// (e.g. `%Q`) fails.
+///
+/// # Errors
+///
+/// This function will return an error if the format contains an invalid specifier.
+pub fn check_date_format(format: &str) -> Result<()> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(anyhow!("Invalid date format: {:?}", format));
     }
+    Ok(())
 }
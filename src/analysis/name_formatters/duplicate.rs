@@ -16,6 +16,12 @@ impl NameFormatter for FormatDuplicate {
     fn argument_template(&self) -> &Regex {
         &DUPLICATE_FORMAT
     }
+    fn name(&self) -> &str {
+        "dup"
+    }
+    fn syntax_help(&self) -> String {
+        "{dup}, alias {duplicate}".to_string()
+    }
     fn replacement_text(
         &self,
         _capture: regex::Captures<'_>,
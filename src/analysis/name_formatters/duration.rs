@@ -0,0 +1,52 @@
+use crate::analysis::name_formatters::{NameFormatter, NameFormatterInvocationInfo};
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref DURATION_FORMAT: regex::Regex =
+        regex::Regex::new(r"^(duration|dur)(\?(.+))?$").expect("Failed to compile regex");
+}
+
+/// Formats a duration format command {duration} to the length of a video file.
+/// `{duration}` yields the seconds (zero padded to four digits), `{duration?hms}` yields `00h13m54s`.
+/// Substitutes an empty string for files without a duration (e.g. images).
+#[derive(Debug, Default)]
+pub struct FormatDuration {}
+
+impl NameFormatter for FormatDuration {
+    fn argument_template(&self) -> &Regex {
+        &DURATION_FORMAT
+    }
+    fn name(&self) -> &str {
+        "duration"
+    }
+    fn syntax_help(&self) -> String {
+        "{duration} (seconds) or {duration?hms}, alias {dur}".to_string()
+    }
+    fn replacement_text(
+        &self,
+        capture: regex::Captures<'_>,
+        invocation_info: &NameFormatterInvocationInfo,
+    ) -> Result<String> {
+        let option = capture.get(3).map_or("s", |m| m.as_str());
+
+        let seconds = match invocation_info.duration {
+            None => return Ok("".to_string()),
+            Some(duration) => duration.as_secs(),
+        };
+
+        let duration = match option {
+            "s" | "sec" | "seconds" => format!("{:04}", seconds),
+            "hms" => format!(
+                "{:02}h{:02}m{:02}s",
+                seconds / 3600,
+                (seconds / 60) % 60,
+                seconds % 60
+            ),
+            _ => return Err(anyhow!("Unknown duration format")),
+        };
+
+        Ok(duration)
+    }
+}
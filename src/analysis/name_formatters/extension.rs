@@ -16,6 +16,12 @@ impl NameFormatter for FormatExtension {
     fn argument_template(&self) -> &Regex {
         &DATE_FORMAT
     }
+    fn name(&self) -> &str {
+        "ext"
+    }
+    fn syntax_help(&self) -> String {
+        "{ext}, {ext?lower} or {ext?upper}, alias {extension}".to_string()
+    }
     fn replacement_text(
         &self,
         capture: regex::Captures<'_>,
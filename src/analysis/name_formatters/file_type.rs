@@ -17,6 +17,13 @@ impl NameFormatter for FormatFileType {
     fn argument_template(&self) -> &Regex {
         &FILE_TYPE_FORMAT
     }
+    fn name(&self) -> &str {
+        "type"
+    }
+    fn syntax_help(&self) -> String {
+        "{type} or {type?IMAGE,VIDEO} with the names of the file types, aliases {ftype} and {t}"
+            .to_string()
+    }
     fn replacement_text(
         &self,
         capture: regex::Captures<'_>,
@@ -16,6 +16,12 @@ impl NameFormatter for FormatName {
     fn argument_template(&self) -> &Regex {
         &NAME_FORMAT
     }
+    fn name(&self) -> &str {
+        "name"
+    }
+    fn syntax_help(&self) -> String {
+        "{name}, alias {n}".to_string()
+    }
     fn replacement_text(
         &self,
         _capture: regex::Captures<'_>,
@@ -0,0 +1,34 @@
+use crate::analysis::name_formatters::{NameFormatter, NameFormatterInvocationInfo};
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref RELPATH_FORMAT: regex::Regex =
+        regex::Regex::new(r"^(relpath)$").expect("Failed to compile regex");
+}
+
+/// Formats a relative path format command {relpath} to the directory of the file relative to its source directory,
+/// e.g. `2023/holiday` for the file `<source>/2023/holiday/IMG_0001.jpg`. The path is empty for files directly in
+/// the source directory.
+#[derive(Debug, Default)]
+pub struct FormatRelativePath {}
+
+impl NameFormatter for FormatRelativePath {
+    fn argument_template(&self) -> &Regex {
+        &RELPATH_FORMAT
+    }
+    fn name(&self) -> &str {
+        "relpath"
+    }
+    fn replacement_text(
+        &self,
+        _capture: regex::Captures<'_>,
+        invocation_info: &NameFormatterInvocationInfo,
+    ) -> Result<String> {
+        Ok(invocation_info.relative_dir.clone())
+    }
+    fn spans_directories(&self) -> bool {
+        true
+    }
+}
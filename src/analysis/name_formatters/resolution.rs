@@ -0,0 +1,35 @@
+use crate::analysis::name_formatters::{NameFormatter, NameFormatterInvocationInfo};
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref VRES_FORMAT: regex::Regex =
+        regex::Regex::new(r"^(vres|vertical_resolution)$").expect("Failed to compile regex");
+}
+
+/// Formats a vertical resolution format command {vres} to a string like `1080p`.
+/// Substitutes an empty string for files without a known resolution (e.g. images).
+#[derive(Debug, Default)]
+pub struct FormatVerticalResolution {}
+
+impl NameFormatter for FormatVerticalResolution {
+    fn argument_template(&self) -> &Regex {
+        &VRES_FORMAT
+    }
+    fn name(&self) -> &str {
+        "vres"
+    }
+    fn syntax_help(&self) -> String {
+        "{vres}, alias {vertical_resolution}".to_string()
+    }
+    fn replacement_text(
+        &self,
+        _capture: regex::Captures<'_>,
+        invocation_info: &NameFormatterInvocationInfo,
+    ) -> Result<String> {
+        Ok(invocation_info
+            .vertical_resolution
+            .map_or("".to_string(), |height| format!("{}p", height)))
+    }
+}
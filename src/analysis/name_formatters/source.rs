@@ -0,0 +1,30 @@
+use crate::analysis::name_formatters::{NameFormatter, NameFormatterInvocationInfo};
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref SOURCE_FORMAT: regex::Regex =
+        regex::Regex::new(r"^(source)$").expect("Failed to compile regex");
+}
+
+/// Formats a source format command {source} to the label of the source directory the file was found in, e.g. `anna`
+/// for `--source-dir /import/anna=anna`. The label is empty for source directories without label.
+#[derive(Debug, Default)]
+pub struct FormatSource {}
+
+impl NameFormatter for FormatSource {
+    fn argument_template(&self) -> &Regex {
+        &SOURCE_FORMAT
+    }
+    fn name(&self) -> &str {
+        "source"
+    }
+    fn replacement_text(
+        &self,
+        _capture: regex::Captures<'_>,
+        invocation_info: &NameFormatterInvocationInfo,
+    ) -> Result<String> {
+        Ok(invocation_info.source_label.to_string())
+    }
+}
@@ -0,0 +1,60 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// The number of bytes read to detect the file type.
+const SIGNATURE_LENGTH: usize = 16;
+
+/// This function detects the type of a file from its first bytes (its signature or "magic number").
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+///
+/// # Returns
+///
+/// * `Option<&'static str>` - The canonical (lower case) extension of the detected type, e.g. `jpg` or `mp4`. `None`
+///   if the file can not be read or has no known image/video signature.
+pub fn sniff_extension(path: &Path) -> Option<&'static str> {
+    let mut header = Vec::with_capacity(SIGNATURE_LENGTH);
+    File::open(path)
+        .ok()?
+        .take(SIGNATURE_LENGTH as u64)
+        .read_to_end(&mut header)
+        .ok()?;
+    extension_of_signature(&header)
+}
+
+fn extension_of_signature(header: &[u8]) -> Option<&'static str> {
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("jpg");
+    }
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("png");
+    }
+    if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        return Some("tiff");
+    }
+    if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        return Some("gif");
+    }
+    if header.starts_with(b"RIFF") && header.len() >= 12 {
+        return match &header[8..12] {
+            b"WEBP" => Some("webp"),
+            b"AVI " => Some("avi"),
+            _ => None,
+        };
+    }
+    // ISO base media files (HEIF, AVIF, MP4, MOV) start with a "ftyp" box naming the major brand
+    if header.len() >= 12 && &header[4..8] == b"ftyp" {
+        return match &header[8..12] {
+            b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"mif1" | b"msf1" => {
+                Some("heic")
+            }
+            b"avif" | b"avis" => Some("avif"),
+            b"qt  " => Some("mov"),
+            _ => Some("mp4"),
+        };
+    }
+    None
+}
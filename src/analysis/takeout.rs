@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// The name part of newer Takeout metadata files, `IMG_1234.jpg.supplemental-metadata.json`. Takeout truncates long
+/// file names, so it may be cut off, e.g. `IMG_20200101_123456.jpg.supplemental-met.json`.
+const SUPPLEMENTAL_METADATA: &str = "supplemental-metadata";
+
+/// Splits the duplicate suffix Takeout appends to files with the same name, `IMG_1234(1)`, off a file stem.
+///
+/// # Returns
+///
+/// * `Option<(&str, &str)>` - The stem without the suffix and the suffix, e.g. `("IMG_1234", "(1)")`.
+fn split_duplicate_suffix(stem: &str) -> Option<(&str, &str)> {
+    let start = stem.strip_suffix(')')?.rfind('(')?;
+    let counter = &stem[start + 1..stem.len() - 1];
+    if start == 0 || counter.is_empty() || !counter.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(stem.split_at(start))
+}
+
+/// The possible names of the Takeout metadata file of a photo/video, in the order they are looked up:
+/// `IMG_1234.jpg.json`, `IMG_1234.jpg.supplemental-metadata.json` and its truncated forms. Takeout moves the
+/// duplicate suffix of a file behind the extension, the metadata of `IMG_1234(1).jpg` is `IMG_1234.jpg(1).json`.
+///
+/// # Arguments
+///
+/// * `path` - The photo/video.
+pub fn metadata_candidates(path: &Path) -> Vec<PathBuf> {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Vec::new();
+    };
+    // the base name and the duplicate suffix, which follows the metadata name part
+    let mut bases = vec![(name.to_string(), "")];
+    if let (Some(stem), Some(extension)) = (
+        path.file_stem().and_then(|stem| stem.to_str()),
+        path.extension().and_then(|extension| extension.to_str()),
+    ) {
+        if let Some((stem, duplicate)) = split_duplicate_suffix(stem) {
+            bases.insert(0, (format!("{}.{}", stem, extension), duplicate));
+        }
+    }
+
+    let parts = std::iter::once(String::new()).chain(
+        (1..=SUPPLEMENTAL_METADATA.len())
+            .rev()
+            .map(|length| format!(".{}", &SUPPLEMENTAL_METADATA[..length])),
+    );
+    let mut candidates = Vec::new();
+    for part in parts {
+        for (base, duplicate) in &bases {
+            candidates.push(path.with_file_name(format!("{}{}{}.json", base, part, duplicate)));
+        }
+    }
+    candidates
+}
+
+/// Finds the Takeout metadata file of a photo/video, see [`metadata_candidates`].
+pub fn find_metadata(path: &Path) -> Option<PathBuf> {
+    metadata_candidates(path)
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+}
+
+/// The photo/video a Takeout metadata file belongs to (the reverse of [`metadata_candidates`]), whether it exists
+/// or not.
+///
+/// # Returns
+///
+/// * `Option<PathBuf>` - The photo/video, `None` if the file is not named like a Takeout metadata file.
+pub fn main_file(metadata: &Path) -> Option<PathBuf> {
+    let name = metadata.file_name()?.to_str()?;
+    let rest = name
+        .strip_suffix(".json")
+        .or_else(|| name.strip_suffix(".JSON"))?;
+    let (rest, duplicate) = match split_duplicate_suffix(rest) {
+        Some((rest, duplicate)) => (rest, Some(duplicate)),
+        None => (rest, None),
+    };
+    let main = match rest.rsplit_once('.') {
+        Some((main, part))
+            if !part.is_empty()
+                && SUPPLEMENTAL_METADATA.starts_with(part)
+                && main.contains('.') =>
+        {
+            main
+        }
+        _ => rest,
+    };
+    let main = match (duplicate, main.rsplit_once('.')) {
+        (None, _) => main.to_string(),
+        (Some(duplicate), Some((stem, extension))) => {
+            format!("{}{}.{}", stem, duplicate, extension)
+        }
+        (Some(duplicate), None) => format!("{}{}", main, duplicate),
+    };
+    if main.is_empty() {
+        return None;
+    }
+    Some(metadata.with_file_name(main))
+}
+
+/// Reads the capture date (`photoTakenTime.timestamp`, in seconds since the epoch) of a Takeout metadata file.
+///
+/// # Returns
+///
+/// * `NaiveDateTime` - The capture date in UTC.
+///
+/// # Errors
+///
+/// This function will return an error if the file can not be read, is not valid JSON or has no valid capture date.
+pub fn read_taken_time(metadata: &Path) -> Result<NaiveDateTime> {
+    let file = File::open(metadata)?;
+    let value: serde_json::Value = serde_json::from_reader(BufReader::new(file))?;
+    let timestamp = &value["photoTakenTime"]["timestamp"];
+    // Takeout writes the timestamp as a string
+    let seconds = timestamp
+        .as_str()
+        .and_then(|timestamp| timestamp.trim().parse::<i64>().ok())
+        .or_else(|| timestamp.as_i64())
+        .ok_or_else(|| anyhow!("No photoTakenTime.timestamp in {:?}", metadata))?;
+    DateTime::from_timestamp(seconds, 0)
+        .map(|date| date.naive_utc())
+        .ok_or_else(|| {
+            anyhow!(
+                "Invalid photoTakenTime.timestamp {} in {:?}",
+                seconds,
+                metadata
+            )
+        })
+}
@@ -1,11 +1,21 @@
+use crate::analysis::{gpmf, MediaInfo};
 use anyhow::anyhow;
-use chrono::NaiveDateTime;
+use chrono::{Datelike, NaiveDateTime, Timelike};
 use ffmpeg_next as ffmpeg;
+use log::{debug, info};
 use std::path::Path;
 use std::sync::Mutex;
+use std::time::Duration;
 
 static FFMPEG_INITIALIZED: Mutex<bool> = Mutex::new(false);
 
+/// Handler name of the GPMF telemetry track in GoPro videos
+const GOPRO_HANDLER_NAME: &str = "GoPro MET";
+/// Maximum number of packets to inspect while searching the GPMF track for a GPS timestamp
+const GOPRO_MAX_PACKETS: usize = 256;
+/// Metadata key of the content identifier linking the video of a Live Photo to its photo
+const CONTENT_IDENTIFIER_KEY: &str = "com.apple.quicktime.content.identifier";
+
 fn init_ffmpeg() -> anyhow::Result<()> {
     match FFMPEG_INITIALIZED.lock() {
         Ok(mut guard) => {
@@ -34,14 +44,134 @@ fn init_ffmpeg() -> anyhow::Result<()> {
 /// This function will return an error if:
 /// * The video file could not be read.
 pub fn get_video_time<P: AsRef<Path> + ?Sized>(path: &P) -> anyhow::Result<Option<NaiveDateTime>> {
+    Ok(get_video_metadata(path)?.0)
+}
+
+/// This function retrieves the date and time plus additional media information (duration,
+/// vertical resolution) from the video metadata. The container is only opened once.
+///
+/// # Arguments
+/// * `path` - A reference to a `Path` object.
+///
+/// # Returns
+/// * A tuple of the creation date (if any) and the `MediaInfo` of the video. Fields of the
+///   `MediaInfo` are `None` if the information is not present in the container.
+///
+/// # Errors
+/// This function will return an error if:
+/// * The video file could not be read.
+pub fn get_video_metadata<P: AsRef<Path> + ?Sized>(
+    path: &P,
+) -> anyhow::Result<(Option<NaiveDateTime>, MediaInfo)> {
     init_ffmpeg()?;
 
-    let instance = ffmpeg::format::input(&path)?;
+    let mut instance = ffmpeg::format::input(&path)?;
 
-    let result = instance
-        .metadata()
-        .get("creation_time")
-        .map(|v| NaiveDateTime::parse_from_str(v, "%Y-%m-%dT%H:%M:%S%Z"));
+    // an unparsable container date is treated like a missing one, the GoPro GPS time may still be found
+    let mut creation_time = instance.metadata().get("creation_time").and_then(|value| {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%Z")
+            .inspect_err(|err| {
+                debug!(
+                    "Ignoring unparsable creation time {:?} of {:?}: {}",
+                    value,
+                    path.as_ref(),
+                    err
+                )
+            })
+            .ok()
+    });
+
+    if !creation_time.as_ref().is_some_and(is_plausible_time) {
+        if let Some(gps_time) = get_gopro_gps_time(&mut instance) {
+            info!(
+                "Using GoPro GPS time {:?} instead of container date {:?} for {:?}",
+                gps_time,
+                creation_time,
+                path.as_ref()
+            );
+            creation_time = Some(gps_time);
+        }
+    }
+
+    // the container duration is given in AV_TIME_BASE units (microseconds)
+    let duration = match instance.duration() {
+        micros if micros > 0 => Some(Duration::from_micros(micros as u64)),
+        _ => None,
+    };
+
+    let vertical_resolution = instance
+        .streams()
+        .best(ffmpeg::media::Type::Video)
+        .and_then(|stream| {
+            ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+                .and_then(|context| context.decoder().video())
+                .map(|decoder| decoder.height())
+                .ok()
+        })
+        .filter(|height| *height > 0);
+
+    Ok((
+        creation_time,
+        MediaInfo {
+            duration,
+            vertical_resolution,
+        },
+    ))
+}
 
-    Ok(result.transpose()?)
+/// Checks if a container date is plausible. Cameras without a set clock and encoders write
+/// default dates like the QuickTime/Unix epoch or midnight of January 1st.
+fn is_plausible_time(time: &NaiveDateTime) -> bool {
+    let default_new_year = time.ordinal0() == 0 && time.num_seconds_from_midnight() == 0;
+    time.year() >= 2000 && !default_new_year
+}
+
+/// Reads the first GPS timestamp (`GPSU`) from the GPMF telemetry track of a GoPro video.
+///
+/// # Returns
+/// * `Some(NaiveDateTime)` - If the video contains a GoPro telemetry track with a plausible GPS time.
+/// * `None` - If the video is not a GoPro video or no GPS time was recorded.
+fn get_gopro_gps_time(instance: &mut ffmpeg::format::context::Input) -> Option<NaiveDateTime> {
+    let gpmf_stream = instance.streams().find(|stream| {
+        stream
+            .metadata()
+            .get("handler_name")
+            .is_some_and(|name| name.contains(GOPRO_HANDLER_NAME))
+    })?;
+    let gpmf_index = gpmf_stream.index();
+
+    debug!("Found GoPro telemetry track at stream {}", gpmf_index);
+
+    instance
+        .packets()
+        .filter(|(stream, _)| stream.index() == gpmf_index)
+        .take(GOPRO_MAX_PACKETS)
+        .filter_map(|(_, packet)| packet.data().and_then(gpmf::find_gps_time))
+        .find(is_plausible_time)
+}
+
+/// This function retrieves the content identifier of a Live Photo from the QuickTime metadata of its video. The
+/// photo of the Live Photo carries the same identifier in its Apple maker note.
+///
+/// # Arguments
+/// * `path` - A reference to a `Path` object.
+///
+/// # Returns
+/// * `Some(String)` - The content identifier of the video.
+/// * `None` - If the video has no content identifier.
+///
+/// # Errors
+/// This function will return an error if:
+/// * The video file could not be read.
+pub fn get_video_content_identifier<P: AsRef<Path> + ?Sized>(
+    path: &P,
+) -> anyhow::Result<Option<String>> {
+    init_ffmpeg()?;
+
+    let instance = ffmpeg::format::input(&path)?;
+    let identifier = instance
+        .metadata()
+        .get(CONTENT_IDENTIFIER_KEY)
+        .map(str::to_string);
+    Ok(identifier)
 }
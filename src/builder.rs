@@ -0,0 +1,923 @@
+use crate::action::{ActionMode, ActualAction, HardlinkFallback, ReflinkFallback, SymlinkFallback};
+use crate::analysis::filename2date::{FileNameToDateTransformer, NaiveFileNameParser};
+use crate::analysis::name_formatters::{check_date_format, NameFormatter};
+use crate::extensions::patterns_overlap;
+use crate::filesystem::{FileSystem, RealFileSystem, SharedFileSystem};
+use crate::name::WINDOWS_INVALID_CHARS;
+use crate::strip::StripTag;
+use crate::{
+    AnalysisType, Analyzer, AnalyzerSettings, CollisionStrategy, DatePriority, Error,
+    ExistingSymlink, FileOrder, LivePhotos, NodateFilter, Sanitize, SkipHidden, SniffContent,
+    SourceDuplicates, TakeoutDates, TargetCase,
+};
+use chrono::{NaiveDate, TimeDelta};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// `AnalyzerBuilder` builds an `Analyzer`. All settings default to the defaults of the command line interface, the
+/// standard name transformers and formatters are registered. The settings are validated by [`build`](#method.build).
+///
+/// # Example
+///
+/// ```no_run
+/// use photo_sort::action::{ActionMode, ActualAction};
+/// use photo_sort::AnalyzerSettings;
+///
+/// let analyzer = AnalyzerSettings::builder()
+///     .source_dir("/photos/inbox")
+///     .target_dir("/photos/sorted")
+///     .file_format("{date?%Y}/{type}{_:date}{-:name}{-:dup}.{ext}")
+///     .mkdir(true)
+///     .action_type(ActionMode::Execute(ActualAction::Copy))
+///     .build()?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct AnalyzerBuilder {
+    settings: AnalyzerSettings,
+    nodate_file_format: Option<String>,
+    name_transformers: Vec<Box<dyn FileNameToDateTransformer + Send + Sync>>,
+    name_formatters: Vec<Box<dyn NameFormatter + Send + Sync>>,
+}
+
+impl Default for AnalyzerBuilder {
+    fn default() -> Self {
+        AnalyzerBuilder::new()
+    }
+}
+
+impl AnalyzerBuilder {
+    /// Creates a new builder with the default settings.
+    pub fn new() -> AnalyzerBuilder {
+        AnalyzerBuilder {
+            settings: AnalyzerSettings::default(),
+            nodate_file_format: None,
+            name_transformers: Vec::new(),
+            name_formatters: Vec::new(),
+        }
+    }
+
+    /// Sets how the date of a file is derived.
+    pub fn analysis_type(mut self, analysis_type: AnalysisType) -> Self {
+        self.settings.analysis_type = analysis_type;
+        self
+    }
+
+    /// Sets the maximum difference between the Exif date and the name date of a file for `AnalysisType::Both`.
+    pub fn agreement_tolerance(mut self, agreement_tolerance: TimeDelta) -> Self {
+        self.settings.agreement_tolerance = agreement_tolerance;
+        self
+    }
+
+    /// Sets a time offset added to every derived date, e.g. for a camera clock that was off.
+    pub fn time_offset(mut self, time_offset: TimeDelta) -> Self {
+        self.settings.time_offset = time_offset;
+        self
+    }
+
+    /// Sets the number of seconds the dates in file names are floored to, e.g. 60 for the minute.
+    pub fn round_seconds(mut self, round_seconds: Option<u32>) -> Self {
+        self.settings.round_seconds = round_seconds;
+        self
+    }
+
+    /// Sets whether files whose Exif data can not be parsed get no date with `AnalysisType::OnlyExif`, instead of
+    /// failing.
+    pub fn lenient_exif(mut self, lenient_exif: bool) -> Self {
+        self.settings.lenient_exif = lenient_exif;
+        self
+    }
+
+    /// Adds a source directory.
+    pub fn source_dir<P: Into<PathBuf>>(mut self, source_dir: P) -> Self {
+        self.settings.source_dirs.push(source_dir.into());
+        self
+    }
+
+    /// Sets the source directories, replacing previously added ones.
+    pub fn source_dirs<I: IntoIterator<Item = P>, P: Into<PathBuf>>(
+        mut self,
+        source_dirs: I,
+    ) -> Self {
+        self.settings.source_dirs = source_dirs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the label of a source directory, `{source}` is replaced with it for the files of the directory.
+    pub fn source_label<P: Into<PathBuf>, S: Into<String>>(
+        mut self,
+        source_dir: P,
+        label: S,
+    ) -> Self {
+        self.settings
+            .source_labels
+            .insert(source_dir.into(), label.into());
+        self
+    }
+
+    /// Adds a mirror directory, transferred files are replicated to it (see `mirror_dirs`).
+    pub fn mirror_dir<P: Into<PathBuf>>(mut self, mirror_dir: P) -> Self {
+        self.settings.mirror_dirs.push(mirror_dir.into());
+        self
+    }
+
+    /// Sets the mirror directories, replacing previously added ones.
+    pub fn mirror_dirs<I: IntoIterator<Item = P>, P: Into<PathBuf>>(
+        mut self,
+        mirror_dirs: I,
+    ) -> Self {
+        self.settings.mirror_dirs = mirror_dirs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the target directory.
+    pub fn target_dir<P: Into<PathBuf>>(mut self, target_dir: P) -> Self {
+        self.settings.target_dir = target_dir.into();
+        self
+    }
+
+    /// Sets whether files are renamed in their own directory, the target directory is not used.
+    pub fn in_place(mut self, in_place: bool) -> Self {
+        self.settings.in_place = in_place;
+        self
+    }
+
+    /// Sets whether actions other than move, and overwriting existing files, are allowed in place.
+    pub fn force(mut self, force: bool) -> Self {
+        self.settings.force = force;
+        self
+    }
+
+    /// Sets whether the source directories are searched recursively.
+    pub fn recursive_source(mut self, recursive_source: bool) -> Self {
+        self.settings.recursive_source = recursive_source;
+        self
+    }
+
+    /// Sets the maximum depth of the files in the source directories, 1 means only immediate children.
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.settings.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the target file format.
+    pub fn file_format<S: Into<String>>(mut self, file_format: S) -> Self {
+        self.settings.file_format = file_format.into();
+        self
+    }
+
+    /// Sets the target file format of files without date, defaults to the file format.
+    pub fn nodate_file_format<S: Into<String>>(mut self, nodate_file_format: S) -> Self {
+        self.nodate_file_format = Some(nodate_file_format.into());
+        self
+    }
+
+    /// Sets the target file format of files not matching the extensions, these files are ignored if not set.
+    pub fn unknown_file_format<S: Into<String>>(mut self, unknown_file_format: Option<S>) -> Self {
+        self.settings.unknown_file_format = unknown_file_format.map(Into::into);
+        self
+    }
+
+    /// Sets the extensions of the files processed using the unknown file format, `*` (the default) matches every
+    /// extension.
+    pub fn unknown_extensions<I: IntoIterator<Item = S>, S: Into<String>>(
+        mut self,
+        unknown_extensions: I,
+    ) -> Self {
+        self.settings.unknown_extensions = unknown_extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets whether names are cleaned, removing prefixes like `IMG` and nodate placeholders.
+    pub fn clean_names(mut self, clean_names: bool) -> Self {
+        self.settings.clean_names = clean_names;
+        self
+    }
+
+    /// Sets the prefixes removed from names (in upper or lower case), `IMG`, `VID` and `MOV` by default.
+    pub fn clean_prefixes<I: IntoIterator<Item = S>, S: Into<String>>(
+        mut self,
+        clean_prefixes: I,
+    ) -> Self {
+        self.settings.clean_prefixes = clean_prefixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the default date format.
+    pub fn date_format<S: Into<String>>(mut self, date_format: S) -> Self {
+        self.settings.date_format = date_format.into();
+        self
+    }
+
+    /// Sets the locale of month and weekday names in dates, e.g. `de-DE`. Unknown locales fall back to English.
+    pub fn locale<S: Into<String>>(mut self, locale: S) -> Self {
+        self.settings.locale = locale.into();
+        self
+    }
+
+    /// Sets the placeholder used instead of the date of files without date, `NODATE` by default.
+    pub fn nodate_placeholder<S: Into<String>>(mut self, nodate_placeholder: S) -> Self {
+        self.settings.nodate_placeholder = nodate_placeholder.into();
+        self
+    }
+
+    /// Sets the extensions of the photos to process.
+    pub fn extensions<I: IntoIterator<Item = S>, S: Into<String>>(mut self, extensions: I) -> Self {
+        self.settings.extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the extensions of the videos to process.
+    #[cfg(feature = "video")]
+    pub fn video_extensions<I: IntoIterator<Item = S>, S: Into<String>>(
+        mut self,
+        video_extensions: I,
+    ) -> Self {
+        self.settings.video_extensions = video_extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the target file format of videos, defaults to the file format.
+    #[cfg(feature = "video")]
+    pub fn video_file_format<S: Into<String>>(mut self, video_file_format: Option<S>) -> Self {
+        self.settings.video_file_format = video_file_format.map(Into::into);
+        self
+    }
+
+    /// Sets the target file format of videos without date, defaults to the nodate file format. If neither is
+    /// set, videos without date use the video file format.
+    #[cfg(feature = "video")]
+    pub fn video_nodate_file_format<S: Into<String>>(
+        mut self,
+        video_nodate_file_format: Option<S>,
+    ) -> Self {
+        self.settings.video_nodate_file_format = video_nodate_file_format.map(Into::into);
+        self
+    }
+
+    /// Sets the maximum Hamming distance of the perceptual hashes of near-duplicate images, `None` disables the
+    /// near-duplicate detection.
+    #[cfg(feature = "perceptual-hash")]
+    pub fn near_dup_distance(mut self, near_dup_distance: Option<u32>) -> Self {
+        self.settings.near_dup_distance = near_dup_distance;
+        self
+    }
+
+    /// Sets the target file format of near-duplicates except the largest image of each group.
+    #[cfg(feature = "perceptual-hash")]
+    pub fn near_dup_format<S: Into<String>>(mut self, near_dup_format: Option<S>) -> Self {
+        self.settings.near_dup_format = near_dup_format.map(Into::into);
+        self
+    }
+
+    /// Sets the extensions of sidecar files, which are processed together with their photo/video.
+    pub fn sidecar_extensions<I: IntoIterator<Item = S>, S: Into<String>>(
+        mut self,
+        sidecar_extensions: I,
+    ) -> Self {
+        self.settings.sidecar_extensions = sidecar_extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets whether RAW and JPEG files with the same file stem are processed as a pair, getting the same target name.
+    pub fn pair_raw_jpeg(mut self, pair_raw_jpeg: bool) -> Self {
+        self.settings.pair_raw_jpeg = pair_raw_jpeg;
+        self
+    }
+
+    /// Sets whether the videos of Live Photos are processed together with their photo.
+    pub fn live_photos(mut self, live_photos: LivePhotos) -> Self {
+        self.settings.live_photos = live_photos;
+        self
+    }
+
+    /// Sets the JSON dump of exiftool the dates of the listed files are read from.
+    pub fn metadata_json<P: Into<PathBuf>>(mut self, metadata_json: Option<P>) -> Self {
+        self.settings.metadata_json = metadata_json.map(Into::into);
+        self
+    }
+
+    /// Sets whether the dates of the exiftool JSON are used instead of the date analysis, or only if it derives no
+    /// date.
+    pub fn metadata_json_priority(mut self, metadata_json_priority: DatePriority) -> Self {
+        self.settings.metadata_json_priority = metadata_json_priority;
+        self
+    }
+
+    /// Sets whether (and with which priority) the dates of Google Takeout metadata files are used.
+    pub fn takeout_dates(mut self, takeout_dates: TakeoutDates) -> Self {
+        self.settings.takeout_dates = takeout_dates;
+        self
+    }
+
+    /// Sets for which files the type is detected from their content instead of their extension.
+    pub fn sniff_content(mut self, sniff_content: SniffContent) -> Self {
+        self.settings.sniff_content = sniff_content;
+        self
+    }
+
+    /// Sets the action performed on the files, e.g. `ActionMode::DryRun(ActualAction::Move)`.
+    pub fn action_type(mut self, action_type: ActionMode) -> Self {
+        self.settings.action_type = action_type;
+        self
+    }
+
+    /// Sets whether missing target subfolders are created.
+    pub fn mkdir(mut self, mkdir: bool) -> Self {
+        self.settings.mkdir = mkdir;
+        self
+    }
+
+    /// Sets what to do if a reflink can not be created.
+    pub fn reflink_fallback(mut self, reflink_fallback: ReflinkFallback) -> Self {
+        self.settings.reflink_fallback = reflink_fallback;
+        self
+    }
+
+    /// Sets what to do if a hardlink can not be created.
+    pub fn hardlink_fallback(mut self, hardlink_fallback: HardlinkFallback) -> Self {
+        self.settings.hardlink_fallback = hardlink_fallback;
+        self
+    }
+
+    /// Sets what to do if a symlink can not be created because of missing privileges.
+    pub fn symlink_fallback(mut self, symlink_fallback: SymlinkFallback) -> Self {
+        self.settings.symlink_fallback = symlink_fallback;
+        self
+    }
+
+    /// Sets what to do if the target file already exists.
+    pub fn collision(mut self, collision: CollisionStrategy) -> Self {
+        self.settings.collision = collision;
+        self
+    }
+
+    /// Sets whether target names that only differ in case collide, by default the target file system is probed.
+    pub fn target_case(mut self, target_case: TargetCase) -> Self {
+        self.settings.target_case = target_case;
+        self
+    }
+
+    /// Sets which characters of target names are replaced, by default the target file system is detected.
+    pub fn sanitize(mut self, sanitize: Sanitize) -> Self {
+        self.settings.sanitize = sanitize;
+        self
+    }
+
+    /// Sets the character invalid characters of target names are replaced with.
+    pub fn sanitize_char(mut self, sanitize_char: char) -> Self {
+        self.settings.sanitize_char = sanitize_char;
+        self
+    }
+
+    /// Sets the path length planned targets are warned about from on, e.g. 240 for tools limited by `MAX_PATH`.
+    pub fn max_path_warn(mut self, max_path_warn: Option<usize>) -> Self {
+        self.settings.max_path_warn = max_path_warn;
+        self
+    }
+
+    /// Sets what to do in symlink modes if the target is already a symlink to the source file.
+    pub fn existing_symlink(mut self, existing_symlink: ExistingSymlink) -> Self {
+        self.settings.existing_symlink = existing_symlink;
+        self
+    }
+
+    /// Sets whether source files are deleted (in move mode) if an identical file already exists at the target.
+    pub fn prune_source_duplicates(mut self, prune_source_duplicates: bool) -> Self {
+        self.settings.prune_source_duplicates = prune_source_duplicates;
+        self
+    }
+
+    /// Sets what to do with source files identical to a source file transferred earlier in the run.
+    pub fn source_duplicates(mut self, source_duplicates: SourceDuplicates) -> Self {
+        self.settings.source_duplicates = source_duplicates;
+        self
+    }
+
+    /// Sets whether copied files are verified by comparing checksums.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.settings.verify = verify;
+        self
+    }
+
+    /// Sets whether files are moved to the trash instead of being deleted permanently.
+    pub fn use_trash(mut self, use_trash: bool) -> Self {
+        self.settings.use_trash = use_trash;
+        self
+    }
+
+    /// Sets whether source directories that are empty after a move run are removed.
+    pub fn prune_empty_dirs(mut self, prune_empty_dirs: bool) -> Self {
+        self.settings.prune_empty_dirs = prune_empty_dirs;
+        self
+    }
+
+    /// Sets whether OS litter files are deleted when pruning empty directories.
+    pub fn prune_litter(mut self, prune_litter: bool) -> Self {
+        self.settings.prune_litter = prune_litter;
+        self
+    }
+
+    /// Sets whether the source directories themselves are removed when pruning empty directories.
+    pub fn prune_roots(mut self, prune_roots: bool) -> Self {
+        self.settings.prune_roots = prune_roots;
+        self
+    }
+
+    /// Sets whether files whose content already exists anywhere in the target directory are skipped.
+    pub fn dedup_target(mut self, dedup_target: bool) -> Self {
+        self.settings.dedup_target = dedup_target;
+        self
+    }
+
+    /// Sets the cache file of the target directory index.
+    pub fn index_cache<P: Into<PathBuf>>(mut self, index_cache: Option<P>) -> Self {
+        self.settings.index_cache = index_cache.map(Into::into);
+        self
+    }
+
+    /// Sets the limit of the throughput of file copies in bytes per second.
+    pub fn bandwidth_limit(mut self, bandwidth_limit: Option<u64>) -> Self {
+        self.settings.bandwidth_limit = bandwidth_limit;
+        self
+    }
+
+    /// Sets whether the modification time of target files is set to their derived date.
+    pub fn set_mtime(mut self, set_mtime: bool) -> Self {
+        self.settings.set_mtime = set_mtime;
+        self
+    }
+
+    /// Sets the groups of Exif tags that are removed from copied JPEG files.
+    pub fn strip_tags(mut self, strip_tags: Vec<StripTag>) -> Self {
+        self.settings.strip_tags = strip_tags;
+        self
+    }
+
+    /// Sets the journal file all performed actions are appended to.
+    pub fn journal<P: Into<PathBuf>>(mut self, journal: Option<P>) -> Self {
+        self.settings.journal = journal.map(Into::into);
+        self
+    }
+
+    /// Sets the state file recording the processed source files.
+    pub fn state_file<P: Into<PathBuf>>(mut self, state_file: Option<P>) -> Self {
+        self.settings.state_file = state_file.map(Into::into);
+        self
+    }
+
+    /// Sets whether the files recorded in the state file are discarded.
+    pub fn reset_state(mut self, reset_state: bool) -> Self {
+        self.settings.reset_state = reset_state;
+        self
+    }
+
+    /// Sets the date files dated before are skipped.
+    pub fn since(mut self, since: Option<NaiveDate>) -> Self {
+        self.settings.since = since;
+        self
+    }
+
+    /// Sets the date (inclusive) files dated after are skipped.
+    pub fn until(mut self, until: Option<NaiveDate>) -> Self {
+        self.settings.until = until;
+        self
+    }
+
+    /// Sets whether files without a derived date are processed.
+    pub fn nodate_filter(mut self, nodate_filter: NodateFilter) -> Self {
+        self.settings.nodate_filter = nodate_filter;
+        self
+    }
+
+    /// Sets which hidden files and directories are skipped while scanning the source directories.
+    pub fn skip_hidden(mut self, skip_hidden: SkipHidden) -> Self {
+        self.settings.skip_hidden = skip_hidden;
+        self
+    }
+
+    /// Sets whether symlinks in the source directories are followed.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.settings.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Sets the minimum size in bytes of the files to process.
+    pub fn min_size(mut self, min_size: Option<u64>) -> Self {
+        self.settings.min_size = min_size;
+        self
+    }
+
+    /// Sets the maximum size in bytes of the files to process.
+    pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+        self.settings.max_size = max_size;
+        self
+    }
+
+    /// Sets the glob patterns of files and directories that are excluded from processing.
+    pub fn exclude<I: IntoIterator<Item = S>, S: Into<String>>(mut self, exclude: I) -> Self {
+        self.settings.exclude = exclude.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the number of threads the actions of a run are executed with.
+    pub fn threads(mut self, threads: Option<usize>) -> Self {
+        self.settings.threads = threads;
+        self
+    }
+
+    /// Sets whether to stop at the first file that can not be planned or processed.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.settings.fail_fast = fail_fast;
+        self
+    }
+
+    /// Sets the order the files of the source directories are processed in.
+    pub fn order(mut self, order: FileOrder) -> Self {
+        self.settings.order = order;
+        self
+    }
+
+    /// Sets the file system the source directories are scanned on and the actions are performed on, e.g. a
+    /// `MemoryFileSystem` to simulate a run.
+    pub fn file_system(mut self, file_system: SharedFileSystem) -> Self {
+        self.settings.file_system = Some(file_system);
+        self
+    }
+
+    /// Adds a name transformer, which is tried before the standard name transformers.
+    pub fn add_transformer<T: 'static + FileNameToDateTransformer + Send + Sync>(
+        mut self,
+        transformer: T,
+    ) -> Self {
+        self.name_transformers.push(Box::new(transformer));
+        self
+    }
+
+    /// Adds a name formatter, which is tried before the standard name formatters.
+    pub fn add_formatter<T: 'static + NameFormatter + Send + Sync>(mut self, formatter: T) -> Self {
+        self.name_formatters.push(Box::new(formatter));
+        self
+    }
+
+    /// Validates the settings and builds the `Analyzer`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// * No source directory is set (`Error::InvalidSettings`).
+    /// * The maximum depth is 0 (`Error::InvalidSettings`).
+    /// * The target directory (unless `mkdir` is set) or a source directory does not exist (`Error::DirectoryNotFound`).
+    /// * The target directory is inside a scanned source directory (`Error::InvalidSettings`).
+    /// * An extension is listed in more than one extension list (photos, videos, sidecars) (`Error::InvalidSettings`).
+    /// * An exclude pattern is not a valid glob (`Error::InvalidSettings`).
+    /// * The date format is not a valid chrono format (`Error::InvalidSettings`).
+    /// * Live Photos are verified without the `video` feature (`Error::InvalidSettings`).
+    /// * A near-duplicate format is set without a near-duplicate distance (`Error::InvalidSettings`).
+    /// * The sanitize character is not valid in file names itself (`Error::InvalidSettings`).
+    /// * A format string is malformed, contains a command no formatter is registered for or a command with an invalid
+    ///   argument (`Error::FormatString`).
+    pub fn build(self) -> Result<Analyzer, Error> {
+        let mut settings = self.settings;
+        // like the nodate file format, the video nodate file format defaults to the video file format
+        #[cfg(feature = "video")]
+        if settings.video_nodate_file_format.is_none() && self.nodate_file_format.is_none() {
+            settings.video_nodate_file_format = settings.video_file_format.clone();
+        }
+        if let Some(nodate_file_format) = self.nodate_file_format {
+            settings.nodate_file_format = nodate_file_format;
+        } else {
+            settings.nodate_file_format = settings.file_format.clone();
+        }
+
+        if settings.source_dirs.is_empty() {
+            return Err(Error::InvalidSettings(
+                "No source directory specified".to_string(),
+            ));
+        }
+        if settings.max_depth == Some(0) {
+            return Err(Error::InvalidSettings(
+                "The maximum depth must be at least 1".to_string(),
+            ));
+        }
+        if !settings.strip_tags.is_empty()
+            && !matches!(
+                settings.action_type,
+                ActionMode::Execute(ActualAction::Copy) | ActionMode::DryRun(ActualAction::Copy)
+            )
+        {
+            return Err(Error::InvalidSettings(
+                "Tags can only be stripped with the copy action, the source files are never modified".to_string(),
+            ));
+        }
+        #[cfg(feature = "perceptual-hash")]
+        if settings.near_dup_format.is_some() && settings.near_dup_distance.is_none() {
+            return Err(Error::InvalidSettings(
+                "The near-duplicate format requires a near-duplicate distance".to_string(),
+            ));
+        }
+        if settings.round_seconds == Some(0) {
+            return Err(Error::InvalidSettings(
+                "The seconds to round dates to must be at least 1".to_string(),
+            ));
+        }
+        let file_system = settings.file_system();
+        let is_dir = |path: &Path| file_system.metadata(path).is_ok_and(|m| m.is_dir());
+        for source in &settings.source_dirs {
+            if !is_dir(source) {
+                return Err(Error::DirectoryNotFound(source.clone()));
+            }
+        }
+        for (source, label) in &settings.source_labels {
+            if !settings.source_dirs.contains(source) {
+                return Err(Error::InvalidSettings(format!(
+                    "The labeled directory {:?} is not a source directory",
+                    source
+                )));
+            }
+            if label.contains(['/', '\\']) {
+                return Err(Error::InvalidSettings(format!(
+                    "The label {:?} of source directory {:?} contains a path separator",
+                    label, source
+                )));
+            }
+        }
+        if settings.in_place {
+            check_in_place(&settings)?;
+        } else if !is_dir(&settings.target_dir)
+            && (file_system.exists(&settings.target_dir) || !settings.mkdir)
+        {
+            // a missing target directory is created by `Analyzer::new` if `mkdir` is set
+            return Err(Error::DirectoryNotFound(settings.target_dir.clone()));
+        }
+        check_mirror_dirs(&settings)?;
+        check_extension_overlap(&settings)?;
+        let sanitize_char = settings.sanitize_char;
+        if sanitize_char == '/'
+            || sanitize_char == '.'
+            || sanitize_char.is_whitespace()
+            || sanitize_char.is_control()
+            || WINDOWS_INVALID_CHARS.contains(&sanitize_char)
+        {
+            return Err(Error::InvalidSettings(format!(
+                "The sanitize character {:?} is not valid in file names",
+                sanitize_char
+            )));
+        }
+        #[cfg(not(feature = "video"))]
+        if settings.live_photos == LivePhotos::Verify {
+            return Err(Error::InvalidSettings(
+                "Verifying Live Photos requires the video feature".to_string(),
+            ));
+        }
+
+        let mut analyzer = Analyzer::new(settings)?;
+        for transformer in self.name_transformers {
+            analyzer.name_transformers.push(transformer);
+        }
+        analyzer.add_transformer(NaiveFileNameParser::default());
+
+        for formatter in self.name_formatters {
+            analyzer.name_formatters.register(formatter);
+        }
+        let analyzer = analyzer.with_default_formatters();
+
+        if let Err(err) = check_date_format(&analyzer.settings.date_format) {
+            return Err(Error::InvalidSettings(err.to_string()));
+        }
+        let formats = [
+            Some(&analyzer.settings.file_format),
+            Some(&analyzer.settings.nodate_file_format),
+            analyzer.settings.unknown_file_format.as_ref(),
+            #[cfg(feature = "video")]
+            analyzer.settings.video_file_format.as_ref(),
+            #[cfg(feature = "video")]
+            analyzer.settings.video_nodate_file_format.as_ref(),
+            #[cfg(feature = "perceptual-hash")]
+            analyzer.settings.near_dup_format.as_ref(),
+        ];
+        let mut errors = Vec::new();
+        for (index, format) in formats.iter().enumerate() {
+            // the nodate format defaults to the file format, report it once
+            let Some(format) = format.filter(|format| !formats[..index].contains(&Some(format)))
+            else {
+                continue;
+            };
+            if let Err(err) = analyzer.validate_format(format) {
+                errors.push(err);
+            }
+        }
+        match errors.len() {
+            0 => {}
+            1 => return Err(errors.remove(0)),
+            _ => {
+                return Err(Error::InvalidSettings(
+                    errors
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ))
+            }
+        }
+
+        Ok(analyzer)
+    }
+}
+
+/// Checks that the settings make sense in place: the files are moved (renamed), existing files are not overwritten
+/// (unless `force` is set) and there is no target directory to index.
+/// Checks the mirror directories: they are not allowed in place, must differ from the target directory and each
+/// other, and must exist unless `mkdir` is set.
+fn check_mirror_dirs(settings: &AnalyzerSettings) -> Result<(), Error> {
+    if settings.mirror_dirs.is_empty() {
+        return Ok(());
+    }
+    if settings.in_place {
+        return Err(Error::InvalidSettings(
+            "Mirror directories can not be used in place".to_string(),
+        ));
+    }
+    for (index, mirror) in settings.mirror_dirs.iter().enumerate() {
+        if *mirror == settings.target_dir || settings.mirror_dirs[..index].contains(mirror) {
+            return Err(Error::InvalidSettings(format!(
+                "The mirror directory {:?} is given twice",
+                mirror
+            )));
+        }
+        let file_system = settings.file_system();
+        let is_dir = file_system.metadata(mirror).is_ok_and(|m| m.is_dir());
+        if !is_dir && (file_system.exists(mirror) || !settings.mkdir) {
+            // a missing mirror directory is created by `Analyzer::new` if `mkdir` is set
+            return Err(Error::DirectoryNotFound(mirror.clone()));
+        }
+    }
+    Ok(())
+}
+
+fn check_in_place(settings: &AnalyzerSettings) -> Result<(), Error> {
+    let action = match settings.action_type {
+        ActionMode::Execute(action) | ActionMode::DryRun(action) => action,
+    };
+    if !settings.force && action != ActualAction::Move {
+        return Err(Error::InvalidSettings(format!(
+            "The {} action makes little sense in place, use move (or force it)",
+            action
+        )));
+    }
+    if !settings.force && settings.collision == CollisionStrategy::Overwrite {
+        return Err(Error::InvalidSettings(
+            "Overwriting existing files in place replaces files that are not processed yet (force it if intended)"
+                .to_string(),
+        ));
+    }
+    if settings.dedup_target {
+        return Err(Error::InvalidSettings(
+            "The target directory can not be indexed in place".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that no extension is matched by more than one extension list, also if the lists contain patterns.
+fn check_extension_overlap(settings: &AnalyzerSettings) -> Result<(), Error> {
+    let mut lists = vec![("extensions", &settings.extensions)];
+    #[cfg(feature = "video")]
+    lists.push(("video extensions", &settings.video_extensions));
+    lists.push(("sidecar extensions", &settings.sidecar_extensions));
+
+    for (index, (name, extensions)) in lists.iter().enumerate() {
+        for (other_name, other_extensions) in &lists[index + 1..] {
+            for extension in extensions.iter() {
+                if let Some(other) = other_extensions
+                    .iter()
+                    .find(|other| patterns_overlap(extension, other))
+                {
+                    return Err(Error::InvalidSettings(
+                        if extension.eq_ignore_ascii_case(other) {
+                            format!(
+                                "Extension {:?} is listed in both {} and {}",
+                                extension, name, other_name
+                            )
+                        } else {
+                            format!(
+                            "Extension patterns {:?} ({}) and {:?} ({}) match the same extensions",
+                            extension, name, other, other_name
+                        )
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl AnalyzerSettings {
+    /// Creates an `AnalyzerBuilder` with the default settings.
+    pub fn builder() -> AnalyzerBuilder {
+        AnalyzerBuilder::new()
+    }
+
+    /// The file system the source directories are scanned on and the actions are performed on (see `file_system`).
+    pub fn file_system(&self) -> &dyn FileSystem {
+        match &self.file_system {
+            Some(file_system) => &**file_system,
+            None => &RealFileSystem,
+        }
+    }
+}
+
+impl Default for AnalyzerSettings {
+    /// The defaults of the command line interface, without source and target directories.
+    fn default() -> Self {
+        AnalyzerSettings {
+            analysis_type: AnalysisType::ExifThenName,
+            agreement_tolerance: TimeDelta::hours(24),
+            time_offset: TimeDelta::zero(),
+            round_seconds: None,
+            lenient_exif: false,
+            source_dirs: Vec::new(),
+            source_labels: HashMap::new(),
+            target_dir: PathBuf::new(),
+            mirror_dirs: Vec::new(),
+            in_place: false,
+            force: false,
+            recursive_source: false,
+            max_depth: None,
+            file_format: crate::DEFAULT_FILE_FORMAT.to_string(),
+            nodate_file_format: crate::DEFAULT_FILE_FORMAT.to_string(),
+            unknown_file_format: None,
+            unknown_extensions: vec!["*".to_string()],
+            date_format: crate::DEFAULT_DATE_FORMAT.to_string(),
+            locale: crate::locale::DEFAULT_LOCALE.to_string(),
+            nodate_placeholder: crate::DEFAULT_NODATE_PLACEHOLDER.to_string(),
+            clean_names: true,
+            clean_prefixes: crate::DEFAULT_CLEAN_PREFIXES
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            extensions: crate::DEFAULT_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+            #[cfg(feature = "video")]
+            video_extensions: crate::DEFAULT_VIDEO_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+            #[cfg(feature = "video")]
+            video_file_format: None,
+            #[cfg(feature = "video")]
+            video_nodate_file_format: None,
+            #[cfg(feature = "perceptual-hash")]
+            near_dup_distance: None,
+            #[cfg(feature = "perceptual-hash")]
+            near_dup_format: None,
+            action_type: ActionMode::Execute(ActualAction::Move),
+            mkdir: false,
+            reflink_fallback: ReflinkFallback::default(),
+            hardlink_fallback: HardlinkFallback::default(),
+            symlink_fallback: SymlinkFallback::default(),
+            collision: CollisionStrategy::default(),
+            target_case: TargetCase::default(),
+            sanitize: Sanitize::default(),
+            sanitize_char: '_',
+            max_path_warn: None,
+            existing_symlink: ExistingSymlink::default(),
+            prune_source_duplicates: false,
+            source_duplicates: SourceDuplicates::default(),
+            verify: false,
+            use_trash: false,
+            sidecar_extensions: Vec::new(),
+            pair_raw_jpeg: false,
+            live_photos: LivePhotos::Name,
+            metadata_json: None,
+            metadata_json_priority: DatePriority::default(),
+            takeout_dates: TakeoutDates::default(),
+            sniff_content: SniffContent::default(),
+            prune_empty_dirs: false,
+            prune_litter: false,
+            prune_roots: false,
+            dedup_target: false,
+            index_cache: None,
+            bandwidth_limit: None,
+            set_mtime: false,
+            strip_tags: Vec::new(),
+            journal: None,
+            state_file: None,
+            reset_state: false,
+            since: None,
+            until: None,
+            nodate_filter: NodateFilter::default(),
+            skip_hidden: SkipHidden::default(),
+            follow_symlinks: false,
+            min_size: None,
+            max_size: None,
+            exclude: Vec::new(),
+            threads: None,
+            order: FileOrder::default(),
+            fail_fast: false,
+            file_system: None,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+
+/// `Error` is the error type of the library functions, so that callers can distinguish the causes of errors.
+///
+/// # Variants
+///
+/// * `DirectoryNotFound` - A source, target or target subfolder directory does not exist.
+/// * `InvalidSettings` - The settings are inconsistent, e.g. an extension is listed as photo and sidecar extension.
+/// * `InvalidFileName` - The file name is missing (e.g. the path ends in `..`).
+/// * `InvalidExtension` - The file does not have one of the configured extensions.
+/// * `ExifParse` - The Exif data (or video metadata) of the file could not be read.
+/// * `FormatString` - A format string is malformed (e.g. an unclosed `{`), contains a command no name formatter is
+///   registered for or a command with an invalid argument.
+/// * `TargetExists` - The target file already exists.
+/// * `Io` - An IO error occurred while accessing the path.
+/// * `Cancelled` - The processing of the file was cancelled (see `CancellationToken`), it was left unchanged.
+/// * `Other` - Any other error.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Directory does not exist: {0:?}")]
+    DirectoryNotFound(PathBuf),
+    #[error("Invalid settings: {0}")]
+    InvalidSettings(String),
+    #[error("Invalid file name: {0:?}")]
+    InvalidFileName(PathBuf),
+    #[error("Invalid file extension: {0:?}")]
+    InvalidExtension(PathBuf),
+    #[error("Error analyzing Exif data of {path:?}: {source}")]
+    ExifParse {
+        path: PathBuf,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("Invalid format string {format:?}: {}", .problems.join("; "))]
+    FormatString {
+        format: String,
+        problems: Vec<String>,
+    },
+    #[error("Target file already exists: {0:?}")]
+    TargetExists(PathBuf),
+    #[error("IO error on {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Processing of {0:?} was cancelled")]
+    Cancelled(PathBuf),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+/// Converts an `anyhow::Error` into an `Error`. If it wraps an `Error` (e.g. returned by a library function and
+/// propagated through `anyhow`), that error is unwrapped, so its variant can still be matched.
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<Error>() {
+            Ok(err) => err,
+            Err(err) => Error::Other(err),
+        }
+    }
+}
+
+impl Error {
+    /// Creates an `Io` error.
+    pub fn io<P: Into<PathBuf>>(path: P, source: std::io::Error) -> Error {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::Error;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::{debug, warn};
+use std::path::Path;
+
+/// `ExcludePatterns` is a compiled list of gitignore-style glob patterns, matched against paths relative to
+/// a source directory. Patterns without a `/` (e.g. `@eaDir` or `*.tmp`) match at any depth, patterns
+/// with a `/` (e.g. `2023/RAW_rejects`) are anchored at the source directory. `*` does not match `/`,
+/// `**` matches any number of directories.
+#[derive(Debug, Clone, Default)]
+pub struct ExcludePatterns {
+    set: GlobSet,
+}
+
+impl ExcludePatterns {
+    /// Compiles a list of patterns.
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The glob patterns.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `Error::InvalidSettings` if a pattern is not a valid glob.
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<ExcludePatterns, Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            let trimmed = pattern.trim_start_matches('/').trim_end_matches('/');
+            let anchored = pattern.trim_end_matches('/').contains('/');
+            let glob = if anchored {
+                trimmed.to_string()
+            } else {
+                format!("**/{}", trimmed)
+            };
+            let glob = GlobBuilder::new(&glob)
+                .literal_separator(true)
+                .build()
+                .map_err(|err| {
+                    Error::InvalidSettings(format!(
+                        "Invalid exclude pattern {:?}: {}",
+                        pattern, err
+                    ))
+                })?;
+            builder.add(glob);
+        }
+        let set = builder
+            .build()
+            .map_err(|err| Error::InvalidSettings(format!("Invalid exclude patterns: {}", err)))?;
+        Ok(ExcludePatterns { set })
+    }
+
+    /// Checks if no patterns are set.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// Checks if a path is excluded.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to check.
+    /// * `root` - The source directory the patterns are relative to.
+    pub fn is_excluded(&self, path: &Path, root: &Path) -> bool {
+        if self.set.is_empty() {
+            return false;
+        }
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let excluded = self.set.is_match(relative);
+        if excluded {
+            debug!("Excluded: {:?}", path);
+        }
+        excluded
+    }
+}
+
+/// The name of the ignore files in source directories (gitignore syntax), their patterns apply to their directory and below.
+pub const IGNORE_FILE_NAME: &str = ".photosortignore";
+
+/// `IgnoreFiles` holds the ignore files (`.photosortignore`, gitignore syntax) of the directories on the current
+/// path while scanning a source directory. The patterns of an ignore file apply to its directory and below,
+/// patterns of deeper ignore files take precedence. Within a file the last matching pattern wins, a pattern
+/// starting with `!` re-includes a path.
+#[derive(Debug, Default)]
+pub(crate) struct IgnoreFiles {
+    stack: Vec<Gitignore>,
+}
+
+impl IgnoreFiles {
+    /// Loads the ignore file of a directory, if it has one.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether an ignore file was loaded, it must be removed by [`leave`](#method.leave) afterward.
+    pub(crate) fn enter(&mut self, directory: &Path) -> bool {
+        let path = directory.join(IGNORE_FILE_NAME);
+        if !path.is_file() {
+            return false;
+        }
+        let mut builder = GitignoreBuilder::new(directory);
+        if let Some(err) = builder.add(&path) {
+            warn!("Error reading ignore file {:?}: {}", path, err);
+        }
+        match builder.build() {
+            Ok(ignore) => {
+                debug!("Loaded ignore file {:?}", path);
+                self.stack.push(ignore);
+                true
+            }
+            Err(err) => {
+                warn!("Error reading ignore file {:?}: {}", path, err);
+                false
+            }
+        }
+    }
+
+    /// Removes the ignore file loaded by the last successful [`enter`](#method.enter).
+    pub(crate) fn leave(&mut self) {
+        self.stack.pop();
+    }
+
+    /// Checks if a path is ignored, the ignore files themselves are always ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to check.
+    /// * `is_dir` - A boolean that indicates whether the path is a directory.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if path.file_name() == Some(IGNORE_FILE_NAME.as_ref()) {
+            return true;
+        }
+        for ignore in self.stack.iter().rev() {
+            match ignore.matched(path, is_dir) {
+                Match::None => continue,
+                Match::Ignore(glob) => {
+                    debug!(
+                        "Ignored: {:?} (pattern {:?} in {:?})",
+                        path,
+                        glob.original(),
+                        glob.from().unwrap_or(ignore.path())
+                    );
+                    return true;
+                }
+                Match::Whitelist(_) => return false,
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,85 @@
+use crate::Error;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// `ExtensionPatterns` is a compiled list of extension patterns, e.g. `jpg` or `jp*g`. Patterns are matched
+/// case-insensitively against the whole extension, `*` matches any number of characters and `?` matches a single
+/// character. Other glob syntax is not supported, so that overlapping patterns can be detected
+/// (see [`patterns_overlap`]).
+#[derive(Debug, Clone, Default)]
+pub struct ExtensionPatterns {
+    set: GlobSet,
+}
+
+impl ExtensionPatterns {
+    /// Compiles a list of extension patterns.
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The extension patterns.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `Error::InvalidSettings` if a pattern contains unsupported glob syntax.
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<ExtensionPatterns, Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            if let Some(unsupported) = pattern
+                .chars()
+                .find(|c| matches!(c, '[' | ']' | '{' | '}' | '\\'))
+            {
+                return Err(Error::InvalidSettings(format!(
+                    "Invalid extension pattern {:?}: {:?} is not supported, use * or ?",
+                    pattern, unsupported
+                )));
+            }
+            let glob = GlobBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|err| {
+                    Error::InvalidSettings(format!(
+                        "Invalid extension pattern {:?}: {}",
+                        pattern, err
+                    ))
+                })?;
+            builder.add(glob);
+        }
+        let set = builder.build().map_err(|err| {
+            Error::InvalidSettings(format!("Invalid extension patterns: {}", err))
+        })?;
+        Ok(ExtensionPatterns { set })
+    }
+
+    /// Checks if an extension (without the leading `.`) matches one of the patterns.
+    pub fn matches(&self, extension: &str) -> bool {
+        self.set.is_match(extension)
+    }
+}
+
+/// Checks if there is an extension matching both patterns, e.g. `m*` and `*4` (both match `mp4`). Patterns are
+/// compared case-insensitively.
+///
+/// # Arguments
+///
+/// * `a` - The first extension pattern.
+/// * `b` - The second extension pattern.
+pub fn patterns_overlap(a: &str, b: &str) -> bool {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+
+    // overlap[i][j]: the suffixes a[i..] and b[j..] match a common extension
+    let mut overlap = vec![vec![false; b.len() + 1]; a.len() + 1];
+    for i in (0..=a.len()).rev() {
+        for j in (0..=b.len()).rev() {
+            overlap[i][j] = match (a.get(i), b.get(j)) {
+                (None, None) => true,
+                // a star matches nothing, or the next character of the other pattern (or its star)
+                (Some('*'), _) => overlap[i + 1][j] || (j < b.len() && overlap[i][j + 1]),
+                (_, Some('*')) => overlap[i][j + 1] || (i < a.len() && overlap[i + 1][j]),
+                (Some(x), Some(y)) => (*x == '?' || *y == '?' || x == y) && overlap[i + 1][j + 1],
+                _ => false,
+            };
+        }
+    }
+    overlap[0][0]
+}
@@ -0,0 +1,87 @@
+use crate::action::ActionMode;
+use crate::outcome::{FileOutcome, OutcomeAction, RunObserver};
+use crate::plan::Plan;
+use anyhow::{anyhow, Result};
+use log::error;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// `CreatedFileList` is a list of the files created by a run (including sidecar files and hardlinks of identical
+/// files), one path per entry, e.g. to feed them to `xargs` or `rsync --files-from`. The paths are relative to the
+/// target directory, targets outside of it (e.g. in place) are written as they are. Entries are separated by a line
+/// break or a NUL character and every entry is flushed when it is written, so the list is complete up to the last
+/// processed file. As a `RunObserver` it records the files of a run as they are processed, also from worker threads.
+/// The files a dry-run would create are listed as well.
+pub struct CreatedFileList {
+    file: Mutex<File>,
+    root: PathBuf,
+    separator: u8,
+}
+
+impl CreatedFileList {
+    /// Creates (or truncates) the list.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the list.
+    /// * `root` - The directory the listed paths are relative to, usually the target directory.
+    /// * `nul` - A boolean that indicates whether the entries are separated by NUL characters instead of line breaks.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be created.
+    pub fn create<P: AsRef<Path>, R: AsRef<Path>>(
+        path: P,
+        root: R,
+        nul: bool,
+    ) -> Result<CreatedFileList> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| anyhow!("Failed to create file list: {:?} - {:?}", path, e))?;
+        Ok(CreatedFileList {
+            file: Mutex::new(file),
+            root: root.as_ref().to_path_buf(),
+            separator: if nul { b'\0' } else { b'\n' },
+        })
+    }
+
+    /// Appends the entry of a created file.
+    pub fn write_entry(&self, target: &Path) {
+        let relative = target.strip_prefix(&self.root).unwrap_or(target);
+        let mut entry = relative.as_os_str().as_encoded_bytes().to_vec();
+        entry.push(self.separator);
+
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+        if let Err(err) = file.write_all(&entry).and_then(|_| file.flush()) {
+            error!("Error writing file list: {}", err);
+        }
+    }
+
+    /// Writes the entries of the files a plan creates, e.g. for a dry-run that does not process the files.
+    pub fn write_plan(&self, plan: &Plan, mode: &ActionMode) {
+        for action in &plan.actions {
+            self.on_file_done(&FileOutcome::from_action(action, mode));
+        }
+    }
+}
+
+impl RunObserver for CreatedFileList {
+    fn on_file_done(&self, outcome: &FileOutcome) {
+        let (OutcomeAction::Transfer(_) | OutcomeAction::Link, Some(target)) =
+            (outcome.action, &outcome.target)
+        else {
+            return;
+        };
+        self.write_entry(target);
+        for (_, target) in &outcome.sidecars {
+            // errors of sidecar files are only logged, the sidecar may not have been transferred
+            if outcome.dry_run || target.symlink_metadata().is_ok() {
+                self.write_entry(target);
+            }
+        }
+    }
+}
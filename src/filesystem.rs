@@ -0,0 +1,670 @@
+use filetime::FileTime;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::io::{Cursor, Error, ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// `FileKind` is an enumeration that defines the kinds of directory entries.
+///
+/// # Variants
+///
+/// * `File` - A regular file.
+/// * `Dir` - A directory.
+/// * `Symlink` - A symbolic link (only returned if symlinks are not followed).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// `FileMetadata` holds the metadata of a directory entry that PhotoSort uses.
+///
+/// # Fields
+///
+/// * `kind` - The kind of the entry.
+/// * `len` - The size of files in bytes.
+/// * `modified` - The modification time, if available.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub kind: FileKind,
+    pub len: u64,
+    pub modified: Option<FileTime>,
+}
+
+impl FileMetadata {
+    /// Checks if the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileKind::Dir
+    }
+
+    /// Checks if the entry is a symbolic link.
+    pub fn is_symlink(&self) -> bool {
+        self.kind == FileKind::Symlink
+    }
+}
+
+impl From<fs::Metadata> for FileMetadata {
+    fn from(metadata: fs::Metadata) -> FileMetadata {
+        let kind = if metadata.is_symlink() {
+            FileKind::Symlink
+        } else if metadata.is_dir() {
+            FileKind::Dir
+        } else {
+            FileKind::File
+        };
+        FileMetadata {
+            kind,
+            len: metadata.len(),
+            modified: Some(FileTime::from_last_modification_time(&metadata)),
+        }
+    }
+}
+
+/// `DirEntry` is an entry of a directory listing.
+///
+/// # Fields
+///
+/// * `path` - The path of the entry.
+/// * `kind` - The kind of the entry, symlinks are not followed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub path: PathBuf,
+    pub kind: FileKind,
+}
+
+impl DirEntry {
+    /// The file name of the entry.
+    pub fn file_name(&self) -> OsString {
+        self.path.file_name().unwrap_or_default().to_os_string()
+    }
+}
+
+/// `FileSystem` abstracts the file system operations of scanning the source directories and performing file
+/// actions, so that runs can be simulated (see [`MemoryFileSystem`]) or use other storage backends. File contents
+/// are still read from the local file system by the analysis (Exif data, hashes), and advanced options of file
+/// actions (fallbacks, verification, trash, chunked copies) are only supported by the local file system, which is
+/// used if no file system is set.
+pub trait FileSystem: Send + Sync {
+    /// Checks if a directory entry exists, symlinks are not followed.
+    fn exists(&self, path: &Path) -> bool {
+        self.symlink_metadata(path).is_ok()
+    }
+
+    /// The metadata of a path, following symlinks.
+    fn metadata(&self, path: &Path) -> Result<FileMetadata>;
+
+    /// The metadata of a path, without following symlinks.
+    fn symlink_metadata(&self, path: &Path) -> Result<FileMetadata>;
+
+    /// Lists the entries of a directory, in no particular order.
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>>;
+
+    /// The canonical form of a path, with all symlinks resolved.
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf>;
+
+    /// Renames a file or directory.
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Copies a file, returning the number of copied bytes.
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64>;
+
+    /// Creates a hard link `link` of the file `original`.
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<()>;
+
+    /// Creates a symbolic link `link` pointing at `original` (relative to the directory of the link, if relative).
+    fn symlink(&self, original: &Path, link: &Path) -> Result<()>;
+
+    /// Creates a directory and all its missing parents.
+    fn create_dir_all(&self, path: &Path) -> Result<()>;
+
+    /// Removes a file or symlink.
+    fn remove_file(&self, path: &Path) -> Result<()>;
+
+    /// Opens a file for reading.
+    fn open(&self, path: &Path) -> Result<Box<dyn Read + Send>>;
+
+    /// Sets the modification time of a file.
+    fn set_modified(&self, path: &Path, modified: FileTime) -> Result<()>;
+}
+
+/// `RealFileSystem` is the local file system.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn metadata(&self, path: &Path) -> Result<FileMetadata> {
+        fs::metadata(path).map(FileMetadata::from)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<FileMetadata> {
+        fs::symlink_metadata(path).map(FileMetadata::from)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>> {
+        fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                // the file type of the directory entry does not follow symlinks
+                let file_type = entry.file_type()?;
+                let kind = if file_type.is_symlink() {
+                    FileKind::Symlink
+                } else if file_type.is_dir() {
+                    FileKind::Dir
+                } else {
+                    FileKind::File
+                };
+                Ok(DirEntry {
+                    path: entry.path(),
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        fs::copy(from, to)
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<()> {
+        fs::hard_link(original, link)
+    }
+
+    fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(original, link)
+        }
+        #[cfg(windows)]
+        {
+            std::os::windows::fs::symlink_file(original, link)
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = (original, link);
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "Symlinks are not supported on this platform",
+            ))
+        }
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn set_modified(&self, path: &Path, modified: FileTime) -> Result<()> {
+        filetime::set_file_mtime(path, modified)
+    }
+}
+
+/// A node of a `MemoryFileSystem`. Hard links share the content of the file.
+#[derive(Debug, Clone)]
+enum MemoryNode {
+    File {
+        content: Arc<Mutex<Vec<u8>>>,
+        modified: FileTime,
+    },
+    Dir,
+    Symlink(PathBuf),
+}
+
+/// The maximum number of symlinks resolved for a path, to detect symlink loops
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// `MemoryFileSystem` is a file system held in memory, e.g. to simulate a run or to test code using the `Analyzer`
+/// without temporary directories. Paths are used as given, they should be absolute.
+#[derive(Debug, Default)]
+pub struct MemoryFileSystem {
+    nodes: Mutex<BTreeMap<PathBuf, MemoryNode>>,
+}
+
+impl MemoryFileSystem {
+    /// Creates an empty file system.
+    pub fn new() -> MemoryFileSystem {
+        MemoryFileSystem::default()
+    }
+
+    /// Adds (or replaces) a file, its missing parent directories are created.
+    pub fn add_file<P: AsRef<Path>, C: Into<Vec<u8>>>(&self, path: P, content: C) {
+        let path = path.as_ref();
+        let mut nodes = self.lock();
+        if let Some(parent) = path.parent() {
+            create_dirs(&mut nodes, parent);
+        }
+        nodes.insert(
+            path.to_path_buf(),
+            MemoryNode::File {
+                content: Arc::new(Mutex::new(content.into())),
+                modified: FileTime::now(),
+            },
+        );
+    }
+
+    /// Adds a directory and its missing parents.
+    pub fn add_dir<P: AsRef<Path>>(&self, path: P) {
+        create_dirs(&mut self.lock(), path.as_ref());
+    }
+
+    /// The content of a file (following symlinks), `None` if there is no such file.
+    pub fn content<P: AsRef<Path>>(&self, path: P) -> Option<Vec<u8>> {
+        let nodes = self.lock();
+        let path = resolve(&nodes, path.as_ref()).ok()?;
+        match nodes.get(&path) {
+            Some(MemoryNode::File { content, .. }) => content.lock().ok().map(|c| c.clone()),
+            _ => None,
+        }
+    }
+
+    /// All paths of the file system (files, directories and symlinks), sorted.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.lock().keys().cloned().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<PathBuf, MemoryNode>> {
+        self.nodes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn not_found(path: &Path) -> Error {
+    Error::new(ErrorKind::NotFound, format!("{:?} does not exist", path))
+}
+
+fn already_exists(path: &Path) -> Error {
+    Error::new(
+        ErrorKind::AlreadyExists,
+        format!("{:?} already exists", path),
+    )
+}
+
+/// Creates a directory and its missing parents.
+fn create_dirs(nodes: &mut BTreeMap<PathBuf, MemoryNode>, path: &Path) {
+    for ancestor in path.ancestors() {
+        if ancestor.as_os_str().is_empty() || nodes.contains_key(ancestor) {
+            continue;
+        }
+        nodes.insert(ancestor.to_path_buf(), MemoryNode::Dir);
+    }
+}
+
+/// Checks that the parent directory of a new entry exists and that the entry does not.
+fn check_new_entry(nodes: &BTreeMap<PathBuf, MemoryNode>, path: &Path) -> Result<()> {
+    if nodes.contains_key(path) {
+        return Err(already_exists(path));
+    }
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => match nodes.get(parent) {
+            Some(MemoryNode::Dir) => Ok(()),
+            _ => Err(not_found(parent)),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Resolves the symlinks of a path (the path itself, not its parents).
+fn resolve(nodes: &BTreeMap<PathBuf, MemoryNode>, path: &Path) -> Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_SYMLINK_DEPTH {
+        match nodes.get(&path) {
+            Some(MemoryNode::Symlink(original)) => {
+                path = match path.parent() {
+                    Some(parent) => parent.join(original),
+                    None => original.clone(),
+                };
+            }
+            Some(_) => return Ok(path),
+            None => return Err(not_found(&path)),
+        }
+    }
+    Err(Error::other(format!(
+        "Too many levels of symlinks: {:?}",
+        path
+    )))
+}
+
+fn node_metadata(node: &MemoryNode) -> FileMetadata {
+    match node {
+        MemoryNode::File { content, modified } => FileMetadata {
+            kind: FileKind::File,
+            len: content.lock().map_or(0, |content| content.len() as u64),
+            modified: Some(*modified),
+        },
+        MemoryNode::Dir => FileMetadata {
+            kind: FileKind::Dir,
+            len: 0,
+            modified: None,
+        },
+        MemoryNode::Symlink(_) => FileMetadata {
+            kind: FileKind::Symlink,
+            len: 0,
+            modified: None,
+        },
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn metadata(&self, path: &Path) -> Result<FileMetadata> {
+        let nodes = self.lock();
+        let path = resolve(&nodes, path)?;
+        nodes.get(&path).map(node_metadata).ok_or(not_found(&path))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<FileMetadata> {
+        self.lock()
+            .get(path)
+            .map(node_metadata)
+            .ok_or(not_found(path))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>> {
+        let nodes = self.lock();
+        match nodes.get(&resolve(&nodes, path)?) {
+            Some(MemoryNode::Dir) => {}
+            _ => return Err(Error::other(format!("{:?} is not a directory", path))),
+        }
+        Ok(nodes
+            .iter()
+            .filter(|(entry, _)| entry.parent() == Some(path))
+            .map(|(entry, node)| DirEntry {
+                path: entry.clone(),
+                kind: node_metadata(node).kind,
+            })
+            .collect())
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        resolve(&self.lock(), path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut nodes = self.lock();
+        if !nodes.contains_key(from) {
+            return Err(not_found(from));
+        }
+        if let Some(MemoryNode::Dir) = nodes.get(to) {
+            return Err(already_exists(to));
+        }
+        nodes.remove(to);
+        check_new_entry(&nodes, to)?;
+        // a directory is renamed with all its entries
+        let moved: Vec<PathBuf> = nodes
+            .keys()
+            .filter(|path| path.starts_with(from))
+            .cloned()
+            .collect();
+        for path in moved {
+            if let Some(node) = nodes.remove(&path) {
+                let relative = path.strip_prefix(from).unwrap_or(Path::new(""));
+                nodes.insert(to.join(relative), node);
+            }
+        }
+        Ok(())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        let mut nodes = self.lock();
+        let source = resolve(&nodes, from)?;
+        let Some(MemoryNode::File { content, modified }) = nodes.get(&source) else {
+            return Err(Error::other(format!("{:?} is not a file", from)));
+        };
+        let content = content
+            .lock()
+            .map_err(|_| Error::other("File content lock poisoned"))?
+            .clone();
+        let modified = *modified;
+        let len = content.len() as u64;
+        if !matches!(nodes.get(to), Some(MemoryNode::File { .. })) {
+            check_new_entry(&nodes, to)?;
+        }
+        nodes.insert(
+            to.to_path_buf(),
+            MemoryNode::File {
+                content: Arc::new(Mutex::new(content)),
+                modified,
+            },
+        );
+        Ok(len)
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<()> {
+        let mut nodes = self.lock();
+        let node = match nodes.get(original) {
+            Some(node @ MemoryNode::File { .. }) => node.clone(),
+            Some(_) => return Err(Error::other(format!("{:?} is not a file", original))),
+            None => return Err(not_found(original)),
+        };
+        check_new_entry(&nodes, link)?;
+        nodes.insert(link.to_path_buf(), node);
+        Ok(())
+    }
+
+    fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
+        let mut nodes = self.lock();
+        check_new_entry(&nodes, link)?;
+        nodes.insert(
+            link.to_path_buf(),
+            MemoryNode::Symlink(original.to_path_buf()),
+        );
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        let mut nodes = self.lock();
+        if let Some(existing) = path
+            .ancestors()
+            .find(|ancestor| nodes.contains_key(*ancestor))
+        {
+            if !matches!(nodes.get(existing), Some(MemoryNode::Dir)) {
+                return Err(already_exists(existing));
+            }
+        }
+        create_dirs(&mut nodes, path);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        let mut nodes = self.lock();
+        match nodes.get(path) {
+            Some(MemoryNode::Dir) => Err(Error::other(format!("{:?} is a directory", path))),
+            Some(_) => {
+                nodes.remove(path);
+                Ok(())
+            }
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Read + Send>> {
+        self.content(path)
+            .map(|content| Box::new(Cursor::new(content)) as Box<dyn Read + Send>)
+            .ok_or(not_found(path))
+    }
+
+    fn set_modified(&self, path: &Path, modified: FileTime) -> Result<()> {
+        let mut nodes = self.lock();
+        let path = resolve(&nodes, path)?;
+        match nodes.get_mut(&path) {
+            Some(MemoryNode::File { modified: time, .. }) => {
+                *time = modified;
+                Ok(())
+            }
+            _ => Err(not_found(&path)),
+        }
+    }
+}
+
+/// `SharedFileSystem` is a file system shared by the `Analyzer` and its file actions.
+#[derive(Clone)]
+pub struct SharedFileSystem(Arc<dyn FileSystem>);
+
+impl SharedFileSystem {
+    /// Creates a new `SharedFileSystem`.
+    pub fn new<F: FileSystem + 'static>(file_system: F) -> SharedFileSystem {
+        SharedFileSystem(Arc::new(file_system))
+    }
+
+    /// Shares an existing file system, e.g. to inspect a `MemoryFileSystem` after a run.
+    pub fn from_arc(file_system: Arc<dyn FileSystem>) -> SharedFileSystem {
+        SharedFileSystem(file_system)
+    }
+}
+
+impl std::ops::Deref for SharedFileSystem {
+    type Target = dyn FileSystem;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl Debug for SharedFileSystem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SharedFileSystem")
+    }
+}
+
+impl PartialEq for SharedFileSystem {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedFileSystem {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{ActionMode, ActualAction};
+    use crate::plan::SkipReason;
+    use crate::{AnalysisType, Analyzer, AnalyzerSettings};
+
+    /// A memory file system with a source directory of photos and a target directory that already contains a file
+    /// of the same name (with other content) and an identical file. The directory of the other files is missing.
+    fn file_system() -> Arc<MemoryFileSystem> {
+        let file_system = MemoryFileSystem::new();
+        file_system.add_file("/source/photo.jpg", "new photo");
+        file_system.add_file("/source/same.jpg", "same photo");
+        file_system.add_file("/source/a/other.jpg", "first other");
+        file_system.add_file("/source/b/other.jpg", "second other");
+        file_system.add_file("/target/photo/photo.jpg", "old photo");
+        file_system.add_file("/target/same/same.jpg", "same photo");
+        Arc::new(file_system)
+    }
+
+    fn analyzer(file_system: &Arc<MemoryFileSystem>, mode: ActionMode) -> Analyzer {
+        AnalyzerSettings::builder()
+            .source_dir("/source")
+            .target_dir("/target")
+            .recursive_source(true)
+            .analysis_type(AnalysisType::OnlyName)
+            .nodate_file_format("{name}/{name}{-:dup}.{ext}")
+            .mkdir(true)
+            .action_type(mode)
+            .file_system(SharedFileSystem::from_arc(file_system.clone()))
+            .build()
+            .unwrap()
+    }
+
+    /// The targets planned for the files of the source directory, sorted by source.
+    fn targets(analyzer: &Analyzer) -> Vec<(PathBuf, Option<SkipReason>, PathBuf)> {
+        let plan = analyzer.plan();
+        assert!(plan.diagnostics.is_empty(), "{:?}", plan.diagnostics);
+        let mut targets: Vec<_> = plan
+            .actions
+            .iter()
+            .map(|action| (&action.source, None, &action.target))
+            .chain(plan.skipped.iter().filter_map(|skipped| {
+                let target = skipped.target.as_ref()?;
+                Some((&skipped.source, Some(skipped.reason), target))
+            }))
+            .map(|(source, reason, target)| (source.clone(), reason, target.clone()))
+            .collect();
+        targets.sort_by(|a, b| a.0.cmp(&b.0));
+        targets
+    }
+
+    fn expected_targets() -> Vec<(PathBuf, Option<SkipReason>, PathBuf)> {
+        [
+            ("/source/a/other.jpg", None, "/target/other/other.jpg"),
+            ("/source/b/other.jpg", None, "/target/other/other-1.jpg"),
+            ("/source/photo.jpg", None, "/target/photo/photo-1.jpg"),
+            (
+                "/source/same.jpg",
+                Some(SkipReason::IdenticalTarget),
+                "/target/same/same.jpg",
+            ),
+        ]
+        .map(|(source, reason, target)| (PathBuf::from(source), reason, PathBuf::from(target)))
+        .to_vec()
+    }
+
+    #[test]
+    fn dry_run_on_memory_file_system() {
+        let file_system = file_system();
+        let paths = file_system.paths();
+        let analyzer = analyzer(&file_system, ActionMode::DryRun(ActualAction::Move));
+
+        assert_eq!(targets(&analyzer), expected_targets());
+        let summary = analyzer.run(analyzer.plan());
+        assert_eq!(summary.total.transferred, 3);
+        assert_eq!(summary.total.skipped_existing, 1);
+        // not even the missing directory is created
+        assert_eq!(file_system.paths(), paths);
+    }
+
+    #[test]
+    fn run_on_memory_file_system() {
+        let file_system = file_system();
+        let analyzer = analyzer(&file_system, ActionMode::Execute(ActualAction::Move));
+
+        assert_eq!(targets(&analyzer), expected_targets());
+        let summary = analyzer.run(analyzer.plan());
+        assert!(summary.is_success());
+        assert_eq!(summary.total.transferred, 3);
+
+        assert!(file_system
+            .metadata(Path::new("/target/other"))
+            .is_ok_and(|metadata| metadata.is_dir()));
+        let content = |path: &str| file_system.content(path).map(String::from_utf8);
+        assert_eq!(
+            content("/target/photo/photo.jpg"),
+            Some(Ok("old photo".into()))
+        );
+        assert_eq!(
+            content("/target/photo/photo-1.jpg"),
+            Some(Ok("new photo".into()))
+        );
+        assert_eq!(
+            content("/target/other/other.jpg"),
+            Some(Ok("first other".into()))
+        );
+        assert_eq!(
+            content("/target/other/other-1.jpg"),
+            Some(Ok("second other".into()))
+        );
+        assert_eq!(content("/source/photo.jpg"), None);
+        // the identical file is skipped, its source is kept
+        assert_eq!(content("/source/same.jpg"), Some(Ok("same photo".into())));
+    }
+}
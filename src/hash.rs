@@ -0,0 +1,61 @@
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// `FileHash` is the SHA-256 checksum of the content of a file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileHash(pub [u8; 32]);
+
+impl Display for FileHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for FileHash {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(anyhow::anyhow!("Invalid file hash: {:?}", s));
+        }
+        let mut hash = [0u8; 32];
+        for (i, byte) in hash.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)
+                .map_err(|_| anyhow::anyhow!("Invalid file hash: {:?}", s))?;
+        }
+        Ok(FileHash(hash))
+    }
+}
+
+/// Computes the SHA-256 checksum of a file, reading the file in chunks.
+///
+/// # Arguments
+///
+/// * `path` - The file to hash.
+///
+/// # Errors
+///
+/// This function will return an error if the file could not be read.
+pub fn hash_file<P: AsRef<Path> + ?Sized>(path: &P) -> std::io::Result<FileHash> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => hasher.update(&buffer[..n]),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(FileHash(hasher.finalize().into()))
+}
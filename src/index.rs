@@ -0,0 +1,263 @@
+use crate::action::{self, TEMPORARY_FILE_PREFIX};
+use crate::hash::{self, FileHash};
+use anyhow::{anyhow, Result};
+use filetime::FileTime;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Default file name of the index cache, placed in the target directory
+pub const INDEX_CACHE_FILE_NAME: &str = ".photosort-index.jsonl";
+
+/// A cached index entry, one line of the index cache file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    modified: i64,
+    hash: String,
+}
+
+/// `TargetIndex` is an index of the content (size and SHA-256 checksum) of all files in the target directory,
+/// used to find files that already exist anywhere in the target directory, possibly under another name.
+#[derive(Debug, Default)]
+pub struct TargetIndex {
+    by_size: HashMap<u64, Vec<(PathBuf, FileHash)>>,
+}
+
+impl TargetIndex {
+    /// Builds the index of a directory (recursively). Files are hashed in parallel. Checksums are cached in
+    /// a file (one JSON object per line, appended while hashing), so an interrupted build can be resumed and
+    /// later runs only hash new or modified files.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory to index.
+    /// * `cache` - An optional path of the cache file.
+    /// * `update_cache` - If not set, the cache file is only read (e.g. in dry-run mode).
+    /// * `threads` - The number of threads used for hashing.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the directory or the cache file can not be read or written.
+    /// Files that can not be hashed are logged and left out of the index.
+    pub fn build(
+        directory: &Path,
+        cache: Option<&Path>,
+        update_cache: bool,
+        threads: usize,
+    ) -> Result<TargetIndex> {
+        let mut files = Vec::new();
+        crate::find_files_in_source(directory.to_path_buf(), true, &mut files)?;
+        files.retain(|file| {
+            let name = file.file_name().map(|name| name.to_string_lossy());
+            !name.is_some_and(|name| {
+                name.starts_with(TEMPORARY_FILE_PREFIX) || name.starts_with(INDEX_CACHE_FILE_NAME)
+            }) && Some(file.as_path()) != cache
+        });
+
+        let cached = match cache {
+            Some(cache) if cache.exists() => read_cache(cache)?,
+            _ => HashMap::new(),
+        };
+
+        let mut entries = Vec::with_capacity(files.len());
+        let mut pending = Vec::new();
+        for file in files {
+            let Ok(metadata) = fs::metadata(&file) else {
+                warn!("Failed to read metadata of {:?}, not indexing it", file);
+                continue;
+            };
+            let size = metadata.len();
+            let modified = modification_time(&metadata);
+
+            match cached.get(&file) {
+                Some(entry) if entry.size == size && entry.modified == modified => {
+                    match entry.hash.parse() {
+                        Ok(hash) => entries.push((file, size, modified, hash)),
+                        Err(_) => pending.push((file, size, modified)),
+                    }
+                }
+                _ => pending.push((file, size, modified)),
+            }
+        }
+
+        info!(
+            "Indexing target directory: {} files cached, {} files to hash",
+            entries.len(),
+            pending.len()
+        );
+
+        let writer = match cache {
+            Some(cache) if update_cache => Some(Mutex::new(BufWriter::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(cache)
+                    .map_err(|e| anyhow!("Failed to open index cache: {:?} - {:?}", cache, e))?,
+            ))),
+            _ => None,
+        };
+        let hashed = Mutex::new(Vec::with_capacity(pending.len()));
+        let next = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((file, size, modified)) = pending.get(index) else {
+                        break;
+                    };
+
+                    let hash = match hash::hash_file(file) {
+                        Ok(hash) => hash,
+                        Err(err) => {
+                            warn!("Failed to hash {:?}, not indexing it: {}", file, err);
+                            continue;
+                        }
+                    };
+                    debug!("Indexed {:?} ({})", file, hash);
+
+                    if let Some(writer) = &writer {
+                        let entry = CacheEntry {
+                            path: file.clone(),
+                            size: *size,
+                            modified: *modified,
+                            hash: hash.to_string(),
+                        };
+                        if let Err(err) = append_cache_entry(writer, &entry) {
+                            warn!("Failed to write index cache: {}", err);
+                        }
+                    }
+
+                    if let Ok(mut hashed) = hashed.lock() {
+                        hashed.push((file.clone(), *size, *modified, hash));
+                    }
+                });
+            }
+        });
+
+        entries.extend(
+            hashed
+                .into_inner()
+                .map_err(|_| anyhow!("Index lock poisoned"))?,
+        );
+
+        if let Some(cache) = cache.filter(|_| update_cache) {
+            drop(writer);
+            write_cache(cache, &entries)?;
+        }
+
+        let mut index = TargetIndex::default();
+        for (file, size, _, hash) in entries {
+            index.by_size.entry(size).or_default().push((file, hash));
+        }
+        Ok(index)
+    }
+
+    /// The number of indexed files.
+    pub fn len(&self) -> usize {
+        self.by_size.values().map(Vec::len).sum()
+    }
+
+    /// Checks if the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.by_size.is_empty()
+    }
+
+    /// Finds an indexed file with the same content as the given file. Files with the same size and checksum are
+    /// compared byte by byte before they are reported as identical. The file itself (e.g. if it is located in the
+    /// target directory) is never reported.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be read.
+    pub fn find(&self, path: &Path) -> Result<Option<PathBuf>> {
+        let size = fs::metadata(path)?.len();
+        let Some(candidates) = self.by_size.get(&size) else {
+            return Ok(None);
+        };
+
+        let hash = hash::hash_file(path)?;
+        for (candidate, candidate_hash) in candidates {
+            if *candidate_hash != hash || action::is_same_file(path, candidate).unwrap_or(true) {
+                continue;
+            }
+            match action::is_identical_file(path, candidate) {
+                Ok(true) => return Ok(Some(candidate.clone())),
+                Ok(false) => warn!(
+                    "Checksum collision between {:?} and {:?}, the content differs",
+                    path, candidate
+                ),
+                Err(err) => warn!("Failed to compare {:?} with {:?}: {}", path, candidate, err),
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn modification_time(metadata: &fs::Metadata) -> i64 {
+    let modified = FileTime::from_last_modification_time(metadata);
+    modified.unix_seconds() * 1_000_000_000 + modified.nanoseconds() as i64
+}
+
+/// Reads the cache file, later entries of a path replace earlier ones. Invalid lines (e.g. a line cut off by
+/// an interrupted run) are ignored.
+fn read_cache(cache: &Path) -> Result<HashMap<PathBuf, CacheEntry>> {
+    let file = File::open(cache)
+        .map_err(|e| anyhow!("Failed to open index cache: {:?} - {:?}", cache, e))?;
+
+    let mut entries = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        match serde_json::from_str::<CacheEntry>(&line?) {
+            Ok(entry) => {
+                entries.insert(entry.path.clone(), entry);
+            }
+            Err(err) => debug!("Ignoring invalid index cache entry: {}", err),
+        }
+    }
+    Ok(entries)
+}
+
+fn append_cache_entry(writer: &Mutex<BufWriter<File>>, entry: &CacheEntry) -> Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    let mut writer = writer
+        .lock()
+        .map_err(|_| anyhow!("Index cache lock poisoned"))?;
+    writer.write_all(line.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Rewrites the cache file with the current entries only, dropping entries of deleted files.
+fn write_cache(cache: &Path, entries: &[(PathBuf, u64, i64, FileHash)]) -> Result<()> {
+    let mut temporary = cache.as_os_str().to_os_string();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut writer = BufWriter::new(
+        File::create(&temporary)
+            .map_err(|e| anyhow!("Failed to write index cache: {:?} - {:?}", temporary, e))?,
+    );
+    for (path, size, modified, hash) in entries {
+        let entry = CacheEntry {
+            path: path.clone(),
+            size: *size,
+            modified: *modified,
+            hash: hash.to_string(),
+        };
+        serde_json::to_writer(&mut writer, &entry)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    drop(writer);
+
+    fs::rename(&temporary, cache)
+        .map_err(|e| anyhow!("Failed to write index cache: {:?} - {:?}", cache, e))
+}
@@ -0,0 +1,456 @@
+use crate::action::{self, ActionMode, ActionOptions, ActualAction};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use filetime::FileTime;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{Debug, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// `JournalAction` is the action recorded in a journal entry.
+///
+/// # Variants
+///
+/// * `File` - A file action (move, copy, link, ...) from `source` to `target`.
+/// * `Delete` - The deletion of `source`, the target refers to the identical file that was kept.
+/// * `MoveStarted` - A move across file systems started copying `source` to `target`. It is followed by a `File`
+///   entry once the move completed, an unmatched entry indicates an interrupted move.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalAction {
+    File(ActualAction),
+    Delete,
+    MoveStarted,
+}
+
+/// `JournalEntry` is a single line of the action journal.
+///
+/// # Fields
+///
+/// * `timestamp` - The time the action was performed.
+/// * `action` - The performed action.
+/// * `source` - The source file of the action.
+/// * `target` - The target file of the action.
+/// * `success` - A boolean that indicates whether the action succeeded.
+/// * `error` - The error message, if the action failed.
+/// * `size` - The size of the target file after the action.
+/// * `modified` - The modification time of the target file after the action (nanoseconds since the unix epoch).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp: DateTime<Utc>,
+    pub action: JournalAction,
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub success: bool,
+    pub error: Option<String>,
+    pub size: Option<u64>,
+    pub modified: Option<i64>,
+}
+
+/// `Journal` is an append-only log of performed actions (JSON lines), which allows reverting a run.
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl Debug for Journal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Journal({:?})", self.path)
+    }
+}
+
+impl PartialEq for Journal {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+impl Eq for Journal {}
+
+impl Journal {
+    /// Opens a journal file for appending, the file is created if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be opened.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Journal> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow!("Failed to open journal file: {:?} - {:?}", path, e))?;
+        Ok(Journal {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends an entry to the journal. The state (size, modification time) of the target file is recorded,
+    /// so that a later revert can detect modified targets.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the entry can not be written.
+    pub fn record(
+        &self,
+        action: JournalAction,
+        source: &Path,
+        target: &Path,
+        result: &Result<()>,
+    ) -> Result<()> {
+        let (size, modified) = match action {
+            JournalAction::File(_) if result.is_ok() => file_state(target, action),
+            _ => (None, None),
+        };
+
+        let entry = JournalEntry {
+            timestamp: Utc::now(),
+            action,
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|err| err.to_string()),
+            size,
+            modified,
+        };
+
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|e| anyhow!("Journal lock poisoned: {:?}", e))?;
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.flush())
+            .map_err(|e| anyhow!("Failed to write journal file: {:?} - {:?}", self.path, e))
+    }
+}
+
+/// Reads all entries of a journal file.
+///
+/// # Errors
+///
+/// This function will return an error if the file can not be read or contains invalid entries.
+pub fn read_journal<P: AsRef<Path>>(path: P) -> Result<Vec<JournalEntry>> {
+    let path = path.as_ref();
+    let file =
+        File::open(path).map_err(|e| anyhow!("Failed to open journal: {:?} - {:?}", path, e))?;
+
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| anyhow!("Invalid journal entry in line {}: {}", number + 1, e))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Reverts the actions recorded in a journal, walking the journal backwards. Moved files are moved back,
+/// links are removed. Copies (and reflinks) are only deleted if `delete_copies` is set. Entries whose
+/// target was modified since the action (size or modification time differ) are skipped with a warning.
+///
+/// # Arguments
+///
+/// * `path` - The path of the journal file.
+/// * `dry_run` - If set, the revert actions are only logged.
+/// * `delete_copies` - If set, copied files are deleted.
+///
+/// # Errors
+///
+/// This function will return an error if the journal can not be read. Errors reverting single entries are
+/// logged and do not abort the revert.
+pub fn revert<P: AsRef<Path>>(path: P, dry_run: bool, delete_copies: bool) -> Result<()> {
+    let entries = read_journal(path)?;
+    let completed_moves: HashSet<_> = entries
+        .iter()
+        .filter(|entry| entry.success && entry.action == JournalAction::File(ActualAction::Move))
+        .map(|entry| (&entry.source, &entry.target))
+        .collect();
+
+    for entry in entries.iter().rev().filter(|entry| entry.success) {
+        let result = match entry.action {
+            JournalAction::MoveStarted
+                if completed_moves.contains(&(&entry.source, &entry.target)) =>
+            {
+                Ok(())
+            }
+            JournalAction::MoveStarted => revert_interrupted_move(entry, dry_run),
+            _ => revert_entry(entry, dry_run, delete_copies),
+        };
+        if let Err(err) = result {
+            error!(
+                "Error reverting {:?} -> {:?}: {}",
+                entry.source, entry.target, err
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn revert_entry(entry: &JournalEntry, dry_run: bool, delete_copies: bool) -> Result<()> {
+    let action = match entry.action {
+        JournalAction::Delete => {
+            warn!("Can not revert the deletion of {:?}", entry.source);
+            return Ok(());
+        }
+        JournalAction::File(action) => action,
+        JournalAction::MoveStarted => return revert_interrupted_move(entry, dry_run),
+    };
+
+    if fs::symlink_metadata(&entry.target).is_err() {
+        warn!(
+            "Skipping revert, target no longer exists: {:?}",
+            entry.target
+        );
+        return Ok(());
+    }
+    if file_state(&entry.target, entry.action) != (entry.size, entry.modified) {
+        warn!(
+            "Skipping revert, target was modified since the action: {:?}",
+            entry.target
+        );
+        return Ok(());
+    }
+
+    match action {
+        ActualAction::Move => {
+            let mode = if dry_run {
+                ActionMode::DryRun(ActualAction::Move)
+            } else {
+                ActionMode::Execute(ActualAction::Move)
+            };
+            info!("Moving back {:?} -> {:?}", entry.target, entry.source);
+            action::file_action(
+                &entry.target,
+                &entry.source,
+                &mode,
+                &ActionOptions {
+                    mkdir: true,
+                    ..ActionOptions::default()
+                },
+            )?;
+            Ok(())
+        }
+        ActualAction::Copy | ActualAction::Reflink if !delete_copies => {
+            debug!("Keeping copy {:?}", entry.target);
+            Ok(())
+        }
+        ActualAction::Copy
+        | ActualAction::Reflink
+        | ActualAction::Hardlink
+        | ActualAction::RelativeSymlink
+        | ActualAction::AbsoluteSymlink => {
+            let is_link = matches!(
+                action,
+                ActualAction::RelativeSymlink | ActualAction::AbsoluteSymlink
+            );
+            if !is_link && !entry.source.exists() {
+                warn!(
+                    "Skipping revert, source no longer exists, keeping {:?}",
+                    entry.target
+                );
+                return Ok(());
+            }
+
+            if dry_run {
+                info!("[Delete] {:?}", entry.target);
+                Ok(())
+            } else {
+                info!("Deleting {:?}", entry.target);
+                fs::remove_file(&entry.target)
+                    .map_err(|e| anyhow!("Failed to delete file: {:?} - {:?}", entry.target, e))
+            }
+        }
+    }
+}
+
+/// Reverts a move across file systems that was interrupted between copying and deleting the source. If both files
+/// still exist and are identical, the copy is deleted. If only the target exists, it is moved back.
+fn revert_interrupted_move(entry: &JournalEntry, dry_run: bool) -> Result<()> {
+    if !entry.target.exists() {
+        return Ok(());
+    }
+
+    if entry.source.exists() {
+        if !action::is_identical_file(&entry.source, &entry.target)? {
+            warn!(
+                "Skipping revert of interrupted move, source and target differ: {:?}",
+                entry.target
+            );
+            return Ok(());
+        }
+        if dry_run {
+            info!("[Delete] {:?}", entry.target);
+            return Ok(());
+        }
+        info!("Deleting copy of interrupted move {:?}", entry.target);
+        return fs::remove_file(&entry.target)
+            .map_err(|e| anyhow!("Failed to delete file: {:?} - {:?}", entry.target, e));
+    }
+
+    let mode = if dry_run {
+        ActionMode::DryRun(ActualAction::Move)
+    } else {
+        ActionMode::Execute(ActualAction::Move)
+    };
+    info!("Moving back {:?} -> {:?}", entry.target, entry.source);
+    action::file_action(
+        &entry.target,
+        &entry.source,
+        &mode,
+        &ActionOptions {
+            mkdir: true,
+            ..ActionOptions::default()
+        },
+    )?;
+    Ok(())
+}
+
+/// Size and modification time (nanoseconds since the unix epoch) of a file, symlinks are not followed
+/// for symlink actions.
+fn file_state(path: &Path, action: JournalAction) -> (Option<u64>, Option<i64>) {
+    let metadata = match action {
+        JournalAction::File(ActualAction::RelativeSymlink | ActualAction::AbsoluteSymlink) => {
+            fs::symlink_metadata(path)
+        }
+        _ => fs::metadata(path),
+    };
+
+    match metadata {
+        Ok(metadata) => {
+            let modified = FileTime::from_last_modification_time(&metadata);
+            (
+                Some(metadata.len()),
+                Some(modified.unix_seconds() * 1_000_000_000 + modified.nanoseconds() as i64),
+            )
+        }
+        Err(_) => (None, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AnalyzerSettings;
+    use std::collections::BTreeMap;
+
+    /// The files below a directory and their content, relative to the directory.
+    fn tree(root: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        let mut files = BTreeMap::new();
+        let mut directories = vec![root.to_path_buf()];
+        while let Some(directory) = directories.pop() {
+            for entry in fs::read_dir(directory).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    directories.push(path);
+                } else {
+                    let relative = path.strip_prefix(root).unwrap().to_path_buf();
+                    files.insert(relative, fs::read(&path).unwrap());
+                }
+            }
+        }
+        files
+    }
+
+    /// Sorts the files of `source` into `target` with the given action, recording it in the journal.
+    fn sort(source: &Path, target: &Path, journal: &Path, action: ActualAction) {
+        let analyzer = AnalyzerSettings::builder()
+            .source_dir(source)
+            .target_dir(target)
+            .nodate_file_format("sorted/{name}.{ext}")
+            .mkdir(true)
+            .journal(Some(journal))
+            .action_type(ActionMode::Execute(action))
+            .build()
+            .unwrap();
+        let summary = analyzer.run(analyzer.plan());
+        assert_eq!(summary.total.failed, 0);
+    }
+
+    /// Creates the source directory with two files, an empty first and second target directory and the path
+    /// of the journal.
+    fn directories() -> (tempfile::TempDir, PathBuf, PathBuf, PathBuf, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let [source, first, second] = ["source", "first", "second"].map(|name| {
+            let path = dir.path().join(name);
+            fs::create_dir(&path).unwrap();
+            path
+        });
+        fs::write(source.join("a.jpg"), b"a").unwrap();
+        fs::write(source.join("b.jpg"), b"bb").unwrap();
+        let journal = dir.path().join("journal.jsonl");
+        (dir, source, first, second, journal)
+    }
+
+    #[test]
+    fn revert_moves_back_in_reverse_order() {
+        let (_dir, source, first, second, journal) = directories();
+        let original = tree(&source);
+        sort(&source, &first, &journal, ActualAction::Move);
+        // the files are moved again, they have to be moved back to `first` before they can be moved to `source`
+        sort(&first.join("sorted"), &second, &journal, ActualAction::Move);
+        assert!(tree(&source).is_empty());
+        assert_eq!(tree(&second).len(), 2);
+        assert_eq!(read_journal(&journal).unwrap().len(), 4);
+
+        revert(&journal, false, false).unwrap();
+        assert_eq!(tree(&source), original);
+        assert!(tree(&first).is_empty());
+        assert!(tree(&second).is_empty());
+    }
+
+    #[test]
+    fn revert_skips_modified_targets() {
+        let (_dir, source, first, _second, journal) = directories();
+        sort(&source, &first, &journal, ActualAction::Move);
+        fs::write(first.join("sorted/b.jpg"), b"changed").unwrap();
+
+        revert(&journal, false, false).unwrap();
+        assert_eq!(
+            tree(&source),
+            BTreeMap::from([(PathBuf::from("a.jpg"), b"a".to_vec())])
+        );
+        assert_eq!(
+            tree(&first),
+            BTreeMap::from([(PathBuf::from("sorted/b.jpg"), b"changed".to_vec())])
+        );
+    }
+
+    #[test]
+    fn dry_run_revert_changes_nothing() {
+        for (action, delete_copies) in [(ActualAction::Move, false), (ActualAction::Copy, true)] {
+            let (_dir, source, first, _second, journal) = directories();
+            sort(&source, &first, &journal, action);
+            let (sources, targets) = (tree(&source), tree(&first));
+            let entries = read_journal(&journal).unwrap();
+
+            revert(&journal, true, delete_copies).unwrap();
+            assert_eq!(tree(&source), sources);
+            assert_eq!(tree(&first), targets);
+            assert_eq!(read_journal(&journal).unwrap(), entries);
+        }
+    }
+
+    #[test]
+    fn revert_deletes_copies_only_if_requested() {
+        let (_dir, source, first, _second, journal) = directories();
+        sort(&source, &first, &journal, ActualAction::Copy);
+
+        revert(&journal, false, false).unwrap();
+        assert_eq!(tree(&first).len(), 2);
+        revert(&journal, false, true).unwrap();
+        assert!(tree(&first).is_empty());
+        assert_eq!(tree(&source).len(), 2);
+    }
+}
@@ -1,20 +1,95 @@
 #![doc = include_str!("../README.md")]
 
-use crate::analysis::name_formatters::{FileType, NameFormatterInvocationInfo};
-use action::ActionMode;
+use crate::analysis::exiftool::ExiftoolDates;
+use crate::analysis::name_formatters::{
+    FileType, FormatDate, FormatDuplicate, FormatDuration, FormatExtension, FormatFileType,
+    FormatName, FormatRelativePath, FormatSource, FormatVerticalResolution, FormatterRegistry,
+    NameFormatterInvocationInfo,
+};
+use crate::analysis::{DateSource, FileAnalysis, MediaInfo};
+use action::{
+    ActionMode, ActionOptions, ActionReport, ActualAction, BandwidthLimiter, CancellationToken,
+    CopyProgress, HardlinkFallback, ReflinkFallback, SymlinkFallback, SymlinkPrivilege,
+};
 use anyhow::{anyhow, Result};
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use exclude::{ExcludePatterns, IgnoreFiles};
+use extensions::ExtensionPatterns;
+use filesystem::{FileKind, FileSystem, RealFileSystem, SharedFileSystem};
+use hash::FileHash;
+use index::TargetIndex;
+use journal::{Journal, JournalAction};
 use lazy_static::lazy_static;
+use locale::DateLocale;
 use log::{debug, error, info, trace, warn};
-use std::ffi::OsStr;
+use name::NameCleaner;
+#[cfg(feature = "perceptual-hash")]
+use near_dup::NearDupReport;
+use outcome::{FileOutcome, OutcomeAction, RunObserver};
+use plan::{
+    Operation, Plan, PlanDiagnostic, PlanEntry, PlannedAction, Reservations, SkipReason,
+    SkippedFile,
+};
+use progress::Progress;
+use state::ProcessedState;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::sync_channel;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Instant;
+use strip::StripTag;
+use summary::RunSummary;
 
 pub mod action;
 pub mod analysis;
+mod builder;
+pub mod error;
+pub mod exclude;
+pub mod extensions;
+pub mod filelist;
+pub mod filesystem;
+pub mod hash;
+pub mod index;
+pub mod journal;
+pub mod locale;
+pub mod manifest;
 pub mod name;
+#[cfg(feature = "perceptual-hash")]
+pub mod near_dup;
+pub mod outcome;
+pub mod plan;
+pub mod progress;
+pub mod report;
+pub mod script;
+pub mod state;
+pub mod stats;
+pub mod strip;
+pub mod summary;
+pub mod verify;
+
+pub use builder::AnalyzerBuilder;
+pub use error::Error;
+
+/// Default target file format
+pub const DEFAULT_FILE_FORMAT: &str = "{type}{_:date}{-:name}{-:dup}.{ext}";
+/// Default placeholder of the date of files without date
+pub const DEFAULT_NODATE_PLACEHOLDER: &str = "NODATE";
+/// Default prefixes removed from the names of files (in upper or lower case), see `clean_prefixes`
+pub const DEFAULT_CLEAN_PREFIXES: &[&str] = &["MOV", "VID", "IMG"];
+/// Default date format (chrono strftime syntax)
+pub const DEFAULT_DATE_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// Default extensions of photos
+pub const DEFAULT_EXTENSIONS: &[&str] =
+    &["jpg", "jpeg", "png", "tiff", "heif", "heic", "avif", "webp"];
+/// Default extensions of videos
+pub const DEFAULT_VIDEO_EXTENSIONS: &[&str] = &["mp4", "mov", "avi"];
 
 /// `AnalysisType` is an enumeration that defines the different types of analysis that can be performed on a file.
 ///
@@ -24,12 +99,14 @@ pub mod name;
 /// * `OnlyName` - Represents the action of analyzing a file based only on its name.
 /// * `ExifThenName` - Represents the action of analyzing a file based first on its Exif data, then on its name if the Exif data is not sufficient.
 /// * `NameThenExif` - Represents the action of analyzing a file based first on its name, then on its Exif data if the name is not sufficient.
+/// * `Both` - Represents the action of analyzing a file based on both its Exif data and its name. The file only gets the Exif date if both dates agree within the `agreement_tolerance`, otherwise it has no date.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AnalysisType {
     OnlyExif,
     OnlyName,
     ExifThenName,
     NameThenExif,
+    Both,
 }
 /// Implementation of the `FromStr` trait for `AnalysisType`.
 ///
@@ -55,42 +132,560 @@ impl FromStr for AnalysisType {
             "exif_name" => Ok(AnalysisType::ExifThenName),
             "name_then_exif" => Ok(AnalysisType::NameThenExif),
             "name_exif" => Ok(AnalysisType::NameThenExif),
+            "exif_and_name" => Ok(AnalysisType::Both),
             _ => Err(anyhow::anyhow!("Invalid analysis type")),
         }
     }
 }
 
+/// `CollisionStrategy` defines what happens if the target file of a file already exists.
+///
+/// # Variants
+///
+/// * `Rename` - Increase the duplicate counter (`{dup}`) until a free target name is found.
+/// * `Skip` - Skip the file, leaving source and target untouched.
+/// * `Overwrite` - Replace the existing target file atomically.
+/// * `Fail` - Fail the file with an error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CollisionStrategy {
+    #[default]
+    Rename,
+    Skip,
+    Overwrite,
+    Fail,
+}
+
+impl FromStr for CollisionStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "rename" => Ok(CollisionStrategy::Rename),
+            "skip" => Ok(CollisionStrategy::Skip),
+            "overwrite" => Ok(CollisionStrategy::Overwrite),
+            "fail" => Ok(CollisionStrategy::Fail),
+            _ => Err(anyhow::anyhow!("Invalid collision strategy")),
+        }
+    }
+}
+
+/// `ExistingSymlink` defines what happens in symlink modes if the target is already a symlink pointing at the source
+/// file, e.g. when re-running the tool on the same files.
+///
+/// # Variants
+///
+/// * `Skip` - Skip the file quietly, the link is already correct.
+/// * `Replace` - Replace the existing symlink with a new one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ExistingSymlink {
+    #[default]
+    Skip,
+    Replace,
+}
+
+impl FromStr for ExistingSymlink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(ExistingSymlink::Skip),
+            "replace" => Ok(ExistingSymlink::Replace),
+            _ => Err(anyhow::anyhow!("Invalid existing symlink policy")),
+        }
+    }
+}
+
+/// `SourceDuplicates` defines what happens to a source file with the same content as a source file transferred
+/// earlier in the same run, e.g. the same photo synced to two phones. Only files of the same size are hashed.
+///
+/// # Variants
+///
+/// * `Process` - Process the file like any other file, it gets a target of its own.
+/// * `Skip` - Skip the file, it is left untouched.
+/// * `Hardlink` - Create the target of the file as a hardlink to the target of the identical file. In move mode
+///   the source file is deleted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SourceDuplicates {
+    #[default]
+    Process,
+    Skip,
+    Hardlink,
+}
+
+impl FromStr for SourceDuplicates {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "process" => Ok(SourceDuplicates::Process),
+            "skip" => Ok(SourceDuplicates::Skip),
+            "hardlink" => Ok(SourceDuplicates::Hardlink),
+            _ => Err(anyhow::anyhow!("Invalid source duplicates policy")),
+        }
+    }
+}
+
+/// `TargetCase` defines whether target file names that only differ in case (e.g. `IMG_1.JPG` and `IMG_1.jpg`)
+/// collide, as they do on case-insensitive file systems like APFS or NTFS.
+///
+/// # Variants
+///
+/// * `Auto` - Probe the file system of the target directory.
+/// * `Sensitive` - Names that only differ in case are different targets.
+/// * `Insensitive` - Names that only differ in case collide, they are de-duplicated using the duplicate counter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TargetCase {
+    #[default]
+    Auto,
+    Sensitive,
+    Insensitive,
+}
+
+impl FromStr for TargetCase {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(TargetCase::Auto),
+            "sensitive" => Ok(TargetCase::Sensitive),
+            "insensitive" => Ok(TargetCase::Insensitive),
+            _ => Err(anyhow::anyhow!("Invalid target case sensitivity")),
+        }
+    }
+}
+
+/// `Sanitize` defines which characters of generated target names are replaced (see
+/// [`sanitize_component`](name::sanitize_component)), so that the targets can be created on the target file system.
+///
+/// # Variants
+///
+/// * `Auto` - Detect the file system of the target directory (see
+///   [`is_windows_file_system`](action::is_windows_file_system)), Windows rules on Windows file systems (FAT, exFAT,
+///   NTFS), POSIX rules otherwise.
+/// * `Windows` - Replace the characters `<>:"|?*\`, control characters and trailing dots and spaces, and avoid
+///   reserved device names like `CON`.
+/// * `Posix` - Only replace NUL characters.
+/// * `Off` - Keep the names as they are.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Sanitize {
+    #[default]
+    Auto,
+    Windows,
+    Posix,
+    Off,
+}
+
+impl FromStr for Sanitize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Sanitize::Auto),
+            "windows" => Ok(Sanitize::Windows),
+            "posix" => Ok(Sanitize::Posix),
+            "off" => Ok(Sanitize::Off),
+            _ => Err(anyhow::anyhow!("Invalid sanitize mode")),
+        }
+    }
+}
+
+/// `NodateFilter` defines whether files without a derived date are processed.
+///
+/// # Variants
+///
+/// * `Include` - Process files without date (using the nodate file format).
+/// * `Exclude` - Skip files without date.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum NodateFilter {
+    #[default]
+    Include,
+    Exclude,
+}
+
+impl FromStr for NodateFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "include" => Ok(NodateFilter::Include),
+            "exclude" => Ok(NodateFilter::Exclude),
+            _ => Err(anyhow::anyhow!("Invalid nodate filter")),
+        }
+    }
+}
+
+/// `SkipHidden` defines which hidden files and directories are skipped while scanning the source directories.
+/// Unless it is `None`, OS litter (see [`action::LITTER_FILES`] and [`action::LITTER_DIRS`]) is skipped, too.
+///
+/// # Variants
+///
+/// * `None` - Process all files.
+/// * `AppleDouble` - Skip AppleDouble files (`._IMG_1234.jpg`), which macOS creates next to files on foreign file
+///   systems. They share the extension of their file, but contain no image.
+/// * `All` - Skip all files and directories starting with a dot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SkipHidden {
+    None,
+    #[default]
+    AppleDouble,
+    All,
+}
+
+impl SkipHidden {
+    /// Checks if a file or directory is skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The file name.
+    /// * `is_dir` - A boolean that indicates whether the entry is a directory.
+    pub fn is_skipped(&self, name: &OsStr, is_dir: bool) -> bool {
+        let name = name.to_string_lossy();
+        match self {
+            SkipHidden::None => false,
+            SkipHidden::AppleDouble | SkipHidden::All => {
+                let litter = if is_dir {
+                    action::LITTER_DIRS
+                } else {
+                    action::LITTER_FILES
+                };
+                litter.iter().any(|litter| name == *litter)
+                    || name.starts_with("._")
+                    || (*self == SkipHidden::All && name.starts_with('.'))
+            }
+        }
+    }
+}
+
+impl FromStr for SkipHidden {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(SkipHidden::None),
+            "apple-double" | "appledouble" => Ok(SkipHidden::AppleDouble),
+            "all" => Ok(SkipHidden::All),
+            _ => Err(anyhow::anyhow!("Invalid skip hidden policy")),
+        }
+    }
+}
+
+/// `FileOrder` defines the order the files of the source directories are processed in. It decides which file
+/// gets which duplicate counter, so the `Name` and `Mtime` orders assign the same targets on every run and machine.
+///
+/// # Variants
+///
+/// * `Name` - Files are grouped by their directory and sorted in natural order by their file name, see [`sort_files`].
+/// * `Mtime` - Files are sorted by their modification time, files with the same modification time by name.
+/// * `None` - Files are processed in the order the file system lists them, which is the fastest, but may differ
+///   between runs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum FileOrder {
+    #[default]
+    Name,
+    Mtime,
+    None,
+}
+
+impl FromStr for FileOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(FileOrder::Name),
+            "mtime" => Ok(FileOrder::Mtime),
+            "none" => Ok(FileOrder::None),
+            _ => Err(anyhow::anyhow!("Invalid file order")),
+        }
+    }
+}
+
+/// `SniffContent` defines for which files the type is detected from their content (see
+/// [`sniff_extension`](analysis::sniff::sniff_extension)), e.g. for exported files without extension. A file whose
+/// content matches one of the configured extensions is processed as if it had that extension.
+///
+/// # Variants
+///
+/// * `Off` - The type of a file is only derived from its extension.
+/// * `NoExtension` - The type of files without extension is detected from their content.
+/// * `Unmatched` - The type of files without extension, or with none of the configured extensions (nor a sidecar
+///   extension), is detected from their content.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SniffContent {
+    #[default]
+    Off,
+    NoExtension,
+    Unmatched,
+}
+
+impl FromStr for SniffContent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(SniffContent::Off),
+            "no_extension" | "noextension" => Ok(SniffContent::NoExtension),
+            "unmatched" => Ok(SniffContent::Unmatched),
+            _ => Err(anyhow::anyhow!("Invalid sniff content mode")),
+        }
+    }
+}
+
+/// `TakeoutDates` defines whether the capture dates of Google Takeout metadata files (`IMG_1234.jpg.json` or
+/// `IMG_1234.jpg.supplemental-metadata.json`, see [`takeout`](analysis::takeout)) are used. Takeout strips the Exif
+/// data of many files, the dates of the metadata files are in UTC.
+///
+/// # Variants
+///
+/// * `Off` - Metadata files are not read.
+/// * `First` - The date of the metadata file is used instead of the date analysis (`analysis_type`), which is the
+///   fallback for files without metadata file.
+/// * `Fallback` - The date of the metadata file is used for files the date analysis derives no date for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TakeoutDates {
+    #[default]
+    Off,
+    First,
+    Fallback,
+}
+
+impl FromStr for TakeoutDates {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(TakeoutDates::Off),
+            "first" => Ok(TakeoutDates::First),
+            "fallback" => Ok(TakeoutDates::Fallback),
+            _ => Err(anyhow::anyhow!("Invalid takeout dates mode")),
+        }
+    }
+}
+
+/// `DatePriority` defines the priority of the dates of a metadata source (e.g. `metadata_json`) over the date
+/// analysis (`analysis_type`).
+///
+/// # Variants
+///
+/// * `First` - The date of the metadata source is used instead of the date analysis, which is the fallback for files
+///   the metadata source has no date for.
+/// * `Fallback` - The date of the metadata source is used for files the date analysis derives no date for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DatePriority {
+    #[default]
+    First,
+    Fallback,
+}
+
+impl FromStr for DatePriority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "first" => Ok(DatePriority::First),
+            "fallback" => Ok(DatePriority::Fallback),
+            _ => Err(anyhow::anyhow!("Invalid date priority")),
+        }
+    }
+}
+
+/// `LivePhotos` defines how the videos of Live Photos (a HEIC/JPEG photo and a MOV video with the same file stem in
+/// the same directory) are processed. A paired video is moved/copied together with its photo, getting the target name
+/// of the photo (with its date) with its own file type and extension.
+///
+/// # Variants
+///
+/// * `Name` - Photos and videos are paired by their file name.
+/// * `Verify` - Photos and videos are paired by their file name, if the content identifier of the video (QuickTime
+///   metadata) matches the one of the photo (Apple maker note). Requires the `video` feature.
+/// * `Off` - Photos and videos are processed independently.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum LivePhotos {
+    #[default]
+    Name,
+    Verify,
+    Off,
+}
+
+impl FromStr for LivePhotos {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(LivePhotos::Name),
+            "verify" => Ok(LivePhotos::Verify),
+            "off" => Ok(LivePhotos::Off),
+            _ => Err(anyhow::anyhow!("Invalid live photo mode")),
+        }
+    }
+}
+
 /// `AnalyzerSettings` is a struct that holds the settings for an `Analyzer`.
 ///
 /// # Fields
 /// * `analysis_type` - An `AnalysisType` that specifies the type of analysis to perform on a file.
+/// * `agreement_tolerance` - The maximum difference between the Exif date and the name date of a file for `AnalysisType::Both`.
+/// * `time_offset` - A time offset added to every derived date (Exif, name or video metadata), e.g. for a camera clock that was off.
+/// * `round_seconds` - An optional number of seconds the dates in file names are floored to, e.g. 10 or 60 (the minute), so that cameras whose clocks differ by a few seconds name the same moment alike. The dates used for filtering (`since`, `until`) and `set_mtime` are not rounded.
+/// * `lenient_exif` - A boolean that indicates whether files whose Exif data can not be parsed get no date with `AnalysisType::OnlyExif`, instead of failing. IO errors still fail the file.
 /// * `source_dirs` - A vector of `Path` references that represent the source directories to analyze.
+/// * `source_labels` - A map of source directories to their labels, `{source}` is replaced with the label of the source directory of a file (empty if it has none).
 /// * `target_dir` - A `Path` reference that represents the target directory for the analysis results.
+/// * `mirror_dirs` - A vector of additional target directories, every transferred file (and its sidecars) is replicated to the same relative path in each of them. With the move action files are moved to `target_dir` and copied to the mirror directories. Failures of a mirror directory are reported per file and do not fail the file.
+/// * `in_place` - A boolean that indicates whether files are renamed in their own directory instead of the target directory (`target_dir` is not used).
+/// * `force` - A boolean that allows actions other than move, and overwriting existing files, in place.
 /// * `recursive_source` - A boolean that indicates whether to analyze source directories recursively.
+/// * `max_depth` - An optional maximum depth of the files in the source directories, 1 means only immediate children. Takes precedence over `recursive_source`.
 /// * `file_format` - A string that represents the target format of the files to analyze.
 /// * `nodate_file_format` - A string that represent the target format of files with no date.
 /// * `unknown_file_format` - An optional string that represents the target format of files not matching the list of extensions
+/// * `unknown_extensions` - A vector of strings that represent the extensions of the files processed using `unknown_file_format`, `*` matches every extension. Other unknown files are skipped.
+/// * `video_file_format` - An optional string that represents the target format of videos, defaults to `file_format` (only with the `video` feature).
+/// * `video_nodate_file_format` - An optional string that represents the target format of videos with no date, defaults to `nodate_file_format` (only with the `video` feature).
+/// * `near_dup_distance` - An optional maximum Hamming distance of the perceptual hashes of near-duplicate images (see `NearDupReport`), e.g. 10. If set, the photos of a plan are compared and the groups of near-duplicates are reported in the plan (see `Plan::near_duplicates`). Not supported by streaming runs (only with the `perceptual-hash` feature).
+/// * `near_dup_format` - An optional string that represents the target format of near-duplicates except the largest image of each group, e.g. to collect them for review. Requires `near_dup_distance` (only with the `perceptual-hash` feature).
+/// * `nodate_placeholder` - A string that is used instead of the date of files without date (`{date}`), it is removed from the names of files sorted by previous runs.
+/// * `clean_names` - A boolean that indicates whether names are cleaned (see `NameCleaner`): prefixes and nodate placeholders are removed. Otherwise, only the extension is removed.
+/// * `clean_prefixes` - A vector of strings that represent the prefixes removed from names (in upper or lower case), e.g. `IMG`.
 /// * `date_format` - A string that represents the format of the dates in the files to analyze.
+/// * `locale` - A string that represents the locale of month and weekday names in dates (e.g. `de-DE` for `%B`), unknown locales fall back to English with a warning.
 /// * `extensions` - A vector of strings that represent the file extensions to consider during analysis.
 /// * `action_type` - An `ActionMode` that specifies the type of action to perform on a file after analysis.
 /// * `mkdir` - A boolean that indicates whether to create the target directory if it does not exist.
+/// * `reflink_fallback` - A `ReflinkFallback` that specifies what to do if a reflink can not be created.
+/// * `hardlink_fallback` - A `HardlinkFallback` that specifies what to do if a hardlink can not be created.
+/// * `symlink_fallback` - A `SymlinkFallback` that specifies what to do if a symlink can not be created because of missing privileges.
+/// * `collision` - A `CollisionStrategy` that specifies what to do if the target file already exists.
+/// * `target_case` - A `TargetCase` that specifies whether target names that only differ in case collide.
+/// * `sanitize` - A `Sanitize` that specifies which characters of target names are replaced, as they are invalid on the target file system.
+/// * `sanitize_char` - The character invalid characters of target names are replaced with.
+/// * `max_path_warn` - An optional path length (in characters of the absolute path), planned targets at least as long are warned about, e.g. for tools limited by the `MAX_PATH` of Windows.
+/// * `existing_symlink` - An `ExistingSymlink` that specifies what to do (in symlink modes) if the target is already a symlink to the source file.
+/// * `prune_source_duplicates` - A boolean that indicates whether to delete source files (in move mode) if an identical file already exists at the target.
+/// * `source_duplicates` - A `SourceDuplicates` that specifies what to do with source files identical to a source file transferred earlier in the run.
+/// * `verify` - A boolean that indicates whether to verify copied files by comparing checksums before the source is deleted.
+/// * `use_trash` - A boolean that indicates whether to move files to the trash instead of deleting them permanently.
+/// * `sidecar_extensions` - A vector of strings that represent the extensions of sidecar files (e.g. `xmp`), which are processed together with their photo/video.
+/// * `pair_raw_jpeg` - A boolean that indicates whether RAW and JPEG files with the same file stem in the same directory are processed as a pair, getting the same target name.
+/// * `live_photos` - A `LivePhotos` that specifies whether the videos of Live Photos are processed together with their photo.
+/// * `metadata_json` - An optional path of a JSON dump of exiftool (`exiftool -json -r`), the `DateTimeOriginal` or `CreateDate` of the listed files is used as a date source (see `ExiftoolDates`). Files that are not listed are analyzed as usual.
+/// * `metadata_json_priority` - A `DatePriority` that specifies whether the dates of the exiftool JSON are used instead of the date analysis, or only if it derives no date.
+/// * `takeout_dates` - A `TakeoutDates` that specifies whether the dates of Google Takeout metadata files are used, and with which priority. Used metadata files are processed like sidecar files if `json` is a sidecar extension, otherwise they are skipped.
+/// * `sniff_content` - A `SniffContent` that specifies for which files the type is detected from their content instead of their extension.
+/// * `prune_empty_dirs` - A boolean that indicates whether to remove source directories that are empty after a move run.
+/// * `prune_litter` - A boolean that indicates whether to delete OS litter files (e.g. `.DS_Store`) when pruning empty directories.
+/// * `prune_roots` - A boolean that indicates whether to also remove the source directories themselves when pruning empty directories.
+/// * `dedup_target` - A boolean that indicates whether to skip files whose content already exists anywhere in the target directory (in move mode, the source is deleted if `prune_source_duplicates` is set).
+/// * `index_cache` - An optional path of the cache file of the target directory index used by `dedup_target`, defaults to a file in the target directory.
+/// * `bandwidth_limit` - An optional limit of the throughput of file copies in bytes per second, shared by all threads.
+/// * `set_mtime` - A boolean that indicates whether to set the modification time of target files to their derived date (in local time). Files without date and symlinks are left untouched.
+/// * `strip_tags` - A vector of `StripTag` groups of Exif tags that are removed from copied JPEG files (see [`strip::strip_tags`]), e.g. for a folder synced to a shared album. Only allowed with the copy action, the source files stay untouched. Other formats are copied unmodified with a warning.
+/// * `journal` - An optional path of a journal file, all performed actions are appended to it so that they can be reverted.
+/// * `state_file` - An optional path of a state file recording the processed source files (see `ProcessedState`), files unchanged since a previous run are skipped.
+/// * `reset_state` - A boolean that indicates whether to discard the files recorded in the state file.
+/// * `since` - An optional date, files dated before it are skipped.
+/// * `until` - An optional date (inclusive), files dated after it are skipped.
+/// * `nodate_filter` - A `NodateFilter` that specifies whether files without a derived date are processed.
+/// * `skip_hidden` - A `SkipHidden` that specifies which hidden files and directories (and OS litter) are skipped while scanning the source directories.
+/// * `follow_symlinks` - A boolean that indicates whether to follow symlinks in the source directories: symlinked directories are searched, symlinked files are processed (their target is analyzed). Otherwise symlinks are skipped.
+/// * `min_size` - An optional minimum size in bytes, smaller files are skipped while scanning the source directories.
+/// * `max_size` - An optional maximum size in bytes, larger files are skipped while scanning the source directories.
+/// * `exclude` - A vector of gitignore-style glob patterns (see `ExcludePatterns`), matched against paths relative to the source directory. Excluded files and directories are not processed at all. Patterns can also be listed in `.photosortignore` files in the source directories.
+/// * `threads` - An optional number of threads the actions of a run are executed with (see `parse_threads`). Planning is always sequential.
+/// * `order` - A `FileOrder` that specifies the order the files of the source directories are planned (and, without `threads`, processed) in.
+/// * `fail_fast` - A boolean that indicates whether to stop at the first file that can not be planned or processed. Files already being processed by other threads are finished, the remaining files are not processed.
+/// * `file_system` - An optional `FileSystem` the source directories are scanned on and the actions are performed on (e.g. a `MemoryFileSystem` to simulate a run), the local file system if not set. File contents are still analyzed on the local file system.
+///
+/// Use [`AnalyzerSettings::builder`] to create an `Analyzer` with validated settings, or modify the
+/// [`Default`](#impl-Default-for-AnalyzerSettings) settings (the defaults of the command line interface).
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct AnalyzerSettings {
     pub analysis_type: AnalysisType,
+    pub agreement_tolerance: TimeDelta,
+    pub time_offset: TimeDelta,
+    pub round_seconds: Option<u32>,
+    pub lenient_exif: bool,
     pub source_dirs: Vec<PathBuf>,
+    pub source_labels: HashMap<PathBuf, String>,
     pub target_dir: PathBuf,
+    pub mirror_dirs: Vec<PathBuf>,
+    pub in_place: bool,
+    pub force: bool,
     pub recursive_source: bool,
+    pub max_depth: Option<usize>,
     pub file_format: String,
     pub nodate_file_format: String,
     pub unknown_file_format: Option<String>,
+    pub unknown_extensions: Vec<String>,
     pub date_format: String,
+    pub locale: String,
+    pub nodate_placeholder: String,
+    pub clean_names: bool,
+    pub clean_prefixes: Vec<String>,
     pub extensions: Vec<String>,
     #[cfg(feature = "video")]
     pub video_extensions: Vec<String>,
+    #[cfg(feature = "video")]
+    pub video_file_format: Option<String>,
+    #[cfg(feature = "video")]
+    pub video_nodate_file_format: Option<String>,
+    #[cfg(feature = "perceptual-hash")]
+    pub near_dup_distance: Option<u32>,
+    #[cfg(feature = "perceptual-hash")]
+    pub near_dup_format: Option<String>,
     pub action_type: ActionMode,
     pub mkdir: bool,
+    pub reflink_fallback: ReflinkFallback,
+    pub hardlink_fallback: HardlinkFallback,
+    pub symlink_fallback: SymlinkFallback,
+    pub collision: CollisionStrategy,
+    pub target_case: TargetCase,
+    pub sanitize: Sanitize,
+    pub sanitize_char: char,
+    pub max_path_warn: Option<usize>,
+    pub existing_symlink: ExistingSymlink,
+    pub prune_source_duplicates: bool,
+    pub source_duplicates: SourceDuplicates,
+    pub verify: bool,
+    pub use_trash: bool,
+    pub sidecar_extensions: Vec<String>,
+    pub pair_raw_jpeg: bool,
+    pub live_photos: LivePhotos,
+    pub metadata_json: Option<PathBuf>,
+    pub metadata_json_priority: DatePriority,
+    pub takeout_dates: TakeoutDates,
+    pub sniff_content: SniffContent,
+    pub prune_empty_dirs: bool,
+    pub prune_litter: bool,
+    pub prune_roots: bool,
+    pub dedup_target: bool,
+    pub index_cache: Option<PathBuf>,
+    pub bandwidth_limit: Option<u64>,
+    pub set_mtime: bool,
+    pub strip_tags: Vec<StripTag>,
+    pub journal: Option<PathBuf>,
+    pub state_file: Option<PathBuf>,
+    pub reset_state: bool,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+    pub nodate_filter: NodateFilter,
+    pub skip_hidden: SkipHidden,
+    pub follow_symlinks: bool,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub exclude: Vec<String>,
+    pub threads: Option<usize>,
+    pub order: FileOrder,
+    pub fail_fast: bool,
+    pub file_system: Option<SharedFileSystem>,
 }
 
+/// The date, date source, transformed name and `MediaInfo` (if read) derived by the date analysis of a file.
+type DateAnalysis = (Option<NaiveDateTime>, DateSource, String, Option<MediaInfo>);
+
+/// A date and the metadata source it was read from.
+type SourcedDate = (NaiveDateTime, DateSource);
+
 lazy_static! {
     static ref RE_DETECT_NAME_FORMAT_COMMAND: regex::Regex = regex::Regex::new(
         r"\{([^\}]*)\}" // finds { ... } blocks
@@ -106,13 +701,48 @@ lazy_static! {
 /// # Fields
 ///
 /// * `name_transformers` - A list of `NameTransformer` objects that are used to transform the names of files during analysis.
-/// * `name_formatters` - A list of `NameFormatter` objects that are used to generate the new names of files after analysis.
+/// * `name_formatters` - The registry of `NameFormatter` objects that are used to generate the new names of files after analysis.
 /// * `settings` - An `AnalyzerSettings` object that holds the settings for the `Analyzer`.
+/// * `journal` - The opened journal file, if a journal is configured in the settings.
+/// * `state` - The opened state file, if a state file is configured in the settings.
+/// * `copy_progress` - A callback receiving the progress of copied files, see [`set_copy_progress`](#method.set_copy_progress).
+/// * `target_index` - The index of the target directory, built on first use if `dedup_target` is set.
+/// * `bandwidth_limiter` - The shared limiter of the copy throughput, if a bandwidth limit is configured in the settings.
+/// * `symlink_privilege` - Whether the privilege to create symlinks turned out to be missing, shared by all files of the runs.
+/// * `observer` - Receives the events of runs, see [`set_observer`](#method.set_observer).
+/// * `progress` - Receives the progress of runs, see [`set_progress`](#method.set_progress).
+/// * `exclude` - The compiled exclude patterns of the settings.
+/// * `near_duplicates` - The near-duplicates of the current plan that are sorted with the near-duplicate format (see `near_dup_format`).
+/// * `exiftool_dates` - The dates of the exiftool JSON, if one is configured in the settings.
+/// * `reserved` - The targets computed by [`run_file`](#method.run_file), so that concurrent (and dry-run) calls never compute the same target.
+/// * `main_file_stems` - The stems of the photos and videos per directory of a sidecar file, read once per directory to find the main files of sidecar files.
 pub struct Analyzer {
     name_transformers:
         Vec<Box<dyn analysis::filename2date::FileNameToDateTransformer + Send + Sync>>,
-    name_formatters: Vec<Box<dyn analysis::name_formatters::NameFormatter + Send + Sync>>,
+    name_formatters: FormatterRegistry,
     pub settings: AnalyzerSettings,
+    journal: Option<Arc<Journal>>,
+    state: Option<ProcessedState>,
+    copy_progress: Option<CopyProgress>,
+    bandwidth_limiter: Option<BandwidthLimiter>,
+    symlink_privilege: SymlinkPrivilege,
+    target_index: OnceLock<Option<TargetIndex>>,
+    observer: Option<Arc<dyn RunObserver + Send + Sync>>,
+    progress: Option<Arc<dyn Progress + Send + Sync>>,
+    exclude: ExcludePatterns,
+    photo_extensions: ExtensionPatterns,
+    #[cfg(feature = "video")]
+    video_extensions: ExtensionPatterns,
+    exiftool_dates: Option<ExiftoolDates>,
+    #[cfg(feature = "perceptual-hash")]
+    near_duplicates: Mutex<HashSet<PathBuf>>,
+    reserved: Mutex<Reservations>,
+    main_file_stems: Mutex<HashMap<PathBuf, HashSet<OsString>>>,
+    case_insensitive_target: bool,
+    scan_target_case: bool,
+    sanitize: Sanitize,
+    name_cleaner: NameCleaner,
+    locale: &'static DateLocale,
 }
 
 /// Implementation of methods for the `Analyzer` struct.
@@ -128,7 +758,10 @@ pub struct Analyzer {
 /// * [`do_file_action`](#method.do_file_action) - Performs the file action specified in the `Analyzer`'s settings on a file.
 /// * [`is_valid_extension`](#method.is_valid_extension) - Checks if a file has a valid extension.
 /// * [`rename_files_in_folder`](#method.rename_files_in_folder) - Renames files in a folder based on the `Analyzer`'s settings.
-/// * [`run`](#method.run) - Runs the `Analyzer`, renaming files in the source directories based on the `Analyzer`'s settings.
+/// * [`plan`](#method.plan) - Plans the actions for all files in the source directories without touching the file system.
+/// * [`plan_files`](#method.plan_files) - Plans the actions for a list of files without touching the file system.
+/// * [`run`](#method.run) - Runs the `Analyzer`, executing a previously computed plan.
+/// * [`execute`](#method.execute) - Executes a single planned action.
 impl Analyzer {
     /// Creates a new `Analyzer` with the given settings.
     ///
@@ -138,32 +771,191 @@ impl Analyzer {
     ///
     /// # Returns
     ///
-    /// * `Result<Analyzer>` - Returns `Ok(Analyzer)` if the `Analyzer` could be created successfully, `Err(anyhow::Error)` otherwise.
+    /// * `Result<Analyzer, Error>` - Returns `Ok(Analyzer)` if the `Analyzer` could be created successfully, `Err(Error)` otherwise.
     ///
     /// # Errors
     ///
-    /// * If the target directory does not exist.
-    /// * If a source directory does not exist.
-    /// * If an error occurs while getting the standard name transformers.
-    pub fn new(settings: AnalyzerSettings) -> Result<Analyzer> {
-        let analyzer = Analyzer {
+    /// * `Error::DirectoryNotFound` if a source directory does not exist, or the target directory does not exist and
+    ///   `mkdir` is not set. Otherwise, a missing target directory is created (in dry-run mode, it is only printed).
+    /// * `Error::InvalidSettings` if the target directory is inside a source directory, at a depth the source
+    ///   directory is scanned to.
+    /// * `Error::Io` if the target directory can not be created.
+    /// * `Error::InvalidSettings` if an exclude pattern is not a valid glob.
+    /// * If the journal file can not be opened.
+    /// * If the state file can not be written.
+    /// * If the exiftool JSON can not be read.
+    pub fn new(settings: AnalyzerSettings) -> Result<Analyzer, Error> {
+        let journal = match (&settings.journal, &settings.action_type) {
+            (Some(path), ActionMode::Execute(_)) => Some(Arc::new(Journal::open(path)?)),
+            _ => None,
+        };
+        let state = match &settings.state_file {
+            Some(path) => Some(ProcessedState::open(
+                path,
+                settings.reset_state,
+                matches!(settings.action_type, ActionMode::Execute(_)),
+            )?),
+            None => None,
+        };
+        let exiftool_dates = settings
+            .metadata_json
+            .as_ref()
+            .map(ExiftoolDates::load)
+            .transpose()?;
+        let bandwidth_limiter = settings.bandwidth_limit.map(BandwidthLimiter::new);
+        let exclude = ExcludePatterns::new(&settings.exclude)?;
+        let name_cleaner = NameCleaner::new(&settings.nodate_placeholder, &settings.clean_prefixes)
+            .map_err(|err| {
+                Error::InvalidSettings(format!(
+                    "Invalid nodate placeholder or clean prefixes: {}",
+                    err
+                ))
+            })?;
+        let locale = DateLocale::from_tag(&settings.locale).unwrap_or_else(|| {
+            warn!(
+                "Unknown locale {:?}, dates are formatted in English",
+                settings.locale
+            );
+            &locale::ENGLISH
+        });
+        let photo_extensions = ExtensionPatterns::new(&settings.extensions)?;
+        #[cfg(feature = "video")]
+        let video_extensions = ExtensionPatterns::new(&settings.video_extensions)?;
+        let mut analyzer = Analyzer {
             name_transformers: Vec::default(),
-            name_formatters: Vec::default(),
+            name_formatters: FormatterRegistry::default(),
             settings,
+            journal,
+            state,
+            copy_progress: None,
+            bandwidth_limiter,
+            symlink_privilege: SymlinkPrivilege::new(),
+            target_index: OnceLock::new(),
+            observer: None,
+            progress: None,
+            exclude,
+            photo_extensions,
+            #[cfg(feature = "video")]
+            video_extensions,
+            exiftool_dates,
+            #[cfg(feature = "perceptual-hash")]
+            near_duplicates: Mutex::new(HashSet::new()),
+            reserved: Mutex::new(Reservations::default()),
+            main_file_stems: Mutex::new(HashMap::new()),
+            case_insensitive_target: false,
+            scan_target_case: false,
+            sanitize: Sanitize::Off,
+            name_cleaner,
+            locale,
         };
 
-        if !analyzer.settings.target_dir.exists() {
-            return Err(anyhow!("Target directory does not exist"));
-        }
+        let file_system = analyzer.settings.file_system();
         for source in &analyzer.settings.source_dirs {
-            if !source.exists() {
-                return Err(anyhow!("Source directory {:?} does not exist", source));
+            if !file_system.exists(source) {
+                return Err(Error::DirectoryNotFound(source.clone()));
+            }
+        }
+        // in place, the files stay in their directories, there is no target directory
+        let target_dir = match analyzer.settings.source_dirs.first() {
+            Some(source) if analyzer.settings.in_place => source,
+            _ => &analyzer.settings.target_dir,
+        };
+        if !analyzer.settings.in_place {
+            analyzer.check_target_not_in_source()?;
+        }
+        if !file_system.exists(target_dir) {
+            if !analyzer.settings.mkdir {
+                return Err(Error::DirectoryNotFound(target_dir.clone()));
+            }
+            match analyzer.settings.action_type {
+                ActionMode::Execute(_) => {
+                    info!("Creating target directory {:?}", target_dir);
+                    file_system
+                        .create_dir_all(target_dir)
+                        .map_err(|err| Error::io(target_dir, err))?;
+                }
+                ActionMode::DryRun(_) => {
+                    info!("[Mkdir] {:?}", target_dir);
+                }
+            }
+        }
+
+        for mirror in &analyzer.settings.mirror_dirs {
+            if file_system.exists(mirror) {
+                continue;
+            }
+            if !analyzer.settings.mkdir {
+                return Err(Error::DirectoryNotFound(mirror.clone()));
+            }
+            match analyzer.settings.action_type {
+                ActionMode::Execute(_) => {
+                    info!("Creating mirror directory {:?}", mirror);
+                    file_system
+                        .create_dir_all(mirror)
+                        .map_err(|err| Error::io(mirror, err))?;
+                }
+                ActionMode::DryRun(_) => {
+                    info!("[Mkdir] {:?}", mirror);
+                }
             }
         }
 
+        let file_system_ignores_case = action::is_case_insensitive_dir(target_dir);
+        analyzer.case_insensitive_target = match analyzer.settings.target_case {
+            TargetCase::Auto => file_system_ignores_case,
+            TargetCase::Sensitive => false,
+            TargetCase::Insensitive => true,
+        };
+        // the file system only finds names that differ in case if it ignores case itself
+        analyzer.scan_target_case = analyzer.case_insensitive_target && !file_system_ignores_case;
+        if analyzer.case_insensitive_target {
+            debug!("Target file names that only differ in case collide");
+        }
+        analyzer.reserved = Mutex::new(Reservations::new(analyzer.case_insensitive_target));
+
+        analyzer.sanitize = match analyzer.settings.sanitize {
+            Sanitize::Auto if action::is_windows_file_system(target_dir) => Sanitize::Windows,
+            Sanitize::Auto => Sanitize::Posix,
+            sanitize => sanitize,
+        };
+        debug!(
+            "Sanitizing target names using {:?} rules",
+            analyzer.sanitize
+        );
+
         Ok(analyzer)
     }
 
+    /// Checks that the target directory is not inside a source directory, at a depth the source directory is
+    /// scanned to. Otherwise, sorted files would be found and processed again.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `Error::InvalidSettings` if the target directory is inside a source directory.
+    fn check_target_not_in_source(&self) -> Result<(), Error> {
+        let max_depth = match self.settings.max_depth {
+            Some(max_depth) => Some(max_depth),
+            None if self.settings.recursive_source => None,
+            None => Some(1),
+        };
+        let target = absolute_path(&self.settings.target_dir);
+        for source in &self.settings.source_dirs {
+            let source = absolute_path(source);
+            let Ok(relative) = target.strip_prefix(&source) else {
+                continue;
+            };
+            // the files of the target directory are found at this depth
+            let depth = relative.components().count() + 1;
+            if depth > 1 && max_depth.is_none_or(|max_depth| depth <= max_depth) {
+                return Err(Error::InvalidSettings(format!(
+                    "The target directory {:?} is inside the source directory {:?}, sorted files would be processed again",
+                    self.settings.target_dir, source
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Adds a name transformer to the `Analyzer`.
     ///
     /// # Arguments
@@ -185,50 +977,198 @@ impl Analyzer {
         &mut self,
         formatter: T,
     ) {
-        self.name_formatters.push(Box::new(formatter));
-    }
-
-    fn analyze_name(&self, name: &str) -> Result<(Option<NaiveDateTime>, String)> {
-        let result = analysis::get_name_time(name, &self.name_transformers)?;
-        match result {
-            Some((time, name)) => Ok((Some(time), name)),
-            None => Ok((None, name.to_string())),
-        }
+        self.name_formatters.register(Box::new(formatter));
     }
 
-    fn analyze_photo_exif(&self, file: &File) -> Result<Option<NaiveDateTime>> {
-        let exif_time = analysis::exif2date::get_exif_time(file)?;
-        Ok(exif_time)
+    /// Adds the standard name formatters (`{name}`, `{dup}`, `{date}`, ...) after the formatters added so far, e.g.
+    /// for an `Analyzer` created with [`new`](#method.new) instead of [`AnalyzerSettings::builder`].
+    pub fn with_default_formatters(mut self) -> Self {
+        self.add_formatter(FormatName::default());
+        self.add_formatter(FormatDuplicate::default());
+        self.add_formatter(FormatDate::default());
+        self.add_formatter(FormatFileType::default());
+        self.add_formatter(FormatExtension::default());
+        self.add_formatter(FormatDuration::default());
+        self.add_formatter(FormatVerticalResolution::default());
+        self.add_formatter(FormatRelativePath::default());
+        self.add_formatter(FormatSource::default());
+        self
     }
 
-    #[cfg(feature = "video")]
-    fn analyze_video_metadata(&self, path: &PathBuf) -> Result<Option<NaiveDateTime>> {
-        let video_time = analysis::video2date::get_video_time(path)?;
-        Ok(video_time)
+    /// The registered name formatters, e.g. to list the available format commands.
+    pub fn formatters(&self) -> &FormatterRegistry {
+        &self.name_formatters
     }
 
-    fn analyze_exif(&self, path: &PathBuf) -> Result<Option<NaiveDateTime>> {
-        #[cfg(feature = "video")]
-        let video = self.is_valid_video_extension(path.extension())?;
-        let photo = self.is_valid_photo_extension(path.extension())?;
+    /// Checks a format string: the braces must be balanced and every command (e.g. `{date}`) must be handled by a
+    /// registered name formatter, which accepts its argument (e.g. the date format of `{date?%Y}`). The commands are
+    /// formatted with a sample file to find invalid arguments.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `Error::FormatString` listing all problems of the format string.
+    pub fn validate_format(&self, format_string: &str) -> Result<(), Error> {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).and_then(|date| date.and_hms_opt(0, 0, 0));
+        let date_string =
+            match analysis::name_formatters::check_date_format(&self.settings.date_format) {
+                Ok(()) => date.map(|date| self.locale.format(&date, &self.settings.date_format)),
+                Err(_) => None,
+            }
+            .unwrap_or_default();
+        let sample = NameFormatterInvocationInfo {
+            date: &date,
+            date_string: &date_string,
+            date_default_format: &self.settings.date_format,
+            nodate_placeholder: &self.settings.nodate_placeholder,
+            locale: self.locale,
+            file_type: &FileType::Image,
+            cleaned_name: "name",
+            duplicate_counter: Some(1),
+            extension: "jpg".to_string(),
+            duration: Some(std::time::Duration::from_secs(1)),
+            vertical_resolution: Some(1080),
+            relative_dir: "dir".to_string(),
+            source_label: "source",
+        };
 
-        #[cfg(feature = "video")]
-        {
-            if video && photo {
-                return Err(anyhow::anyhow!("File has both photo and video extensions. Do not include the same extension in both settings"));
+        let mut problems = Vec::new();
+        let mut unknown_commands = false;
+        let mut literal_start = 0;
+        fn check_literal(literal: &str, problems: &mut Vec<String>) {
+            if literal.contains('{') {
+                problems.push("unclosed \"{\"".to_string());
+            }
+            if literal.contains('}') {
+                problems.push("unmatched \"}\"".to_string());
             }
         }
 
-        if photo {
-            let file = File::open(path)?;
-            return self.analyze_photo_exif(&file);
+        for capture in RE_DETECT_NAME_FORMAT_COMMAND.captures_iter(format_string) {
+            let match_all = capture.get(0).expect("Capture group 0 should always exist");
+            check_literal(
+                &format_string[literal_start..match_all.start()],
+                &mut problems,
+            );
+            literal_start = match_all.end();
+
+            let inner_command_string = capture
+                .get(1)
+                .expect("Capture group 1 should always exist")
+                .as_str();
+            if inner_command_string.contains('{') {
+                problems.push(format!("unclosed \"{{\" in {{{}}}", inner_command_string));
+                continue;
+            }
+            let command = RE_COMMAND_SPLIT
+                .captures(inner_command_string)
+                .and_then(|captures| captures.get(3))
+                .map(|x| x.as_str())
+                .unwrap_or("");
+
+            match self.name_formatters.find(command) {
+                None => {
+                    problems.push(self.name_formatters.unknown_command(command));
+                    unknown_commands = true;
+                }
+                Some((formatter, captures)) => {
+                    if let Err(err) = formatter.replacement_text(captures, &sample) {
+                        problems.push(format!("invalid command {{{}}}: {}", command, err));
+                    }
+                }
+            }
         }
-        #[cfg(feature = "video")]
-        if video {
-            return self.analyze_video_metadata(path);
+        check_literal(&format_string[literal_start..], &mut problems);
+        if unknown_commands {
+            problems.push(self.name_formatters.available_commands());
         }
 
-        Err(anyhow::anyhow!("File extension is not valid"))
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::FormatString {
+                format: format_string.to_string(),
+                problems,
+            })
+        }
+    }
+
+    /// Sets a callback that receives the progress of copied files (also when moving across file systems), e.g. to
+    /// display the progress of large files. While a callback is set, files are copied in chunks.
+    ///
+    /// # Arguments
+    /// * `callback` - A function receiving the source file, the number of bytes copied so far and the total number of bytes.
+    pub fn set_copy_progress<F: Fn(&Path, u64, u64) + Send + Sync + 'static>(
+        &mut self,
+        callback: F,
+    ) {
+        self.copy_progress = Some(CopyProgress::new(callback));
+    }
+
+    /// Sets an observer that receives the progress of runs: it is notified when a file is started, done (including
+    /// skipped files) or failed. It is called from the thread executing the action.
+    ///
+    /// # Arguments
+    /// * `observer` - The observer.
+    pub fn set_observer(&mut self, observer: Arc<dyn RunObserver + Send + Sync>) {
+        self.observer = Some(observer);
+    }
+
+    /// Sets a progress reporter for runs: it is told the number of files of a run, advanced after every file and
+    /// finished at the end of the run. It is called from the thread executing the run.
+    ///
+    /// # Arguments
+    /// * `progress` - The progress reporter.
+    pub fn set_progress(&mut self, progress: Arc<dyn Progress + Send + Sync>) {
+        self.progress = Some(progress);
+    }
+
+    fn analyze_name(&self, name: &str) -> Result<(Option<NaiveDateTime>, String)> {
+        let result = analysis::get_name_time(name, &self.name_transformers)?;
+        match result {
+            Some((time, name)) => Ok((Some(time), name)),
+            None => Ok((None, name.to_string())),
+        }
+    }
+
+    fn analyze_photo_exif(&self, file: &File) -> Result<Option<NaiveDateTime>> {
+        let exif_time = analysis::exif2date::get_exif_time(file)?;
+        Ok(exif_time)
+    }
+
+    #[cfg(feature = "video")]
+    fn analyze_video_metadata(&self, path: &PathBuf) -> Result<(Option<NaiveDateTime>, MediaInfo)> {
+        let (video_time, media_info) = analysis::video2date::get_video_metadata(path)?;
+        Ok((video_time, media_info))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(path = %path.display()))
+    )]
+    fn analyze_exif(&self, path: &PathBuf) -> Result<(Option<NaiveDateTime>, Option<MediaInfo>)> {
+        let extension = self.file_extension(path);
+        #[cfg(feature = "video")]
+        let video = self.is_valid_video_extension(extension.as_deref())?;
+        let photo = self.is_valid_photo_extension(extension.as_deref())?;
+
+        #[cfg(feature = "video")]
+        {
+            if video && photo {
+                return Err(anyhow::anyhow!("File has both photo and video extensions. Do not include the same extension in both settings"));
+            }
+        }
+
+        if photo {
+            let file = File::open(path)?;
+            return Ok((self.analyze_photo_exif(&file)?, None));
+        }
+        #[cfg(feature = "video")]
+        if video {
+            let (video_time, media_info) = self.analyze_video_metadata(path)?;
+            return Ok((video_time, Some(media_info)));
+        }
+
+        Err(anyhow::anyhow!("File extension is not valid"))
     }
 
     /// Analyzes a file for a date based on the `Analyzer`'s settings.
@@ -243,72 +1183,294 @@ impl Analyzer {
     ///
     /// # Errors
     /// This function will return an error if:
-    /// * The file name cannot be retrieved or is invalid.
-    /// * The file cannot be opened.
-    /// * An error occurs during the analysis of the file's Exif data or name.
-    pub fn analyze(&self, path: &PathBuf) -> Result<(Option<NaiveDateTime>, String)> {
+    /// * The path has no file name (`Error::InvalidFileName`).
+    /// * The file does not have one of the configured extensions (`Error::InvalidExtension`).
+    /// * An error occurs during the analysis of the file's Exif data with `AnalysisType::OnlyExif` (`Error::ExifParse`) or name.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(path = %path.display()))
+    )]
+    pub fn analyze(&self, path: &PathBuf) -> Result<(Option<NaiveDateTime>, String), Error> {
+        let analysis = self.analyze_file(path)?;
+        Ok((analysis.date, analysis.name))
+    }
+
+    /// Analyzes a file for a date based on the `Analyzer`'s settings, see [`analyze`](#method.analyze).
+    /// Additionally, returns where the date was derived from and the `MediaInfo` (duration, resolution) of video files.
+    ///
+    /// # Arguments
+    /// * `path` - A `PathBuf` that represents the path of the file to analyze.
+    ///
+    /// # Returns
+    /// * `Result<FileAnalysis>` - The date, date source and transformed name like [`analyze`](#method.analyze)
+    ///   plus the `MediaInfo` of the file. For images, the `MediaInfo` is empty.
+    ///
+    /// # Errors
+    /// See [`analyze`](#method.analyze).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(path = %path.display(), date_source = tracing::field::Empty))
+    )]
+    pub fn analyze_file(&self, path: &PathBuf) -> Result<FileAnalysis, Error> {
+        // names that are not valid UTF-8 are analyzed in their lossy form, the file keeps its original name
         let name = path
             .file_name()
-            .ok_or(anyhow::anyhow!("No file name"))?
-            .to_str()
-            .ok_or(anyhow::anyhow!("Invalid file name"))?;
+            .ok_or_else(|| Error::InvalidFileName(path.clone()))?
+            .to_string_lossy();
+        let name = name.as_ref();
 
         let valid_extension = self
-            .is_valid_extension(path.extension())
+            .is_valid_extension(self.file_extension(path).as_deref())
             .unwrap_or_else(|err| {
                 warn!("Error checking file extension: {}", err);
                 false
             });
         if !valid_extension {
             warn!("Skipping file with invalid extension: {:?}", path);
-            return Err(anyhow::anyhow!("Invalid file extension"));
+            return Err(Error::InvalidExtension(path.clone()));
         }
 
+        // the dates of metadata files either replace the analyzed date, or are used if the analysis derives none
+        let (first, fallback) = self.metadata_dates(path);
+        let (date, date_source, name, media_info) =
+            match (self.analyze_date(path, name), first, fallback) {
+                (Ok((_, _, name, media_info)), Some((date, date_source)), _)
+                | (Ok((None, _, name, media_info)), None, Some((date, date_source))) => {
+                    (Some(date), date_source, name, media_info)
+                }
+                (Ok(analysis), _, _) => analysis,
+                // e.g. a file without Exif data with `AnalysisType::OnlyExif`
+                (Err(err), Some((date, date_source)), _)
+                | (Err(err), None, Some((date, date_source))) => {
+                    debug!(
+                        "Using the {} date, the date analysis failed: {}",
+                        date_source, err
+                    );
+                    let name = self
+                        .analyze_name(name)
+                        .map(|(_, name)| name)
+                        .unwrap_or_else(|_| name.to_string());
+                    (Some(date), date_source, name, None)
+                }
+                (Err(err), None, None) => return Err(err),
+            };
+
+        // e.g. for a camera clock that was off, applied to dates from all sources
+        let date = date.map(|date| {
+            date.checked_add_signed(self.settings.time_offset)
+                .unwrap_or_else(|| {
+                    warn!(
+                        "Time offset is out of range for the date {} of {:?}",
+                        date, path
+                    );
+                    date
+                })
+        });
+
+        // the container of videos might not have been opened during the date analysis
+        #[cfg(feature = "video")]
+        let media_info = match media_info {
+            None if self.is_valid_video_extension(self.file_extension(path).as_deref())? => {
+                match self.analyze_video_metadata(path) {
+                    Ok((_, media_info)) => Some(media_info),
+                    Err(err) => {
+                        warn!("Error reading video metadata: {} for {:?}", err, path);
+                        None
+                    }
+                }
+            }
+            media_info => media_info,
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("date_source", tracing::field::display(date_source));
+        Ok(FileAnalysis {
+            date,
+            date_source,
+            name,
+            media_info: media_info.unwrap_or_default(),
+        })
+    }
+
+    /// Derives the date of a file from its Exif data and/or name, depending on the analysis type.
+    ///
+    /// # Returns
+    /// * `Result<DateAnalysis>` - The date, its source, the transformed name and the `MediaInfo` of videos whose
+    ///   metadata was read.
+    ///
+    /// # Errors
+    /// See [`analyze`](#method.analyze).
+    fn analyze_date(&self, path: &PathBuf, name: &str) -> Result<DateAnalysis, Error> {
+        let exif_source = |date: &Option<NaiveDateTime>| match date {
+            Some(_) => DateSource::Exif,
+            None => DateSource::None,
+        };
+        let name_source = |date: &Option<NaiveDateTime>| match date {
+            Some(_) => DateSource::Name,
+            None => DateSource::None,
+        };
+
         Ok(match self.settings.analysis_type {
             AnalysisType::OnlyExif => {
-                let exif_result = self
-                    .analyze_exif(path)
-                    .map_err(|e| anyhow!("Error analyzing Exif data: {}", e))?;
+                let (exif_result, media_info) = match self.analyze_exif(path) {
+                    Err(e) if self.settings.lenient_exif => match exif_io_error(e) {
+                        Ok(source) => {
+                            return Err(Error::Io {
+                                path: path.clone(),
+                                source,
+                            })
+                        }
+                        Err(e) => {
+                            warn!("Error analyzing Exif data: {} for {:?}", e, path);
+                            (None, None)
+                        }
+                    },
+                    result => result.map_err(|e| Error::ExifParse {
+                        path: path.clone(),
+                        source: e.into(),
+                    })?,
+                };
                 let name_result = self.analyze_name(name);
+                let date_source = exif_source(&exif_result);
 
                 match name_result {
-                    Ok((_, name)) => (exif_result, name),
-                    Err(_err) => (exif_result, name.to_string()),
+                    Ok((_, name)) => (exif_result, date_source, name, media_info),
+                    Err(_err) => (exif_result, date_source, name.to_string(), media_info),
                 }
             }
-            AnalysisType::OnlyName => self.analyze_name(name)?,
+            AnalysisType::OnlyName => {
+                let (date, name) = self.analyze_name(name)?;
+                (date, name_source(&date), name, None)
+            }
             AnalysisType::ExifThenName => {
                 let exif_result = self.analyze_exif(path);
-                let exif_result = match exif_result {
+                let (exif_result, media_info) = match exif_result {
                     Err(e) => {
                         warn!("Error analyzing Exif data: {} for {:?}", e, path);
                         info!("Falling back to name analysis");
-                        None
+                        (None, None)
                     }
-                    Ok(date) => date,
+                    Ok(result) => result,
                 };
                 let name_result = self.analyze_name(name);
 
                 match exif_result {
                     Some(date) => match name_result {
-                        Ok((_, name)) => (Some(date), name),
-                        Err(_err) => (Some(date), name.to_string()),
+                        Ok((_, name)) => (Some(date), DateSource::Exif, name, media_info),
+                        Err(_err) => (Some(date), DateSource::Exif, name.to_string(), media_info),
                     },
-                    None => name_result?,
+                    None => {
+                        let (date, name) = name_result?;
+                        (date, name_source(&date), name, media_info)
+                    }
                 }
             }
             AnalysisType::NameThenExif => {
                 let name_result = self.analyze_name(name)?;
                 if name_result.0.is_none() {
-                    (self.analyze_exif(path)?, name_result.1)
+                    let (exif_result, media_info) = match self.analyze_exif(path) {
+                        Err(e) => {
+                            warn!("Error analyzing Exif data: {} for {:?}", e, path);
+                            info!("No date found in the name, continuing without date");
+                            (None, None)
+                        }
+                        Ok(result) => result,
+                    };
+                    let date_source = exif_source(&exif_result);
+                    (exif_result, date_source, name_result.1, media_info)
                 } else {
-                    name_result
+                    (name_result.0, DateSource::Name, name_result.1, None)
+                }
+            }
+            AnalysisType::Both => {
+                let (exif_result, media_info) = match self.analyze_exif(path) {
+                    Err(e) => {
+                        warn!("Error analyzing Exif data: {} for {:?}", e, path);
+                        (None, None)
+                    }
+                    Ok(result) => result,
+                };
+                let (name_date, name) = self.analyze_name(name)?;
+
+                match (exif_result, name_date) {
+                    (Some(exif_date), Some(name_date))
+                        if (exif_date - name_date).abs() <= self.settings.agreement_tolerance =>
+                    {
+                        (Some(exif_date), DateSource::Exif, name, media_info)
+                    }
+                    (exif_date, name_date) => {
+                        info!(
+                            "Exif date {:?} and name date {:?} do not agree for {:?}",
+                            exif_date, name_date, path
+                        );
+                        (None, DateSource::None, name, media_info)
+                    }
                 }
             }
         })
     }
 
+    /// The dates of a file from metadata files: the exiftool JSON (see `metadata_json`) and the Google Takeout
+    /// metadata file (see `takeout_dates`). The exiftool JSON takes precedence over Takeout metadata files of the
+    /// same priority.
+    ///
+    /// # Returns
+    ///
+    /// * `(Option<SourcedDate>, Option<SourcedDate>)` - The date used instead of the date analysis, and the date used
+    ///   if the analysis derives no date.
+    fn metadata_dates(&self, path: &Path) -> (Option<SourcedDate>, Option<SourcedDate>) {
+        let exiftool = self
+            .exiftool_dates
+            .as_ref()
+            .and_then(|dates| dates.date(path))
+            .map(|date| (date, DateSource::Exiftool));
+        let takeout = match self.settings.takeout_dates {
+            TakeoutDates::Off => None,
+            TakeoutDates::First | TakeoutDates::Fallback => self
+                .takeout_date(path)
+                .map(|date| (date, DateSource::Takeout)),
+        };
+
+        let mut first = None;
+        let mut fallback = None;
+        if self.settings.takeout_dates == TakeoutDates::First {
+            first = takeout;
+        } else {
+            fallback = takeout;
+        }
+        match self.settings.metadata_json_priority {
+            DatePriority::First => first = exiftool.or(first),
+            DatePriority::Fallback => fallback = exiftool.or(fallback),
+        }
+        (first, fallback)
+    }
+
+    /// Reads the date of the Google Takeout metadata file of a file (see `takeout_dates`), errors are logged.
+    fn takeout_date(&self, path: &Path) -> Option<NaiveDateTime> {
+        let metadata = analysis::takeout::find_metadata(path)?;
+        match analysis::takeout::read_taken_time(&metadata) {
+            Ok(date) => {
+                debug!(
+                    "Read date {} from Takeout metadata file {:?}",
+                    date, metadata
+                );
+                Some(date)
+            }
+            Err(err) => {
+                warn!(
+                    "Error reading Takeout metadata file {:?}: {}",
+                    metadata, err
+                );
+                None
+            }
+        }
+    }
+
     /// Replaces {name}, {date}, ... in a format with actual values
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(format = format_string))
+    )]
     fn replace_filepath_parts<'a, 'b>(
         &self,
         format_string: &'b str,
@@ -340,7 +1502,7 @@ impl Analyzer {
 
             if start > current_string_index {
                 final_string.push(FormatString::Literal(
-                    format_string[current_string_index..start].to_string(),
+                    format_string[current_string_index..start].replace("\\", ""),
                 ));
             }
 
@@ -367,40 +1529,39 @@ impl Analyzer {
                 .map(|x| x.as_str())
                 .unwrap_or("");
 
-            let mut found_command = false;
-
-            for formatter in &self.name_formatters {
-                if let Some(matched) = formatter.argument_template().captures(actual_command) {
-                    let mut command_substitution = match formatter.replacement_text(matched, info) {
-                        Ok(replaced_text) => replaced_text,
-                        Err(err) => {
-                            return Err(anyhow!("Failed to format the file name with the given format string: {:?}. Got error: {{{}}}", actual_command, err));
-                        }
-                    };
-
-                    if !command_substitution.is_empty() && !command_modifier.is_empty() {
-                        // prefix_substitution
-                        command_substitution =
-                            format!("{}{}", command_modifier, command_substitution);
-                    }
-                    found_command = true;
-                    final_string.push(FormatString::Command(
-                        inner_command_string,
-                        command_substitution,
-                    ));
-                    break;
+            let Some((formatter, matched)) = self.name_formatters.find(actual_command) else {
+                return Err(anyhow!(
+                    "Failed to format file name with the given format string: {} ({})",
+                    self.name_formatters.unknown_command(actual_command),
+                    self.name_formatters.available_commands()
+                ));
+            };
+            let mut command_substitution = match formatter.replacement_text(matched, info) {
+                Ok(replaced_text) => replaced_text,
+                Err(err) => {
+                    return Err(anyhow!("Failed to format the file name with the given format string: {:?}. Got error: {{{}}}", actual_command, err));
                 }
+            };
+            if formatter.spans_directories() {
+                command_substitution = command_substitution.replace("\\", "");
+            } else {
+                command_substitution = command_substitution.replace("/", "").replace("\\", "");
             }
 
-            if !found_command {
-                return Err(anyhow!("Failed to format file name with the given format string. There exists no formatter for the format command: {{{}}}", actual_command));
+            if !command_substitution.is_empty() && !command_modifier.is_empty() {
+                // prefix_substitution
+                command_substitution = format!("{}{}", command_modifier, command_substitution);
             }
+            final_string.push(FormatString::Command(
+                inner_command_string,
+                command_substitution,
+            ));
 
             current_string_index = end;
         }
         if format_string.len() > current_string_index {
             final_string.push(FormatString::Literal(
-                format_string[current_string_index..].to_string(),
+                format_string[current_string_index..].replace("\\", ""),
             ));
         }
 
@@ -419,7 +1580,9 @@ impl Analyzer {
             .join(""))
     }
 
-    /// Performs the file action specified in the `Analyzer`'s settings on a file.
+    /// Performs the file action specified in the `Analyzer`'s settings on a file. The file is planned on its own
+    /// (see [`plan_files`](#method.plan_files)) and executed immediately. It can be called from multiple threads:
+    /// targets are reserved while they are computed, so two sources never get the same (deduplicated) target.
     ///
     /// # Arguments
     ///
@@ -427,199 +1590,2409 @@ impl Analyzer {
     ///
     /// # Returns
     ///
-    /// * `Result<()>` - Returns `Ok(())` if the file action could be performed successfully, `Err(anyhow::Error)` otherwise.
+    /// * `Result<FileOutcome, Error>` - Returns what was done with the file if the file action could be performed
+    ///   successfully (or the file was skipped), `Err(Error)` otherwise.
     ///
     /// # Errors
     ///
     /// This function will return an error if:
-    /// * The analysis of the file fails.
+    /// * The analysis of the file fails (see [`analyze`](#method.analyze)).
     /// * An IO error occurs while analyzing the date
-    /// * An IO error occurs while doing the file action
-    pub fn run_file(&self, path: &PathBuf) -> Result<()> {
-        let valid_ext = self.is_valid_extension(path.extension());
-        let is_unknown_file = match valid_ext {
-            Ok(false) => match self.settings.unknown_file_format {
-                None => {
-                    info!(
-                        "Skipping file because extension is not in the list: {:?}",
-                        path
-                    );
-                    return Ok(());
-                }
-                Some(_) => {
-                    debug!("Processing unknown file: {:?}", path);
-                    true
-                }
-            },
-            Ok(true) => {
-                debug!("Processing file: {:?}", path);
-                false
-            }
-            Err(err) => {
-                warn!("Error checking file extension: {}", err);
-                return Ok(());
-            }
+    /// * The computed target is invalid or already exists (`Error::TargetExists`)
+    /// * An IO error occurs while doing the file action (see [`action::file_action`])
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(path = %path.display()))
+    )]
+    pub fn run_file(&self, path: &PathBuf) -> Result<FileOutcome, Error> {
+        // the file is analyzed without the lock, only computing and reserving the target happen under it, so that
+        // concurrent calls never compute the same target for different sources
+        let step = self.analyze_planned_file(path)?;
+        let entry = {
+            let mut reserved = self.reserved.lock().expect("reservation lock poisoned");
+            self.reserve_target(path, step, &mut reserved)?
         };
 
-        let (date, cleaned_name) = if !is_unknown_file {
-            let (date, cleaned_name) = self.analyze(path).map_err(|err| {
-                error!("Error extracting date: {}", err);
-                err
-            })?;
-            let cleaned_name = name::clean_image_name(cleaned_name.as_str());
+        match entry {
+            PlanEntry::Action(action) => self.execute_cancellable(&action, None).map_err(|err| {
+                let mut reserved = self.reserved.lock().expect("reservation lock poisoned");
+                reserved.release(&action);
+                err.into()
+            }),
+            PlanEntry::Skipped(skipped) => {
+                let outcome = FileOutcome::from_skipped(&skipped, &self.settings.action_type);
+                self.record_state(&outcome);
+                if let Some(observer) = &self.observer {
+                    observer.on_file_done(&outcome);
+                }
+                Ok(outcome)
+            }
+        }
+    }
 
-            debug!(
-                "Analysis results: Date: {:?}, Cleaned name: {:?}",
-                date, cleaned_name
-            );
+    /// Plans the actions of all files in the source directories, see [`plan_files`](#method.plan_files).
+    /// Files are processed in the configured `order` (see [`order_files`]).
+    pub fn plan(&self) -> Plan {
+        let mut files = self.find_files();
+        order_files(&mut files.files, self.settings.order);
 
-            if date.is_none() {
-                warn!("No date was derived for file {:?}.", path);
-            }
+        let mut plan = self.plan_files(&files.files);
+        plan.filtered = files.filtered;
+        plan
+    }
 
-            (date, cleaned_name)
-        } else {
-            (
-                None,
-                path.with_extension("")
-                    .file_name()
-                    .ok_or(anyhow::anyhow!("No file name"))?
-                    .to_str()
-                    .ok_or(anyhow::anyhow!("Invalid file name"))?
-                    .to_string(),
-            )
-        };
+    /// Finds all files in the source directories (see [`find_files_in_source`]), leaving out the files and
+    /// directories matching the `exclude` patterns and the files outside of `min_size` and `max_size`. Errors
+    /// reading a source directory are logged.
+    ///
+    /// # Returns
+    ///
+    /// * `SourceFiles` - The found files, in the order the file system lists them.
+    pub fn find_files(&self) -> SourceFiles {
+        let mut iter = self.file_iter();
+        let files = iter.by_ref().collect();
+        SourceFiles {
+            files,
+            filtered: iter.take_filtered(),
+        }
+    }
 
-        let date_string = match date {
-            None => "NODATE".to_string(),
-            Some(date) => date.format(&self.settings.date_format).to_string(),
+    /// Finds the files in the source directories like [`find_files`](#method.find_files), but lazily: the files
+    /// are found while the iterator is consumed, see [`FileIter`].
+    pub fn file_iter(&self) -> FileIter<'_> {
+        let options = ScanOptions {
+            max_depth: match self.settings.max_depth {
+                Some(max_depth) => Some(max_depth),
+                None if self.settings.recursive_source => None,
+                None => Some(1),
+            },
+            exclude: &self.exclude,
+            min_size: self.settings.min_size,
+            max_size: self.settings.max_size,
+            follow_symlinks: self.settings.follow_symlinks,
+            skip_hidden: self.settings.skip_hidden,
+            use_ignore_files: true,
+            sorted: self.settings.order != FileOrder::None,
+            file_system: self.settings.file_system(),
         };
+        FileIter::new(self.settings.source_dirs.clone(), options)
+    }
 
-        let mut ftype = FileType::None;
-        if self.is_valid_photo_extension(path.extension())? {
-            ftype = FileType::Image;
-        }
-        #[cfg(feature = "video")]
-        if self.is_valid_video_extension(path.extension())? {
-            ftype = FileType::Video
-        }
+    /// Computes the complete set of actions for a list of files without touching the file system. Targets are
+    /// validated as a whole: collisions with existing files are handled according to the collision strategy,
+    /// collisions between planned actions (two sources mapping to the same target) are always resolved using the
+    /// duplicate counter, in the order of `files`. As planned targets are reserved in memory, the duplicate counter
+    /// increments in dry-run mode exactly as in a real run. Files that can not be planned (failed analysis, targets
+    /// outside of the target directory, missing target subfolders without `mkdir`) are reported as diagnostics.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - The files to plan, in execution order.
+    ///
+    /// # Returns
+    ///
+    /// * `Plan` - The planned actions and diagnostics.
+    pub fn plan_files(&self, files: &[PathBuf]) -> Plan {
+        let mut plan = Plan::default();
+        let mut reserved = Reservations::new(self.case_insensitive_target);
+        // build the index before planning the first file
+        self.target_index();
 
-        let mut file_name_info = NameFormatterInvocationInfo {
-            date: &date,
-            date_string: &date_string,
-            date_default_format: &self.settings.date_format,
-            file_type: &ftype,
-            cleaned_name: &cleaned_name,
-            duplicate_counter: None,
-            extension: path
-                .extension()
-                .map(|ext| ext.to_string_lossy().to_string())
-                .unwrap_or("".to_owned()),
+        let source_hashes = match self.settings.source_duplicates {
+            SourceDuplicates::Process => HashMap::new(),
+            SourceDuplicates::Skip | SourceDuplicates::Hardlink => self.hash_same_size_files(files),
         };
+        // the target of the first transferred file of every content
+        let mut originals: HashMap<FileHash, PathBuf> = HashMap::new();
+        #[cfg(feature = "perceptual-hash")]
+        if let Some(max_distance) = self.settings.near_dup_distance {
+            plan.near_duplicates = Some(self.find_near_duplicates(files, max_distance));
+        }
 
-        let new_file_path = |file_name_info: &NameFormatterInvocationInfo| -> Result<PathBuf> {
-            let format_string = if is_unknown_file {
-                self.settings
-                    .unknown_file_format
-                    .as_ref()
-                    .ok_or(anyhow!("No unknown format string specified"))?
-                    .as_str()
-            } else if date.is_some() {
-                self.settings.file_format.as_str()
-            } else {
-                self.settings.nodate_file_format.as_str()
+        for path in files {
+            // the list of files is complete before any file is renamed, but a file must never be processed again
+            // under the name this run gave it
+            if reserved.contains(path) {
+                debug!("Skipping file, it is a target of this run: {:?}", path);
+                plan.skipped
+                    .push(SkippedFile::new(path, SkipReason::AlreadyNamed));
+                continue;
+            }
+            let hash = source_hashes.get(path);
+            let entry = match hash.and_then(|hash| originals.get(hash)) {
+                Some(original) => self.plan_source_duplicate(path, original, &reserved),
+                None => self.plan_file(path, &reserved),
             };
-
-            let path_split: Vec<_> = format_string
-                .split('/')
-                .map(|component| self.replace_filepath_parts(component, file_name_info))
-                .collect();
-            for entry in &path_split {
-                if let Err(err) = entry {
-                    return Err(anyhow!("Failed to format filename: {}", err));
+            match entry {
+                Ok(PlanEntry::Action(action)) => {
+                    if let (Some(hash), Operation::Transfer { .. }) = (hash, &action.operation) {
+                        originals.insert(*hash, action.target.clone());
+                    }
+                    reserved.reserve(&action);
+                    plan.actions.push(action);
                 }
-            }
-            let path_split = path_split.into_iter().map(Result::unwrap);
-
-            let mut target_path = self.settings.target_dir.clone();
-            for path_component in path_split {
-                let component = path_component.replace("/", "").replace("\\", "");
-                if component != ".." {
-                    target_path.push(component);
+                Ok(PlanEntry::Skipped(skipped)) => plan.skipped.push(skipped),
+                Err(err) => {
+                    error!("Error planning file {:?}: {}", path, err);
+                    plan.diagnostics.push(PlanDiagnostic {
+                        path: path.clone(),
+                        message: err.to_string(),
+                    });
+                    if self.settings.fail_fast {
+                        warn!("Stopped planning at the first error, the remaining files were not planned");
+                        break;
+                    }
                 }
             }
-            Ok(target_path)
-        };
+        }
 
-        let mut new_path = new_file_path(&file_name_info)?;
-        let mut dup_counter = 0;
+        plan
+    }
 
-        while new_path.exists() {
-            debug!("Target file already exists: {:?}", new_path);
-            dup_counter += 1;
-            file_name_info.duplicate_counter = Some(dup_counter);
-            new_path = new_file_path(&file_name_info)?;
-        }
+    /// Finds the groups of near-duplicates among the photos of a plan (see `near_dup_distance`). If a near-duplicate
+    /// format is set, the images except the largest of each group are sorted with it.
+    #[cfg(feature = "perceptual-hash")]
+    fn find_near_duplicates(&self, files: &[PathBuf], max_distance: u32) -> NearDupReport {
+        let photos: Vec<PathBuf> = files
+            .iter()
+            .filter(|path| {
+                self.is_valid_photo_extension(self.file_extension(path).as_deref())
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect();
+        let threads = self.settings.threads.unwrap_or_else(default_threads);
+        let report = NearDupReport::find(&photos, max_distance, threads);
 
-        if dup_counter > 0 {
-            info!("De-duplicated target file: {:?}", new_path);
+        if self.settings.near_dup_format.is_some() {
+            if let Ok(mut near_duplicates) = self.near_duplicates.lock() {
+                *near_duplicates = report.smaller().map(|member| member.path.clone()).collect();
+            }
         }
+        report
+    }
 
-        action::file_action(
-            path,
-            &new_path,
-            &self.settings.action_type,
-            self.settings.mkdir,
-        )?;
-        Ok(())
+    /// Checks if a file is a near-duplicate sorted with the near-duplicate format.
+    #[cfg(feature = "perceptual-hash")]
+    fn is_near_duplicate(&self, path: &Path) -> bool {
+        self.near_duplicates
+            .lock()
+            .is_ok_and(|near_duplicates| near_duplicates.contains(path))
     }
 
-    fn is_valid_photo_extension(&self, ext: Option<&OsStr>) -> Result<bool> {
-        match ext {
-            None => Ok(false),
-            Some(ext) => {
-                let ext = ext
-                    .to_str()
-                    .ok_or(anyhow::anyhow!("Invalid file extension"))?
-                    .to_lowercase();
-                Ok(self
-                    .settings
-                    .extensions
-                    .iter()
-                    .any(|valid_ext| ext == valid_ext.as_str()))
+    /// Hashes the files that have the same size as another file, to detect identical source files (see
+    /// `source_duplicates`). The files are hashed in parallel, files that can not be read are left out.
+    fn hash_same_size_files(&self, files: &[PathBuf]) -> HashMap<PathBuf, FileHash> {
+        let mut by_size: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+        for path in files {
+            if let Ok(metadata) = fs::metadata(path) {
+                by_size.entry(metadata.len()).or_default().push(path);
             }
         }
+        let candidates: Vec<&PathBuf> = by_size
+            .into_values()
+            .filter(|paths| paths.len() > 1)
+            .flatten()
+            .collect();
+        if candidates.is_empty() {
+            return HashMap::new();
+        }
+        debug!(
+            "Hashing {} source files with the same size as another source file",
+            candidates.len()
+        );
+
+        let threads = self
+            .settings
+            .threads
+            .unwrap_or_else(default_threads)
+            .clamp(1, candidates.len());
+        let next = AtomicUsize::new(0);
+        let hashes = Mutex::new(HashMap::new());
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    while let Some(path) = candidates.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        match hash::hash_file(path) {
+                            Ok(hash) => {
+                                if let Ok(mut hashes) = hashes.lock() {
+                                    hashes.insert(path.to_path_buf(), hash);
+                                }
+                            }
+                            Err(err) => warn!("Error hashing source file {:?}: {}", path, err),
+                        }
+                    }
+                });
+            }
+        });
+        hashes
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
     }
 
-    #[cfg(feature = "video")]
-    fn is_valid_video_extension(&self, ext: Option<&OsStr>) -> Result<bool> {
-        match ext {
-            None => Ok(false),
-            Some(ext) => {
-                let ext = ext
-                    .to_str()
-                    .ok_or(anyhow::anyhow!("Invalid file extension"))?
-                    .to_lowercase();
-                Ok(self
-                    .settings
-                    .video_extensions
-                    .iter()
-                    .any(|valid_ext| ext == valid_ext.as_str()))
+    /// Plans a file with the same content as a source file transferred earlier in the run, according to
+    /// `source_duplicates`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The duplicate source file.
+    /// * `original` - The planned target of the identical source file.
+    /// * `reserved` - The targets of the files planned so far.
+    fn plan_source_duplicate(
+        &self,
+        path: &PathBuf,
+        original: &Path,
+        reserved: &Reservations,
+    ) -> Result<PlanEntry> {
+        if self.settings.source_duplicates == SourceDuplicates::Skip {
+            info!(
+                "Skipping file because an identical source file is transferred: {:?} = {:?}",
+                path, original
+            );
+            return Ok(PlanEntry::Skipped(SkippedFile {
+                target: Some(original.to_path_buf()),
+                ..SkippedFile::new(path, SkipReason::SourceDuplicate)
+            }));
+        }
+
+        let mut entry = self.plan_file(path, reserved)?;
+        if let PlanEntry::Action(action) = &mut entry {
+            if let Operation::Transfer { overwrite } = action.operation {
+                debug!(
+                    "Linking file to the target of an identical source file: {:?} = {:?}",
+                    path, original
+                );
+                action.operation = Operation::LinkDuplicate {
+                    original: original.to_path_buf(),
+                    overwrite,
+                };
             }
         }
+        Ok(entry)
     }
 
-    fn is_valid_extension(&self, ext: Option<&OsStr>) -> Result<bool> {
-        let valid_photo = self.is_valid_photo_extension(ext)?;
-        #[cfg(feature = "video")]
-        let valid_video = self.is_valid_video_extension(ext)?;
-        #[cfg(not(feature = "video"))]
-        let valid_video = false;
-        Ok(valid_photo || valid_video)
-    }
+    /// Executes the actions of a plan. The planned targets are used verbatim, they are not recomputed, so a plan
+    /// that was inspected (or modified) by the caller is executed exactly as it is. Errors of single actions are
+    /// logged and do not abort the run. If `threads` is set, the actions are executed in parallel, a file and its
+    /// sidecars are always processed by the same thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The plan to execute, see [`plan_files`](#method.plan_files).
+    ///
+    /// # Returns
+    ///
+    /// * `RunSummary` - The statistics of the run, including the files that could not be planned or failed.
+    pub fn run(&self, plan: Plan) -> RunSummary {
+        self.run_with_outcomes(plan, |_| {})
+    }
+
+    /// Executes the actions of a plan like [`run`](#method.run), passing the outcome of every file to a callback:
+    /// first the skipped files, then the performed actions in the order they finished. Failed actions have no
+    /// outcome. The callback is always called on the calling thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The plan to execute, see [`plan_files`](#method.plan_files).
+    /// * `on_outcome` - A function receiving the outcome of each file.
+    ///
+    /// # Returns
+    ///
+    /// * `RunSummary` - The statistics of the run, see [`run`](#method.run).
+    pub fn run_with_outcomes<F: FnMut(FileOutcome)>(
+        &self,
+        plan: Plan,
+        on_outcome: F,
+    ) -> RunSummary {
+        self.run_cancellable(plan, None, on_outcome)
+    }
+
+    /// Executes the actions of a plan like [`run`](#method.run) until the token is cancelled. The token is checked
+    /// before every file and between the chunks of chunked copies (see [`set_copy_progress`](#method.set_copy_progress)
+    /// and `bandwidth_limit`), an aborted copy leaves the file unchanged. A file and its sidecars are completed
+    /// together, running threads finish their current file. Files that were not processed are not counted.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The plan to execute, see [`plan_files`](#method.plan_files).
+    /// * `token` - The token that stops the run when it is cancelled.
+    ///
+    /// # Returns
+    ///
+    /// * `RunSummary` - The statistics of the files processed so far, marked as `cancelled` if the run was stopped.
+    pub fn run_with_cancel(&self, plan: Plan, token: &CancellationToken) -> RunSummary {
+        self.run_cancellable(plan, Some(token), |_| {})
+    }
+
+    /// Processes the files of the source directories while they are found (see [`file_iter`](#method.file_iter)),
+    /// instead of planning all files first: every file is planned on its own like in [`run_file`](#method.run_file)
+    /// and executed right away, so the first action starts immediately and the list of files is never held in
+    /// memory. Files are processed in the order they are found, the `order` setting only decides if the entries of
+    /// a directory are sorted. Identical source files are not detected (see `source_duplicates`) and the progress
+    /// reporter is not used, as the number of files is unknown. Files that can not be planned are reported to the
+    /// observer (see [`RunObserver::on_invalid`]). The run stops when the token is cancelled, see
+    /// [`run_with_cancel`](#method.run_with_cancel).
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token that stops the run when it is cancelled.
+    ///
+    /// # Returns
+    ///
+    /// * `RunSummary` - The statistics of the run, see [`run`](#method.run).
+    pub fn run_streaming(&self, token: &CancellationToken) -> RunSummary {
+        let start = Instant::now();
+        let action = match self.settings.action_type {
+            ActionMode::Execute(action) | ActionMode::DryRun(action) => action,
+        };
+        let mut summary = RunSummary::new(action, &self.settings.source_dirs);
+        let files = Mutex::new(self.file_iter());
+        // with `fail_fast`, no further file is processed after a file could not be planned or processed
+        let stopped = AtomicBool::new(false);
+        // the sources moved, deleted or linked by the run, pruning considers them absent in dry-run mode
+        let mut vacated = HashSet::new();
+
+        let mut record = |path: PathBuf, filtered: Vec<PathBuf>, file: StreamedFile| {
+            for path in &filtered {
+                summary.record_filtered(path);
+            }
+            let failed = match file {
+                StreamedFile::Done(outcome) => {
+                    summary.record(&outcome);
+                    if outcome.action != OutcomeAction::Skip {
+                        vacated.extend(outcome.sidecars.iter().map(|(sidecar, _)| sidecar.clone()));
+                        vacated.insert(outcome.source);
+                    }
+                    false
+                }
+                StreamedFile::Invalid => {
+                    summary.record_invalid(&path);
+                    true
+                }
+                StreamedFile::Failed(Error::Cancelled(_)) => {
+                    debug!("Cancelled processing file: {:?}", path);
+                    false
+                }
+                StreamedFile::Failed(err) => {
+                    error!("Error processing file: {}", err);
+                    summary.record_failed(&path);
+                    true
+                }
+            };
+            if failed && self.settings.fail_fast {
+                stopped.store(true, Ordering::Relaxed);
+            }
+        };
+        // the next file to process and the files filtered since the previous one
+        let next_file = || {
+            let mut files = files.lock().expect("file iterator lock poisoned");
+            (!token.is_cancelled() && !stopped.load(Ordering::Relaxed))
+                .then(|| files.next())
+                .flatten()
+                .map(|path| (path, files.take_filtered()))
+        };
+
+        let threads = self.settings.threads.unwrap_or(1).max(1);
+        if threads == 1 {
+            while let Some((path, filtered)) = next_file() {
+                let file = self.stream_file(&path, token);
+                record(path, filtered, file);
+            }
+        } else {
+            // the workers wait while the results are not recorded yet, instead of piling them up
+            let (sender, receiver) = sync_channel(threads);
+            thread::scope(|scope| {
+                for _ in 0..threads {
+                    let sender = sender.clone();
+                    let next_file = &next_file;
+                    scope.spawn(move || {
+                        while let Some((path, filtered)) = next_file() {
+                            let file = self.stream_file(&path, token);
+                            if sender.send((path, filtered, file)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+                drop(sender);
+                for (path, filtered, file) in receiver {
+                    record(path, filtered, file);
+                }
+            });
+        }
+        let filtered = files
+            .into_inner()
+            .map(|mut files| files.take_filtered())
+            .unwrap_or_default();
+        for path in &filtered {
+            summary.record_filtered(path);
+        }
+
+        summary.cancelled = token.is_cancelled();
+        if summary.cancelled {
+            warn!("Run cancelled, the remaining files were not processed");
+        } else if stopped.load(Ordering::Relaxed) {
+            warn!("Run stopped at the first error, the remaining files were not processed");
+        }
+        self.prune_vacated_dirs(&vacated);
+
+        summary.elapsed = start.elapsed();
+        summary
+    }
+
+    /// Plans and executes a single file of a streaming run, see [`run_streaming`](#method.run_streaming).
+    fn stream_file(&self, path: &PathBuf, cancel: &CancellationToken) -> StreamedFile {
+        let is_target = |reserved: &Reservations| {
+            let is_target = reserved.contains(path);
+            if is_target {
+                debug!("Skipping file, it is a target of this run: {:?}", path);
+            }
+            is_target
+        };
+        let target_of_run = || {
+            Ok(PlanEntry::Skipped(SkippedFile::new(
+                path,
+                SkipReason::AlreadyNamed,
+            )))
+        };
+
+        // the file is analyzed without the lock, only computing and reserving the target happen under it
+        let entry = if is_target(&self.reserved.lock().expect("reservation lock poisoned")) {
+            target_of_run()
+        } else {
+            self.analyze_planned_file(path).and_then(|step| {
+                let mut reserved = self.reserved.lock().expect("reservation lock poisoned");
+                // the file may have become a target while it was analyzed
+                if is_target(&reserved) {
+                    target_of_run()
+                } else {
+                    self.reserve_target(path, step, &mut reserved)
+                }
+            })
+        };
+
+        match entry {
+            Ok(PlanEntry::Action(action)) => {
+                match self.execute_cancellable(&action, Some(cancel)) {
+                    Ok(outcome) => StreamedFile::Done(outcome),
+                    Err(err) => {
+                        let mut reserved = self.reserved.lock().expect("reservation lock poisoned");
+                        reserved.release(&action);
+                        StreamedFile::Failed(err.into())
+                    }
+                }
+            }
+            Ok(PlanEntry::Skipped(skipped)) => {
+                let outcome = FileOutcome::from_skipped(&skipped, &self.settings.action_type);
+                self.record_state(&outcome);
+                if let Some(observer) = &self.observer {
+                    observer.on_file_done(&outcome);
+                }
+                StreamedFile::Done(outcome)
+            }
+            Err(err) => {
+                error!("Error planning file {:?}: {}", path, err);
+                if let Some(observer) = &self.observer {
+                    observer.on_invalid(path, &Error::from(err));
+                }
+                StreamedFile::Invalid
+            }
+        }
+    }
+
+    fn run_cancellable<F: FnMut(FileOutcome)>(
+        &self,
+        plan: Plan,
+        cancel: Option<&CancellationToken>,
+        mut on_outcome: F,
+    ) -> RunSummary {
+        let is_cancelled = || cancel.is_some_and(CancellationToken::is_cancelled);
+        let start = Instant::now();
+        let action = match self.settings.action_type {
+            ActionMode::Execute(action) | ActionMode::DryRun(action) => action,
+        };
+        let mut summary = RunSummary::new(action, &self.settings.source_dirs);
+
+        for diagnostic in &plan.diagnostics {
+            summary.record_invalid(&diagnostic.path);
+        }
+        for path in &plan.filtered {
+            summary.record_filtered(path);
+        }
+        // with `fail_fast`, no file is processed if a file could not be planned, and no further file after an error
+        let stopped = AtomicBool::new(self.settings.fail_fast && !plan.is_valid());
+        let is_stopped = || is_cancelled() || stopped.load(Ordering::Relaxed);
+        if let Some(progress) = &self.progress {
+            progress.begin((plan.actions.len() + plan.skipped.len()) as u64);
+            if let Some(total_bytes) = self.copied_bytes(&plan) {
+                progress.begin_bytes(total_bytes);
+            }
+        }
+        for skipped in &plan.skipped {
+            let outcome = FileOutcome::from_skipped(skipped, &self.settings.action_type);
+            self.record_state(&outcome);
+            if let Some(observer) = &self.observer {
+                observer.on_file_done(&outcome);
+            }
+            if let Some(progress) = &self.progress {
+                progress.advance(&outcome.source);
+            }
+            summary.record(&outcome);
+            on_outcome(outcome);
+        }
+
+        let mut record = |action: &PlannedAction, result: Result<FileOutcome>| {
+            if let Some(progress) = &self.progress {
+                progress.advance(&action.source);
+            }
+            match result {
+                Ok(outcome) => {
+                    summary.record(&outcome);
+                    on_outcome(outcome);
+                }
+                Err(err) if matches!(err.downcast_ref(), Some(Error::Cancelled(_))) => {
+                    debug!("Cancelled processing file: {:?}", action.source);
+                }
+                Err(err) => {
+                    error!("Error processing file: {}", err);
+                    summary.record_failed(&action.source);
+                    if self.settings.fail_fast {
+                        stopped.store(true, Ordering::Relaxed);
+                    }
+                }
+            }
+        };
+
+        // hardlinks to the targets of identical source files are created after those targets
+        let (links, actions): (Vec<_>, Vec<_>) = plan
+            .actions
+            .iter()
+            .partition(|action| matches!(action.operation, Operation::LinkDuplicate { .. }));
+
+        let threads = self.settings.threads.unwrap_or(1).max(1);
+        for batch in [actions, links] {
+            if threads == 1 {
+                for action in batch {
+                    if is_stopped() {
+                        break;
+                    }
+                    record(action, self.execute_cancellable(action, cancel));
+                }
+                continue;
+            }
+            // every planned action (including its sidecars) is one job, the workers take the next job when they are
+            // done with one. The results are recorded on this thread, so `on_outcome` does not need to be `Send`, and
+            // the workers wait while the results are not recorded yet, instead of piling them up
+            let next = AtomicUsize::new(0);
+            let (sender, receiver) = sync_channel(threads);
+            thread::scope(|scope| {
+                for _ in 0..threads.min(batch.len()) {
+                    let sender = sender.clone();
+                    let next = &next;
+                    let actions = &batch;
+                    let is_stopped = &is_stopped;
+                    scope.spawn(move || {
+                        while let Some(action) = actions.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            if is_stopped() {
+                                break;
+                            }
+                            let result = self.execute_cancellable(action, cancel);
+                            if sender.send((*action, result)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+                drop(sender);
+                for (action, result) in receiver {
+                    record(action, result);
+                }
+            });
+        }
+
+        if let Some(progress) = &self.progress {
+            progress.finish();
+        }
+        summary.cancelled = is_cancelled();
+        if summary.cancelled {
+            warn!("Run cancelled, the remaining files were not processed");
+        } else if stopped.load(Ordering::Relaxed) {
+            warn!("Run stopped at the first error, the remaining files were not processed");
+        }
+        self.prune_source_dirs(&plan);
+
+        summary.elapsed = start.elapsed();
+        summary
+    }
+
+    /// Removes the source directories that are empty after a move run, if `prune_empty_dirs` is set. In dry-run mode
+    /// no directory is removed, the files moved by the plan are considered absent instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The executed plan.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<PathBuf>` - The removed directories (or the directories that would be removed in dry-run mode).
+    pub fn prune_source_dirs(&self, plan: &Plan) -> Vec<PathBuf> {
+        let vacated = plan
+            .actions
+            .iter()
+            .flat_map(|action| {
+                std::iter::once(action.source.clone())
+                    .chain(action.sidecars.iter().map(|(sidecar, _)| sidecar.clone()))
+            })
+            .collect();
+        self.prune_vacated_dirs(&vacated)
+    }
+
+    /// Removes the source directories that are empty after a move run, see
+    /// [`prune_source_dirs`](#method.prune_source_dirs).
+    ///
+    /// # Arguments
+    ///
+    /// * `vacated` - The source files moved, deleted or linked by the run.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<PathBuf>` - The removed directories (or the directories that would be removed in dry-run mode).
+    fn prune_vacated_dirs(&self, vacated: &HashSet<PathBuf>) -> Vec<PathBuf> {
+        let is_move = matches!(
+            self.settings.action_type,
+            ActionMode::Execute(ActualAction::Move) | ActionMode::DryRun(ActualAction::Move)
+        );
+        if !self.settings.prune_empty_dirs || !is_move {
+            return Vec::new();
+        }
+
+        let absent = HashSet::new();
+        let removed_files = match self.settings.action_type {
+            ActionMode::DryRun(_) => vacated,
+            ActionMode::Execute(_) => &absent,
+        };
+        let options = action::PruneOptions {
+            include_root: self.settings.prune_roots,
+            prune_litter: self.settings.prune_litter,
+        };
+
+        let mut pruned = Vec::new();
+        for source in &self.settings.source_dirs {
+            match action::prune_empty_dirs(
+                source,
+                removed_files,
+                &self.settings.action_type,
+                &options,
+            ) {
+                Ok(dirs) => {
+                    if let ActionMode::Execute(_) = self.settings.action_type {
+                        for dir in &dirs {
+                            info!("Removed empty source directory: {:?}", dir);
+                        }
+                    }
+                    pruned.extend(dirs);
+                }
+                Err(err) => error!("Error pruning source directory: {}", err),
+            }
+        }
+        pruned
+    }
+
+    /// Executes a single planned action, including the actions of its sidecar files. The observer (see
+    /// [`set_observer`](#method.set_observer)) is notified.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file action fails. Errors of sidecar files are only logged.
+    pub fn execute(&self, action: &PlannedAction) -> Result<()> {
+        self.execute_cancellable(action, None).map(|_| ())
+    }
+
+    /// Executes a single planned action like [`execute`](#method.execute), a chunked copy is aborted if the token
+    /// is cancelled (`Error::Cancelled`, the observer is not notified).
+    ///
+    /// # Returns
+    ///
+    /// * `FileOutcome` - The outcome of the action, including the failures of mirror directories.
+    fn execute_cancellable(
+        &self,
+        action: &PlannedAction,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<FileOutcome> {
+        let report = ActionReport::new();
+        let outcome = |mirror_failures| {
+            let events = report.events();
+            FileOutcome {
+                mirror_failures,
+                hardlink_fallbacks: events.hardlink_fallbacks,
+                symlink_fallbacks: events.symlink_fallbacks,
+                undeleted_sources: events.undeleted_sources,
+                ..FileOutcome::from_action(action, &self.settings.action_type)
+            }
+        };
+        let Some(observer) = &self.observer else {
+            let outcome = outcome(self.execute_action(action, cancel, &report)?);
+            self.record_state(&outcome);
+            return Ok(outcome);
+        };
+
+        observer.on_file_start(&action.source);
+        match self.execute_action(action, cancel, &report) {
+            Ok(mirror_failures) => {
+                let outcome = outcome(mirror_failures);
+                self.record_state(&outcome);
+                observer.on_file_done(&outcome);
+                Ok(outcome)
+            }
+            Err(err) => {
+                let err = Error::from(err);
+                if !matches!(err, Error::Cancelled(_)) {
+                    observer.on_error(&action.source, &err);
+                }
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Performs a single planned action and its sidecars, and replicates the targets to the mirror directories.
+    /// Substituted links and undeleted sources are recorded in the report.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<PathBuf>` - The mirror targets that could not be created, see `FileOutcome::mirror_failures`.
+    fn execute_action(
+        &self,
+        action: &PlannedAction,
+        cancel: Option<&CancellationToken>,
+        report: &ActionReport,
+    ) -> Result<Vec<PathBuf>> {
+        let options = match action.operation {
+            Operation::Transfer { overwrite } => {
+                let options = ActionOptions {
+                    overwrite,
+                    cancel: cancel.cloned(),
+                    report: Some(report.clone()),
+                    ..self.action_options()
+                };
+                self.perform_file_action(&action.source, &action.target, action.date, &options)?;
+                options
+            }
+            Operation::LinkDuplicate {
+                ref original,
+                overwrite,
+            } => {
+                // a link is not interruptible, a cancelled run does not start it
+                if cancel.is_some_and(CancellationToken::is_cancelled) {
+                    return Err(Error::Cancelled(action.source.clone()).into());
+                }
+                let options = ActionOptions {
+                    overwrite,
+                    report: Some(report.clone()),
+                    ..self.action_options()
+                };
+                self.link_duplicate(action, original, &options)?;
+                options
+            }
+            Operation::DeleteDuplicate => {
+                info!(
+                    "Deleting source file because an identical target file already exists: {:?} = {:?}",
+                    action.source, action.target
+                );
+                let result = action::delete_file(
+                    &action.source,
+                    &self.settings.action_type,
+                    &self.action_options(),
+                );
+                self.record_journal(
+                    JournalAction::Delete,
+                    &action.source,
+                    &action.target,
+                    &result,
+                );
+                return result.map(|_| Vec::new());
+            }
+        };
+
+        // the sidecars of a processed file are always completed
+        let options = ActionOptions {
+            cancel: None,
+            ..options
+        };
+        for (sidecar, sidecar_path) in &action.sidecars {
+            debug!(
+                "Processing sidecar file: {:?} -> {:?}",
+                sidecar, sidecar_path
+            );
+            if let Err(err) = self.perform_file_action(sidecar, sidecar_path, action.date, &options)
+            {
+                error!("Error processing sidecar file {:?}: {}", sidecar, err);
+            }
+        }
+
+        Ok(self.replicate(action, &options))
+    }
+
+    /// Replicates the target of an action and its sidecars to the mirror directories (see `mirror_dirs`), at the
+    /// same path relative to the mirror directory. Moved files are copied from their target, the targets of linked
+    /// duplicates are hardlinked to the replica of their original. Errors are logged.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<PathBuf>` - The mirror targets that could not be created.
+    fn replicate(&self, action: &PlannedAction, options: &ActionOptions) -> Vec<PathBuf> {
+        let mut failures = Vec::new();
+        if self.settings.mirror_dirs.is_empty() {
+            return failures;
+        }
+        let (actual, dry_run) = match self.settings.action_type {
+            ActionMode::Execute(actual) => (actual, false),
+            ActionMode::DryRun(actual) => (actual, true),
+        };
+        let is_move = actual == ActualAction::Move;
+        let actual = match (&action.operation, actual) {
+            (Operation::LinkDuplicate { .. }, _) => ActualAction::Hardlink,
+            (_, ActualAction::Move) => ActualAction::Copy,
+            (_, actual) => actual,
+        };
+        let mode = if dry_run {
+            ActionMode::DryRun(actual)
+        } else {
+            ActionMode::Execute(actual)
+        };
+        let files = std::iter::once((&action.source, &action.target))
+            .chain(action.sidecars.iter().map(|(s, t)| (s, t)));
+
+        for mirror in &self.settings.mirror_dirs {
+            for (source, target) in files.clone() {
+                let Some(mirror_target) = self.mirror_path(mirror, target) else {
+                    continue;
+                };
+                let mirror_source = match &action.operation {
+                    Operation::LinkDuplicate { original, .. } if target == &action.target => {
+                        match self.mirror_path(mirror, original) {
+                            Some(original) => original,
+                            None => continue,
+                        }
+                    }
+                    // the source of a moved file is gone, its target is copied instead
+                    _ if is_move => target.clone(),
+                    _ => source.clone(),
+                };
+                debug!(
+                    "Replicating file to mirror directory: {:?} -> {:?}",
+                    mirror_source, mirror_target
+                );
+                let result = self.perform_file_action_as(
+                    &mirror_source,
+                    &mirror_target,
+                    &mode,
+                    action.date,
+                    options,
+                );
+                if let Err(err) = result {
+                    error!(
+                        "Error replicating {:?} to mirror directory {:?}: {}",
+                        source, mirror, err
+                    );
+                    failures.push(mirror_target);
+                }
+            }
+        }
+        failures
+    }
+
+    /// The path of a target file in a mirror directory, `None` if the file is not in the target directory.
+    fn mirror_path(&self, mirror: &Path, target: &Path) -> Option<PathBuf> {
+        target
+            .strip_prefix(&self.settings.target_dir)
+            .ok()
+            .map(|relative| mirror.join(relative))
+    }
+
+    /// Creates the target of a source file as a hardlink to the target of an identical source file (see
+    /// `SourceDuplicates::Hardlink`). In move mode the source file is deleted afterward.
+    fn link_duplicate(
+        &self,
+        action: &PlannedAction,
+        original: &PathBuf,
+        options: &ActionOptions,
+    ) -> Result<()> {
+        info!(
+            "Linking file to the target of an identical source file: {:?} -> {:?} = {:?}",
+            action.source, action.target, original
+        );
+        let (link_mode, is_move) = match self.settings.action_type {
+            ActionMode::Execute(actual) => (
+                ActionMode::Execute(ActualAction::Hardlink),
+                actual == ActualAction::Move,
+            ),
+            ActionMode::DryRun(actual) => (
+                ActionMode::DryRun(ActualAction::Hardlink),
+                actual == ActualAction::Move,
+            ),
+        };
+
+        let result = action::file_action(original, &action.target, &link_mode, options)
+            .map_err(anyhow::Error::from);
+        if let ActionMode::Execute(_) = link_mode {
+            self.record_journal(
+                JournalAction::File(ActualAction::Hardlink),
+                original,
+                &action.target,
+                &result,
+            );
+        }
+        result?;
+
+        if is_move {
+            let result = action::delete_file(&action.source, &self.settings.action_type, options);
+            self.record_journal(
+                JournalAction::Delete,
+                &action.source,
+                &action.target,
+                &result,
+            );
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Plans the action of a single file, or the reason why it is skipped.
+    fn plan_file(&self, path: &PathBuf, reserved: &Reservations) -> Result<PlanEntry> {
+        match self.analyze_planned_file(path)? {
+            PlanStep::Done(entry) => Ok(entry),
+            PlanStep::Analyzed(file) => self.plan_target(path, file, reserved),
+        }
+    }
+
+    /// Computes the target of an analyzed file and reserves it, see [`plan_file`](#method.plan_file). Only this step
+    /// depends on the targets planned so far, concurrent runs call it under the reservation lock.
+    fn reserve_target(
+        &self,
+        path: &PathBuf,
+        step: PlanStep,
+        reserved: &mut Reservations,
+    ) -> Result<PlanEntry> {
+        let entry = match step {
+            PlanStep::Done(entry) => entry,
+            PlanStep::Analyzed(file) => self.plan_target(path, file, reserved)?,
+        };
+        if let PlanEntry::Action(action) = &entry {
+            reserved.reserve(action);
+        }
+        Ok(entry)
+    }
+
+    /// The first step of [`plan_file`](#method.plan_file): checks whether the file is processed and analyzes it.
+    /// Files that are skipped or already exist in the target directory are planned completely.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                path = %path.display(),
+                date_source = tracing::field::Empty,
+                format = tracing::field::Empty
+            )
+        )
+    )]
+    fn analyze_planned_file(&self, path: &PathBuf) -> Result<PlanStep<'_>> {
+        if self
+            .state
+            .as_ref()
+            .is_some_and(|state| state.is_processed(path))
+        {
+            debug!(
+                "Skipping file, it was processed by a previous run: {:?}",
+                path
+            );
+            return Ok(PlanStep::Done(PlanEntry::Skipped(SkippedFile::new(
+                path,
+                SkipReason::PreviouslyProcessed,
+            ))));
+        }
+
+        let valid_ext = self.is_valid_extension(self.file_extension(path).as_deref());
+        let is_unknown_file = match valid_ext {
+            Ok(false) if self.is_takeout_metadata(path) => {
+                debug!(
+                    "Skipping Takeout metadata file, it is read together with its main file: {:?}",
+                    path
+                );
+                return Ok(PlanStep::Done(PlanEntry::Skipped(SkippedFile::new(
+                    path,
+                    SkipReason::Sidecar,
+                ))));
+            }
+            Ok(false)
+                if self.is_sidecar_extension(path.extension())
+                    && (!path.exists() || self.has_main_file(path)) =>
+            {
+                debug!(
+                    "Skipping sidecar file, it is processed together with its main file: {:?}",
+                    path
+                );
+                return Ok(PlanStep::Done(PlanEntry::Skipped(SkippedFile::new(
+                    path,
+                    SkipReason::Sidecar,
+                ))));
+            }
+            Ok(false) => match self.settings.unknown_file_format {
+                None => {
+                    info!(
+                        "Skipping file because extension is not in the list: {:?}",
+                        path
+                    );
+                    return Ok(PlanStep::Done(PlanEntry::Skipped(SkippedFile::new(
+                        path,
+                        SkipReason::UnknownExtension,
+                    ))));
+                }
+                Some(_) if !self.is_listed_unknown_extension(path.extension()) => {
+                    info!(
+                        "Skipping unknown file because extension is not in the list of unknown extensions: {:?}",
+                        path
+                    );
+                    return Ok(PlanStep::Done(PlanEntry::Skipped(SkippedFile::new(
+                        path,
+                        SkipReason::UnlistedUnknown,
+                    ))));
+                }
+                Some(_) => {
+                    debug!("Processing unknown file: {:?}", path);
+                    true
+                }
+            },
+            Ok(true) => {
+                debug!("Processing file: {:?}", path);
+                false
+            }
+            Err(err) => {
+                warn!("Error checking file extension: {}", err);
+                return Ok(PlanStep::Done(PlanEntry::Skipped(SkippedFile::new(
+                    path,
+                    SkipReason::UnknownExtension,
+                ))));
+            }
+        };
+
+        // the RAW file of a pair is processed together with its JPEG file
+        let pair = if is_unknown_file {
+            None
+        } else {
+            self.find_pair(path)
+        };
+        if pair.is_some() && is_raw_file(path) {
+            debug!(
+                "Skipping RAW file, it is processed together with its JPEG file: {:?}",
+                path
+            );
+            return Ok(PlanStep::Done(PlanEntry::Skipped(SkippedFile::new(
+                path,
+                SkipReason::Paired,
+            ))));
+        }
+
+        // the video of a Live Photo is processed together with its photo
+        let live_photo = self.find_live_photo(path, is_unknown_file);
+        if live_photo.is_some() && is_live_photo_video(path) {
+            debug!(
+                "Skipping video, it is processed together with the photo of its Live Photo: {:?}",
+                path
+            );
+            return Ok(PlanStep::Done(PlanEntry::Skipped(SkippedFile::new(
+                path,
+                SkipReason::Paired,
+            ))));
+        }
+
+        let (date, date_source, cleaned_name, media_info) = if !is_unknown_file {
+            let analysis = match (self.analyze_file(path), &pair) {
+                // the pair gets the date of the RAW file, if only the RAW file has one
+                (Ok(analysis), Some(raw)) if analysis.date.is_none() => Ok(self
+                    .analyze_file(raw)
+                    .ok()
+                    .filter(|raw_analysis| raw_analysis.date.is_some())
+                    .inspect(|_| debug!("Using the date of the RAW file of the pair: {:?}", raw))
+                    .unwrap_or(analysis)),
+                (Err(err), Some(raw)) => self.analyze_file(raw).map_err(|_| err),
+                (result, _) => result,
+            }
+            .map_err(|err| {
+                error!("Error extracting date: {}", err);
+                err
+            })?;
+            let date = analysis.date;
+            let cleaned_name = if self.settings.clean_names {
+                self.name_cleaner.clean(analysis.name.as_str())
+            } else {
+                name::remove_extension(analysis.name.as_str())
+            };
+
+            debug!(
+                "Analysis results: Date: {:?}, Cleaned name: {:?}",
+                date, cleaned_name
+            );
+
+            if date.is_none() {
+                warn!("No date was derived for file {:?}.", path);
+            }
+
+            if !self.is_in_date_range(date) {
+                info!(
+                    "Skipping file because its date is outside of the date range: {:?}",
+                    path
+                );
+                return Ok(PlanStep::Done(PlanEntry::Skipped(SkippedFile {
+                    source: path.clone(),
+                    target: None,
+                    reason: SkipReason::Filtered,
+                    date,
+                    date_source: analysis.date_source,
+                })));
+            }
+
+            (
+                date,
+                analysis.date_source,
+                cleaned_name,
+                analysis.media_info,
+            )
+        } else {
+            (
+                None,
+                DateSource::None,
+                path.with_extension("")
+                    .file_name()
+                    .ok_or(anyhow::anyhow!("No file name"))?
+                    .to_string_lossy()
+                    .to_string(),
+                MediaInfo::default(),
+            )
+        };
+
+        let format_string = if is_unknown_file {
+            self.settings
+                .unknown_file_format
+                .as_ref()
+                .ok_or(anyhow!("No unknown format string specified"))?
+                .as_str()
+        } else {
+            self.file_format(self.file_type(path)?, date.is_some())
+        };
+        #[cfg(feature = "perceptual-hash")]
+        let format_string = match &self.settings.near_dup_format {
+            Some(near_dup_format) if !is_unknown_file && self.is_near_duplicate(path) => {
+                debug!(
+                    "Sorting near-duplicate with the near-duplicate format: {:?}",
+                    path
+                );
+                near_dup_format.as_str()
+            }
+            _ => format_string,
+        };
+        #[cfg(feature = "tracing")]
+        tracing::Span::current()
+            .record("date_source", tracing::field::display(date_source))
+            .record("format", format_string);
+
+        if let Some(index) = self.target_index() {
+            match index.find(path) {
+                Ok(Some(existing)) => {
+                    info!(
+                        "File already exists in the target directory: {:?} = {:?}",
+                        path, existing
+                    );
+                    return self
+                        .plan_identical_file(
+                            path,
+                            &existing,
+                            format_string,
+                            is_unknown_file,
+                            date,
+                            date_source,
+                        )
+                        .map(PlanStep::Done);
+                }
+                Ok(None) => {}
+                Err(err) => warn!(
+                    "Failed to look up file {:?} in the target index: {}",
+                    path, err
+                ),
+            }
+        }
+
+        // the files following the file, paired with the file whose target name they get: the sidecars of the file,
+        // the RAW file of a pair and its sidecars, and the video of a Live Photo (paired with itself, it gets the
+        // target name of the file with its own file type and extension)
+        let mut sidecars: Vec<(PathBuf, PathBuf)> = if is_unknown_file {
+            Vec::new()
+        } else {
+            self.find_sidecars(path)
+                .into_iter()
+                .map(|sidecar| (sidecar, path.clone()))
+                .collect()
+        };
+        if let Some(raw) = &pair {
+            sidecars.push((raw.clone(), path.clone()));
+            for sidecar in self.find_sidecars(raw) {
+                if !sidecars.iter().any(|(existing, _)| *existing == sidecar) {
+                    sidecars.push((sidecar, raw.clone()));
+                }
+            }
+        }
+        if let Some(video) = live_photo {
+            sidecars.push((video.clone(), video));
+        }
+        Ok(PlanStep::Analyzed(AnalyzedFile {
+            is_unknown_file,
+            date,
+            date_source,
+            cleaned_name,
+            media_info,
+            format_string,
+            sidecars,
+        }))
+    }
+
+    /// The second step of [`plan_file`](#method.plan_file): computes the target of an analyzed file, resolving
+    /// collisions with existing files and with the targets planned so far.
+    fn plan_target(
+        &self,
+        path: &PathBuf,
+        file: AnalyzedFile,
+        reserved: &Reservations,
+    ) -> Result<PlanEntry> {
+        let AnalyzedFile {
+            is_unknown_file,
+            date,
+            date_source,
+            cleaned_name,
+            media_info,
+            format_string,
+            sidecars,
+        } = file;
+
+        // the date in the name, rounded to group the files of cameras whose clocks differ by a few seconds
+        let name_date = match self.settings.round_seconds {
+            Some(seconds) => date.map(|date| analysis::floor_date(date, seconds)),
+            None => date,
+        };
+        let date_string = match name_date {
+            None => self.settings.nodate_placeholder.clone(),
+            Some(date) => self.locale.format(&date, &self.settings.date_format),
+        };
+
+        let ftype = self.file_type(path)?;
+
+        let mut file_name_info = NameFormatterInvocationInfo {
+            date: &name_date,
+            date_string: &date_string,
+            date_default_format: &self.settings.date_format,
+            nodate_placeholder: &self.settings.nodate_placeholder,
+            locale: self.locale,
+            file_type: &ftype,
+            cleaned_name: &cleaned_name,
+            duplicate_counter: None,
+            extension: self
+                .file_extension(path)
+                .map(|ext| ext.to_string_lossy().to_string())
+                .unwrap_or("".to_owned()),
+            duration: media_info.duration,
+            vertical_resolution: media_info.vertical_resolution,
+            relative_dir: self.relative_source_dir(path),
+            source_label: self.source_label(path),
+        };
+
+        let target_root = self.target_root(path);
+        let new_file_path = |file_name_info: &NameFormatterInvocationInfo| -> Result<PathBuf> {
+            let path_split: Vec<_> = format_string
+                .split('/')
+                .map(|component| self.replace_filepath_parts(component, file_name_info))
+                .collect();
+            for entry in &path_split {
+                if let Err(err) = entry {
+                    return Err(anyhow!("Failed to format filename: {}", err));
+                }
+            }
+            let path_split = path_split.into_iter().map(Result::unwrap);
+
+            // the components are free of separators, except for the relative paths of `{relpath}`. They are
+            // sanitized before the collision checks, so that collisions are detected on the final names
+            let mut target_path = target_root.to_path_buf();
+            for path_component in path_split {
+                for component in path_component.split('/') {
+                    if !component.is_empty() && component != "." && component != ".." {
+                        target_path.push(name::sanitize_component(
+                            component,
+                            self.sanitize,
+                            self.settings.sanitize_char,
+                        ));
+                    }
+                }
+            }
+            Ok(target_path)
+        };
+
+        let video_type = FileType::Video;
+        let sidecar_target = |(sidecar, owner): &(PathBuf, PathBuf),
+                              target: &PathBuf,
+                              file_name_info: &NameFormatterInvocationInfo|
+         -> Result<PathBuf> {
+            if sidecar == owner {
+                let mut file_name_info = file_name_info.clone();
+                file_name_info.file_type = &video_type;
+                file_name_info.extension = self
+                    .file_extension(sidecar)
+                    .map(|ext| ext.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                new_file_path(&file_name_info)
+            } else if owner == path {
+                Ok(sidecar_target_path(path, sidecar, target))
+            } else {
+                Ok(sidecar_target_path(
+                    owner,
+                    sidecar,
+                    &sidecar_target_path(path, owner, target),
+                ))
+            }
+        };
+        let target_exists = |target: &PathBuf, file_name_info: &NameFormatterInvocationInfo| {
+            self.target_exists(target)
+                || sidecars.iter().any(|sidecar| {
+                    sidecar_target(sidecar, target, file_name_info)
+                        .is_ok_and(|target| self.target_exists(&target))
+                })
+        };
+        let target_reserved = |target: &PathBuf, file_name_info: &NameFormatterInvocationInfo| {
+            reserved.contains(target)
+                || sidecars.iter().any(|sidecar| {
+                    sidecar_target(sidecar, target, file_name_info)
+                        .is_ok_and(|target| reserved.contains(&target))
+                })
+        };
+
+        let is_own_name = |target: &PathBuf| {
+            target == path
+                || (self.case_insensitive_target
+                    && target.to_string_lossy().to_lowercase()
+                        == path.to_string_lossy().to_lowercase())
+        };
+        let already_named = || {
+            info!(
+                "Skipping file because it already has the target name: {:?}",
+                path
+            );
+            Ok(PlanEntry::Skipped(SkippedFile {
+                source: path.clone(),
+                target: Some(path.clone()),
+                reason: SkipReason::AlreadyNamed,
+                date,
+                date_source,
+            }))
+        };
+
+        let mut new_path = new_file_path(&file_name_info)?;
+        if is_own_name(&new_path) {
+            return already_named();
+        }
+        let mut overwrite = false;
+        let mut deduplicate = target_reserved(&new_path, &file_name_info);
+
+        if !deduplicate && self.is_symlink_to(&new_path, path) {
+            match self.settings.existing_symlink {
+                ExistingSymlink::Skip => {
+                    info!(
+                        "Skipping file because the target is already a symlink to it: {:?} -> {:?}",
+                        path, new_path
+                    );
+                    return Ok(PlanEntry::Skipped(SkippedFile {
+                        source: path.clone(),
+                        target: Some(new_path),
+                        reason: SkipReason::SymlinkExists,
+                        date,
+                        date_source,
+                    }));
+                }
+                ExistingSymlink::Replace => {
+                    info!("Replacing existing symlink: {:?}", new_path);
+                    overwrite = true;
+                }
+            }
+        } else if target_exists(&new_path, &file_name_info) {
+            match self.settings.collision {
+                CollisionStrategy::Rename => deduplicate = true,
+                CollisionStrategy::Skip => {
+                    info!(
+                        "Skipping file because the target file already exists: {:?} -> {:?}",
+                        path, new_path
+                    );
+                    return Ok(PlanEntry::Skipped(SkippedFile {
+                        source: path.clone(),
+                        target: Some(new_path),
+                        reason: SkipReason::TargetExists,
+                        date,
+                        date_source,
+                    }));
+                }
+                CollisionStrategy::Overwrite => {
+                    info!("Overwriting existing target file: {:?}", new_path);
+                    overwrite = true;
+                }
+                CollisionStrategy::Fail => {
+                    return Err(Error::TargetExists(new_path).into());
+                }
+            }
+        }
+
+        // collisions with other planned actions are always resolved using the duplicate counter
+        if deduplicate {
+            let mut dup_counter = 0;
+            overwrite = false;
+
+            while target_exists(&new_path, &file_name_info)
+                || target_reserved(&new_path, &file_name_info)
+            {
+                debug!("Target file already exists: {:?}", new_path);
+
+                let identical = self.settings.collision == CollisionStrategy::Rename
+                    && self.path_exists(&new_path)
+                    && !reserved.contains(&new_path)
+                    && self
+                        .is_identical_file(path, &new_path)
+                        .unwrap_or_else(|err| {
+                            warn!(
+                                "Failed to compare file with existing target file {:?}: {}",
+                                new_path, err
+                            );
+                            false
+                        });
+                if identical {
+                    return self.plan_identical_file(
+                        path,
+                        &new_path,
+                        format_string,
+                        is_unknown_file,
+                        date,
+                        date_source,
+                    );
+                }
+
+                dup_counter += 1;
+                file_name_info.duplicate_counter = Some(dup_counter);
+                let candidate = new_file_path(&file_name_info)?;
+                if candidate == new_path {
+                    // the format string does not depend on the duplicate counter, the same target
+                    // would be computed forever
+                    return Err(anyhow!(
+                        "Target file already exists and can not be de-duplicated, the format string {:?} does not contain {{dup}}: {:?}",
+                        format_string,
+                        new_path
+                    ));
+                }
+                // e.g. `IMG_1-1.jpg` in place, its name is taken by `IMG_1.jpg`
+                if is_own_name(&candidate) {
+                    return already_named();
+                }
+                new_path = candidate;
+            }
+
+            info!("De-duplicated target file: {:?}", new_path);
+        }
+
+        if !new_path.starts_with(target_root) {
+            return Err(anyhow!(
+                "Target file is outside of the target directory: {:?}",
+                new_path
+            ));
+        }
+        if !self.settings.mkdir {
+            if let Some(parent) = new_path.parent().filter(|parent| !self.path_exists(parent)) {
+                return Err(anyhow!(
+                    "Target subfolder does not exist. Use --mkdir to create it: {:?}",
+                    parent
+                ));
+            }
+        }
+
+        let sidecars = sidecars
+            .iter()
+            .map(|sidecar| {
+                sidecar_target(sidecar, &new_path, &file_name_info)
+                    .map(|target| (sidecar.0.clone(), target))
+            })
+            .collect::<Result<_>>()?;
+
+        let action = PlannedAction {
+            source: path.clone(),
+            target: new_path,
+            operation: Operation::Transfer { overwrite },
+            date,
+            date_source,
+            sidecars,
+            duplicate_counter: file_name_info.duplicate_counter,
+            format: format_string.to_string(),
+            unknown_extension: is_unknown_file,
+        };
+        self.warn_long_paths(&action);
+        Ok(PlanEntry::Action(action))
+    }
+
+    /// Warns about targets of an action whose path length reaches `max_path_warn`.
+    fn warn_long_paths(&self, action: &PlannedAction) {
+        let Some(limit) = self.settings.max_path_warn else {
+            return;
+        };
+        for target in action.targets() {
+            let length = action::path_length(target);
+            if length >= limit {
+                warn!(
+                    "Target path is {} characters long, older tools may fail to access it: {:?}",
+                    length, target
+                );
+            }
+        }
+    }
+
+    /// Checks if a date lies within `since` and `until`, files without date are checked against the `nodate_filter`.
+    fn is_in_date_range(&self, date: Option<NaiveDateTime>) -> bool {
+        match date {
+            None => self.settings.nodate_filter == NodateFilter::Include,
+            Some(date) => {
+                self.settings.since.is_none_or(|since| date.date() >= since)
+                    && self.settings.until.is_none_or(|until| date.date() <= until)
+            }
+        }
+    }
+
+    fn is_sidecar_extension(&self, ext: Option<&OsStr>) -> bool {
+        ext.and_then(OsStr::to_str).is_some_and(|ext| {
+            let ext = ext.to_lowercase();
+            self.settings
+                .sidecar_extensions
+                .iter()
+                .any(|sidecar_ext| ext == sidecar_ext.to_lowercase())
+        })
+    }
+
+    /// Checks if an unknown file is processed using the unknown file format, `*` matches every extension (also files
+    /// without extension).
+    fn is_listed_unknown_extension(&self, ext: Option<&OsStr>) -> bool {
+        if self
+            .settings
+            .unknown_extensions
+            .iter()
+            .any(|unknown_ext| unknown_ext == "*")
+        {
+            return true;
+        }
+        ext.and_then(OsStr::to_str).is_some_and(|ext| {
+            let ext = ext.to_lowercase();
+            self.settings
+                .unknown_extensions
+                .iter()
+                .any(|unknown_ext| ext == unknown_ext.to_lowercase())
+        })
+    }
+
+    /// Finds the sidecar files of a file. Sidecar files either share the file stem with their
+    /// photo/video (`DSC01234.xmp`) or append their extension to the full file name (`DSC01234.arw.xmp`).
+    fn find_sidecars(&self, path: &Path) -> Vec<PathBuf> {
+        let mut sidecars = Vec::new();
+        let (Some(stem), Some(name)) = (path.file_stem(), path.file_name()) else {
+            return sidecars;
+        };
+
+        for base in [stem, name] {
+            for extension in &self.settings.sidecar_extensions {
+                let candidate = [extension.to_lowercase(), extension.to_uppercase()]
+                    .into_iter()
+                    .map(|extension| {
+                        let mut file_name = base.to_os_string();
+                        file_name.push(".");
+                        file_name.push(extension);
+                        path.with_file_name(file_name)
+                    })
+                    .find(|candidate| candidate.is_file());
+                if let Some(candidate) = candidate {
+                    trace!("Found sidecar file {:?} for {:?}", candidate, path);
+                    sidecars.push(candidate);
+                }
+            }
+        }
+        // the Takeout metadata file might be named differently, e.g. `IMG_1234.jpg.supplemental-metadata.json`
+        if self.settings.takeout_dates != TakeoutDates::Off
+            && self.is_sidecar_extension(Some(OsStr::new("json")))
+        {
+            if let Some(metadata) = analysis::takeout::find_metadata(path) {
+                if !sidecars.contains(&metadata) {
+                    trace!("Found Takeout metadata file {:?} for {:?}", metadata, path);
+                    sidecars.push(metadata);
+                }
+            }
+        }
+
+        sidecars
+    }
+
+    /// Checks if a file is the Google Takeout metadata file of a photo/video (see `takeout_dates`), its date is read
+    /// when the photo/video is analyzed.
+    fn is_takeout_metadata(&self, path: &Path) -> bool {
+        self.settings.takeout_dates != TakeoutDates::Off
+            && analysis::takeout::main_file(path).is_some_and(|main| {
+                main.is_file()
+                    && self
+                        .is_valid_extension(self.file_extension(&main).as_deref())
+                        .unwrap_or(false)
+            })
+    }
+
+    /// Finds the other file of a RAW+JPEG pair (see `pair_raw_jpeg`): a file with the same file stem in the same
+    /// directory, with a RAW extension for a JPEG file and vice versa. Both files must have one of the photo
+    /// extensions.
+    fn find_pair(&self, path: &Path) -> Option<PathBuf> {
+        if !self.settings.pair_raw_jpeg {
+            return None;
+        }
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        let partner_extensions = if JPEG_EXTENSIONS.contains(&extension.as_str()) {
+            RAW_EXTENSIONS
+        } else if RAW_EXTENSIONS.contains(&extension.as_str()) {
+            JPEG_EXTENSIONS
+        } else {
+            return None;
+        };
+        if !self
+            .is_valid_photo_extension(path.extension())
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        let partner = partner_extensions
+            .iter()
+            .flat_map(|extension| [extension.to_string(), extension.to_uppercase()])
+            .map(|extension| path.with_extension(extension))
+            .find(|candidate| {
+                candidate.is_file()
+                    && self
+                        .is_valid_photo_extension(candidate.extension())
+                        .unwrap_or(false)
+            });
+        if let Some(partner) = &partner {
+            trace!("Found RAW+JPEG pair {:?} and {:?}", path, partner);
+        }
+        partner
+    }
+
+    /// Finds the other file of a Live Photo (see `live_photos`): a file with the same file stem in the same directory,
+    /// with a video extension for a photo and vice versa. The photo must have one of the photo extensions, the video
+    /// must be processed on its own otherwise (one of the extensions, or a listed unknown extension if unknown files
+    /// are processed). If the Live Photo is verified, the content identifiers of both files must match.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The photo or video.
+    /// * `is_unknown_file` - Whether the file has none of the extensions, photos are only paired if they have one.
+    fn find_live_photo(&self, path: &Path, is_unknown_file: bool) -> Option<PathBuf> {
+        if self.settings.live_photos == LivePhotos::Off {
+            return None;
+        }
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        let is_photo = LIVE_PHOTO_EXTENSIONS.contains(&extension.as_str());
+        let partner_extensions = if is_photo {
+            LIVE_VIDEO_EXTENSIONS
+        } else if LIVE_VIDEO_EXTENSIONS.contains(&extension.as_str()) {
+            LIVE_PHOTO_EXTENSIONS
+        } else {
+            return None;
+        };
+        if is_photo && is_unknown_file {
+            return None;
+        }
+
+        let is_processed_photo = |photo: &Path| {
+            self.is_valid_photo_extension(photo.extension())
+                .unwrap_or(false)
+        };
+        let is_processed_video = |video: &Path| {
+            self.is_valid_extension(video.extension()).unwrap_or(false)
+                || (self.settings.unknown_file_format.is_some()
+                    && self.is_listed_unknown_extension(video.extension()))
+        };
+        let is_processed = |file: &Path, is_photo: bool| {
+            if is_photo {
+                is_processed_photo(file)
+            } else {
+                is_processed_video(file)
+            }
+        };
+        if !is_processed(path, is_photo) {
+            return None;
+        }
+
+        let partner = partner_extensions
+            .iter()
+            .flat_map(|extension| [extension.to_string(), extension.to_uppercase()])
+            .map(|extension| path.with_extension(extension))
+            .find(|candidate| candidate.is_file() && is_processed(candidate, !is_photo))?;
+        let (photo, video) = if is_photo {
+            (path, partner.as_path())
+        } else {
+            (partner.as_path(), path)
+        };
+        if self.settings.live_photos == LivePhotos::Verify && !self.is_same_live_photo(photo, video)
+        {
+            debug!(
+                "Not pairing {:?} and {:?}, their content identifiers differ",
+                photo, video
+            );
+            return None;
+        }
+        trace!("Found Live Photo {:?} and {:?}", photo, video);
+        Some(partner)
+    }
+
+    /// Checks if the content identifier of the video of a Live Photo matches the one of its photo. Files without
+    /// (readable) identifier do not match.
+    #[cfg(feature = "video")]
+    fn is_same_live_photo(&self, photo: &Path, video: &Path) -> bool {
+        let photo_identifier = File::open(photo)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| analysis::exif2date::get_exif_content_identifier(&file));
+        let video_identifier = analysis::video2date::get_video_content_identifier(video);
+        match (photo_identifier, video_identifier) {
+            (Ok(Some(photo_identifier)), Ok(Some(video_identifier))) => {
+                photo_identifier == video_identifier
+            }
+            (Err(err), _) | (_, Err(err)) => {
+                debug!(
+                    "Failed to read the content identifiers of {:?} and {:?}: {}",
+                    photo, video, err
+                );
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Verifying Live Photos requires the `video` feature (see `AnalyzerSettingsBuilder::build`).
+    #[cfg(not(feature = "video"))]
+    fn is_same_live_photo(&self, _photo: &Path, _video: &Path) -> bool {
+        false
+    }
+
+    /// Checks if there is a photo/video file the sidecar file belongs to.
+    fn has_main_file(&self, sidecar: &Path) -> bool {
+        let Some(stem) = sidecar.file_stem() else {
+            return false;
+        };
+
+        // DSC01234.arw.xmp
+        let full_name = sidecar.with_file_name(stem);
+        if full_name.is_file()
+            && self
+                .is_valid_extension(full_name.extension())
+                .unwrap_or(false)
+        {
+            return true;
+        }
+
+        // DSC01234.xmp, the extensions may be patterns, so the directory is listed (once for all its sidecars)
+        let parent = match sidecar.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut main_file_stems = self
+            .main_file_stems
+            .lock()
+            .expect("main file stems lock poisoned");
+        main_file_stems
+            .entry(parent.to_path_buf())
+            .or_insert_with(|| self.read_main_file_stems(parent))
+            .contains(stem)
+    }
+
+    /// Reads the stems of the photos and videos of a directory, see [`has_main_file`](#method.has_main_file).
+    fn read_main_file_stems(&self, directory: &Path) -> HashSet<OsString> {
+        match fs::read_dir(directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| self.is_valid_extension(path.extension()).unwrap_or(false))
+                .filter_map(|path| path.file_stem().map(OsStr::to_os_string))
+                .collect(),
+            Err(err) => {
+                warn!(
+                    "Error searching main files of sidecars in {:?}: {}",
+                    directory, err
+                );
+                HashSet::new()
+            }
+        }
+    }
+
+    /// Performs the configured file action, sets the modification time of the target to the date of the file
+    /// (if `set_mtime` is set) and records the action in the journal.
+    fn perform_file_action(
+        &self,
+        source: &Path,
+        target: &Path,
+        date: Option<NaiveDateTime>,
+        options: &ActionOptions,
+    ) -> Result<()> {
+        self.perform_file_action_as(source, target, &self.settings.action_type, date, options)
+    }
+
+    /// Performs a file action like [`perform_file_action`](#method.perform_file_action), with another action mode
+    /// (e.g. copying to the mirror directories instead of moving).
+    fn perform_file_action_as(
+        &self,
+        source: &Path,
+        target: &Path,
+        mode: &ActionMode,
+        date: Option<NaiveDateTime>,
+        options: &ActionOptions,
+    ) -> Result<()> {
+        let mut result =
+            action::file_action(source, target, mode, options).map_err(anyhow::Error::from);
+        if result.is_ok() {
+            self.strip_tags(source, target, mode);
+        }
+        if let (Ok(()), Some(date)) = (&result, self.mtime_date(mode, date)) {
+            result = match (&options.file_system, mode) {
+                (Some(file_system), ActionMode::Execute(_)) => file_system
+                    .set_modified(target, action::local_file_time(date))
+                    .map_err(|e| {
+                        anyhow!("Failed to set modification time: {:?} - {:?}", target, e)
+                    }),
+                _ => action::set_modification_time(target, date, mode),
+            };
+        }
+        if let ActionMode::Execute(action) = mode {
+            self.record_journal(JournalAction::File(*action), source, target, &result);
+        }
+        result
+    }
+
+    /// Removes the configured Exif tags (see `strip_tags`) from a copied file. Files that can not be stripped are
+    /// left unmodified with a warning, they do not fail the action. In dry-run mode the files that would be stripped
+    /// are logged.
+    fn strip_tags(&self, source: &Path, target: &Path, mode: &ActionMode) {
+        if self.settings.strip_tags.is_empty() {
+            return;
+        }
+        let tags = self
+            .settings
+            .strip_tags
+            .iter()
+            .map(StripTag::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        match mode {
+            ActionMode::DryRun(ActualAction::Copy) if strip::can_strip(source) => {
+                info!("[Strip {}] {:?}", tags, target);
+            }
+            ActionMode::Execute(ActualAction::Copy) if strip::can_strip(target) => {
+                match strip::strip_tags(target, &self.settings.strip_tags) {
+                    Ok(true) => debug!("Stripped {} tags of {:?}", tags, target),
+                    Ok(false) => {}
+                    Err(err) => warn!(
+                        "Failed to strip {} tags, the file was copied unmodified: {:?} - {}",
+                        tags, target, err
+                    ),
+                }
+            }
+            ActionMode::DryRun(ActualAction::Copy) | ActionMode::Execute(ActualAction::Copy) => {
+                warn!(
+                    "Only JPEG files can be stripped of {} tags, the file is copied unmodified: {:?}",
+                    tags, target
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// The date the modification time of a target file is set to: the date of the file if `set_mtime` is set and
+    /// the action creates a file of its own (not for symlinks).
+    fn mtime_date(&self, mode: &ActionMode, date: Option<NaiveDateTime>) -> Option<NaiveDateTime> {
+        let action = match mode {
+            ActionMode::Execute(action) | ActionMode::DryRun(action) => *action,
+        };
+        date.filter(|_| self.settings.set_mtime && action::supports_set_mtime(action))
+    }
+
+    /// Records a processed file in the state file, if a state file is configured: transferred files (and their
+    /// sidecars) and files skipped because they are already sorted. In dry-run mode nothing is recorded.
+    fn record_state(&self, outcome: &FileOutcome) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let Some(target) = &outcome.target else {
+            return;
+        };
+        match (outcome.action, outcome.skipped_reason) {
+            (OutcomeAction::Transfer(_) | OutcomeAction::Link, _) => {
+                state.record(&outcome.source, target);
+                for (sidecar, target) in &outcome.sidecars {
+                    state.record(sidecar, target);
+                }
+            }
+            (
+                OutcomeAction::Skip,
+                Some(
+                    SkipReason::IdenticalTarget
+                    | SkipReason::SymlinkExists
+                    | SkipReason::SourceDuplicate,
+                ),
+            ) => state.record(&outcome.source, target),
+            _ => {}
+        }
+    }
+
+    /// Appends an entry to the journal, if a journal is configured. Errors writing the journal are logged.
+    fn record_journal(
+        &self,
+        action: JournalAction,
+        source: &Path,
+        target: &Path,
+        result: &Result<()>,
+    ) {
+        if let Some(journal) = &self.journal {
+            if let Err(err) = journal.record(action, source, target, result) {
+                error!("Error writing journal entry for {:?}: {}", source, err);
+            }
+        }
+    }
+
+    /// The directory the targets of a file are relative to: the target directory, or the directory of the file
+    /// itself in place.
+    fn target_root<'a>(&'a self, path: &'a Path) -> &'a Path {
+        match path.parent() {
+            Some(parent) if self.settings.in_place => parent,
+            _ => &self.settings.target_dir,
+        }
+    }
+
+    /// Checks if a path (e.g. the parent directory of a target file) exists, symlinks are followed.
+    fn path_exists(&self, path: &Path) -> bool {
+        match &self.settings.file_system {
+            Some(file_system) => file_system.metadata(path).is_ok(),
+            None => action::extended_length_path(path).exists(),
+        }
+    }
+
+    /// Checks if two files have identical content, see [`action::is_identical_file`].
+    fn is_identical_file(&self, a: &Path, b: &Path) -> std::io::Result<bool> {
+        match &self.settings.file_system {
+            Some(file_system) => action::is_identical_file_on(&**file_system, a, b),
+            None => action::is_identical_file(a, b),
+        }
+    }
+
+    /// Checks if a target file exists. For case-insensitive targets, a file whose name only differs in case counts.
+    fn target_exists(&self, target: &Path) -> bool {
+        if let Some(file_system) = &self.settings.file_system {
+            file_system.exists(target)
+        } else if self.scan_target_case {
+            action::entry_exists_ignore_case(target)
+        } else {
+            action::entry_exists(target)
+        }
+    }
+
+    /// Checks if the action is a symlink action and the target is a symlink that already points at the source.
+    fn is_symlink_to(&self, target: &Path, source: &Path) -> bool {
+        let is_symlink_action = matches!(
+            self.settings.action_type,
+            ActionMode::Execute(ActualAction::RelativeSymlink | ActualAction::AbsoluteSymlink)
+                | ActionMode::DryRun(ActualAction::RelativeSymlink | ActualAction::AbsoluteSymlink)
+        );
+
+        if !is_symlink_action {
+            return false;
+        }
+        match &self.settings.file_system {
+            Some(file_system) => {
+                file_system
+                    .symlink_metadata(target)
+                    .is_ok_and(|metadata| metadata.is_symlink())
+                    && matches!(
+                        (file_system.canonicalize(source), file_system.canonicalize(target)),
+                        (Ok(source), Ok(target)) if source == target
+                    )
+            }
+            None => {
+                action::is_symlink(target) && action::is_same_file(source, target).unwrap_or(false)
+            }
+        }
+    }
+
+    /// The index of the target directory, built on first use. `None` if `dedup_target` is not set or the index
+    /// could not be built.
+    fn target_index(&self) -> Option<&TargetIndex> {
+        self.target_index
+            .get_or_init(|| {
+                if !self.settings.dedup_target {
+                    return None;
+                }
+                if !self.settings.target_dir.exists() {
+                    // dry run with a target directory that is not created yet
+                    return Some(TargetIndex::default());
+                }
+
+                let cache =
+                    self.settings.index_cache.clone().unwrap_or_else(|| {
+                        self.settings.target_dir.join(index::INDEX_CACHE_FILE_NAME)
+                    });
+                let threads = default_threads();
+
+                let update_cache = matches!(self.settings.action_type, ActionMode::Execute(_));
+
+                match TargetIndex::build(
+                    &self.settings.target_dir,
+                    Some(&cache),
+                    update_cache,
+                    threads,
+                ) {
+                    Ok(index) => {
+                        info!("Indexed {} files in the target directory", index.len());
+                        Some(index)
+                    }
+                    Err(err) => {
+                        error!("Error indexing the target directory: {}", err);
+                        None
+                    }
+                }
+            })
+            .as_ref()
+    }
+
+    /// The total size of the files a plan copies: all transferred files (including sidecar files) in copy mode, the
+    /// files moved to another file system in move mode. Identical files are linked, not copied.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u64>` - The size in bytes, `None` if the plan copies no files (e.g. it links them or is a dry-run).
+    fn copied_bytes(&self, plan: &Plan) -> Option<u64> {
+        if self.settings.file_system.is_some() {
+            return None;
+        }
+        let transferred = plan
+            .actions
+            .iter()
+            .filter(|action| matches!(action.operation, Operation::Transfer { .. }));
+        // the file systems are compared once per pair of source and target directory
+        let mut crosses = HashMap::new();
+        let copied: Vec<_> = match self.settings.action_type {
+            ActionMode::Execute(ActualAction::Copy) => transferred.collect(),
+            ActionMode::Execute(ActualAction::Move) => transferred
+                .filter(|action| {
+                    *crosses
+                        .entry((action.source.parent(), action.target.parent()))
+                        .or_insert_with(|| {
+                            action::crosses_file_systems(&action.source, &action.target)
+                        })
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        if copied.is_empty() {
+            return None;
+        }
+        let size = |path: &Path| fs::metadata(path).map_or(0, |metadata| metadata.len());
+        Some(
+            copied
+                .iter()
+                .map(|action| {
+                    size(&action.source)
+                        + action
+                            .sidecars
+                            .iter()
+                            .map(|(sidecar, _)| size(sidecar))
+                            .sum::<u64>()
+                })
+                .sum(),
+        )
+    }
+
+    /// The `ActionOptions` derived from the settings.
+    fn action_options(&self) -> ActionOptions {
+        ActionOptions {
+            mkdir: self.settings.mkdir,
+            reflink_fallback: self.settings.reflink_fallback,
+            hardlink_fallback: self.settings.hardlink_fallback,
+            symlink_fallback: self.settings.symlink_fallback,
+            symlink_privilege: self.symlink_privilege.clone(),
+            overwrite: false,
+            verify: self.settings.verify,
+            use_trash: self.settings.use_trash,
+            copy_progress: self.copy_progress.clone(),
+            bandwidth_limiter: self.bandwidth_limiter.clone(),
+            journal: self.journal.clone(),
+            cancel: None,
+            report: None,
+            file_system: self.settings.file_system.clone(),
+        }
+    }
+
+    /// Plans a file whose content is identical to an existing target file. The file is skipped, in move
+    /// mode the source file is deleted if `prune_source_duplicates` is set.
+    fn plan_identical_file(
+        &self,
+        path: &PathBuf,
+        existing: &PathBuf,
+        format_string: &str,
+        unknown_extension: bool,
+        date: Option<NaiveDateTime>,
+        date_source: DateSource,
+    ) -> Result<PlanEntry> {
+        let is_move = matches!(
+            self.settings.action_type,
+            ActionMode::Execute(ActualAction::Move) | ActionMode::DryRun(ActualAction::Move)
+        );
+
+        if is_move
+            && self.settings.prune_source_duplicates
+            && !action::is_same_file(path, existing)?
+        {
+            return Ok(PlanEntry::Action(PlannedAction {
+                source: path.clone(),
+                target: existing.clone(),
+                operation: Operation::DeleteDuplicate,
+                date,
+                date_source,
+                sidecars: Vec::new(),
+                duplicate_counter: None,
+                format: format_string.to_string(),
+                unknown_extension,
+            }));
+        }
+
+        info!(
+            "Skipping file because an identical target file already exists: {:?} = {:?}",
+            path, existing
+        );
+        Ok(PlanEntry::Skipped(SkippedFile {
+            source: path.clone(),
+            target: Some(existing.clone()),
+            reason: SkipReason::IdenticalTarget,
+            date,
+            date_source,
+        }))
+    }
+
+    fn is_valid_photo_extension(&self, ext: Option<&OsStr>) -> Result<bool> {
+        match ext {
+            None => Ok(false),
+            Some(ext) => {
+                let ext = ext.to_string_lossy().to_lowercase();
+                Ok(self.photo_extensions.matches(&ext))
+            }
+        }
+    }
+
+    #[cfg(feature = "video")]
+    fn is_valid_video_extension(&self, ext: Option<&OsStr>) -> Result<bool> {
+        match ext {
+            None => Ok(false),
+            Some(ext) => {
+                let ext = ext.to_string_lossy().to_lowercase();
+                Ok(self.video_extensions.matches(&ext))
+            }
+        }
+    }
+
+    /// The extension of a file. Depending on `sniff_content`, the extension of the type detected from the content is
+    /// used instead, if it is one of the configured extensions.
+    fn file_extension<'a>(&self, path: &'a Path) -> Option<Cow<'a, OsStr>> {
+        let extension = path.extension();
+        let sniff = match self.settings.sniff_content {
+            SniffContent::Off => false,
+            SniffContent::NoExtension => extension.is_none(),
+            SniffContent::Unmatched => {
+                !self.is_valid_extension(extension).unwrap_or(false)
+                    && !self.is_sidecar_extension(extension)
+            }
+        };
+        if sniff {
+            if let Some(sniffed) = analysis::sniff::sniff_extension(path).filter(|sniffed| {
+                self.is_valid_extension(Some(OsStr::new(sniffed)))
+                    .unwrap_or(false)
+            }) {
+                trace!(
+                    "Detected extension {:?} from the content of {:?}",
+                    sniffed,
+                    path
+                );
+                return Some(Cow::Owned(sniffed.into()));
+            }
+        }
+        extension.map(Cow::Borrowed)
+    }
+
+    /// The type of a file, derived from its extension (see `file_extension`).
+    fn file_type(&self, path: &Path) -> Result<FileType> {
+        let extension = self.file_extension(path);
+        #[cfg(feature = "video")]
+        if self.is_valid_video_extension(extension.as_deref())? {
+            return Ok(FileType::Video);
+        }
+        if self.is_valid_photo_extension(extension.as_deref())? {
+            return Ok(FileType::Image);
+        }
+        Ok(FileType::None)
+    }
+
+    /// The (innermost) source directory containing a file, the file was found in it.
+    fn source_dir_of(&self, path: &Path) -> Option<&PathBuf> {
+        let parent = path.parent()?;
+        self.settings
+            .source_dirs
+            .iter()
+            .filter(|source| parent.starts_with(source))
+            .max_by_key(|source| source.components().count())
+    }
+
+    /// The label of the source directory of a file (see `source_labels`), empty if it has none.
+    fn source_label(&self, path: &Path) -> &str {
+        self.source_dir_of(path)
+            .and_then(|source| self.settings.source_labels.get(source))
+            .map_or("", String::as_str)
+    }
+
+    /// The directory of a file relative to the (innermost) source directory containing it, joined with `/`. Only
+    /// normal components are kept and their separators are removed, so the path can not leave the target directory.
+    /// The path is empty for files directly in a source directory.
+    fn relative_source_dir(&self, path: &Path) -> String {
+        let relative = self.source_dir_of(path).and_then(|source| {
+            path.parent()
+                .and_then(|parent| parent.strip_prefix(source).ok())
+        });
+        let Some(relative) = relative else {
+            return String::new();
+        };
+        relative
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => {
+                    Some(name.to_string_lossy().replace("/", "").replace("\\", ""))
+                }
+                _ => None,
+            })
+            .filter(|component| !component.is_empty() && component != "..")
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// The format string of a file with one of the configured extensions. Videos use the video formats, if they are set.
+    #[cfg_attr(not(feature = "video"), allow(unused_variables))]
+    fn file_format(&self, file_type: FileType, has_date: bool) -> &str {
+        #[cfg(feature = "video")]
+        if file_type == FileType::Video {
+            let video_format = if has_date {
+                &self.settings.video_file_format
+            } else {
+                &self.settings.video_nodate_file_format
+            };
+            if let Some(video_format) = video_format {
+                return video_format;
+            }
+        }
+        if has_date {
+            &self.settings.file_format
+        } else {
+            &self.settings.nodate_file_format
+        }
+    }
+
+    fn is_valid_extension(&self, ext: Option<&OsStr>) -> Result<bool> {
+        let valid_photo = self.is_valid_photo_extension(ext)?;
+        #[cfg(feature = "video")]
+        let valid_video = self.is_valid_video_extension(ext)?;
+        #[cfg(not(feature = "video"))]
+        let valid_video = false;
+        Ok(valid_photo || valid_video)
+    }
+}
+
+/// The canonical path of an existing path. Otherwise, the missing components are appended to the canonical path of
+/// the nearest existing ancestor.
+fn absolute_path(path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            absolute_path(parent).join(name)
+        }
+        _ => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// Extracts the IO error (e.g. permission denied) of a failed Exif analysis, other errors (corrupt or unsupported
+/// Exif data) are returned unchanged. A file that ends unexpectedly is not an IO error, its Exif data is truncated.
+fn exif_io_error(err: anyhow::Error) -> std::result::Result<std::io::Error, anyhow::Error> {
+    let err = match err.downcast::<exif::Error>() {
+        Ok(exif::Error::Io(err)) => err,
+        Ok(err) => return Err(err.into()),
+        Err(err) => err.downcast::<std::io::Error>()?,
+    };
+    if err.kind() == std::io::ErrorKind::UnexpectedEof {
+        return Err(err.into());
+    }
+    Ok(err)
+}
+
+/// Extensions of JPEG files, see `pair_raw_jpeg`
+const JPEG_EXTENSIONS: &[&str] = &["jpg", "jpeg"];
+
+/// Extensions of RAW files, see `pair_raw_jpeg`
+const RAW_EXTENSIONS: &[&str] = &[
+    "3fr", "ari", "arw", "cr2", "cr3", "crw", "dcr", "dng", "erf", "iiq", "k25", "kdc", "mef",
+    "mos", "mrw", "nef", "nrw", "orf", "pef", "raf", "raw", "rw2", "rwl", "sr2", "srf", "srw",
+    "x3f",
+];
+
+/// Extensions of the photos of Live Photos, see `live_photos`
+const LIVE_PHOTO_EXTENSIONS: &[&str] = &["heic", "heif", "jpg", "jpeg"];
+
+/// Extensions of the videos of Live Photos, see `live_photos`
+const LIVE_VIDEO_EXTENSIONS: &[&str] = &["mov"];
+
+/// Checks if a file has one of the extensions of the videos of Live Photos.
+fn is_live_photo_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| LIVE_VIDEO_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Checks if a file has one of the RAW extensions.
+fn is_raw_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| RAW_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Computes the target path of a sidecar file from the target path of its photo/video, keeping the
+/// naming style of the sidecar file (`DSC01234.xmp` or `DSC01234.arw.xmp`). Google Takeout metadata files get the
+/// second style (`IMG_1234.jpg.json`), whatever their name.
+fn sidecar_target_path(source: &Path, sidecar: &Path, target: &Path) -> PathBuf {
+    let extension = sidecar.extension().unwrap_or_default();
+    if sidecar.file_stem() == source.file_name()
+        || analysis::takeout::main_file(sidecar).as_deref() == Some(source)
+    {
+        let mut file_name = target.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(extension);
+        target.with_file_name(file_name)
+    } else {
+        target.with_extension(extension)
+    }
 }
 
-/// Finds all files in a source directory and its subdirectories.
+/// Finds all files in a source directory and its subdirectories, directories are traversed in natural order of the
+/// names of their entries. Symlinks are followed, symlink loops are detected.
 ///
 /// # Arguments
 /// * `directory` - The directory to search for files.
@@ -634,19 +4007,1141 @@ pub fn find_files_in_source(
     recursive: bool,
     result: &mut Vec<PathBuf>,
 ) -> Result<()> {
-    let entries = fs::read_dir(directory)?;
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_dir() {
-            if recursive {
-                debug!("Processing subfolder: {:?}", path);
-                find_files_in_source(path, recursive, result)?;
+    let options = ScanOptions {
+        max_depth: if recursive { None } else { Some(1) },
+        exclude: &ExcludePatterns::default(),
+        min_size: None,
+        max_size: None,
+        follow_symlinks: true,
+        skip_hidden: SkipHidden::None,
+        use_ignore_files: false,
+        sorted: true,
+        file_system: &RealFileSystem,
+    };
+    let mut files = FileIter::new(vec![directory], options);
+    while let Some(file) = files.next_file() {
+        result.push(file?);
+    }
+    Ok(())
+}
+
+/// The result of a file of a streaming run, see [`Analyzer::run_streaming`].
+enum StreamedFile {
+    Done(FileOutcome),
+    Invalid,
+    Failed(Error),
+}
+
+/// The result of analyzing a file for planning, see [`Analyzer::plan_file`]: either the file is planned completely
+/// (e.g. skipped), or its target is computed from the analysis.
+enum PlanStep<'a> {
+    Done(PlanEntry),
+    Analyzed(AnalyzedFile<'a>),
+}
+
+/// The analysis of a file whose target is computed, it does not depend on the targets planned so far.
+///
+/// # Fields
+///
+/// * `is_unknown_file` - Whether the file is processed with the unknown file format.
+/// * `date` - The date of the file.
+/// * `date_source` - Where the date comes from.
+/// * `cleaned_name` - The cleaned original name of the file.
+/// * `media_info` - The duration and resolution of videos.
+/// * `format_string` - The format string of the target.
+/// * `sidecars` - The files following the file, paired with the file whose target name they get.
+struct AnalyzedFile<'a> {
+    is_unknown_file: bool,
+    date: Option<NaiveDateTime>,
+    date_source: DateSource,
+    cleaned_name: String,
+    media_info: MediaInfo,
+    format_string: &'a str,
+    sidecars: Vec<(PathBuf, PathBuf)>,
+}
+
+/// `SourceFiles` holds the files found in the source directories.
+///
+/// # Fields
+///
+/// * `files` - The files to process.
+/// * `filtered` - The files left out because of their size (see `min_size` and `max_size`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceFiles {
+    pub files: Vec<PathBuf>,
+    pub filtered: Vec<PathBuf>,
+}
+
+/// The options of scanning a source directory. A `max_depth` of 1 means only the immediate children of the
+/// source directory are found, `None` means unlimited depth. If `sorted` is set, the entries of a directory are
+/// visited in natural order of their names, otherwise in the order the file system lists them. `.photosortignore`
+/// files are read from the local file system.
+struct ScanOptions<'a> {
+    max_depth: Option<usize>,
+    exclude: &'a ExcludePatterns,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    follow_symlinks: bool,
+    skip_hidden: SkipHidden,
+    use_ignore_files: bool,
+    sorted: bool,
+    file_system: &'a dyn FileSystem,
+}
+
+impl ScanOptions<'_> {
+    fn filters_size(&self) -> bool {
+        self.min_size.is_some() || self.max_size.is_some()
+    }
+
+    fn is_in_size_range(&self, size: u64) -> bool {
+        self.min_size.is_none_or(|min_size| size >= min_size)
+            && self.max_size.is_none_or(|max_size| size <= max_size)
+    }
+}
+
+/// `FileIter` finds the files in the source directories lazily, so that files can be processed while the source
+/// directories are scanned. Directories are traversed depth-first like [`find_files_in_source`], only the entries of
+/// the directories on the current path are held in memory. Excluded directories are not descended into, files
+/// outside of the size range are collected separately (see [`take_filtered`](#method.take_filtered)). Errors reading
+/// a source directory are logged and end the scan of that source directory.
+pub struct FileIter<'a> {
+    options: ScanOptions<'a>,
+    sources: std::vec::IntoIter<PathBuf>,
+    root: PathBuf,
+    stack: Vec<ScanFrame>,
+    visited: HashSet<PathBuf>,
+    ignores: IgnoreFiles,
+    filtered: Vec<PathBuf>,
+}
+
+/// A directory on the current path of a `FileIter`: its remaining entries, which are at the given `depth`.
+struct ScanFrame {
+    entries: std::vec::IntoIter<filesystem::DirEntry>,
+    depth: usize,
+    has_ignore_file: bool,
+}
+
+impl<'a> FileIter<'a> {
+    fn new(sources: Vec<PathBuf>, options: ScanOptions<'a>) -> FileIter<'a> {
+        FileIter {
+            options,
+            sources: sources.into_iter(),
+            root: PathBuf::new(),
+            stack: Vec::new(),
+            visited: HashSet::new(),
+            ignores: IgnoreFiles::default(),
+            filtered: Vec::new(),
+        }
+    }
+
+    /// Returns the files left out because of their size (see `min_size` and `max_size`) since the last call.
+    pub fn take_filtered(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.filtered)
+    }
+
+    /// The next file, or the error that ended the scan of the current source directory.
+    fn next_file(&mut self) -> Option<Result<PathBuf>> {
+        loop {
+            let Some(frame) = self.stack.last_mut() else {
+                let source = self.sources.next()?;
+                info!("Processing source folder: {:?}", source);
+                self.root = source.clone();
+                self.visited.clear();
+                self.ignores = IgnoreFiles::default();
+                if let Err(err) = self.enter(source, 1) {
+                    return Some(Err(err));
+                }
+                continue;
+            };
+
+            let depth = frame.depth;
+            let Some(entry) = frame.entries.next() else {
+                if self.stack.pop().is_some_and(|frame| frame.has_ignore_file) {
+                    self.ignores.leave();
+                }
+                continue;
+            };
+            match self.visit(entry, depth) {
+                Ok(Some(path)) => return Some(Ok(path)),
+                Ok(None) => {}
+                Err(err) => {
+                    self.stack.clear();
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+
+    /// Lists the entries of a directory, they are at the given `depth` (1 for the entries of the source directory).
+    /// If symlinks are followed, `visited` holds the canonical paths of the visited directories to detect symlink
+    /// loops.
+    fn enter(&mut self, directory: PathBuf, depth: usize) -> Result<()> {
+        let file_system = self.options.file_system;
+        if self.options.follow_symlinks
+            && !self.visited.insert(file_system.canonicalize(&directory)?)
+        {
+            warn!(
+                "Not processing folder, it was already processed (symlink loop?): {:?}",
+                directory
+            );
+            return Ok(());
+        }
+
+        let has_ignore_file = self.options.use_ignore_files && self.ignores.enter(&directory);
+        let mut entries = file_system.read_dir(&directory)?;
+        if self.options.sorted {
+            entries.sort_by(|a, b| {
+                name::natural_cmp(
+                    &a.file_name().to_string_lossy(),
+                    &b.file_name().to_string_lossy(),
+                )
+            });
+        }
+        self.stack.push(ScanFrame {
+            entries: entries.into_iter(),
+            depth,
+            has_ignore_file,
+        });
+        Ok(())
+    }
+
+    /// Visits a directory entry: returns it if it is a file to process, descends into it if it is a directory.
+    fn visit(&mut self, entry: filesystem::DirEntry, depth: usize) -> Result<Option<PathBuf>> {
+        let options = &self.options;
+        let path = entry.path.clone();
+        if options.exclude.is_excluded(&path, &self.root) {
+            return Ok(None);
+        }
+
+        // the kind of the directory entry does not follow symlinks
+        let metadata = if entry.kind == FileKind::Symlink {
+            if !options.follow_symlinks {
+                debug!("Skipping symlink, symlinks are not followed: {:?}", path);
+                return Ok(None);
+            }
+            match options.file_system.metadata(&path) {
+                Ok(metadata) => Some(metadata),
+                Err(err) => {
+                    warn!("Skipping dangling symlink {:?}: {}", path, err);
+                    return Ok(None);
+                }
             }
         } else {
-            trace!("Found file: {:?}", &path);
-            result.push(path);
+            None
+        };
+        let is_dir = metadata
+            .as_ref()
+            .map_or(entry.kind == FileKind::Dir, |metadata| metadata.is_dir());
+        if options.skip_hidden.is_skipped(&entry.file_name(), is_dir) {
+            debug!("Skipping hidden file or OS litter: {:?}", path);
+            return Ok(None);
+        }
+        if options.use_ignore_files && self.ignores.is_ignored(&path, is_dir) {
+            return Ok(None);
+        }
+
+        if is_dir {
+            if options.max_depth.is_none_or(|max_depth| depth < max_depth) {
+                debug!("Processing subfolder: {:?}", path);
+                self.enter(path, depth + 1)?;
+            } else if options.max_depth != Some(1) {
+                debug!(
+                    "Not processing subfolder, maximum depth reached: {:?}",
+                    path
+                );
+            }
+            return Ok(None);
+        }
+
+        if options.filters_size() {
+            let size = match metadata {
+                Some(metadata) => metadata.len,
+                None => options.file_system.metadata(&path)?.len,
+            };
+            if !options.is_in_size_range(size) {
+                debug!(
+                    "Skipping file because of its size ({} bytes): {:?}",
+                    size, path
+                );
+                self.filtered.push(path);
+                return Ok(None);
+            }
         }
+        trace!("Found file: {:?}", &path);
+        Ok(Some(path))
+    }
+}
+
+impl Iterator for FileIter<'_> {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        loop {
+            match self.next_file()? {
+                Ok(path) => return Some(path),
+                Err(err) => error!("Error processing folder: {}", err),
+            }
+        }
+    }
+}
+
+/// Sorts a list of files, so that they are processed in a deterministic order. Files are grouped by
+/// their directory; within a directory, files are sorted in natural order by their file name
+/// (see [`name::natural_cmp`]), e.g. `DSC09999.arw` is sorted before `DSC10000.arw`.
+///
+/// # Arguments
+/// * `files` - The files to sort.
+pub fn sort_files(files: &mut [PathBuf]) {
+    files.sort_by(|a, b| {
+        let a_parent = a.parent().map(Path::to_string_lossy).unwrap_or_default();
+        let b_parent = b.parent().map(Path::to_string_lossy).unwrap_or_default();
+        let a_name = a
+            .file_name()
+            .map(OsStr::to_string_lossy)
+            .unwrap_or_default();
+        let b_name = b
+            .file_name()
+            .map(OsStr::to_string_lossy)
+            .unwrap_or_default();
+
+        name::natural_cmp(&a_parent, &b_parent).then_with(|| name::natural_cmp(&a_name, &b_name))
+    });
+}
+
+/// Sorts a list of files in the given order, see [`FileOrder`]. Files whose modification time can not be read
+/// are sorted first in the `Mtime` order.
+///
+/// # Arguments
+/// * `files` - The files to sort.
+/// * `order` - The order.
+pub fn order_files(files: &mut [PathBuf], order: FileOrder) {
+    match order {
+        FileOrder::Name => sort_files(files),
+        FileOrder::Mtime => {
+            sort_files(files);
+            // the sort is stable, files with the same modification time stay in name order
+            files.sort_by_cached_key(|file| fs::metadata(file).and_then(|m| m.modified()).ok());
+        }
+        FileOrder::None => {}
+    }
+}
+
+/// The default number of threads: the number of threads the system can run in parallel (see
+/// [`thread::available_parallelism`]), usually the number of CPUs, at least 1. It is used where `threads` is not set
+/// and for `auto`, see [`parse_threads`].
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, usize::from)
+}
+
+/// Parses a number of threads, see [`AnalyzerSettings::threads`]. `auto` is the default number of threads (see
+/// [`default_threads`]).
+///
+/// # Errors
+///
+/// This function will return an error if the string is neither `auto` nor a positive number.
+pub fn parse_threads(s: &str) -> Result<usize> {
+    if s.trim().eq_ignore_ascii_case("auto") {
+        return Ok(default_threads());
+    }
+    match s.trim().parse::<usize>() {
+        Ok(threads) if threads > 0 => Ok(threads),
+        _ => Err(anyhow!("Invalid number of threads: {:?}", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Creates a source and a target directory, the source directory contains the given files.
+    fn directories(files: &[(&str, &[u8])]) -> (TempDir, PathBuf, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        let target = dir.path().join("target");
+        for (name, content) in files {
+            let path = source.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        fs::create_dir_all(&target).unwrap();
+        (dir, source, target)
+    }
+
+    /// A minimal JPEG file whose Exif data contains the given date (`YYYY:MM:DD HH:MM:SS`).
+    fn exif_jpeg(date: &str) -> Vec<u8> {
+        let date = [date.as_bytes(), b"\0"].concat();
+        let mut tiff = b"MM\0\x2a".to_vec();
+        tiff.extend_from_slice(&8u32.to_be_bytes());
+        tiff.extend_from_slice(&1u16.to_be_bytes());
+        tiff.extend_from_slice(&0x0132u16.to_be_bytes());
+        tiff.extend_from_slice(&2u16.to_be_bytes());
+        tiff.extend_from_slice(&(date.len() as u32).to_be_bytes());
+        tiff.extend_from_slice(&26u32.to_be_bytes());
+        tiff.extend_from_slice(&0u32.to_be_bytes());
+        tiff.extend_from_slice(&date);
+        let segment = [&b"Exif\0\0"[..], &tiff].concat();
+        [
+            &[0xFF, 0xD8, 0xFF, 0xE1][..],
+            &(segment.len() as u16 + 2).to_be_bytes(),
+            &segment,
+            &[0xFF, 0xD9],
+        ]
+        .concat()
+    }
+
+    fn date_time(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    /// Analyzes files with the given analysis type, the files are created in a temporary directory.
+    fn analyze_dates(
+        analysis_type: AnalysisType,
+        files: &[(&str, &[u8])],
+    ) -> Vec<(Option<NaiveDateTime>, DateSource)> {
+        let (_dir, source, target) = directories(files);
+        let analyzer = builder(&source, &target)
+            .analysis_type(analysis_type)
+            .build()
+            .unwrap();
+        files
+            .iter()
+            .map(|(name, _)| {
+                let analysis = analyzer.analyze_file(&source.join(name)).unwrap();
+                (analysis.date, analysis.date_source)
+            })
+            .collect()
+    }
+
+    /// A builder copying the files of `source` to `target`, dates are only taken from the names.
+    fn builder(source: &Path, target: &Path) -> AnalyzerBuilder {
+        AnalyzerSettings::builder()
+            .source_dir(source)
+            .target_dir(target)
+            .analysis_type(AnalysisType::OnlyName)
+            .action_type(ActionMode::Execute(ActualAction::Copy))
+    }
+
+    /// Creates 64 distinct source files that all get the same target name, only the duplicate counter
+    /// distinguishes them.
+    fn same_target_sources() -> (TempDir, PathBuf, PathBuf, AnalyzerBuilder) {
+        let contents: Vec<(String, Vec<u8>)> = (0..64)
+            .map(|i| (format!("file{i}.jpg"), format!("content {i}").into_bytes()))
+            .collect();
+        let files: Vec<(&str, &[u8])> = contents
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.as_slice()))
+            .collect();
+        let (dir, source, target) = directories(&files);
+        let builder = builder(&source, &target).nodate_file_format("photo{-:dup}.{ext}");
+        (dir, source, target, builder)
+    }
+
+    /// Checks that every source file was copied to its own target.
+    fn assert_distinct_targets(source: &Path, target: &Path) {
+        let mut sources: Vec<Vec<u8>> = fs::read_dir(source)
+            .unwrap()
+            .map(|entry| fs::read(entry.unwrap().path()).unwrap())
+            .collect();
+        let mut targets: Vec<Vec<u8>> = fs::read_dir(target)
+            .unwrap()
+            .map(|entry| fs::read(entry.unwrap().path()).unwrap())
+            .collect();
+        sources.sort();
+        targets.sort();
+        assert_eq!(sources, targets);
+    }
+
+    #[test]
+    fn concurrent_runs_never_share_a_target() {
+        let (_dir, source, target, builder) = same_target_sources();
+        let analyzer = builder.build().unwrap();
+        let paths: Vec<PathBuf> = fs::read_dir(&source)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+
+        let outcomes: Vec<FileOutcome> = std::thread::scope(|scope| {
+            let workers: Vec<_> = paths
+                .chunks(8)
+                .map(|chunk| {
+                    let analyzer = &analyzer;
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|path| analyzer.run_file(path).unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        let targets: HashSet<&PathBuf> = outcomes
+            .iter()
+            .map(|outcome| outcome.target.as_ref().unwrap())
+            .collect();
+        assert_eq!(targets.len(), paths.len());
+        for outcome in &outcomes {
+            assert_eq!(
+                fs::read(outcome.target.as_ref().unwrap()).unwrap(),
+                fs::read(&outcome.source).unwrap()
+            );
+        }
+        assert_distinct_targets(&source, &target);
+    }
+
+    #[test]
+    fn exif_and_name_dates_must_agree() {
+        let exif = exif_jpeg("2023:07:14 10:20:30");
+        let close = exif_jpeg("2023:07:14 18:00:00");
+        let other = exif_jpeg("2020:01:01 08:00:00");
+        let results = analyze_dates(
+            AnalysisType::Both,
+            &[
+                ("20230714_102030.jpg", b"no exif"),
+                ("photo.jpg", &exif),
+                ("IMG_20230714_102030.jpg", &exif),
+                ("20230714_102031.jpg", &close),
+                ("20230714_102032.jpg", &other),
+                ("holiday.jpg", b"no exif"),
+            ],
+        );
+        assert_eq!(
+            results,
+            [
+                // only the name has a date
+                (None, DateSource::None),
+                // only the Exif data has a date
+                (None, DateSource::None),
+                // both agree exactly
+                (Some(date_time("2023-07-14 10:20:30")), DateSource::Exif),
+                // both agree within the tolerance
+                (Some(date_time("2023-07-14 18:00:00")), DateSource::Exif),
+                // both disagree
+                (None, DateSource::None),
+                // neither has a date
+                (None, DateSource::None),
+            ]
+        );
+    }
+
+    #[test]
+    fn name_then_exif_falls_back_to_exif() {
+        let exif = exif_jpeg("2023:07:14 10:20:30");
+        let other = exif_jpeg("2020:01:01 08:00:00");
+        let results = analyze_dates(
+            AnalysisType::NameThenExif,
+            &[
+                ("20230714_102030.jpg", b"no exif"),
+                ("photo.jpg", &exif),
+                ("IMG_20230714_102030.jpg", &exif),
+                ("20230714_102032.jpg", &other),
+                ("holiday.jpg", b"no exif"),
+            ],
+        );
+        assert_eq!(
+            results,
+            [
+                // only the name has a date
+                (Some(date_time("2023-07-14 10:20:30")), DateSource::Name),
+                // only the Exif data has a date
+                (Some(date_time("2023-07-14 10:20:30")), DateSource::Exif),
+                // both agree
+                (Some(date_time("2023-07-14 10:20:30")), DateSource::Name),
+                // both disagree, the name wins
+                (Some(date_time("2023-07-14 10:20:32")), DateSource::Name),
+                // neither has a date
+                (None, DateSource::None),
+            ]
+        );
+    }
+
+    #[test]
+    fn name_then_exif_sorts_unreadable_exif_as_nodate() {
+        let mut garbage = exif_jpeg("2023:07:14 10:20:30");
+        // the TIFF header of the Exif data is broken
+        garbage[12..16].copy_from_slice(b"XXXX");
+        let (_dir, source, target) = directories(&[("holiday.jpg", &garbage)]);
+        let analyzer = builder(&source, &target)
+            .analysis_type(AnalysisType::NameThenExif)
+            .file_format("{date}-{name}.{ext}")
+            .nodate_file_format("nodate/{name}.{ext}")
+            .mkdir(true)
+            .build()
+            .unwrap();
+
+        let outcome = analyzer.run_file(&source.join("holiday.jpg")).unwrap();
+        assert_eq!(outcome.date, None);
+        assert_eq!(outcome.target, Some(target.join("nodate/holiday.jpg")));
+        assert_eq!(
+            fs::read(target.join("nodate/holiday.jpg")).unwrap(),
+            garbage
+        );
+    }
+
+    #[test]
+    fn shuffled_files_are_sorted_in_shooting_order() {
+        let mut files: Vec<PathBuf> = [
+            "b/DSC10000.ARW",
+            "a/IMG_10.jpg",
+            "b/DSC09999.ARW",
+            "a/IMG_9.jpg",
+            "b/DSC09998.ARW",
+            "a/IMG_1.jpg",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        sort_files(&mut files);
+        assert_eq!(
+            files,
+            [
+                "a/IMG_1.jpg",
+                "a/IMG_9.jpg",
+                "a/IMG_10.jpg",
+                "b/DSC09998.ARW",
+                "b/DSC09999.ARW",
+                "b/DSC10000.ARW",
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn found_files_are_sorted_unless_disabled() {
+        let names = [
+            "DSC10000.jpg",
+            "DSC09998.jpg",
+            "DSC10001.jpg",
+            "DSC09999.jpg",
+        ];
+        let files: Vec<(&str, &[u8])> = names.iter().map(|name| (*name, &b""[..])).collect();
+        let (_dir, source, target) = directories(&files);
+        let found = |order: FileOrder| {
+            let analyzer = builder(&source, &target).order(order).build().unwrap();
+            let mut files = analyzer.find_files().files;
+            order_files(&mut files, order);
+            files
+                .iter()
+                .map(|file| file.file_name().unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            found(FileOrder::Name),
+            [
+                "DSC09998.jpg",
+                "DSC09999.jpg",
+                "DSC10000.jpg",
+                "DSC10001.jpg"
+            ]
+        );
+        let mut unsorted = found(FileOrder::None);
+        unsorted.sort();
+        assert_eq!(unsorted, found(FileOrder::Name));
+    }
+
+    #[test]
+    fn identical_existing_target_is_skipped() {
+        let (_dir, source, target) = directories(&[
+            ("same/photo.jpg", b"content"),
+            ("same_size/photo.jpg", b"contenT"),
+            ("other/photo.jpg", b"other content"),
+        ]);
+        fs::write(target.join("photo.jpg"), b"content").unwrap();
+        let analyzer = builder(&source, &target)
+            .recursive_source(true)
+            .nodate_file_format("{name}{-:dup}.{ext}")
+            .build()
+            .unwrap();
+
+        let outcome = analyzer.run_file(&source.join("same/photo.jpg")).unwrap();
+        assert_eq!(outcome.skipped_reason, Some(SkipReason::IdenticalTarget));
+        assert_eq!(outcome.target, Some(target.join("photo.jpg")));
+
+        // the sizes are equal, only the content tells the files apart
+        let outcome = analyzer
+            .run_file(&source.join("same_size/photo.jpg"))
+            .unwrap();
+        assert_eq!(outcome.skipped_reason, None);
+        assert_eq!(outcome.target, Some(target.join("photo-1.jpg")));
+
+        let outcome = analyzer.run_file(&source.join("other/photo.jpg")).unwrap();
+        assert_eq!(outcome.skipped_reason, None);
+        assert_eq!(outcome.target, Some(target.join("photo-2.jpg")));
+        assert_eq!(fs::read(target.join("photo.jpg")).unwrap(), b"content");
+        assert_eq!(fs::read(target.join("photo-1.jpg")).unwrap(), b"contenT");
+        assert_eq!(
+            fs::read(target.join("photo-2.jpg")).unwrap(),
+            b"other content"
+        );
+
+        // a later run finds a copy of an already sorted file behind the first taken name, too
+        fs::create_dir(source.join("again")).unwrap();
+        fs::write(source.join("again/photo.jpg"), b"contenT").unwrap();
+        let analyzer = builder(&source, &target)
+            .recursive_source(true)
+            .nodate_file_format("{name}{-:dup}.{ext}")
+            .build()
+            .unwrap();
+        let outcome = analyzer.run_file(&source.join("again/photo.jpg")).unwrap();
+        assert_eq!(outcome.skipped_reason, Some(SkipReason::IdenticalTarget));
+        assert_eq!(outcome.target, Some(target.join("photo-1.jpg")));
+        assert!(!target.join("photo-3.jpg").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn relative_symlinks_between_nested_directories() {
+        let (_dir, source, target) = directories(&[("a/b/photo.jpg", b"content")]);
+        let analyzer = builder(&source, &target)
+            .recursive_source(true)
+            .nodate_file_format("2020/07/{name}.{ext}")
+            .mkdir(true)
+            .action_type(ActionMode::Execute(ActualAction::RelativeSymlink))
+            .build()
+            .unwrap();
+
+        analyzer.run_file(&source.join("a/b/photo.jpg")).unwrap();
+        let link = target.join("2020/07/photo.jpg");
+        assert_eq!(
+            fs::read_link(&link).unwrap(),
+            Path::new("../../../source/a/b/photo.jpg")
+        );
+        assert_eq!(fs::read(&link).unwrap(), b"content");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlink_at_target_is_kept() {
+        let (_dir, source, target) = directories(&[("photo.jpg", b"content")]);
+        std::os::unix::fs::symlink("missing.jpg", target.join("photo.jpg")).unwrap();
+        let analyzer = builder(&source, &target)
+            .nodate_file_format("{name}{-:dup}.{ext}")
+            .build()
+            .unwrap();
+
+        let outcome = analyzer.run_file(&source.join("photo.jpg")).unwrap();
+        assert_eq!(outcome.target, Some(target.join("photo-1.jpg")));
+        assert_eq!(
+            fs::read_link(target.join("photo.jpg")).unwrap(),
+            Path::new("missing.jpg")
+        );
+        assert_eq!(fs::read(target.join("photo-1.jpg")).unwrap(), b"content");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn existing_symlink_to_the_source() {
+        for (existing_symlink, skipped_reason) in [
+            (ExistingSymlink::Skip, Some(SkipReason::SymlinkExists)),
+            (ExistingSymlink::Replace, None),
+        ] {
+            let (_dir, source, target) = directories(&[("photo.jpg", b"content")]);
+            std::os::unix::fs::symlink(source.join("photo.jpg"), target.join("photo.jpg")).unwrap();
+            let analyzer = builder(&source, &target)
+                .nodate_file_format("{name}{-:dup}.{ext}")
+                .action_type(ActionMode::Execute(ActualAction::RelativeSymlink))
+                .existing_symlink(existing_symlink)
+                .build()
+                .unwrap();
+
+            let outcome = analyzer.run_file(&source.join("photo.jpg")).unwrap();
+            assert_eq!(outcome.skipped_reason, skipped_reason);
+            assert_eq!(outcome.target, Some(target.join("photo.jpg")));
+            assert_eq!(fs::read(target.join("photo.jpg")).unwrap(), b"content");
+            assert_eq!(fs::read_dir(&target).unwrap().count(), 1);
+            // a replaced link is relative, the existing one is absolute
+            let link = fs::read_link(target.join("photo.jpg")).unwrap();
+            assert_eq!(link.is_relative(), skipped_reason.is_none());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn existing_symlink_to_another_file() {
+        for existing_symlink in [ExistingSymlink::Skip, ExistingSymlink::Replace] {
+            for collision in [CollisionStrategy::Rename, CollisionStrategy::Overwrite] {
+                let (_dir, source, target) =
+                    directories(&[("photo.jpg", b"content"), ("other.jpg", b"other content")]);
+                let other = source.join("other.jpg");
+                std::os::unix::fs::symlink(&other, target.join("photo.jpg")).unwrap();
+                let analyzer = builder(&source, &target)
+                    .nodate_file_format("{name}{-:dup}.{ext}")
+                    .action_type(ActionMode::Execute(ActualAction::RelativeSymlink))
+                    .existing_symlink(existing_symlink)
+                    .collision(collision)
+                    .build()
+                    .unwrap();
+
+                // the link is not counted as already linked, it is only replaced if overwriting is allowed
+                let outcome = analyzer.run_file(&source.join("photo.jpg")).unwrap();
+                assert_eq!(outcome.skipped_reason, None);
+                let link = target.join("photo.jpg");
+                if collision == CollisionStrategy::Overwrite {
+                    assert_eq!(outcome.target, Some(link.clone()));
+                    assert_eq!(fs::read(&link).unwrap(), b"content");
+                    assert_eq!(fs::read_dir(&target).unwrap().count(), 1);
+                } else {
+                    assert_eq!(outcome.target, Some(target.join("photo-1.jpg")));
+                    assert_eq!(fs::read_link(&link).unwrap(), other);
+                    assert_eq!(fs::read(target.join("photo-1.jpg")).unwrap(), b"content");
+                }
+            }
+        }
+    }
+
+    /// The names of the files found in the source directory, sorted.
+    fn found_names(analyzer: &Analyzer) -> Vec<String> {
+        let mut names: Vec<String> = analyzer
+            .find_files()
+            .files
+            .iter()
+            .map(|file| file.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn max_depth_limits_the_scan() {
+        let (_dir, source, target) = directories(&[
+            ("a.jpg", b""),
+            ("d1/b.jpg", b""),
+            ("d1/d2/c.jpg", b""),
+            ("d1/d2/d3/d.jpg", b""),
+        ]);
+        let found = |recursive: bool, max_depth: Option<usize>| {
+            let analyzer = builder(&source, &target)
+                .recursive_source(recursive)
+                .max_depth(max_depth)
+                .build()
+                .unwrap();
+            found_names(&analyzer)
+        };
+
+        assert_eq!(found(false, None), ["a.jpg"]);
+        assert_eq!(found(true, None), ["a.jpg", "b.jpg", "c.jpg", "d.jpg"]);
+        assert_eq!(found(true, Some(1)), ["a.jpg"]);
+        assert_eq!(found(true, Some(2)), ["a.jpg", "b.jpg"]);
+        assert_eq!(found(true, Some(3)), ["a.jpg", "b.jpg", "c.jpg"]);
+        assert_eq!(found(false, Some(2)), ["a.jpg", "b.jpg"]);
+        assert!(builder(&source, &target)
+            .max_depth(Some(0))
+            .build()
+            .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn max_depth_limits_symlinked_directories() {
+        let (dir, source, target) = directories(&[
+            ("a.jpg", b""),
+            ("d1/b.jpg", b""),
+            ("d1/real/c.jpg", b""),
+            ("d1/real/sub/d.jpg", b""),
+        ]);
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(outside.join("sub")).unwrap();
+        fs::write(outside.join("e.jpg"), b"").unwrap();
+        fs::write(outside.join("sub/f.jpg"), b"").unwrap();
+        // the linked directory sits at the same depth as `d1/real`
+        std::os::unix::fs::symlink(&outside, source.join("d1/link")).unwrap();
+        let found = |max_depth: Option<usize>| {
+            let analyzer = builder(&source, &target)
+                .recursive_source(true)
+                .follow_symlinks(true)
+                .max_depth(max_depth)
+                .build()
+                .unwrap();
+            found_names(&analyzer)
+        };
+
+        assert_eq!(found(Some(1)), ["a.jpg"]);
+        assert_eq!(found(Some(2)), ["a.jpg", "b.jpg"]);
+        assert_eq!(found(Some(3)), ["a.jpg", "b.jpg", "c.jpg", "e.jpg"]);
+        assert_eq!(
+            found(None),
+            ["a.jpg", "b.jpg", "c.jpg", "d.jpg", "e.jpg", "f.jpg"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_followed_without_loops() {
+        use std::os::unix::fs::symlink;
+
+        let (dir, source, target) = directories(&[("a.jpg", b""), ("real/b.jpg", b"")]);
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(outside.join("sub")).unwrap();
+        fs::write(outside.join("c.jpg"), b"").unwrap();
+        fs::write(outside.join("sub/d.jpg"), b"").unwrap();
+        // a loop back to the source directory, a second path to a directory of the source directory and links to
+        // a directory and a file outside of it
+        symlink(&source, source.join("real/loop")).unwrap();
+        symlink(source.join("real"), source.join("link")).unwrap();
+        symlink(outside.join("sub"), source.join("ext")).unwrap();
+        symlink(outside.join("c.jpg"), source.join("file.jpg")).unwrap();
+        symlink(outside.join("missing.jpg"), source.join("dangling.jpg")).unwrap();
+        let found = |follow_symlinks: bool| {
+            let analyzer = builder(&source, &target)
+                .recursive_source(true)
+                .follow_symlinks(follow_symlinks)
+                .build()
+                .unwrap();
+            found_names(&analyzer)
+        };
+
+        assert_eq!(found(false), ["a.jpg", "b.jpg"]);
+        assert_eq!(found(true), ["a.jpg", "b.jpg", "d.jpg", "file.jpg"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_are_analyzed_lossily() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let (_dir, source, target) = directories(&[]);
+        fs::create_dir_all(&source).unwrap();
+        // `café` in Latin-1
+        let path = source.join(OsStr::from_bytes(b"IMG_20230714_102030_caf\xe9.jpg"));
+        fs::write(&path, b"content").unwrap();
+        let analyzer = builder(&source, &target)
+            .file_format("{date?%Y}/{name}.{ext}")
+            .mkdir(true)
+            .build()
+            .unwrap();
+
+        let analysis = analyzer.analyze_file(&path).unwrap();
+        assert_eq!(analysis.date, Some(date_time("2023-07-14 10:20:30")));
+
+        let outcome = analyzer.run_file(&path).unwrap();
+        let expected = target.join("2023/caf\u{FFFD}.jpg");
+        assert_eq!(outcome.target, Some(expected.clone()));
+        assert_eq!(fs::read(expected).unwrap(), b"content");
+        assert_eq!(fs::read(&path).unwrap(), b"content");
+    }
+
+    #[test]
+    fn time_offset_shifts_all_dates() {
+        let exif = exif_jpeg("2023:12:31 23:30:00");
+        let (_dir, source, target) =
+            directories(&[("20231231_233000.jpg", b"name"), ("photo.jpg", &exif)]);
+        let analyzer = builder(&source, &target)
+            .analysis_type(AnalysisType::ExifThenName)
+            .time_offset(TimeDelta::hours(1))
+            .file_format("{date?%Y}/{date?%Y%m%d_%H%M%S}.{ext}")
+            .mkdir(true)
+            .build()
+            .unwrap();
+
+        for (name, source_of_date) in [
+            ("20231231_233000.jpg", DateSource::Name),
+            ("photo.jpg", DateSource::Exif),
+        ] {
+            let analysis = analyzer.analyze_file(&source.join(name)).unwrap();
+            assert_eq!(analysis.date, Some(date_time("2024-01-01 00:30:00")));
+            assert_eq!(analysis.date_source, source_of_date);
+        }
+        let outcome = analyzer
+            .run_file(&source.join("20231231_233000.jpg"))
+            .unwrap();
+        assert_eq!(
+            outcome.target,
+            Some(target.join("2024/20240101_003000.jpg"))
+        );
+    }
+
+    #[test]
+    fn configured_nodate_placeholder() {
+        let (dir, source, target) = directories(&[("beach.jpg", b"content")]);
+        let resorted = dir.path().join("resorted");
+        fs::create_dir_all(&resorted).unwrap();
+        let sort = |source: &Path, target: &Path, name: &str| {
+            let analyzer = builder(source, target)
+                .file_format("{date}_{name}.{ext}")
+                .nodate_file_format("{date}_{name}.{ext}")
+                .nodate_placeholder("(no.date)+")
+                .build()
+                .unwrap();
+            analyzer.run_file(&source.join(name)).unwrap().target
+        };
+
+        assert_eq!(
+            sort(&source, &target, "beach.jpg"),
+            Some(target.join("(no.date)+_beach.jpg"))
+        );
+        // the placeholder is removed from the names of files sorted before
+        assert_eq!(
+            sort(&target, &resorted, "(no.date)+_beach.jpg"),
+            Some(resorted.join("(no.date)+_beach.jpg"))
+        );
+    }
+
+    #[test]
+    fn configurable_name_cleaning() {
+        let target_name = |clean_names: bool, prefixes: &[&str], name: &str| {
+            let (_dir, source, target) = directories(&[(name, b"")]);
+            let analyzer = builder(&source, &target)
+                .nodate_file_format("{name}.{ext}")
+                .clean_names(clean_names)
+                .clean_prefixes(prefixes.iter().copied())
+                .build()
+                .unwrap();
+            let outcome = analyzer.run_file(&source.join(name)).unwrap();
+            let target = outcome.target.unwrap();
+            target.file_name().unwrap().to_string_lossy().to_string()
+        };
+
+        assert_eq!(
+            target_name(true, DEFAULT_CLEAN_PREFIXES, "IMG_1234.jpg"),
+            "1234.jpg"
+        );
+        assert_eq!(target_name(true, &["PXL"], "IMG_1234.jpg"), "IMG_1234.jpg");
+        assert_eq!(target_name(true, &["PXL"], "PXL_1234.jpg"), "1234.jpg");
+        assert_eq!(target_name(true, &[], "IMG_1234.jpg"), "IMG_1234.jpg");
+        assert_eq!(
+            target_name(false, DEFAULT_CLEAN_PREFIXES, "IMG_1234-NODATE.jpg"),
+            "IMG_1234-NODATE.jpg"
+        );
+        assert_eq!(
+            target_name(true, DEFAULT_CLEAN_PREFIXES, "IMG_1234-NODATE.jpg"),
+            "1234.jpg"
+        );
+    }
+
+    #[test]
+    fn sidecars_follow_their_main_files() {
+        let (_dir, source, target) = directories(&[
+            ("DSC01234.JPG", b""),
+            ("DSC01234.xmp", b""),
+            ("DSC05678.jpeg", b""),
+            ("DSC05678.jpeg.xmp", b""),
+            ("orphan.xmp", b""),
+            ("sub/orphan.jpg", b""),
+            ("sub/DSC01234.xmp", b""),
+        ]);
+        let analyzer = builder(&source, &target)
+            .recursive_source(true)
+            .sidecar_extensions(["xmp"])
+            .extensions(["jp*g"])
+            .nodate_file_format("{name}.{ext}")
+            .build()
+            .unwrap();
+        let mut files = analyzer.find_files().files;
+        sort_files(&mut files);
+
+        let plan = analyzer.plan_files(&files);
+        let sidecars: Vec<&PathBuf> = plan
+            .skipped
+            .iter()
+            .filter(|skipped| skipped.reason == SkipReason::Sidecar)
+            .map(|skipped| &skipped.source)
+            .collect();
+        assert_eq!(
+            sidecars,
+            [
+                &source.join("DSC01234.xmp"),
+                &source.join("DSC05678.jpeg.xmp")
+            ]
+        );
+        let action = |name: &str| {
+            plan.actions
+                .iter()
+                .find(|action| action.source == source.join(name))
+                .unwrap()
+        };
+        assert_eq!(
+            action("DSC01234.JPG").sidecars,
+            [(source.join("DSC01234.xmp"), target.join("DSC01234.xmp"))]
+        );
+        assert_eq!(
+            action("DSC05678.jpeg").sidecars,
+            [(
+                source.join("DSC05678.jpeg.xmp"),
+                target.join("DSC05678.jpeg.xmp")
+            )]
+        );
+        // sidecars without main file are processed like other files
+        for orphan in ["orphan.xmp", "sub/DSC01234.xmp"] {
+            assert!(plan
+                .skipped
+                .iter()
+                .any(|skipped| skipped.source == source.join(orphan)
+                    && skipped.reason == SkipReason::UnknownExtension));
+        }
+    }
+
+    #[test]
+    fn thread_counts() {
+        assert!(default_threads() >= 1);
+        assert_eq!(parse_threads("auto").unwrap(), default_threads());
+        assert_eq!(parse_threads(" AUTO ").unwrap(), default_threads());
+        assert_eq!(parse_threads("4").unwrap(), 4);
+        assert!(parse_threads("0").is_err());
+        assert!(parse_threads("-1").is_err());
+        assert!(parse_threads("many").is_err());
+    }
+
+    #[test]
+    fn existing_target_without_dup_is_not_overwritten() {
+        let (_dir, source, target) = directories(&[("photo.jpg", b"new")]);
+        fs::write(target.join("photo.jpg"), b"existing").unwrap();
+        let analyzer = builder(&source, &target)
+            .nodate_file_format("photo.{ext}")
+            .collision(CollisionStrategy::Rename)
+            .build()
+            .unwrap();
+        let path = source.join("photo.jpg");
+
+        let plan = analyzer.plan_files(std::slice::from_ref(&path));
+        assert!(plan.actions.is_empty());
+        assert_eq!(plan.diagnostics.len(), 1);
+        assert_eq!(plan.diagnostics[0].path, path);
+
+        assert!(analyzer.run_file(&path).is_err());
+        assert_eq!(fs::read(target.join("photo.jpg")).unwrap(), b"existing");
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn streaming_runs_never_share_a_target() {
+        let (_dir, source, target, builder) = same_target_sources();
+        let analyzer = builder.threads(Some(8)).build().unwrap();
+
+        let summary = analyzer.run_streaming(&CancellationToken::new());
+        assert!(!summary.cancelled);
+        assert_distinct_targets(&source, &target);
+    }
+
+    #[test]
+    fn cancelled_runs_do_not_link_duplicates() {
+        let (_dir, source, target) = directories(&[("a.jpg", b"same"), ("b.jpg", b"same")]);
+        let analyzer = builder(&source, &target)
+            .source_duplicates(SourceDuplicates::Hardlink)
+            .build()
+            .unwrap();
+        let plan = analyzer.plan();
+        let link = plan
+            .actions
+            .iter()
+            .find(|action| matches!(action.operation, Operation::LinkDuplicate { .. }))
+            .unwrap();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = analyzer
+            .execute_cancellable(link, Some(&token))
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::Cancelled(_))));
+        assert!(!link.target.exists());
     }
-    Ok(())
 }
@@ -0,0 +1,259 @@
+use chrono::{Datelike, NaiveDateTime};
+
+/// `DateLocale` holds the month and weekday names of a language, used for the `%B`, `%b` (`%h`), `%A` and `%a`
+/// specifiers of date formats (chrono always formats them in English).
+///
+/// # Fields
+///
+/// * `language` - The language code, e.g. `de`.
+/// * `months` - The full month names, starting with January.
+/// * `short_months` - The abbreviated month names, starting with January.
+/// * `weekdays` - The full weekday names, starting with Monday.
+/// * `short_weekdays` - The abbreviated weekday names, starting with Monday.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DateLocale {
+    pub language: &'static str,
+    pub months: [&'static str; 12],
+    pub short_months: [&'static str; 12],
+    pub weekdays: [&'static str; 7],
+    pub short_weekdays: [&'static str; 7],
+}
+
+pub const ENGLISH: DateLocale = DateLocale {
+    language: "en",
+    months: [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ],
+    short_months: [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ],
+    weekdays: [
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+    ],
+    short_weekdays: ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+};
+
+pub const GERMAN: DateLocale = DateLocale {
+    language: "de",
+    months: [
+        "Januar",
+        "Februar",
+        "März",
+        "April",
+        "Mai",
+        "Juni",
+        "Juli",
+        "August",
+        "September",
+        "Oktober",
+        "November",
+        "Dezember",
+    ],
+    short_months: [
+        "Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez",
+    ],
+    weekdays: [
+        "Montag",
+        "Dienstag",
+        "Mittwoch",
+        "Donnerstag",
+        "Freitag",
+        "Samstag",
+        "Sonntag",
+    ],
+    short_weekdays: ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+};
+
+pub const FRENCH: DateLocale = DateLocale {
+    language: "fr",
+    months: [
+        "janvier",
+        "février",
+        "mars",
+        "avril",
+        "mai",
+        "juin",
+        "juillet",
+        "août",
+        "septembre",
+        "octobre",
+        "novembre",
+        "décembre",
+    ],
+    short_months: [
+        "janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.", "nov.",
+        "déc.",
+    ],
+    weekdays: [
+        "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+    ],
+    short_weekdays: ["lun.", "mar.", "mer.", "jeu.", "ven.", "sam.", "dim."],
+};
+
+pub const SPANISH: DateLocale = DateLocale {
+    language: "es",
+    months: [
+        "enero",
+        "febrero",
+        "marzo",
+        "abril",
+        "mayo",
+        "junio",
+        "julio",
+        "agosto",
+        "septiembre",
+        "octubre",
+        "noviembre",
+        "diciembre",
+    ],
+    short_months: [
+        "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov", "dic",
+    ],
+    weekdays: [
+        "lunes",
+        "martes",
+        "miércoles",
+        "jueves",
+        "viernes",
+        "sábado",
+        "domingo",
+    ],
+    short_weekdays: ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+};
+
+pub const ITALIAN: DateLocale = DateLocale {
+    language: "it",
+    months: [
+        "gennaio",
+        "febbraio",
+        "marzo",
+        "aprile",
+        "maggio",
+        "giugno",
+        "luglio",
+        "agosto",
+        "settembre",
+        "ottobre",
+        "novembre",
+        "dicembre",
+    ],
+    short_months: [
+        "gen", "feb", "mar", "apr", "mag", "giu", "lug", "ago", "set", "ott", "nov", "dic",
+    ],
+    weekdays: [
+        "lunedì",
+        "martedì",
+        "mercoledì",
+        "giovedì",
+        "venerdì",
+        "sabato",
+        "domenica",
+    ],
+    short_weekdays: ["lun", "mar", "mer", "gio", "ven", "sab", "dom"],
+};
+
+pub const DUTCH: DateLocale = DateLocale {
+    language: "nl",
+    months: [
+        "januari",
+        "februari",
+        "maart",
+        "april",
+        "mei",
+        "juni",
+        "juli",
+        "augustus",
+        "september",
+        "oktober",
+        "november",
+        "december",
+    ],
+    short_months: [
+        "jan", "feb", "mrt", "apr", "mei", "jun", "jul", "aug", "sep", "okt", "nov", "dec",
+    ],
+    weekdays: [
+        "maandag",
+        "dinsdag",
+        "woensdag",
+        "donderdag",
+        "vrijdag",
+        "zaterdag",
+        "zondag",
+    ],
+    short_weekdays: ["ma", "di", "wo", "do", "vr", "za", "zo"],
+};
+
+/// The supported locales.
+pub const LOCALES: &[&DateLocale] = &[&ENGLISH, &GERMAN, &FRENCH, &SPANISH, &ITALIAN, &DUTCH];
+
+/// The default locale (English, as formatted by chrono).
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+impl DateLocale {
+    /// Looks up the locale of a language tag, e.g. `de-DE`, `de_DE` or `de`. Only the language is considered,
+    /// case-insensitively.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&'static DateLocale>` - The locale, or `None` if the language is not supported.
+    pub fn from_tag(tag: &str) -> Option<&'static DateLocale> {
+        let language = tag.split(['-', '_', '.']).next()?.to_lowercase();
+        LOCALES
+            .iter()
+            .find(|locale| locale.language == language)
+            .copied()
+    }
+
+    /// Formats a date with a chrono format string, using the month and weekday names of the locale.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The date to format.
+    /// * `format` - The chrono format string, e.g. `%d. %B %Y`.
+    pub fn format(&self, date: &NaiveDateTime, format: &str) -> String {
+        if self == &ENGLISH {
+            return date.format(format).to_string();
+        }
+
+        let month = date.month0() as usize;
+        let weekday = date.weekday().num_days_from_monday() as usize;
+        let mut localized = String::with_capacity(format.len());
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                localized.push(c);
+                continue;
+            }
+            // other specifiers (including `%%`) are left to chrono
+            match chars.next() {
+                Some('B') => localized.push_str(self.months[month]),
+                Some('b' | 'h') => localized.push_str(self.short_months[month]),
+                Some('A') => localized.push_str(self.weekdays[weekday]),
+                Some('a') => localized.push_str(self.short_weekdays[weekday]),
+                Some(specifier) => {
+                    localized.push('%');
+                    localized.push(specifier);
+                }
+                None => localized.push('%'),
+            }
+        }
+        date.format(&localized).to_string()
+    }
+}
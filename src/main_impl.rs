@@ -1,14 +1,35 @@
-use chrono::Utc;
-use clap::{arg, Parser};
+use chrono::{NaiveDate, TimeDelta, Utc};
+use clap::Parser;
 use fern::colors::{Color, ColoredLevelConfig};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use indicatif_log_bridge::LogWrapper;
-use log::{debug, error, info, LevelFilter};
-use photo_sort::{action, find_files_in_source, AnalysisType, Analyzer};
+use log::{debug, error, info, warn, LevelFilter};
+use photo_sort::action::CancellationToken;
+use photo_sort::filelist::CreatedFileList;
+use photo_sort::manifest::{Manifest, ManifestHash};
+use photo_sort::outcome::{FileOutcome, RunObserver};
+use photo_sort::plan::Plan;
+use photo_sort::progress::Progress;
+use photo_sort::report::{OutcomeLog, Report};
+use photo_sort::script::{self, ScriptFormat};
+use photo_sort::stats::DateStats;
+use photo_sort::strip::StripTag;
+use photo_sort::summary::{
+    RunSummary, EXIT_ACTION_FAILED, EXIT_INCONSISTENT, EXIT_INVALID_ARGUMENTS, EXIT_PLANNING_FAILED,
+};
+use photo_sort::verify::{self, VerifyReport};
+use photo_sort::{
+    action, analysis, order_files, AnalysisType, AnalyzerSettings, DatePriority, FileOrder,
+    LivePhotos, Sanitize, SniffContent, SourceDuplicates, TakeoutDates, TargetCase,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::Arc;
-use threadpool::ThreadPool;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 
 /// A simple command line tool to sort photos by date.
 #[derive(Parser, Debug)]
@@ -20,20 +41,71 @@ from the EXIF data or file name and renames the image file according to a given
 format string."
 )]
 struct Arguments {
-    /// The source directory to read the photos from.
-    #[arg(short, long, num_args = 1.., required = true)]
+    /// The source directory to read the photos from. A label can be appended with `=`, e.g. `/import/anna=anna`,
+    /// `{source}` is replaced with it.
+    #[arg(short, long, num_args = 1.., required_unless_present_any = ["revert", "verify_tree"])]
     source_dir: Vec<String>,
-    /// The target directory to write the sorted photos to.
-    #[arg(short, long)]
-    target_dir: String,
+    /// The target directory to write the sorted photos to. If several are given, the files are sorted into the first
+    /// one and replicated to the same paths in the others (mirrors). With the move action, files are moved to the first
+    /// target directory and copied to the mirrors. Files that can not be replicated to a mirror are reported, but do not
+    /// fail.
+    #[arg(short, long, num_args = 1.., required_unless_present_any = ["revert", "in_place"], conflicts_with = "in_place")]
+    target_dir: Vec<String>,
+    /// Renames the files in their own directories instead of moving them to a target directory. Files that already
+    /// have their target name are skipped. Only the move action is allowed, and existing files are not overwritten,
+    /// unless `--force` is given.
+    #[arg(long, default_value = "false")]
+    in_place: bool,
+    /// Allows other actions than move, and `--on-collision overwrite`, with `--in-place`.
+    #[arg(long, default_value = "false", requires = "in_place")]
+    force: bool,
     /// Whether to search the source directories recursively.
     /// If the flag is not set only immediate children of the source directories are considered.
     #[arg(short, long, default_value = "false")]
     recursive: bool,
+    /// Searches the source directories recursively up to this depth, 1 means only immediate children of the source
+    /// directories (the behavior without `--recursive`). Without this option `--recursive` has no depth limit.
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// If set, symlinks in the source directories are followed: symlinked directories are searched (symlink loops are
+    /// detected) and symlinked files are processed. Otherwise, symlinks are skipped.
+    #[arg(long, default_value = "false")]
+    follow_symlinks: bool,
+    /// Which hidden files are skipped, possible values are none, apple-double, all. Apple-double skips the `._*` files
+    /// macOS creates next to files on foreign file systems, all skips every file and directory starting with a dot.
+    /// Unless none, OS litter (.DS_Store, Thumbs.db, desktop.ini, @eaDir) is skipped, too.
+    #[arg(long, default_value = "apple-double")]
+    skip_hidden: photo_sort::SkipHidden,
+    /// Excludes files and directories matching a glob pattern (relative to the source directory, gitignore-style, `**`
+    /// supported), e.g. `@eaDir` or `2023/RAW_rejects`. Excluded directories are not searched. Can be repeated.
+    /// Patterns can also be listed in `.photosortignore` files (gitignore syntax, applying to their directory and below).
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// Skips files smaller than this size, e.g. `50K` to ignore thumbnails. Supported units are K, M and G.
+    #[arg(long, value_parser = action::parse_byte_size)]
+    min_size: Option<u64>,
+    /// Skips files larger than this size, e.g. `4G`. Supported units are K, M and G.
+    #[arg(long, value_parser = action::parse_byte_size)]
+    max_size: Option<u64>,
     /// Date format string to use as default date format.
     /// See [https://docs.rs/chrono/latest/chrono/format/strftime/index.html] for more information.
     #[arg(long, default_value = "%Y%m%d-%H%M%S")]
     date_format: String,
+    /// The locale of month and weekday names in dates (`%B`, `%b`, `%A` and `%a`), e.g. de-DE. Supported languages:
+    /// en, de, fr, es, it, nl. Other locales fall back to English.
+    #[arg(long, default_value = "en-US")]
+    locale: String,
+    /// The string `{date}` is replaced with for files without date. It is also removed from the names of files sorted
+    /// by previous runs.
+    #[arg(long, default_value = "NODATE")]
+    nodate_placeholder: String,
+    /// If set, names are not cleaned: the prefixes (see `clean_prefixes`) and nodate placeholders are kept in `{name}`.
+    #[arg(long, default_value = "false")]
+    no_clean_names: bool,
+    /// A comma separated list of prefixes removed from names for `{name}` (in upper or lower case), e.g.
+    /// IMG,VID,DSC,PXL.
+    #[arg(long, default_value = "MOV,VID,IMG", value_delimiter = ',', num_args = 0..)]
+    clean_prefixes: Vec<String>,
     /// The target file format. Everything outside a {...} block is copied as is. The target file format may contain "/" to
     /// indicate that the file should be placed in a subdirectory. Use the `--mkdir` flag to create the subdirectories.
     /// `{name}` is replaced with a filename without the date part.
@@ -45,6 +117,11 @@ struct Arguments {
     /// and rerunning the program again, the custom type will be seen as part of the file name.
     /// `{ext?upper/lower/copy}` is replaced with the original file extension. If `?upper` or `?lower` is specified, the extension will be made lower/upper case.
     ///      leaving out `?...` or using `copy` copies the original file extension.
+    /// `{duration}` is replaced with the length of a video in seconds, `{duration?hms}` with the length in the format `00h13m54s`.
+    /// `{vres}` is replaced with the vertical resolution of a video, e.g. `1080p`. Both are empty for images.
+    /// `{relpath}` is replaced with the directory of the file relative to its source directory, e.g. `2023/holiday`.
+    /// `{source}` is replaced with the label of the source directory of the file (see `--source-dir`), it is empty for
+    /// source directories without label.
     /// Commands of the form {label:cmd} are replaced by {cmd}; if the replacement string is not empty then a prefix of "label" is added.
     /// This might be useful to add separators only if there is e.g. a {dup} part.
     #[arg(short, long, default_value = "{type}{_:date}{-:name}{-:dup}.{ext}")]
@@ -57,32 +134,306 @@ struct Arguments {
     /// The target file format for files that do not match the specified extensions list. If not present
     /// files that do not match the extension list are ignored, hence not moved, copied etc. See the `file_format` for an extensive description
     /// of possible format values. By using `--unknown others/{name}{.:ext}` all unknown files are moved to the subdirectory "others" relative
-    /// to the target directory (specified by `--target-dir`).
+    /// to the target directory (specified by `--target-dir`). Use `--unknown others/{relpath}/{name}{.:ext}` to keep the
+    /// directory structure of the source directories.
     #[arg(long = "unknown")]
     unknown_file_format: Option<String>,
+    /// A comma separated list of the extensions of the files processed using the unknown format (e.g. gif,bmp,mp3).
+    /// Unknown files with other extensions are skipped. `*` matches every extension.
+    #[arg(long, default_value = "*", value_delimiter = ',', num_args = 0.., requires = "unknown_file_format")]
+    unknown_extensions: Vec<String>,
     /// If the file format contains a "/", indicating that the file should be placed in a subdirectory,
-    /// the mkdir flag controls if the tool is allowed to create non-existing subdirectories. No folder is created in dry-run mode.
+    /// the mkdir flag controls if the tool is allowed to create non-existing subdirectories. A missing target directory
+    /// is created as well. No folder is created in dry-run mode.
     #[arg(long, default_value = "false", alias = "mkdirs")]
     mkdir: bool,
-    /// A comma separated list of file extensions to include in the analysis.
+    /// A comma separated list of file extensions to include in the analysis. Extensions may be patterns, `*` matches
+    /// any number of characters and `?` a single character, e.g. `jp*g,tif*,cr2,nef`.
     #[arg(short, long, default_value = "jpg,jpeg,png,tiff,heif,heic,avif,webp", value_delimiter = ',', num_args = 0..)]
     extensions: Vec<String>,
+    /// A comma separated list of sidecar file extensions (e.g. xmp,aae,thm). Sidecar files sharing the name of a processed
+    /// photo/video (DSC01234.xmp or DSC01234.arw.xmp) are moved/copied together with it, getting the same target name.
+    #[arg(long, value_delimiter = ',', num_args = 0..)]
+    sidecars: Vec<String>,
+    /// Processes RAW and JPEG files with the same name in the same directory (DSC01234.arw and DSC01234.jpg) as a
+    /// pair: the RAW file is moved/copied together with the JPEG file, getting the same target name with its own
+    /// extension. The date of the JPEG file is used, or the date of the RAW file if only it has one. Both extensions
+    /// must be in the list of extensions.
+    #[arg(long, default_value = "false")]
+    pair_raw_jpeg: bool,
+    /// Processes the videos of Live Photos (IMG_1234.heic and IMG_1234.mov in the same directory) together with their
+    /// photo, getting the target name of the photo (with its date) with their own `{type}` and extension. Possible
+    /// values are name (pair by file name), verify (also compare the content identifiers of the files, requires video
+    /// support) and off.
+    #[arg(long, default_value = "name")]
+    live_photos: LivePhotos,
+    /// Detects the type of files from their content (e.g. exported JPEGs without extension), possible values are off,
+    /// no_extension (files without extension) and unmatched (also files with none of the extensions). Files whose
+    /// content matches one of the extensions are analyzed as such, `{ext}` and `{type}` use the detected type.
+    /// Without a value, no_extension is used.
+    #[arg(long, default_value = "off", default_missing_value = "no_extension", num_args = 0..=1)]
+    sniff_content: SniffContent,
+    /// Reads the dates of the listed files from a JSON dump of exiftool (`exiftool -json -r <dir>`), e.g. for formats
+    /// PhotoSort can not analyze: the DateTimeOriginal or CreateDate tag (time zones are ignored). Relative SourceFile
+    /// paths match the files whose path ends with them. Files that are not listed are analyzed as usual.
+    #[arg(long)]
+    metadata_json: Option<PathBuf>,
+    /// Whether the dates of the exiftool JSON are used instead of the date analysis (first) or only if the analysis
+    /// derives no date (fallback).
+    #[arg(long, default_value = "first", requires = "metadata_json")]
+    metadata_json_priority: DatePriority,
+    /// Reads the capture dates of Google Takeout metadata files (IMG_1234.jpg.json or
+    /// IMG_1234.jpg.supplemental-metadata.json, also IMG_1234.jpg(1).json for IMG_1234(1).jpg), possible values are
+    /// first (used instead of the date analysis), fallback (used if the analysis derives no date) and off. The dates
+    /// are in UTC. Read metadata files are moved/copied like sidecars if json is in --sidecars, otherwise they are
+    /// skipped. Without a value, fallback is used.
+    #[arg(long, default_value = "off", default_missing_value = "fallback", num_args = 0..=1)]
+    takeout_dates: TakeoutDates,
     #[cfg(feature = "video")]
-    /// A comma separated list of video extensions to include in the analysis.
+    /// A comma separated list of video extensions to include in the analysis. Extensions may be patterns, see
+    /// `--extensions`.
     #[arg(long, default_value = "mp4,mov,avi", value_delimiter = ',', num_args = 0..)]
     video_extensions: Vec<String>,
-    /// The sorting mode, possible values are name_then_exif, exif_then_name, only_name, only_exif.
+    #[cfg(feature = "video")]
+    /// The target file format of videos, defaults to the file format. Supports the same commands as --file-format.
+    #[arg(long = "video-format")]
+    video_file_format: Option<String>,
+    #[cfg(feature = "video")]
+    /// The target file format of videos without date, defaults to the nodate format. If neither is set, the video
+    /// format is used.
+    #[arg(long = "video-nodate")]
+    video_nodate_file_format: Option<String>,
+    #[cfg(feature = "perceptual-hash")]
+    /// Compares the photos by their perceptual hash and reports groups of near-duplicates (e.g. an original, a
+    /// re-compressed copy and a thumbnail), whose hashes differ in at most the given number of bits (0 to 64). Without
+    /// a value, 10 is used. Nothing is changed because of the report unless `--near-dup-format` is set.
+    #[arg(long, num_args = 0..=1, default_missing_value = "10", value_parser = clap::value_parser!(u32).range(0..=64))]
+    near_dups: Option<u32>,
+    #[cfg(feature = "perceptual-hash")]
+    /// Writes the report of `--near-dups` as JSON to the given file.
+    #[arg(long, requires = "near_dups")]
+    near_dups_json: Option<String>,
+    #[cfg(feature = "perceptual-hash")]
+    /// The target file format of near-duplicates except the largest image of each group, e.g.
+    /// `review/{date}_{name}.{ext}`. Supports the same commands as --file-format.
+    #[arg(long, requires = "near_dups")]
+    near_dup_format: Option<String>,
+    /// The sorting mode, possible values are name_then_exif, exif_then_name, only_name, only_exif, exif_and_name.
     /// Name analysis tries to extract the date from the file name, Exif analysis tries to extract the date from the EXIF data.
+    /// With exif_and_name, files only get a date if both dates agree (see `agreement_tolerance`), otherwise they
+    /// follow the `nodate` format.
     #[arg(short, long, default_value = "exif_then_name")]
     analysis_mode: AnalysisType,
-    /// The action mode, possible values are move, copy, hardlink, relative_symlink, absolute_symlink.
-    /// Move will move the files, Copy will copy the files, Hardlink (alias: hard) will create hardlinks, RelativeSymlink (alias: relsym) will create relative symlinks, AbsoluteSymlink (alias: abssym) will create absolute symlinks.
+    /// The maximum difference between the EXIF date and the name date of a file for the exif_and_name analysis mode,
+    /// e.g. 24h, 90m, 30s or 2d.
+    #[arg(long, default_value = "24h", value_parser = analysis::parse_duration)]
+    agreement_tolerance: TimeDelta,
+    /// A time offset added to every derived date (EXIF, name and video metadata) before formatting, e.g. +01:00 for a
+    /// camera clock that was an hour behind. Format: [+-]HH:MM[:SS] or a number of seconds.
+    #[arg(long, default_value = "0", allow_hyphen_values = true, value_parser = analysis::parse_time_offset)]
+    time_offset: TimeDelta,
+    /// Floors the dates in file names to a multiple of this number of seconds, e.g. 10, or 60 for the minute, so that
+    /// cameras whose clocks differ by a few seconds name the same moment alike. Filtering by date is not affected.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    round_seconds: Option<u32>,
+    /// If set, files whose EXIF data can not be parsed (e.g. truncated or unsupported) get no date in the only_exif
+    /// analysis mode and follow the `nodate` format, instead of failing. IO errors (e.g. permission denied) still fail
+    /// the file.
+    #[arg(long, default_value = "false")]
+    lenient_exif: bool,
+    /// The action mode, possible values are move, copy, hardlink, relative_symlink, absolute_symlink, reflink.
+    /// Move will move the files, Copy will copy the files, Hardlink (alias: hard) will create hardlinks, RelativeSymlink (alias: relsym) will create relative symlinks, AbsoluteSymlink (alias: abssym) will create absolute symlinks,
+    /// Reflink (alias: clone) will create copy-on-write clones on supporting file systems (e.g. btrfs, XFS, APFS).
     #[arg(short, long, default_value = "move")]
     move_mode: action::ActualAction,
+    /// What to do if a reflink can not be created (e.g. the file system does not support it), possible values are copy, error.
+    /// Copy will copy the file instead, Error will skip the file with an error.
+    #[arg(long, default_value = "copy")]
+    reflink_fallback: action::ReflinkFallback,
+    /// What to do if a hardlink can not be created (e.g. source and target are on different file systems), possible values
+    /// are copy, symlink, error. Copy will copy the file instead, Symlink will create an absolute symlink instead, Error will
+    /// skip the file with an error.
+    #[arg(long, default_value = "copy")]
+    hardlink_fallback: action::HardlinkFallback,
+    /// What to do if a symlink can not be created because the user lacks the privilege (on Windows, unless Developer
+    /// Mode is enabled), possible values are error, hardlink, copy. Error will skip the file with an error, Hardlink will
+    /// create a hardlink instead, Copy will copy the file instead.
+    #[arg(long, default_value = "error")]
+    symlink_fallback: action::SymlinkFallback,
+    /// What to do if the target file already exists, possible values are rename, skip, overwrite, fail.
+    /// Rename will increase the duplicate counter (see `{dup}`) until a free name is found, Skip will leave the file untouched,
+    /// Overwrite will replace the existing target file, Fail will skip the file with an error.
+    #[arg(long, default_value = "rename")]
+    on_collision: photo_sort::CollisionStrategy,
+    /// If set, target file names that only differ in case (e.g. IMG_1.JPG and IMG_1.jpg) collide and are de-duplicated
+    /// using `{dup}`, e.g. for targets later synced to a case-insensitive file system. Otherwise, this is only the case
+    /// if the file system of the target directory is case-insensitive (e.g. APFS or NTFS).
+    #[arg(long, default_value = "false")]
+    case_insensitive_target: bool,
+    /// Replaces characters of target names that are invalid on the target file system (e.g. `:` in
+    /// `{date?%H:%M}`), possible values are auto, windows, posix, off. Windows replaces the characters <>:"|?*\,
+    /// control characters and trailing dots and spaces, and avoids reserved names like CON. Posix only replaces NUL
+    /// characters. Auto uses the windows rules if the target directory is on a Windows file system (FAT, exFAT, NTFS).
+    #[arg(long, default_value = "auto")]
+    sanitize: Sanitize,
+    /// The character invalid characters of target names are replaced with, see `sanitize`.
+    #[arg(long, default_value = "_")]
+    sanitize_char: char,
+    /// Warns about planned targets whose absolute path is at least this many characters long and flags them in the
+    /// dry-run report, e.g. for tools limited by the 260 characters of MAX_PATH on Windows. Without a value, 240 is
+    /// used. PhotoSort itself accesses long paths on Windows in extended-length form.
+    #[arg(long, num_args = 0..=1, default_missing_value = "240")]
+    max_path_warn: Option<usize>,
+    /// In symlink modes: what to do if the target is already a symlink pointing at the source file (e.g. when
+    /// re-running the tool), possible values are skip, replace. Skip will leave the existing link untouched, Replace
+    /// will create a new link.
+    #[arg(long, default_value = "skip")]
+    existing_symlink: photo_sort::ExistingSymlink,
+    /// If a target file with identical content already exists, the file is skipped. In move mode, this flag deletes
+    /// the (duplicate) source file instead of leaving it in place.
+    #[arg(long, default_value = "false")]
+    prune_source_duplicates: bool,
+    /// What to do with a source file that has the same content as a source file transferred earlier in the run (e.g.
+    /// the same album synced to two phones), possible values are process, skip, hardlink. Process handles it like any
+    /// other file, Skip leaves it untouched, Hardlink creates its target as a hardlink to the target of the identical
+    /// file (in move mode the source is deleted). Only files of the same size are hashed.
+    #[arg(long, default_value = "process")]
+    source_duplicates: photo_sort::SourceDuplicates,
+    /// Only process files dated on or after this date (YYYY-MM-DD), other files are skipped.
+    #[arg(long)]
+    since: Option<NaiveDate>,
+    /// Only process files dated on or before this date (YYYY-MM-DD), other files are skipped.
+    #[arg(long)]
+    until: Option<NaiveDate>,
+    /// Whether files without a derived date are processed, possible values are include, exclude.
+    #[arg(long, default_value = "include")]
+    nodate_filter: photo_sort::NodateFilter,
+    /// If set, copied files (also when moving files across file systems) are verified by comparing the checksums of
+    /// source and target. In move mode the source is only deleted if the checksums match.
+    #[arg(long, default_value = "false")]
+    verify: bool,
+    /// If set, files are moved to the trash of the operating system instead of being deleted permanently, e.g. the source
+    /// file when moving across file systems, or the existing target file when overwriting.
+    #[arg(long, default_value = "false")]
+    use_trash: bool,
+    /// If set, source directories that are empty after a move run are removed (bottom-up). The source directories themselves
+    /// are kept, see `--prune-roots`. In dry-run mode the directories that would be removed are reported.
+    #[arg(long, default_value = "false")]
+    prune_empty_dirs: bool,
+    /// If set, `--prune-empty-dirs` also deletes files operating systems leave in folders (.DS_Store, Thumbs.db, desktop.ini),
+    /// so that directories only containing those are removed as well.
+    #[arg(long, default_value = "false", requires = "prune_empty_dirs")]
+    prune_litter: bool,
+    /// If set, `--prune-empty-dirs` also removes the source directories themselves if they are empty.
+    #[arg(long, default_value = "false", requires = "prune_empty_dirs")]
+    prune_roots: bool,
+    /// If set, the target directory is indexed (file size and checksum) and files whose content already exists anywhere
+    /// in the target directory, possibly under another name, are skipped. In move mode, `--prune-source-duplicates` deletes
+    /// those source files instead. Matches are confirmed by comparing the files byte by byte.
+    #[arg(long, default_value = "false")]
+    dedup_target: bool,
+    /// The cache file of the target directory index (see `--dedup-target`), which allows resuming the indexing and only
+    /// hashing new or modified files in later runs. Defaults to `.photosort-index.jsonl` in the target directory.
+    #[arg(long, requires = "dedup_target")]
+    index_cache: Option<String>,
+    /// Limits the throughput of file copies (also when moving across file systems), e.g. `40M` for 40 MiB/s. Supported units
+    /// are K, M and G. The limit holds in aggregate when using `--threads`. Moves within a file system are not affected.
+    #[arg(long, value_parser = action::parse_byte_size)]
+    bandwidth_limit: Option<u64>,
+    /// If set, the modification time of each target file is set to the derived date (interpreted in local time), e.g. for
+    /// gallery apps sorting by modification time. Files without a date and symlinks are left untouched; note that for
+    /// hardlinks the source file shares the modification time.
+    #[arg(long, default_value = "false")]
+    set_mtime: bool,
+    /// Removes groups of EXIF tags from the copied JPEG files, e.g. `gps` or `gps,serial` for a folder synced to a shared
+    /// album: gps removes the location, serial the serial numbers of camera and lens. Only the EXIF segment of the copies
+    /// is rewritten, the source files stay untouched. Other formats (e.g. HEIC, RAW) are copied unmodified with a warning.
+    /// Requires the copy action.
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    strip_tags: Vec<StripTag>,
+    /// If set, all performed actions are appended to the specified journal file (JSON lines), which allows reverting
+    /// them later using `--revert`.
+    #[arg(long)]
+    journal: Option<String>,
+    /// Reverts the actions recorded in the specified journal file instead of sorting files: moved files are moved back,
+    /// links are removed. Files that were modified since are skipped. Can be combined with `--dry-run`.
+    #[arg(long)]
+    revert: Option<String>,
+    /// If set, `--revert` also deletes copied files (as long as the source still exists).
+    #[arg(long, default_value = "false", requires = "revert")]
+    revert_delete_copies: bool,
+    /// If set, the processed source files (path, size, modification time and target) are recorded in the specified
+    /// state file. Files that are unchanged since a previous run are skipped as previously processed.
+    #[arg(long)]
+    state: Option<String>,
+    /// Discards the files recorded in the state file, so that all files are processed again.
+    #[arg(long, default_value = "false", requires = "state")]
+    reset_state: bool,
+    /// If set, only the plan (all source -> target actions and the files that can not be processed) is computed and
+    /// printed, no files are touched.
+    #[arg(long, default_value = "false", conflicts_with = "commit")]
+    plan_only: bool,
+    /// If set, the planned actions are written as a script to the specified file instead of being executed, e.g. to
+    /// review them before running the script. The script creates the target directories and performs the same
+    /// actions as a run (including duplicate counters), the header records the invocation. Setting the modification
+    /// time, stripping tags and mirror directories are not part of the script.
+    #[arg(long, conflicts_with_all = ["verify_tree", "plan_only"])]
+    export_script: Option<String>,
+    /// The shell of the exported script, possible values are sh (POSIX shell) and ps1 (PowerShell).
+    #[arg(long, default_value = "sh", requires = "export_script")]
+    export_script_format: ScriptFormat,
+    /// If set, the files are only processed if the complete plan is valid, i.e. all files could be analyzed and got a
+    /// valid target. Otherwise, files with problems are reported and all other files are processed.
+    #[arg(long, default_value = "false")]
+    commit: bool,
+    /// If set, the (first) target directory is verified instead of sorting files into it: every file is analyzed and
+    /// its computed path is compared with its actual path. Files in the wrong folder, with the wrong name, duplicates,
+    /// stray files (none of the extensions) and files that can not be analyzed are reported, nothing is changed. Exits
+    /// with code 4 if files do not match.
+    #[arg(long, default_value = "false", conflicts_with_all = ["in_place", "plan_only", "commit"])]
+    verify_tree: bool,
+    /// Writes the result of `--verify-tree` as JSON to the given file.
+    #[arg(long, requires = "verify_tree")]
+    verify_tree_json: Option<String>,
+    /// If set, `--verify-tree` moves the files in the wrong folder or with the wrong name to their computed path.
+    /// Duplicates are only reported.
+    #[arg(long, default_value = "false", requires = "verify_tree")]
+    apply_fixes: bool,
     /// Dry-run
     /// If set, the tool will not move any files but only print the actions it would take.
     #[arg(short = 'n', long, default_value = "false")]
     dry_run: bool,
+    /// If set, the report of a dry-run (source, target, action, date and date source of every file) is additionally
+    /// written as JSON to the specified file.
+    #[arg(long, requires = "dry_run")]
+    report: Option<String>,
+    /// If set, the outcome of every file (source, target, action, date, date source, duplicate counter, skip reason or
+    /// error) is written as JSON Lines to the specified file, followed by a summary line. `-` writes to stdout instead
+    /// of the summary table.
+    #[arg(long)]
+    outcome_log: Option<String>,
+    /// If set, a CSV row (original path, new path, capture date) is appended to the specified file for every
+    /// transferred file. A new manifest starts with a header row.
+    #[arg(long)]
+    manifest: Option<String>,
+    /// Adds a checksum column of the new file to the manifest, possible values are none, sha256.
+    #[arg(long, default_value = "none", requires = "manifest")]
+    manifest_hash: ManifestHash,
+    /// If set, the paths of the files created by the run (including sidecar files), relative to the target directory,
+    /// are written to the specified file, one per line, e.g. for `rsync --files-from`. A dry-run lists the files it
+    /// would create.
+    #[arg(long)]
+    files_from_out: Option<String>,
+    /// Separates the paths of the created files by NUL characters instead of line breaks, e.g. for `xargs -0`.
+    #[arg(long, default_value = "false", requires = "files_from_out")]
+    print0: bool,
+    /// If set, the number of placed files per year and month of their date (and the number of files without date or
+    /// with unknown extension) is printed after the summary, e.g. to spot implausible dates.
+    #[arg(long, default_value = "false")]
+    stats: bool,
+    /// If set, the statistics of `--stats` are written as JSON to the specified file.
+    #[arg(long)]
+    stats_json: Option<String>,
     /// Be verbose, if set, the tool will print more information about the actions it takes.
     #[arg(short, long, default_value = "false")]
     verbose: bool,
@@ -92,24 +443,82 @@ struct Arguments {
     /// Logfile, if set, the tool will log its output to the specified file. Appending to the specified file if it already exists.
     #[arg(short, long = "log")]
     logfile: Option<String>,
-    /// If set, suppresses the output of the tool to stdout/stderr. Only displaying error messages. Specifying a logfile at the same
-    /// time will redirect the full output that would have been displayed to stdout/stderr to the logfile. Specifying `--debug` or `--verbose`
-    /// plus `--quiet` without a logfile will result in an error.
+    /// Where the log output is written to, possible values are stdout, file (the file of `--log`), syslog and
+    /// journald (with the processed file as `PHOTOSORT_FILE` field). Multiple targets can be combined, e.g.
+    /// `--log-target journald,stdout`. Defaults to stdout and the file of `--log`, which is always written.
+    #[arg(long, value_delimiter = ',')]
+    log_target: Vec<LogTarget>,
+    #[cfg(feature = "tracing")]
+    /// Writes the spans of the processed files (file path, date source and format string) and the log messages in
+    /// them as JSON Lines to the given file, e.g. to follow a single file through a run with `--threads`.
+    #[arg(long)]
+    trace_json: Option<String>,
+    /// If set, suppresses the output of the tool to stdout/stderr. Only displaying error messages. Specifying a logfile (or a syslog/journald
+    /// log target) at the same time will redirect the full output that would have been displayed to stdout/stderr to it. Specifying
+    /// `--debug` or `--verbose` plus `--quiet` without a logfile will result in an error.
     #[arg(short, long, default_value = "false")]
     quiet: bool,
-    /// If set, display a progress bar while processing files.
+    /// If set, display a progress bar while processing files. When files are copied (also when moving across file
+    /// systems), the bar advances by the copied bytes and shows the throughput. With `--threads`, a line per worker
+    /// beneath the bar shows the file it processes.
     #[arg(short, long, default_value = "false")]
     progress: bool,
-    /// If set, use multi-threading
-    #[arg(long)]
+    /// If set, use multi-threading with the given number of threads. Without a value (or with `auto`), the number of
+    /// CPUs is used.
+    #[arg(long, num_args = 0..=1, default_missing_value = "auto", value_parser = photo_sort::parse_threads)]
     threads: Option<usize>,
+    /// The order files are processed in, possible values are name (grouped by directory, natural order of the file
+    /// names, e.g. DSC09999 before DSC10000), mtime (by modification time) and none (the order the file system lists
+    /// them, fastest). With name and mtime, duplicate counters are assigned the same way on every run.
+    #[arg(long, default_value = "name")]
+    order: FileOrder,
+    /// Same as `--order none`.
+    #[arg(long, default_value = "false", conflicts_with = "order")]
+    no_sort: bool,
+    /// If set, the run stops at the first file that can not be planned or processed, e.g. to try a new format string.
+    /// Files already being processed by other threads are finished, the summary lists the processed files.
+    #[arg(long, default_value = "false")]
+    fail_fast: bool,
+}
+
+/// `LogTarget` is an enumeration that defines where the log output is written to.
+///
+/// # Variants
+///
+/// * `Stdout` - The console (stdout, or stderr if the outcome log is written to stdout).
+/// * `File` - The file of `--log`.
+/// * `Syslog` - The local syslog daemon (RFC 3164).
+/// * `Journald` - The systemd journal, using its native protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum LogTarget {
+    Stdout,
+    File,
+    Syslog,
+    Journald,
+}
+
+impl FromStr for LogTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "stdout" => Ok(LogTarget::Stdout),
+            "file" => Ok(LogTarget::File),
+            "syslog" => Ok(LogTarget::Syslog),
+            "journald" => Ok(LogTarget::Journald),
+            _ => Err(anyhow::anyhow!("Invalid log target")),
+        }
+    }
 }
 
 fn setup_loggers<Q: AsRef<Path>>(
     general_log_level: LevelFilter,
     stdout_log_level: LevelFilter,
+    targets: &[LogTarget],
     file: Option<Q>,
     progress: Option<MultiProgress>,
+    log_to_stderr: bool,
+    trace: bool,
 ) -> anyhow::Result<()> {
     let colors = ColoredLevelConfig::new().info(Color::Green);
 
@@ -136,10 +545,19 @@ fn setup_loggers<Q: AsRef<Path>>(
                         .map_err(|err| anyhow::anyhow!("Failed to open log file: {:?}", err))?,
                 ),
         );
+    } else if targets.contains(&LogTarget::File) {
+        return Err(anyhow::anyhow!("The log target file requires --log <file>"));
+    }
+
+    if targets.contains(&LogTarget::Syslog) {
+        config = config.chain(syslog_output()?);
+    }
+    if targets.contains(&LogTarget::Journald) {
+        config = config.chain(journald_output()?);
     }
 
-    let (max_level, log) = config
-        .chain(
+    if targets.is_empty() || targets.contains(&LogTarget::Stdout) {
+        config = config.chain(
             fern::Dispatch::new()
                 .format(move |out, message, record| {
                     if message.to_string().starts_with("[") {
@@ -155,9 +573,15 @@ fn setup_loggers<Q: AsRef<Path>>(
                     }
                 })
                 .level(stdout_log_level)
-                .chain(std::io::stdout()),
-        )
-        .into_log();
+                .chain(if log_to_stderr {
+                    fern::Output::from(std::io::stderr())
+                } else {
+                    fern::Output::from(std::io::stdout())
+                }),
+        );
+    }
+    let (max_level, log) = config.into_log();
+    let log = with_tracing(log, trace);
 
     if let Some(bar) = progress {
         LogWrapper::new(bar, log)
@@ -173,6 +597,196 @@ fn setup_loggers<Q: AsRef<Path>>(
     Ok(())
 }
 
+/// Forwards the log messages to the loggers of the log targets and, as events of the current span, to the tracing
+/// subscriber of `--trace-json`.
+#[cfg(feature = "tracing")]
+struct TracingBridge {
+    log: Box<dyn log::Log>,
+    tracer: tracing_log::LogTracer,
+}
+
+#[cfg(feature = "tracing")]
+impl log::Log for TracingBridge {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.log.enabled(metadata) || self.tracer.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.log.enabled(record.metadata()) {
+            self.log.log(record);
+        }
+        if self.tracer.enabled(record.metadata()) {
+            self.tracer.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.log.flush();
+        self.tracer.flush();
+    }
+}
+
+/// Bridges the log messages to tracing if `--trace-json` is set.
+#[cfg(feature = "tracing")]
+fn with_tracing(log: Box<dyn log::Log>, trace: bool) -> Box<dyn log::Log> {
+    if trace {
+        Box::new(TracingBridge {
+            log,
+            tracer: tracing_log::LogTracer::new(),
+        })
+    } else {
+        log
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+fn with_tracing(log: Box<dyn log::Log>, _trace: bool) -> Box<dyn log::Log> {
+    log
+}
+
+/// Installs the tracing subscriber writing the spans (when they are closed) and events as JSON Lines to a file.
+#[cfg(feature = "tracing")]
+fn setup_tracing(path: &str) -> anyhow::Result<()> {
+    let file = std::fs::File::create(path)
+        .map_err(|err| anyhow::anyhow!("Failed to create trace file: {:?} - {:?}", path, err))?;
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_writer(Mutex::new(file))
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_thread_ids(true)
+        .with_max_level(tracing::Level::TRACE)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber)?;
+    Ok(())
+}
+
+/// The file of `--trace-json`, if set.
+fn trace_json(args: &Arguments) -> Option<&str> {
+    #[cfg(feature = "tracing")]
+    {
+        args.trace_json.as_deref()
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = args;
+        None
+    }
+}
+
+thread_local! {
+    /// The file the current thread is processing, recorded by [`CliObserver`] for the structured log targets. It is
+    /// kept after an error, so that the error is logged with the file.
+    static CURRENT_FILE: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// The syslog severity of a log level, trace and debug messages are both debug messages.
+fn syslog_severity(level: log::Level) -> u8 {
+    match level {
+        log::Level::Error => 3,
+        log::Level::Warn => 4,
+        log::Level::Info => 6,
+        log::Level::Debug | log::Level::Trace => 7,
+    }
+}
+
+/// The log output to the local syslog daemon, the log levels are mapped to the syslog severities by fern.
+#[cfg(unix)]
+fn syslog_output() -> anyhow::Result<fern::Output> {
+    let formatter = syslog::Formatter3164 {
+        facility: syslog::Facility::LOG_USER,
+        hostname: None,
+        process: env!("CARGO_PKG_NAME").to_string(),
+        pid: std::process::id(),
+    };
+    let logger = syslog::unix(formatter)
+        .map_err(|err| anyhow::anyhow!("Failed to connect to the local syslog daemon: {}", err))?;
+    Ok(logger.into())
+}
+
+#[cfg(not(unix))]
+fn syslog_output() -> anyhow::Result<fern::Output> {
+    Err(anyhow::anyhow!(
+        "The log target syslog is not supported on this platform"
+    ))
+}
+
+/// The socket of the native protocol of journald.
+#[cfg(unix)]
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// The log output to the systemd journal. Every message is sent as a datagram of fields: the message, its
+/// priority (the syslog severity of the log level), the source location and the file the logging thread is
+/// processing as `PHOTOSORT_FILE`, if any.
+#[cfg(unix)]
+fn journald_output() -> anyhow::Result<fern::Output> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()
+        .and_then(|socket| socket.connect(JOURNALD_SOCKET).map(|_| socket))
+        .map_err(|err| anyhow::anyhow!("Failed to connect to journald: {:?}", err))?;
+
+    Ok(fern::Output::call(move |record| {
+        let mut datagram = Vec::new();
+        journald_field(
+            &mut datagram,
+            "MESSAGE",
+            record.args().to_string().as_bytes(),
+        );
+        journald_field(
+            &mut datagram,
+            "PRIORITY",
+            syslog_severity(record.level()).to_string().as_bytes(),
+        );
+        journald_field(
+            &mut datagram,
+            "SYSLOG_IDENTIFIER",
+            env!("CARGO_PKG_NAME").as_bytes(),
+        );
+        journald_field(
+            &mut datagram,
+            "SYSLOG_PID",
+            std::process::id().to_string().as_bytes(),
+        );
+        journald_field(&mut datagram, "TARGET", record.target().as_bytes());
+        if let Some(file) = record.file() {
+            journald_field(&mut datagram, "CODE_FILE", file.as_bytes());
+        }
+        if let Some(line) = record.line() {
+            journald_field(&mut datagram, "CODE_LINE", line.to_string().as_bytes());
+        }
+        CURRENT_FILE.with_borrow(|path| {
+            if let Some(path) = path {
+                journald_field(
+                    &mut datagram,
+                    "PHOTOSORT_FILE",
+                    path.as_os_str().as_encoded_bytes(),
+                );
+            }
+        });
+        // a message that can not be logged can not be reported either
+        let _ = socket.send(&datagram);
+    }))
+}
+
+#[cfg(not(unix))]
+fn journald_output() -> anyhow::Result<fern::Output> {
+    Err(anyhow::anyhow!(
+        "The log target journald is not supported on this platform"
+    ))
+}
+
+/// Appends a field to a datagram of the native journald protocol. Values with line breaks are length-prefixed.
+#[cfg(unix)]
+fn journald_field(datagram: &mut Vec<u8>, name: &str, value: &[u8]) {
+    datagram.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        datagram.push(b'\n');
+        datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        datagram.push(b'=');
+    }
+    datagram.extend_from_slice(value);
+    datagram.push(b'\n');
+}
+
 pub fn main() {
     let args = Arguments::parse();
 
@@ -185,10 +799,15 @@ pub fn main() {
             log_level = LevelFilter::Trace;
         }
 
-        if args.quiet && args.logfile.is_none() {
+        let other_log_target = args.logfile.is_some()
+            || args
+                .log_target
+                .iter()
+                .any(|target| matches!(target, LogTarget::Syslog | LogTarget::Journald));
+        if args.quiet && !other_log_target {
             if args.debug || args.verbose {
-                eprintln!("Error: Cannot use --debug/--verbose with --quiet. Maybe you wanted to specify a --logfile to log the full output to, while suppressing the STDOUT/STDERR output?");
-                return;
+                eprintln!("Error: Cannot use --debug/--verbose with --quiet. Maybe you wanted to specify a --logfile (or --log-target) to log the full output to, while suppressing the STDOUT/STDERR output?");
+                std::process::exit(EXIT_INVALID_ARGUMENTS);
             }
 
             log_level = LevelFilter::Error;
@@ -206,184 +825,798 @@ pub fn main() {
     let multi = MultiProgress::new();
     let multi_clone = args.progress.then_some(multi.clone());
 
+    let trace_file = trace_json(&args).map(str::to_string);
     if let Err(e) = setup_loggers(
         log_level_general,
         console_log_level,
+        &args.log_target,
         args.logfile,
         multi_clone,
+        args.outcome_log.as_deref() == Some("-"),
+        trace_file.is_some(),
     ) {
         eprintln!("Error starting application: {:?}", e);
-        return;
+        std::process::exit(EXIT_INVALID_ARGUMENTS);
+    }
+    #[cfg(feature = "tracing")]
+    if let Err(e) = trace_file.as_deref().map(setup_tracing).transpose() {
+        eprintln!("Error starting application: {:?}", e);
+        std::process::exit(EXIT_INVALID_ARGUMENTS);
     }
 
     debug!("Initializing program");
 
     debug!("Video features enabled: {}", cfg!(feature = "video"));
+    debug!(
+        "Perceptual hash features enabled: {}",
+        cfg!(feature = "perceptual-hash")
+    );
+    debug!("Tracing features enabled: {}", cfg!(feature = "tracing"));
 
-    let result = Analyzer::new(photo_sort::AnalyzerSettings {
-        analysis_type: args.analysis_mode,
-        source_dirs: args.source_dir.iter().map(PathBuf::from).collect(),
-        target_dir: PathBuf::from(args.target_dir.as_str()),
-        recursive_source: args.recursive,
-        file_format: args.file_format.clone(),
-        nodate_file_format: args.nodate_file_format.unwrap_or(args.file_format.clone()),
-        unknown_file_format: args.unknown_file_format,
-        date_format: args.date_format.clone(),
-        extensions: args.extensions.clone(),
-        mkdir: args.mkdir,
-        action_type: if args.dry_run {
+    if let Some(journal) = args.revert {
+        info!("Reverting actions of journal {:?}", journal);
+        if let Err(e) =
+            photo_sort::journal::revert(journal, args.dry_run, args.revert_delete_copies)
+        {
+            eprintln!("{:?}", e);
+            std::process::exit(EXIT_ACTION_FAILED);
+        }
+        return;
+    }
+
+    let source_dirs: Vec<(PathBuf, Option<String>)> = args
+        .source_dir
+        .iter()
+        .map(|entry| split_source_label(entry))
+        .collect();
+    let builder = AnalyzerSettings::builder()
+        .analysis_type(args.analysis_mode)
+        .agreement_tolerance(args.agreement_tolerance)
+        .time_offset(args.time_offset)
+        .round_seconds(args.round_seconds)
+        .lenient_exif(args.lenient_exif)
+        .source_dirs(source_dirs.iter().map(|(dir, _)| dir))
+        .target_dir(args.target_dir.first().cloned().unwrap_or_default())
+        .mirror_dirs(args.target_dir.iter().skip(1))
+        .in_place(args.in_place)
+        .force(args.force)
+        .recursive_source(args.recursive)
+        .max_depth(args.max_depth)
+        .follow_symlinks(args.follow_symlinks)
+        .skip_hidden(args.skip_hidden)
+        .exclude(args.exclude)
+        .min_size(args.min_size)
+        .max_size(args.max_size)
+        .file_format(args.file_format)
+        .unknown_file_format(args.unknown_file_format)
+        .unknown_extensions(args.unknown_extensions)
+        .date_format(args.date_format)
+        .locale(args.locale)
+        .nodate_placeholder(args.nodate_placeholder)
+        .clean_names(!args.no_clean_names)
+        .clean_prefixes(args.clean_prefixes)
+        .extensions(args.extensions)
+        .mkdir(args.mkdir)
+        .reflink_fallback(args.reflink_fallback)
+        .hardlink_fallback(args.hardlink_fallback)
+        .symlink_fallback(args.symlink_fallback)
+        .collision(args.on_collision)
+        .sanitize(args.sanitize)
+        .sanitize_char(args.sanitize_char)
+        .max_path_warn(args.max_path_warn)
+        .target_case(if args.case_insensitive_target {
+            TargetCase::Insensitive
+        } else {
+            TargetCase::Auto
+        })
+        .existing_symlink(args.existing_symlink)
+        .prune_source_duplicates(args.prune_source_duplicates)
+        .source_duplicates(args.source_duplicates)
+        .since(args.since)
+        .until(args.until)
+        .nodate_filter(args.nodate_filter)
+        .verify(args.verify)
+        .use_trash(args.use_trash)
+        .sidecar_extensions(args.sidecars)
+        .pair_raw_jpeg(args.pair_raw_jpeg)
+        .live_photos(args.live_photos)
+        .sniff_content(args.sniff_content)
+        .metadata_json(args.metadata_json.clone())
+        .metadata_json_priority(args.metadata_json_priority)
+        .takeout_dates(args.takeout_dates)
+        .prune_empty_dirs(args.prune_empty_dirs)
+        .prune_litter(args.prune_litter)
+        .prune_roots(args.prune_roots)
+        .dedup_target(args.dedup_target)
+        .index_cache(args.index_cache)
+        .bandwidth_limit(args.bandwidth_limit)
+        .set_mtime(args.set_mtime)
+        .strip_tags(args.strip_tags.clone())
+        .journal(args.journal)
+        .state_file(args.state)
+        .reset_state(args.reset_state)
+        .threads(args.threads)
+        .fail_fast(args.fail_fast)
+        .order(if args.no_sort {
+            FileOrder::None
+        } else {
+            args.order
+        })
+        .action_type(if args.dry_run {
             action::ActionMode::DryRun(args.move_mode)
         } else {
             action::ActionMode::Execute(args.move_mode)
-        },
-        #[cfg(feature = "video")]
-        video_extensions: args.video_extensions.clone(),
-    });
-    let mut analyzer = match result {
+        });
+    #[cfg(feature = "video")]
+    let builder = builder
+        .video_extensions(args.video_extensions)
+        .video_file_format(args.video_file_format)
+        .video_nodate_file_format(args.video_nodate_file_format);
+    #[cfg(feature = "perceptual-hash")]
+    let builder = builder
+        .near_dup_distance(args.near_dups)
+        .near_dup_format(args.near_dup_format.clone());
+    let mut builder = match args.nodate_file_format {
+        Some(nodate_file_format) => builder.nodate_file_format(nodate_file_format),
+        None => builder,
+    };
+    if args.verify_tree {
+        // the sorted tree is planned as the source of a move into itself, without --apply-fixes only as a dry run
+        let target = args.target_dir.first().cloned().unwrap_or_default();
+        builder = builder
+            .source_dirs([target])
+            .recursive_source(true)
+            .max_depth(None)
+            .mirror_dirs(Vec::<PathBuf>::new())
+            .action_type(if args.apply_fixes && !args.dry_run {
+                action::ActionMode::Execute(action::ActualAction::Move)
+            } else {
+                action::ActionMode::DryRun(action::ActualAction::Move)
+            });
+    } else {
+        for (dir, label) in source_dirs {
+            if let Some(label) = label {
+                builder = builder.source_label(dir, label);
+            }
+        }
+    }
+
+    let mut analyzer = match builder.build() {
         Ok(a) => {
             debug!("Program initialized");
             a
         }
         Err(e) => {
-            eprintln!("{:?}", e);
-            return;
+            eprintln!("{:?}", anyhow::Error::from(e));
+            std::process::exit(EXIT_INVALID_ARGUMENTS);
         }
     };
 
-    // add file name -> date parsers
-    analyzer.add_transformer(photo_sort::analysis::filename2date::NaiveFileNameParser::default());
-
-    // add date -> file name formatters
-    analyzer.add_formatter(photo_sort::analysis::name_formatters::FormatName::default());
-    analyzer.add_formatter(photo_sort::analysis::name_formatters::FormatDuplicate::default());
-    analyzer.add_formatter(photo_sort::analysis::name_formatters::FormatDate::default());
-    analyzer.add_formatter(photo_sort::analysis::name_formatters::FormatFileType::default());
-    analyzer.add_formatter(photo_sort::analysis::name_formatters::FormatExtension::default());
+    let cancel = CancellationToken::new();
+    let progress = args.progress.then(|| {
+        let workers = analyzer.settings.threads.is_some_and(|threads| threads > 1);
+        Arc::new(CliProgress::new(multi.clone(), cancel.clone(), workers))
+    });
+    if let Some(progress) = &progress {
+        let multi = multi.clone();
+        let progress = progress.clone();
+        let bars = Mutex::new(HashMap::new());
+        analyzer.set_copy_progress(move |source: &Path, copied, total| {
+            let mut bars = bars.lock().expect("progress bar lock poisoned");
+            let (bar, reported) = bars.entry(source.to_path_buf()).or_insert_with(|| {
+                let bar = multi.add(ProgressBar::new(total));
+                bar.set_style(
+                    ProgressStyle::with_template(
+                        "  [{bytes}/{total_bytes}] [{wide_bar:.cyan/grey}] {binary_bytes_per_sec} {msg}",
+                    )
+                    .unwrap()
+                    .progress_chars("=>-"),
+                );
+                bar.set_message(
+                    source
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                );
+                (bar, 0)
+            });
+            // the run progresses by the bytes copied since the last report of the file
+            progress.copied(copied.saturating_sub(*reported));
+            *reported = copied;
+            bar.set_position(copied);
+            if copied >= total {
+                bar.finish_and_clear();
+                multi.remove(bar);
+                bars.remove(source);
+            }
+        });
+    }
 
     debug!("Running program");
 
-    let mut files = Vec::new();
+    // files are processed while the source folders are scanned, unless the whole list of files is needed: for
+    // sorting, the progress total, the plan or detecting identical source files and near-duplicates
+    let streaming = analyzer.settings.order == FileOrder::None
+        && !args.progress
+        && !args.dry_run
+        && !args.plan_only
+        && args.export_script.is_none()
+        && !args.verify_tree
+        && !args.commit
+        && analyzer.settings.source_duplicates == SourceDuplicates::Process
+        && !near_dups_enabled(&analyzer.settings);
+
+    let start = Instant::now();
+    let plan = if streaming {
+        debug!("Processing files while scanning the source folders");
+        None
+    } else {
+        let source_files = analyzer.find_files();
+        let mut files = source_files.files;
+
+        debug!("Found {} files in source folders", files.len());
+
+        order_files(&mut files, analyzer.settings.order);
 
-    for source_dir in &analyzer.settings.source_dirs {
-        info!("Processing source folder: {:?}", source_dir);
-        let result = find_files_in_source(
-            source_dir.clone(),
-            analyzer.settings.recursive_source,
-            &mut files,
+        let spinner = args.progress.then(|| {
+            let spinner = multi.add(ProgressBar::new_spinner());
+            spinner.set_message("Planning");
+            spinner.enable_steady_tick(Duration::from_millis(100));
+            spinner
+        });
+        let mut plan = analyzer.plan_files(&files);
+        plan.filtered = source_files.filtered;
+        if let Some(spinner) = spinner {
+            spinner.finish_and_clear();
+        }
+
+        debug!(
+            "Planned {} actions, {} files with problems",
+            plan.actions.len(),
+            plan.diagnostics.len()
         );
-        if let Err(err) = result {
-            error!("Error processing folder: {}", err);
+
+        #[cfg(feature = "perceptual-hash")]
+        if let Some(report) = &plan.near_duplicates {
+            if let Err(err) = report.write_table(&mut std::io::stdout().lock()) {
+                error!("Error printing near-duplicate report: {}", err);
+            }
+            if let Some(path) = &args.near_dups_json {
+                if let Err(err) = report.write_json(path) {
+                    error!("Error writing near-duplicate report: {}", err);
+                }
+            }
+        }
+
+        if args.plan_only {
+            for action in &plan.actions {
+                println!("{}", action);
+            }
+            exit_if_invalid(&plan);
+            return;
+        }
+        if let Some(path) = &args.export_script {
+            let invocation: Vec<String> = std::env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect();
+            if let Err(err) = script::export_script(
+                path,
+                &plan,
+                args.move_mode,
+                args.export_script_format,
+                &invocation,
+            ) {
+                error!("Error exporting script: {}", err);
+                std::process::exit(EXIT_ACTION_FAILED);
+            }
+            info!(
+                "Exported {} actions to script {:?}",
+                plan.actions.len(),
+                path
+            );
+            exit_if_invalid(&plan);
+            return;
+        }
+        if args.verify_tree {
+            let report = VerifyReport::from_plan(&plan);
+            if let Err(err) = report.write_table(&mut std::io::stdout().lock()) {
+                error!("Error printing verify report: {}", err);
+            }
+            if let Some(path) = &args.verify_tree_json {
+                if let Err(err) = report.write_json(path) {
+                    error!("Error writing verify report: {}", err);
+                }
+            }
+            if !args.apply_fixes {
+                if !report.is_consistent() {
+                    std::process::exit(EXIT_INCONSISTENT);
+                }
+                return;
+            }
+            plan = verify::fixes(plan);
+        }
+        Some(plan)
+    };
+
+    let outcome_log = match args
+        .outcome_log
+        .as_ref()
+        .map(|path| OutcomeLog::create(path, args.dry_run))
+    {
+        None => None,
+        Some(Ok(log)) => Some(Arc::new(log)),
+        Some(Err(err)) => {
+            error!("{:?}", err);
+            std::process::exit(EXIT_INVALID_ARGUMENTS);
+        }
+    };
+    let log_to_stdout = args.outcome_log.as_deref() == Some("-");
+
+    let files_from_out = match args.files_from_out.as_ref().map(|path| {
+        CreatedFileList::create(
+            path,
+            args.target_dir.first().cloned().unwrap_or_default(),
+            args.print0,
+        )
+    }) {
+        None => None,
+        Some(Ok(list)) => Some(list),
+        Some(Err(err)) => {
+            error!("{:?}", err);
+            std::process::exit(EXIT_INVALID_ARGUMENTS);
+        }
+    };
+
+    if let Some(plan) = &plan {
+        if args.commit && !plan.is_valid() {
+            error!(
+                "Not processing any files, {} files could not be planned",
+                plan.diagnostics.len()
+            );
+            if let Some(log) = &outcome_log {
+                log.write_invalid(plan);
+            }
+            exit_if_invalid(plan);
+        }
+
+        if args.dry_run {
+            if let Some(log) = &outcome_log {
+                log.write_plan(plan, &analyzer.settings.action_type);
+                let mut summary = RunSummary::from_plan(
+                    plan,
+                    &analyzer.settings.action_type,
+                    &analyzer.settings.source_dirs,
+                );
+                summary.elapsed = start.elapsed();
+                log.write_summary(&summary);
+            }
+            if let Some(list) = &files_from_out {
+                list.write_plan(plan, &analyzer.settings.action_type);
+            }
+            let stats = DateStats::from_plan(plan, &analyzer.settings.action_type);
+            if log_to_stdout {
+                write_stats(&stats, false, args.stats_json.as_deref());
+                exit_if_invalid(plan);
+                return;
+            }
+            let mut report = Report::from_plan(plan, args.move_mode, args.set_mtime);
+            report.rmdir = analyzer.prune_source_dirs(plan);
+            report.time_offset = analyzer.settings.time_offset.num_seconds();
+            if let Some(limit) = args.max_path_warn {
+                report.flag_long_paths(limit);
+            }
+            if !args.strip_tags.is_empty() {
+                report.flag_stripped();
+            }
+            if let Err(err) = report.write_table(&mut std::io::stdout().lock()) {
+                error!("Error printing report: {}", err);
+            }
+            if let Some(path) = args.report {
+                if let Err(err) = report.write_json(path) {
+                    error!("Error writing report: {}", err);
+                }
+            }
+            write_stats(&stats, args.stats, args.stats_json.as_deref());
+            exit_if_invalid(plan);
+            return;
         }
     }
 
-    debug!("Found {} files in source folders", files.len());
+    let manifest = match args
+        .manifest
+        .as_ref()
+        .map(|path| Manifest::open(path, args.manifest_hash))
+    {
+        None => None,
+        Some(Ok(manifest)) => Some(manifest),
+        Some(Err(err)) => {
+            error!("{:?}", err);
+            std::process::exit(EXIT_INVALID_ARGUMENTS);
+        }
+    };
 
-    let threadpool = args.threads.map(|v| v.max(1)).map(ThreadPool::new);
-    let (sender, receiver) = channel();
+    if let (Some(log), Some(plan)) = (&outcome_log, &plan) {
+        log.write_invalid(plan);
+    }
+    let stats = (args.stats || args.stats_json.is_some()).then(Arc::<Mutex<DateStats>>::default);
+    if outcome_log.is_some()
+        || manifest.is_some()
+        || files_from_out.is_some()
+        || stats.is_some()
+        || args.log_target.contains(&LogTarget::Journald)
+        || progress
+            .as_ref()
+            .is_some_and(|progress| progress.workers.is_some())
+    {
+        analyzer.set_observer(Arc::new(CliObserver {
+            outcome_log: outcome_log.clone(),
+            manifest,
+            files_from_out,
+            stats: stats.clone(),
+            progress: progress.clone(),
+        }));
+    }
 
-    let context = match threadpool {
-        None => ExecutionContext::SingleThreaded(Box::new(NormalContext { analyzer })),
-        Some(pool) => ExecutionContext::MultiThreaded(ThreadPoolContext {
-            output: sender,
-            receiver,
-            pool,
-            analyzer: Arc::new(analyzer),
-        }),
+    if let Some(progress) = progress {
+        analyzer.set_progress(progress);
+    }
+
+    cancel_on_interrupt(&cancel);
+    let mut summary = match plan {
+        Some(plan) => analyzer.run_with_cancel(plan, &cancel),
+        None => analyzer.run_streaming(&cancel),
     };
 
-    let jobs = files.len();
+    if summary.total.hardlink_fallbacks > 0 {
+        warn!(
+            "{} hardlinks could not be created and were substituted (see --hardlink-fallback)",
+            summary.total.hardlink_fallbacks
+        );
+    }
 
-    if args.progress {
-        let bar = ProgressBar::new(files.len() as u64);
-        bar.set_style(
-            ProgressStyle::with_template(
-                "{spinner:.green} [{elapsed_precise}] [{wide_bar:.green/grey}] {pos}/{len} ({eta})",
-            )
-            .unwrap()
-            .progress_chars("=>-"),
+    if !summary.undeleted_sources.is_empty() {
+        warn!(
+            "{} files were copied but their source could not be deleted, they exist twice:",
+            summary.undeleted_sources.len()
         );
+        for (source, target) in &summary.undeleted_sources {
+            warn!("  {:?} (copied to {:?})", source, target);
+        }
+    }
 
-        multi.add(bar.clone());
+    if summary.total.symlink_fallbacks > 0 {
+        warn!(
+            "{} symlinks could not be created and were substituted (see --symlink-fallback)",
+            summary.total.symlink_fallbacks
+        );
+    }
 
-        for (i, file) in files.into_iter().enumerate() {
-            bar.set_message(format!("{:?}", file));
-            process_file(file, &context);
-            bar.set_position(i as u64);
+    summary.elapsed = start.elapsed();
+    if let Some(log) = &outcome_log {
+        log.write_summary(&summary);
+    }
+    // an outcome log written to stdout replaces the summary table
+    if !log_to_stdout {
+        if args.quiet {
+            println!("{}", summary.totals_line());
+        } else if let Err(err) = summary.write_table(&mut std::io::stdout().lock()) {
+            error!("Error printing summary: {}", err);
         }
+    }
+    if let Some(stats) = stats {
+        let stats = stats.lock().map(|stats| stats.clone()).unwrap_or_default();
+        write_stats(
+            &stats,
+            args.stats && !log_to_stdout,
+            args.stats_json.as_deref(),
+        );
+    }
+
+    debug!("Finished execution");
+    if !summary.is_success() {
+        std::process::exit(summary.exit_code());
+    }
+}
+
+/// The token cancelled by the Ctrl-C handler
+static INTERRUPT_TOKEN: OnceLock<CancellationToken> = OnceLock::new();
 
-        if let Some(context) = context.multi_threading() {
-            bar.set_position(0);
-            let mut count = 0;
-            for (i, _) in context.iter().take(jobs).enumerate() {
-                bar.set_position(i as u64);
-                count += 1;
+/// Cancels the token when Ctrl-C is pressed, so that the run stops after the files in progress. A second Ctrl-C
+/// terminates the program immediately. On platforms other than unix, Ctrl-C always terminates the program.
+fn cancel_on_interrupt(token: &CancellationToken) {
+    if INTERRUPT_TOKEN.set(token.clone()).is_err() {
+        return;
+    }
+    #[cfg(unix)]
+    {
+        extern "C" fn on_interrupt(_: libc::c_int) {
+            if let Some(token) = INTERRUPT_TOKEN.get() {
+                token.cancel();
             }
+            // SAFETY: restoring the default handler is async-signal-safe
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+            }
+        }
+        // SAFETY: the handler only performs atomic operations and async-signal-safe calls
+        let previous = unsafe {
+            libc::signal(
+                libc::SIGINT,
+                on_interrupt as *const () as libc::sighandler_t,
+            )
+        };
+        if previous == libc::SIG_ERR {
+            warn!(
+                "Failed to install the Ctrl-C handler, Ctrl-C terminates the program immediately"
+            );
+        }
+    }
+}
 
-            if count != jobs {
-                error!("Not all jobs got executed")
+/// Splits the label off a `--source-dir` entry (`<dir>=<label>`). Entries that exist as a path, or whose label would
+/// contain a path separator, are taken as a directory without label.
+fn split_source_label(entry: &str) -> (PathBuf, Option<String>) {
+    if !Path::new(entry).exists() {
+        if let Some((dir, label)) = entry.rsplit_once('=') {
+            if !label.contains(['/', '\\']) {
+                return (PathBuf::from(dir), Some(label.to_string()));
             }
         }
+    }
+    (PathBuf::from(entry), None)
+}
 
-        bar.finish_with_message("Finished processing files");
-    } else {
-        for file in files {
-            process_file(file, &context);
+/// Prints the date statistics and writes them as JSON, if requested.
+fn write_stats(stats: &DateStats, print: bool, json: Option<&str>) {
+    if print {
+        println!();
+        if let Err(err) = stats.write_table(&mut std::io::stdout().lock()) {
+            error!("Error printing statistics: {}", err);
+        }
+    }
+    if let Some(path) = json {
+        if let Err(err) = stats.write_json(path) {
+            error!("Error writing statistics: {}", err);
         }
+    }
+}
+
+/// Checks if near-duplicates are detected, which needs the whole list of files.
+fn near_dups_enabled(settings: &AnalyzerSettings) -> bool {
+    #[cfg(feature = "perceptual-hash")]
+    {
+        settings.near_dup_distance.is_some()
+    }
+    #[cfg(not(feature = "perceptual-hash"))]
+    {
+        let _ = settings;
+        false
+    }
+}
+
+/// Exits with [`EXIT_PLANNING_FAILED`] if files could not be planned.
+fn exit_if_invalid(plan: &Plan) {
+    if !plan.is_valid() {
+        std::process::exit(EXIT_PLANNING_FAILED);
+    }
+}
+
+/// Writes the outcome log, the manifest and the list of created files as files are processed, also from worker
+/// threads, and records the file each thread processes for the journald log target and the worker lines of the
+/// progress bar.
+struct CliObserver {
+    outcome_log: Option<Arc<OutcomeLog>>,
+    manifest: Option<Manifest>,
+    files_from_out: Option<CreatedFileList>,
+    stats: Option<Arc<Mutex<DateStats>>>,
+    progress: Option<Arc<CliProgress>>,
+}
 
-        if let Some(context) = context.multi_threading() {
-            if context.iter().take(jobs).count() != jobs {
-                error!("Not all jobs got executed")
+impl RunObserver for CliObserver {
+    fn on_file_start(&self, path: &Path) {
+        CURRENT_FILE.set(Some(path.to_path_buf()));
+        if let Some(progress) = &self.progress {
+            progress.worker_started(path);
+        }
+    }
+
+    fn on_file_done(&self, outcome: &FileOutcome) {
+        CURRENT_FILE.set(None);
+        if let Some(progress) = &self.progress {
+            progress.worker_idle();
+        }
+        if let Some(log) = &self.outcome_log {
+            log.on_file_done(outcome);
+        }
+        if let Some(stats) = &self.stats {
+            if let Ok(mut stats) = stats.lock() {
+                stats.record(outcome);
             }
         }
+        if let Some(manifest) = &self.manifest {
+            manifest.on_file_done(outcome);
+        }
+        if let Some(list) = &self.files_from_out {
+            list.on_file_done(outcome);
+        }
     }
 
-    debug!("Finished execution");
+    fn on_error(&self, path: &Path, err: &photo_sort::Error) {
+        if let Some(progress) = &self.progress {
+            progress.worker_idle();
+        }
+        if let Some(log) = &self.outcome_log {
+            log.on_error(path, err);
+        }
+    }
 }
 
-struct ThreadPoolContext {
-    pub pool: ThreadPool,
-    pub output: Sender<()>,
-    pub receiver: Receiver<()>,
-    pub analyzer: Arc<Analyzer>,
+/// Displays the progress of a run as a progress bar, the bar is created when the run begins. If the run copies files,
+/// the bar advances by the copied bytes (see [`CliProgress::copied`]), the number of files is displayed next to it.
+/// With multiple threads, a line per worker thread beneath the bar shows the file the worker processes.
+struct CliProgress {
+    multi: MultiProgress,
+    bar: OnceLock<ProgressBar>,
+    cancel: CancellationToken,
+    bytes: AtomicBool,
+    files: AtomicU64,
+    total_files: AtomicU64,
+    workers: Option<Mutex<Vec<WorkerLine>>>,
 }
 
-struct NormalContext {
-    pub analyzer: Analyzer,
+impl CliProgress {
+    fn new(multi: MultiProgress, cancel: CancellationToken, workers: bool) -> CliProgress {
+        CliProgress {
+            multi,
+            bar: OnceLock::new(),
+            cancel,
+            bytes: AtomicBool::new(false),
+            files: AtomicU64::new(0),
+            total_files: AtomicU64::new(0),
+            workers: workers.then(Mutex::default),
+        }
+    }
+
+    /// Advances the bar by bytes copied by a worker thread, if it displays the progress by bytes.
+    fn copied(&self, bytes: u64) {
+        if let Some(bar) = self
+            .bar
+            .get()
+            .filter(|_| self.bytes.load(Ordering::Relaxed))
+        {
+            bar.inc(bytes);
+        }
+    }
+
+    /// Shows the file the calling worker thread starts to process on its line. A worker takes the line of a worker
+    /// that is done with its file, or a new line if all lines are busy.
+    fn worker_started(&self, path: &Path) {
+        let Some(mut workers) = self
+            .workers
+            .as_ref()
+            .and_then(|workers| workers.lock().ok())
+        else {
+            return;
+        };
+        let worker = thread::current().id();
+        let index = match workers
+            .iter()
+            .position(|line| line.worker == Some(worker))
+            .or_else(|| workers.iter().position(|line| line.worker.is_none()))
+        {
+            Some(index) => index,
+            None => {
+                let bar = self.multi.add(ProgressBar::new_spinner());
+                bar.set_style(
+                    ProgressStyle::with_template("  {spinner:.cyan} {prefix} {wide_msg}").unwrap(),
+                );
+                bar.set_prefix(format!("worker {}", workers.len() + 1));
+                bar.enable_steady_tick(Duration::from_millis(100));
+                workers.push(WorkerLine { worker: None, bar });
+                workers.len() - 1
+            }
+        };
+        let line = &mut workers[index];
+        line.worker = Some(worker);
+        line.bar.set_message(
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        );
+    }
+
+    /// Frees the line of the calling worker thread, it is done with its file.
+    fn worker_idle(&self) {
+        let Some(mut workers) = self
+            .workers
+            .as_ref()
+            .and_then(|workers| workers.lock().ok())
+        else {
+            return;
+        };
+        let worker = thread::current().id();
+        if let Some(line) = workers.iter_mut().find(|line| line.worker == Some(worker)) {
+            line.worker = None;
+            line.bar.set_message("");
+        }
+    }
 }
 
-enum ExecutionContext {
-    MultiThreaded(ThreadPoolContext),
-    SingleThreaded(Box<NormalContext>),
+/// `WorkerLine` is the line of the progress bar showing the file a worker thread processes.
+struct WorkerLine {
+    worker: Option<ThreadId>,
+    bar: ProgressBar,
 }
 
-impl ExecutionContext {
-    pub fn multi_threading(self) -> Option<Receiver<()>> {
-        if let ExecutionContext::MultiThreaded(context) = self {
-            Some(context.receiver)
-        } else {
-            None
+impl Progress for CliProgress {
+    fn begin(&self, total: u64) {
+        let bar = self.bar.get_or_init(|| self.multi.add(ProgressBar::new(0)));
+        self.total_files.store(total, Ordering::Relaxed);
+        bar.set_length(total);
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{spinner:.green} [{elapsed_precise}] [{wide_bar:.green/grey}] {pos}/{len} ({eta}) {msg}",
+            )
+            .unwrap()
+            .progress_chars("=>-"),
+        );
+    }
+
+    fn begin_bytes(&self, total_bytes: u64) {
+        let Some(bar) = self.bar.get() else {
+            return;
+        };
+        self.bytes.store(true, Ordering::Relaxed);
+        bar.set_length(total_bytes);
+        bar.set_position(0);
+        bar.set_prefix(format!("0/{}", self.total_files.load(Ordering::Relaxed)));
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{spinner:.green} [{elapsed_precise}] [{wide_bar:.green/grey}] {prefix} files {binary_bytes}/{binary_total_bytes} {binary_bytes_per_sec} ({eta}) {msg}",
+            )
+            .unwrap()
+            .progress_chars("=>-"),
+        );
+    }
+
+    fn advance(&self, path: &Path) {
+        if let Some(bar) = self.bar.get() {
+            bar.set_message(
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            );
+            if self.bytes.load(Ordering::Relaxed) {
+                let files = self.files.fetch_add(1, Ordering::Relaxed) + 1;
+                bar.set_prefix(format!(
+                    "{}/{}",
+                    files,
+                    self.total_files.load(Ordering::Relaxed)
+                ));
+            } else {
+                bar.inc(1);
+            }
         }
     }
-}
 
-fn process_file(file: PathBuf, context: &ExecutionContext) {
-    match context {
-        ExecutionContext::SingleThreaded(context) => {
-            let result = context.analyzer.run_file(&file);
-            if let Err(err) = result {
-                error!("Error processing file: {}", err);
+    fn finish(&self) {
+        // the workers are done, their lines are removed
+        if let Some(mut workers) = self
+            .workers
+            .as_ref()
+            .and_then(|workers| workers.lock().ok())
+        {
+            for line in workers.drain(..) {
+                line.bar.finish_and_clear();
+                self.multi.remove(&line.bar);
             }
         }
-        ExecutionContext::MultiThreaded(context) => {
-            let output = context.output.clone();
-            let analyzer = context.analyzer.clone();
-            context.pool.execute(move || {
-                let result = analyzer.run_file(&file);
-                if let Err(err) = result {
-                    error!("Error processing file: {}", err);
-                }
-                output.send(()).expect("thread pool channel closed")
-            })
+        if let Some(bar) = self.bar.get() {
+            if self.cancel.is_cancelled() {
+                bar.abandon_with_message("Cancelled");
+            } else {
+                bar.finish_with_message("Finished processing files");
+            }
         }
     }
 }
@@ -0,0 +1,159 @@
+use crate::hash;
+use crate::outcome::{FileOutcome, OutcomeAction, RunObserver};
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use log::{error, warn};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// `ManifestHash` is an enumeration that defines the checksum column of a `Manifest`.
+///
+/// # Variants
+///
+/// * `None` - No checksum column, hashing costs time.
+/// * `Sha256` - The SHA-256 checksum of the target file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ManifestHash {
+    #[default]
+    None,
+    Sha256,
+}
+
+impl FromStr for ManifestHash {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(ManifestHash::None),
+            "sha256" => Ok(ManifestHash::Sha256),
+            _ => Err(anyhow!("Invalid manifest hash")),
+        }
+    }
+}
+
+/// `Manifest` is a CSV file with one row per transferred file (including sidecar files): the original path, the
+/// new path, the capture date and, optionally, the checksum of the new file. Rows are appended to an existing
+/// manifest and every row is flushed when it is written, so the manifest is complete up to the last processed file
+/// even if the program is aborted. As a `RunObserver` it records the files of a run as they are processed, also
+/// from worker threads.
+pub struct Manifest {
+    file: Mutex<File>,
+    hash: ManifestHash,
+}
+
+impl Manifest {
+    /// Opens the manifest for appending, a new (or empty) manifest starts with the header row.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the manifest.
+    /// * `hash` - The checksum column of the manifest.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be opened, or if an existing manifest has other
+    /// columns (e.g. it was written without checksums).
+    pub fn open<P: AsRef<Path>>(path: P, hash: ManifestHash) -> Result<Manifest> {
+        let path = path.as_ref();
+        let header = Self::header(hash);
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(path)
+            .map_err(|e| anyhow!("Failed to open manifest: {:?} - {:?}", path, e))?;
+
+        let mut existing = String::new();
+        BufReader::new(&file)
+            .read_line(&mut existing)
+            .map_err(|e| anyhow!("Failed to read manifest: {:?} - {:?}", path, e))?;
+        if existing.is_empty() {
+            writeln!(file, "{}", header)
+                .and_then(|_| file.flush())
+                .map_err(|e| anyhow!("Failed to write manifest: {:?} - {:?}", path, e))?;
+        } else if existing.trim_end() != header {
+            return Err(anyhow!(
+                "Manifest {:?} has other columns ({}) than expected ({})",
+                path,
+                existing.trim_end(),
+                header
+            ));
+        }
+
+        Ok(Manifest {
+            file: Mutex::new(file),
+            hash,
+        })
+    }
+
+    fn header(hash: ManifestHash) -> &'static str {
+        match hash {
+            ManifestHash::None => "original_path,new_path,capture_date",
+            ManifestHash::Sha256 => "original_path,new_path,capture_date,sha256",
+        }
+    }
+
+    /// Appends the row of a transferred file.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The original path of the file.
+    /// * `target` - The new path of the file.
+    /// * `date` - The capture date of the file, if any.
+    pub fn write_row(&self, source: &Path, target: &Path, date: Option<NaiveDateTime>) {
+        let mut row = [
+            escape(&source.to_string_lossy()),
+            escape(&target.to_string_lossy()),
+            date.map(|date| date.format("%Y-%m-%dT%H:%M:%S").to_string())
+                .unwrap_or_default(),
+        ]
+        .join(",");
+
+        // hash before locking, so that worker threads hash in parallel
+        if self.hash == ManifestHash::Sha256 {
+            row.push(',');
+            match hash::hash_file(target) {
+                Ok(hash) => row.push_str(&hash.to_string()),
+                Err(err) => warn!("Error hashing {:?} for the manifest: {}", target, err),
+            }
+        }
+
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+        if let Err(err) = writeln!(file, "{}", row).and_then(|_| file.flush()) {
+            error!("Error writing manifest: {}", err);
+        }
+    }
+}
+
+impl RunObserver for Manifest {
+    fn on_file_done(&self, outcome: &FileOutcome) {
+        if outcome.dry_run {
+            return;
+        }
+        let (OutcomeAction::Transfer(_), Some(target)) = (outcome.action, &outcome.target) else {
+            return;
+        };
+        self.write_row(&outcome.source, target, outcome.date);
+        for (sidecar, target) in &outcome.sidecars {
+            // errors of sidecar files are only logged, the sidecar may not have been transferred
+            if target.symlink_metadata().is_ok() {
+                self.write_row(sidecar, target, outcome.date);
+            }
+        }
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,28 +1,149 @@
+use crate::{Sanitize, DEFAULT_CLEAN_PREFIXES, DEFAULT_NODATE_PLACEHOLDER};
 use lazy_static::lazy_static;
 use log::trace;
 use regex::Regex;
+use std::cmp::Ordering;
 
 // Regular expressions for matching and cleaning image names.
 lazy_static! {
-    /// Matches image names with optional prefixes and suffixes.
+    /// Matches and removes file extensions.
     ///
-    /// This regex matches image names that optionally start with "IMG", "img", "NO_DATE", or "no_date", "VID", "vid", "MOV", "mov",
-    /// followed by any characters, and ending with a file extension.
-    static ref RE_IMAGE_NAME: Regex = Regex::new(r"^((MOV|VID|mov|vid|IMG|img|NO_?DATE|no_?date)?[-_]*)*(.*?)[-_]*?\.([A-Za-z0-9]+)$").expect("Failed to compile regex");
+    /// This regex matches the final period followed by alphanumeric characters (at least one of them alphabetic,
+    /// so that e.g. `trip.to.rome.2019` keeps its year), only the last extension of e.g. `archive.tar.gz` is matched.
+    static ref RE_REMOVE_EXT: Regex =
+        Regex::new(r"\.[A-Za-z0-9]*[A-Za-z][A-Za-z0-9]*$").expect("Failed to compile regex");
 
-    /// Matches and removes file extensions.
+    /// Splits names into tokens: words (letters, numbers and combining marks of any script) and the separators
+    /// between them. Combining marks belong to the preceding character, also if it is a separator.
+    static ref RE_NAME_TOKEN: Regex =
+        Regex::new(r"[\p{L}\p{N}][\p{L}\p{N}\p{M}]*|[^\p{L}\p{N}]+").expect("Failed to compile regex");
+
+    /// Matches the separators at the start of a name, with their combining marks.
+    static ref RE_LEADING_SEPARATORS: Regex =
+        Regex::new(r"^(?:[-_]\p{M}*)+").expect("Failed to compile regex");
+
+    /// Matches the separators at the end of a name, with their combining marks.
+    static ref RE_TRAILING_SEPARATORS: Regex =
+        Regex::new(r"(?:[-_]\p{M}*)+$").expect("Failed to compile regex");
+
+    /// Cleans names using the default nodate placeholder.
+    static ref DEFAULT_NAME_CLEANER: NameCleaner =
+        NameCleaner::new(DEFAULT_NODATE_PLACEHOLDER, DEFAULT_CLEAN_PREFIXES).expect("Failed to compile regex");
+}
+
+/// `NameCleaner` cleans image names by removing certain prefixes, suffixes, file extensions and nodate placeholders,
+/// e.g. of files sorted by a previous run.
+///
+/// Prefixes are removed wherever they appear as a standalone word, e.g. `IMG` of `Übung-IMG_1234.jpg`, and at the
+/// start of a name also if they are directly followed by digits, e.g. `DSC` of `DSC09999.arw`. Words consist of
+/// letters, numbers and combining marks of any script, so that e.g. `IMG` of `ÜbungIMG.jpg` is kept.
+///
+/// # Fields
+///
+/// * `prefixes` - The prefixes to remove (by default "IMG", "VID" and "MOV"), each in upper and lower case.
+/// * `remove_nodate` - Matches the nodate placeholders for removal: "NO_DATE" or "no_date" (with or without an
+///   underscore) and the configured placeholder.
+#[derive(Debug, Clone)]
+pub struct NameCleaner {
+    prefixes: Vec<String>,
+    remove_nodate: Regex,
+}
+
+impl NameCleaner {
+    /// Creates a `NameCleaner`.
+    ///
+    /// # Arguments
     ///
-    /// This regex matches any sequence of characters followed by a period and one or more alphabetic characters,
-    /// effectively matching file extensions for removal.
-    static ref RE_REMOVE_EXT: Regex = Regex::new(r"\.[A-Za-z]+$").expect("Failed to compile regex");
+    /// * `nodate_placeholder` - The nodate placeholder, it is removed in addition to "NO_DATE" (matched literally).
+    /// * `prefixes` - The prefixes that are removed from the start of names, each in upper or lower case (matched
+    ///   literally).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the regular expressions can not be compiled (e.g. the placeholder is too
+    /// long).
+    pub fn new<S: AsRef<str>>(
+        nodate_placeholder: &str,
+        prefixes: &[S],
+    ) -> Result<NameCleaner, regex::Error> {
+        let mut nodate = String::from("NO_?DATE|no_?date");
+        if !nodate_placeholder.is_empty() {
+            nodate.push('|');
+            nodate.push_str(&regex::escape(nodate_placeholder));
+        }
+        let mut cased_prefixes: Vec<String> = Vec::new();
+        for prefix in prefixes.iter().map(AsRef::as_ref) {
+            for prefix in [prefix.to_uppercase(), prefix.to_lowercase()] {
+                if !prefix.is_empty() && !cased_prefixes.contains(&prefix) {
+                    cased_prefixes.push(prefix);
+                }
+            }
+        }
+        Ok(NameCleaner {
+            prefixes: cased_prefixes,
+            // the combining marks following a placeholder are matched too, a placeholder with a mark is kept
+            remove_nodate: Regex::new(&format!(r"({})(\p{{M}}*)", nodate))?,
+        })
+    }
+
+    /// Strips a prefix from the first word of a name if it is directly followed by digits, e.g. `DSC09999`.
+    fn strip_leading_prefix<'a>(&self, word: &'a str) -> &'a str {
+        self.prefixes
+            .iter()
+            .filter_map(|prefix| word.strip_prefix(prefix.as_str()))
+            .find(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(word)
+    }
 
-    /// Matches and removes "NO_DATE" or "no_date" from image names.
+    /// Cleans an image name by removing certain prefixes, suffixes, file extensions and nodate placeholders.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A string slice that holds the name of the image.
     ///
-    /// This regex matches "NO_DATE" or "no_date", with or without an underscore, for removal from image names.
-    static ref RE_REMOVE_NODATE: Regex = Regex::new(r"(NO_?DATE|no_?date)").expect("Failed to compile regex");
+    /// # Returns
+    ///
+    /// * `String` - The cleaned image name.
+    pub fn clean(&self, name: &str) -> String {
+        let stem = RE_REMOVE_EXT.replace(name, "");
+        let stem = self
+            .remove_nodate
+            .replace_all(&stem, |captures: &regex::Captures| match &captures[2] {
+                "" => String::new(),
+                _ => captures[0].to_string(),
+            });
+
+        let mut result = String::with_capacity(stem.len());
+        // set after a removed prefix, so that its separator is dropped as well
+        let mut removed = false;
+        for (index, token) in RE_NAME_TOKEN.find_iter(&stem).enumerate() {
+            let token = token.as_str();
+            if self.prefixes.iter().any(|prefix| prefix == token) {
+                removed = true;
+                continue;
+            }
+            let token = if index == 0 {
+                self.strip_leading_prefix(token)
+            } else {
+                token
+            };
+            let token = if removed {
+                RE_LEADING_SEPARATORS.replace(token, "")
+            } else {
+                token.into()
+            };
+            removed = false;
+            result.push_str(&token);
+        }
+        let result = RE_LEADING_SEPARATORS.replace(&result, "");
+        let result = RE_TRAILING_SEPARATORS.replace(&result, "").to_string();
+        trace!("Cleaned name: {:?} -> {:?}", name, result);
+        result
+    }
 }
 
-/// Cleans an image name by removing certain prefixes, suffixes, and file extensions.
+/// Cleans an image name by removing certain prefixes, suffixes, and file extensions, using the default nodate
+/// placeholder and prefixes (see [`NameCleaner`]).
 ///
 /// # Arguments
 ///
@@ -32,19 +153,239 @@ lazy_static! {
 ///
 /// * `String` - The cleaned image name.
 pub fn clean_image_name(name: &str) -> String {
-    let caps = RE_IMAGE_NAME.captures(name);
-    let result = match caps {
-        None => RE_REMOVE_EXT.replace(name, "").to_string(),
-        Some(caps) => {
-            if let (Some(cap_name), Some(_cap_ext)) = (caps.get(3), caps.get(4)) {
-                RE_REMOVE_NODATE.replace(cap_name.as_str(), "").to_string()
+    DEFAULT_NAME_CLEANER.clean(name)
+}
+
+/// Removes the file extension from a name, e.g. if name cleaning is disabled.
+pub fn remove_extension(name: &str) -> String {
+    RE_REMOVE_EXT.replace(name, "").to_string()
+}
+
+/// Compares two names in natural order, numeric parts of the names are compared by their value.
+/// E.g. `IMG_9.jpg` < `IMG_10.jpg` and `DSC09999.arw` < `DSC10000.arw`.
+///
+/// # Arguments
+///
+/// * `a` - The first name.
+/// * `b` - The second name.
+///
+/// # Returns
+///
+/// * `Ordering` - The natural ordering of both names.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut x_number = String::new();
+                while let Some(digit) = a_chars.next_if(char::is_ascii_digit) {
+                    x_number.push(digit);
+                }
+                let mut y_number = String::new();
+                while let Some(digit) = b_chars.next_if(char::is_ascii_digit) {
+                    y_number.push(digit);
+                }
+                let x_value = x_number.trim_start_matches('0');
+                let y_value = y_number.trim_start_matches('0');
+                let ordering = x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.cmp(y);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+/// Characters that are invalid in file names on Windows file systems (NTFS, FAT, exFAT), besides control characters
+pub const WINDOWS_INVALID_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+
+/// Names of devices that can not be used as file names on Windows, also with an extension (e.g. `CON.jpg`)
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Replaces the characters of a path component (a file or directory name) that are invalid on the target file
+/// system with a substitute character.
+///
+/// # Arguments
+///
+/// * `component` - The path component, without separators.
+/// * `sanitize` - The rules of the target file system, `Sanitize::Auto` must be resolved before.
+/// * `substitute` - The character invalid characters are replaced with.
+///
+/// # Returns
+///
+/// * `String` - The sanitized component. With `Sanitize::Windows`, the characters `<>:"|?*\`, control characters
+///   and trailing dots and spaces are replaced, and the substitute is appended to reserved device names (`CON_.jpg`).
+///   With `Sanitize::Posix`, only NUL characters are replaced.
+pub fn sanitize_component(component: &str, sanitize: Sanitize, substitute: char) -> String {
+    match sanitize {
+        Sanitize::Windows => {}
+        Sanitize::Posix => return component.replace('\0', &substitute.to_string()),
+        Sanitize::Auto | Sanitize::Off => return component.to_string(),
+    }
+
+    let mut sanitized: String = component
+        .chars()
+        .map(|c| {
+            if c.is_control() || WINDOWS_INVALID_CHARS.contains(&c) {
+                substitute
             } else {
-                RE_REMOVE_NODATE
-                    .replace(RE_REMOVE_EXT.replace(name, "").as_ref(), "")
-                    .to_string()
+                c
+            }
+        })
+        .collect();
+
+    let kept = sanitized.trim_end_matches(['.', ' ']).len();
+    let trailing = sanitized[kept..].chars().count();
+    sanitized.truncate(kept);
+    sanitized.extend(std::iter::repeat_n(substitute, trailing));
+
+    let stem_len = sanitized.find('.').unwrap_or(sanitized.len());
+    let stem = sanitized[..stem_len].trim_end().to_uppercase();
+    if WINDOWS_RESERVED_NAMES.contains(&stem.as_str()) {
+        sanitized.insert(stem_len, substitute);
+    }
+
+    if sanitized != component {
+        trace!(
+            "Sanitized path component {:?} to {:?}",
+            component,
+            sanitized
+        );
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_image_names() {
+        let cases = [
+            ("IMG_1234.jpg", "1234"),
+            ("IMG1234.JPG", "1234"),
+            ("img_0001.jpeg", "0001"),
+            ("VID_20230714_102030.mp4", "20230714_102030"),
+            ("mov_clip.MOV", "clip"),
+            ("trip.to.rome.2019.jpg", "trip.to.rome.2019"),
+            ("trip.to.rome.2019", "trip.to.rome.2019"),
+            ("archive.tar.gz", "archive.tar"),
+            ("Übung-IMG_1234.jpg", "Übung-1234"),
+            ("ÜbungIMG.jpg", "ÜbungIMG"),
+            ("写真_IMG_0001.jpeg", "写真_0001"),
+            ("IMGoriginal.jpg", "IMGoriginal"),
+            ("holiday-IMG.jpg", "holiday"),
+            ("NO_DATE_holiday.jpg", "holiday"),
+            ("nodate-beach.png", "beach"),
+            ("NODATE-IMG_42.heic", "42"),
+            ("DSC09999.arw", "DSC09999"),
+            ("photo.JPG", "photo"),
+            ("", ""),
+            // emoji are neither letters nor numbers, prefixes next to them are standalone words
+            ("🎉IMG_0001.jpg", "🎉0001"),
+            ("IMG_🎉_party.jpg", "🎉_party"),
+            ("party🎉.jpg", "party🎉"),
+            (
+                "👨\u{200D}👩\u{200D}👧-IMG_7.jpg",
+                "👨\u{200D}👩\u{200D}👧-7",
+            ),
+            // combining marks belong to the preceding character
+            ("cafe\u{301}_IMG_7.jpg", "cafe\u{301}_7"),
+            ("cafe\u{301}.jpg", "cafe\u{301}"),
+            ("IMG\u{301}_1234.jpg", "IMG\u{301}_1234"),
+            ("IMG_\u{301}1234.jpg", "1234"),
+            ("DSC\u{301}09999.arw", "DSC\u{301}09999"),
+            ("NODATE\u{301}_beach.jpg", "NODATE\u{301}_beach"),
+            ("NODATE_\u{301}beach.jpg", "beach"),
+            ("beach_\u{301}.jpg", "beach"),
+            ("photo.jpg\u{301}", "photo.jpg\u{301}"),
+            ("photo.\u{301}jpg", "photo.\u{301}jpg"),
+        ];
+        for (name, cleaned) in cases {
+            assert_eq!(clean_image_name(name), cleaned, "cleaning {:?}", name);
+        }
+    }
+
+    #[test]
+    fn clean_configured_placeholder_literally() {
+        let cleaner = NameCleaner::new("(no.date)+", DEFAULT_CLEAN_PREFIXES).unwrap();
+        let cases = [
+            ("(no.date)+_beach.jpg", "beach"),
+            ("beach-(no.date)+.jpg", "beach"),
+            ("NODATE_beach.jpg", "beach"),
+            ("NO_DATE_beach.jpg", "beach"),
+            ("noXdate_beach.jpg", "noXdate_beach"),
+            ("no.datee_beach.jpg", "no.datee_beach"),
+        ];
+        for (name, cleaned) in cases {
+            assert_eq!(cleaner.clean(name), cleaned, "cleaning {:?}", name);
+        }
+    }
+
+    #[test]
+    fn clean_with_configured_placeholder_and_prefixes() {
+        let cleaner = NameCleaner::new("unknown", &["PXL", "dsc"]).unwrap();
+        let cases = [
+            ("PXL_20230101_120000.jpg", "20230101_120000"),
+            ("DSC09999.arw", "09999"),
+            ("unknown-sunset.jpg", "sunset"),
+            ("NO_DATE-sunset.jpg", "sunset"),
+            ("IMG_1.jpg", "IMG_1"),
+        ];
+        for (name, cleaned) in cases {
+            assert_eq!(cleaner.clean(name), cleaned, "cleaning {:?}", name);
+        }
+    }
+
+    #[test]
+    fn natural_order() {
+        let ordered = [
+            "",
+            "DSC09999.arw",
+            "DSC10000.arw",
+            "IMG_02.jpg",
+            "IMG_2.jpg",
+            "IMG_10.jpg",
+            "IMG_10a.jpg",
+            "IMG_10b.jpg",
+            "img_1.jpg",
+        ];
+        for (index, a) in ordered.iter().enumerate() {
+            for (other, b) in ordered.iter().enumerate() {
+                assert_eq!(
+                    natural_cmp(a, b),
+                    index.cmp(&other),
+                    "comparing {:?} and {:?}",
+                    a,
+                    b
+                );
             }
         }
-    };
-    trace!("Cleaned name: {:?} -> {:?}", name, result);
-    result
+    }
+
+    #[test]
+    fn remove_only_the_final_extension() {
+        assert_eq!(remove_extension("archive.tar.gz"), "archive.tar");
+        assert_eq!(remove_extension("IMG_1234.jpg"), "IMG_1234");
+        assert_eq!(remove_extension("trip.2019"), "trip.2019");
+    }
 }
@@ -0,0 +1,283 @@
+use anyhow::{anyhow, Result};
+use image::imageops::FilterType;
+use log::{debug, info};
+use serde::{Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The default maximum Hamming distance of the perceptual hashes of near-duplicate images.
+pub const DEFAULT_NEAR_DUP_DISTANCE: u32 = 10;
+
+/// `PerceptualHash` is the difference hash (dHash) of an image: the image is reduced to 9x8 gray pixels, every bit
+/// tells if a pixel is brighter than its right neighbor. Re-compressed or resized copies of an image have (nearly) the
+/// same hash. It is displayed (and serialized) as 16 hex digits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PerceptualHash(pub u64);
+
+impl Display for PerceptualHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl Serialize for PerceptualHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl PerceptualHash {
+    /// Computes the perceptual hash of an image file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be read or decoded, e.g. it is not an image or its
+    /// format (e.g. HEIC, RAW) is not supported.
+    pub fn of_file(path: &Path) -> Result<(PerceptualHash, u32, u32)> {
+        let image = image::ImageReader::open(path)?
+            .with_guessed_format()?
+            .decode()
+            .map_err(|err| anyhow!("Failed to decode image {:?}: {}", path, err))?;
+        let (width, height) = (image.width(), image.height());
+        let pixels = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+        let mut hash = 0u64;
+        for y in 0..8 {
+            for x in 0..8 {
+                let brighter = pixels.get_pixel(x, y)[0] > pixels.get_pixel(x + 1, y)[0];
+                hash = (hash << 1) | u64::from(brighter);
+            }
+        }
+        Ok((PerceptualHash(hash), width, height))
+    }
+
+    /// The Hamming distance of two hashes, i.e. the number of differing bits (0 to 64).
+    pub fn distance(&self, other: &PerceptualHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+/// `NearDupMember` is an image of a group of near-duplicates.
+///
+/// # Fields
+///
+/// * `path` - The image file.
+/// * `width` - The width of the image in pixels.
+/// * `height` - The height of the image in pixels.
+/// * `size` - The size of the file in bytes.
+/// * `hash` - The perceptual hash of the image.
+/// * `distance` - The Hamming distance to the hash of the largest image of the group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NearDupMember {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub size: u64,
+    pub hash: PerceptualHash,
+    pub distance: u32,
+}
+
+/// `NearDupGroup` is a group of images that look alike, e.g. an original, a re-compressed copy and a thumbnail.
+/// Every image of a group is within the maximum distance of another image of the group.
+///
+/// # Fields
+///
+/// * `members` - The images, the largest (most pixels, then largest file) first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NearDupGroup {
+    pub members: Vec<NearDupMember>,
+}
+
+impl NearDupGroup {
+    /// The largest image of the group, it is the one to keep.
+    pub fn largest(&self) -> &NearDupMember {
+        &self.members[0]
+    }
+
+    /// The images of the group except the largest one.
+    pub fn smaller(&self) -> &[NearDupMember] {
+        &self.members[1..]
+    }
+}
+
+/// `NearDupReport` holds the groups of near-duplicate images found among a list of files.
+///
+/// # Fields
+///
+/// * `hashed` - The number of images that could be hashed, other files are not considered.
+/// * `max_distance` - The maximum Hamming distance of the hashes of near-duplicates.
+/// * `groups` - The groups of near-duplicates, in the order of their largest image.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NearDupReport {
+    pub hashed: usize,
+    pub max_distance: u32,
+    pub groups: Vec<NearDupGroup>,
+}
+
+impl NearDupReport {
+    /// Finds the groups of near-duplicate images among a list of files. The images are hashed in parallel, files that
+    /// can not be decoded (e.g. videos or unsupported formats) are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - The files to compare.
+    /// * `max_distance` - The maximum Hamming distance of the hashes of near-duplicates, e.g. 10.
+    /// * `threads` - The number of threads hashing the images.
+    pub fn find(files: &[PathBuf], max_distance: u32, threads: usize) -> NearDupReport {
+        let hashed = hash_files(files, threads);
+        info!("Computed the perceptual hash of {} images", hashed.len());
+
+        // groups are the connected components of the images within the maximum distance of each other
+        let mut parents: Vec<usize> = (0..hashed.len()).collect();
+        fn root(parents: &mut [usize], mut index: usize) -> usize {
+            while parents[index] != index {
+                parents[index] = parents[parents[index]];
+                index = parents[index];
+            }
+            index
+        }
+        for a in 0..hashed.len() {
+            for b in a + 1..hashed.len() {
+                if hashed[a].hash.distance(&hashed[b].hash) <= max_distance {
+                    let (root_a, root_b) = (root(&mut parents, a), root(&mut parents, b));
+                    parents[root_b] = root_a;
+                }
+            }
+        }
+
+        let mut groups: Vec<Vec<NearDupMember>> = vec![Vec::new(); hashed.len()];
+        for (index, member) in hashed.iter().enumerate() {
+            groups[root(&mut parents, index)].push(member.clone());
+        }
+        let mut groups: Vec<NearDupGroup> = groups
+            .into_iter()
+            .filter(|members| members.len() > 1)
+            .map(|mut members| {
+                members.sort_by(|a, b| {
+                    let pixels = |m: &NearDupMember| u64::from(m.width) * u64::from(m.height);
+                    pixels(b)
+                        .cmp(&pixels(a))
+                        .then(b.size.cmp(&a.size))
+                        .then(a.path.cmp(&b.path))
+                });
+                let largest = members[0].hash;
+                for member in &mut members {
+                    member.distance = member.hash.distance(&largest);
+                }
+                NearDupGroup { members }
+            })
+            .collect();
+        groups.sort_by(|a, b| a.largest().path.cmp(&b.largest().path));
+
+        NearDupReport {
+            hashed: hashed.len(),
+            max_distance,
+            groups,
+        }
+    }
+
+    /// The images of all groups except the largest image of each group.
+    pub fn smaller(&self) -> impl Iterator<Item = &NearDupMember> {
+        self.groups.iter().flat_map(NearDupGroup::smaller)
+    }
+
+    /// Writes the groups, the largest image of each group first, followed by the totals.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing fails.
+    pub fn write_table<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        for (index, group) in self.groups.iter().enumerate() {
+            writeln!(out, "Group {}:", index + 1)?;
+            for (position, member) in group.members.iter().enumerate() {
+                writeln!(
+                    out,
+                    "  {}  {:>5}x{:<5}  {:>10} bytes  distance {:>2}  {}",
+                    if position == 0 { "keep" } else { "    " },
+                    member.width,
+                    member.height,
+                    member.size,
+                    member.distance,
+                    member.path.display()
+                )?;
+            }
+        }
+        if !self.groups.is_empty() {
+            writeln!(out)?;
+        }
+        writeln!(
+            out,
+            "{} images compared, {} groups of near-duplicates ({} images besides the largest of each group)",
+            self.hashed,
+            self.groups.len(),
+            self.smaller().count()
+        )
+    }
+
+    /// Writes the report as JSON to a file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be written.
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| {
+            anyhow!(
+                "Failed to create near-duplicate report: {:?} - {:?}",
+                path,
+                e
+            )
+        })?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Hashes the images of a list of files in parallel, in the order of the files.
+fn hash_files(files: &[PathBuf], threads: usize) -> Vec<NearDupMember> {
+    let threads = threads.clamp(1, files.len().max(1));
+    let next = AtomicUsize::new(0);
+    let hashed = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                while let Some((index, path)) = {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    files.get(index).map(|path| (index, path))
+                } {
+                    let member = PerceptualHash::of_file(path).and_then(|(hash, width, height)| {
+                        Ok(NearDupMember {
+                            path: path.clone(),
+                            width,
+                            height,
+                            size: std::fs::metadata(path)?.len(),
+                            hash,
+                            distance: 0,
+                        })
+                    });
+                    match member {
+                        Ok(member) => {
+                            if let Ok(mut hashed) = hashed.lock() {
+                                hashed.push((index, member));
+                            }
+                        }
+                        Err(err) => debug!("Not comparing file {:?}: {}", path, err),
+                    }
+                }
+            });
+        }
+    });
+    let mut hashed = hashed
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    hashed.sort_by_key(|(index, _)| *index);
+    hashed.into_iter().map(|(_, member)| member).collect()
+}
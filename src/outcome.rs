@@ -0,0 +1,132 @@
+use crate::action::{ActionMode, ActualAction};
+use crate::analysis::DateSource;
+use crate::plan::{Operation, PlannedAction, SkipReason, SkippedFile};
+use crate::Error;
+use chrono::NaiveDateTime;
+use std::path::{Path, PathBuf};
+
+/// `OutcomeAction` is an enumeration that defines what was done with a file.
+///
+/// # Variants
+///
+/// * `Transfer` - The file action (move, copy, ...) was performed.
+/// * `Delete` - The file was deleted, because an identical file already exists at the target.
+/// * `Link` - The target was created as a hardlink to an identical source file transferred earlier in the run.
+/// * `Skip` - The file was left untouched, see `FileOutcome::skipped_reason`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutcomeAction {
+    Transfer(ActualAction),
+    Delete,
+    Link,
+    Skip,
+}
+
+/// `FileOutcome` describes what happened to a single file of a run.
+///
+/// # Fields
+///
+/// * `source` - The source file.
+/// * `target` - The target file. For deletions and skipped identical files this is the existing identical file,
+///   for other skipped files the computed target, if any.
+/// * `action` - What was done with the file.
+/// * `dry_run` - A boolean that indicates whether the action was only printed.
+/// * `date` - The date derived from the source file, if any.
+/// * `date_source` - Where the date was derived from.
+/// * `duplicate_counter` - The duplicate counter (`{dup}`) used to make the target unique, if any.
+/// * `skipped_reason` - Why the file was skipped, if it was skipped.
+/// * `unknown_extension` - A boolean that indicates whether the file has none of the configured extensions and
+///   was processed using the unknown file format.
+/// * `sidecars` - The sidecar files processed together with the file, paired with their target paths.
+/// * `mirror_failures` - The targets in the mirror directories (see `mirror_dirs`) the file or its sidecars could not
+///   be replicated to, the errors are logged.
+/// * `hardlink_fallbacks` - The number of hardlinks of the file and its sidecars that were substituted by a copy or
+///   symlink (see `hardlink_fallback`).
+/// * `symlink_fallbacks` - The number of symlinks of the file and its sidecars that were substituted by a hardlink or
+///   copy (see `symlink_fallback`).
+/// * `undeleted_sources` - The sources of the file and its sidecars (paired with their targets) that were moved across
+///   file systems by copying them, but could not be deleted afterward. They exist twice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOutcome {
+    pub source: PathBuf,
+    pub target: Option<PathBuf>,
+    pub action: OutcomeAction,
+    pub dry_run: bool,
+    pub date: Option<NaiveDateTime>,
+    pub date_source: DateSource,
+    pub duplicate_counter: Option<u32>,
+    pub skipped_reason: Option<SkipReason>,
+    pub unknown_extension: bool,
+    pub sidecars: Vec<(PathBuf, PathBuf)>,
+    pub mirror_failures: Vec<PathBuf>,
+    pub hardlink_fallbacks: usize,
+    pub symlink_fallbacks: usize,
+    pub undeleted_sources: Vec<(PathBuf, PathBuf)>,
+}
+
+impl FileOutcome {
+    /// The outcome of a performed planned action.
+    pub fn from_action(action: &PlannedAction, mode: &ActionMode) -> FileOutcome {
+        let (actual_action, dry_run) = match mode {
+            ActionMode::Execute(action) => (*action, false),
+            ActionMode::DryRun(action) => (*action, true),
+        };
+        FileOutcome {
+            source: action.source.clone(),
+            target: Some(action.target.clone()),
+            action: match action.operation {
+                Operation::Transfer { .. } => OutcomeAction::Transfer(actual_action),
+                Operation::DeleteDuplicate => OutcomeAction::Delete,
+                Operation::LinkDuplicate { .. } => OutcomeAction::Link,
+            },
+            dry_run,
+            date: action.date,
+            date_source: action.date_source,
+            duplicate_counter: action.duplicate_counter,
+            skipped_reason: None,
+            unknown_extension: action.unknown_extension,
+            sidecars: action.sidecars.clone(),
+            mirror_failures: Vec::new(),
+            hardlink_fallbacks: 0,
+            symlink_fallbacks: 0,
+            undeleted_sources: Vec::new(),
+        }
+    }
+
+    /// The outcome of a skipped file.
+    pub fn from_skipped(skipped: &SkippedFile, mode: &ActionMode) -> FileOutcome {
+        FileOutcome {
+            source: skipped.source.clone(),
+            target: skipped.target.clone(),
+            action: OutcomeAction::Skip,
+            dry_run: matches!(mode, ActionMode::DryRun(_)),
+            date: skipped.date,
+            date_source: skipped.date_source,
+            duplicate_counter: None,
+            skipped_reason: Some(skipped.reason),
+            unknown_extension: false,
+            sidecars: Vec::new(),
+            mirror_failures: Vec::new(),
+            hardlink_fallbacks: 0,
+            symlink_fallbacks: 0,
+            undeleted_sources: Vec::new(),
+        }
+    }
+}
+
+/// `RunObserver` receives events while files are processed, e.g. to display the progress of a run in a user
+/// interface. When files are processed by multiple threads, the methods are called from the worker threads.
+/// All methods do nothing by default.
+pub trait RunObserver {
+    /// Called before the action of a file is performed.
+    fn on_file_start(&self, _path: &Path) {}
+
+    /// Called after a file was processed or skipped.
+    fn on_file_done(&self, _outcome: &FileOutcome) {}
+
+    /// Called if processing a file failed.
+    fn on_error(&self, _path: &Path, _err: &Error) {}
+
+    /// Called if a file could not be planned while the files are planned one by one, see
+    /// [`Analyzer::run_streaming`](crate::Analyzer::run_streaming).
+    fn on_invalid(&self, _path: &Path, _err: &Error) {}
+}
@@ -0,0 +1,266 @@
+use crate::analysis::DateSource;
+#[cfg(feature = "perceptual-hash")]
+use crate::near_dup::NearDupReport;
+use chrono::NaiveDateTime;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// `Operation` is an enumeration that defines what is done with a source file of a `PlannedAction`.
+///
+/// # Variants
+///
+/// * `Transfer` - The configured file action (move, copy, ...) is performed. If `overwrite` is set, an existing target file is replaced.
+/// * `DeleteDuplicate` - The source file is deleted, because an identical file already exists at the target (see `prune_source_duplicates`).
+/// * `LinkDuplicate` - The target is created as a hardlink to `original`, the target of an identical source file
+///   transferred earlier in the run (see `SourceDuplicates::Hardlink`). If `overwrite` is set, an existing target file
+///   is replaced. In move mode the source file is deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Transfer { overwrite: bool },
+    DeleteDuplicate,
+    LinkDuplicate { original: PathBuf, overwrite: bool },
+}
+
+/// `PlannedAction` is a single validated action of a `Plan`.
+///
+/// # Fields
+///
+/// * `source` - The source file.
+/// * `target` - The computed target file. For `DeleteDuplicate` this is the existing identical file.
+/// * `operation` - The `Operation` performed on the source file.
+/// * `date` - The date derived from the source file, if any.
+/// * `date_source` - Where the date was derived from.
+/// * `sidecars` - The sidecar files of the source, paired with their target paths.
+/// * `duplicate_counter` - The duplicate counter (`{dup}`) used to make the target unique, if any.
+/// * `format` - The format string the target was computed from (file, nodate or unknown file format).
+/// * `unknown_extension` - A boolean that indicates whether the file has none of the configured extensions and
+///   is processed using the unknown file format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedAction {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub operation: Operation,
+    pub date: Option<NaiveDateTime>,
+    pub date_source: DateSource,
+    pub sidecars: Vec<(PathBuf, PathBuf)>,
+    pub duplicate_counter: Option<u32>,
+    pub format: String,
+    pub unknown_extension: bool,
+}
+
+impl PlannedAction {
+    /// The target files of the action: the target of the file followed by the targets of its sidecars.
+    pub fn targets(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.target.as_path())
+            .chain(self.sidecars.iter().map(|(_, target)| target.as_path()))
+    }
+}
+
+/// `Reservations` holds the targets of planned transfers, which may not exist yet (and never do in dry-run
+/// mode). Targets are checked against the reservations in addition to the file system, so that the duplicate
+/// counter increments in a dry run exactly as it does in a real run. For case-insensitive targets, targets that
+/// only differ in case are the same reservation.
+#[derive(Debug, Default)]
+pub(crate) struct Reservations {
+    targets: HashSet<PathBuf>,
+    case_insensitive: bool,
+}
+
+impl Reservations {
+    pub(crate) fn new(case_insensitive: bool) -> Reservations {
+        Reservations {
+            targets: HashSet::new(),
+            case_insensitive,
+        }
+    }
+
+    fn key(&self, target: &Path) -> PathBuf {
+        if self.case_insensitive {
+            PathBuf::from(target.to_string_lossy().to_lowercase())
+        } else {
+            target.to_path_buf()
+        }
+    }
+
+    /// Checks if the target is reserved.
+    pub(crate) fn contains(&self, target: &Path) -> bool {
+        self.targets.contains(&self.key(target))
+    }
+
+    /// Reserves the targets of an action. Deletions of duplicates do not create a file, they reserve nothing.
+    pub(crate) fn reserve(&mut self, action: &PlannedAction) {
+        if let Operation::Transfer { .. } | Operation::LinkDuplicate { .. } = action.operation {
+            let keys: Vec<_> = action.targets().map(|target| self.key(target)).collect();
+            self.targets.extend(keys);
+        }
+    }
+
+    /// Releases the targets of an action, e.g. because the action failed.
+    pub(crate) fn release(&mut self, action: &PlannedAction) {
+        for target in action.targets() {
+            let key = self.key(target);
+            self.targets.remove(&key);
+        }
+    }
+}
+
+impl Display for PlannedAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.operation {
+            Operation::Transfer { overwrite: false } => {
+                write!(f, "{:?} -> {:?}", self.source, self.target)?
+            }
+            Operation::Transfer { overwrite: true } => {
+                write!(f, "{:?} -> {:?} (overwrite)", self.source, self.target)?
+            }
+            Operation::DeleteDuplicate => write!(
+                f,
+                "{:?} (delete, identical to {:?})",
+                self.source, self.target
+            )?,
+            Operation::LinkDuplicate { ref original, .. } => write!(
+                f,
+                "{:?} -> {:?} (hardlink to identical {:?})",
+                self.source, self.target, original
+            )?,
+        }
+        for (sidecar, target) in &self.sidecars {
+            write!(f, "\n  + {:?} -> {:?}", sidecar, target)?;
+        }
+        Ok(())
+    }
+}
+
+/// `SkipReason` is an enumeration that defines why a file is skipped.
+///
+/// # Variants
+///
+/// * `Sidecar` - The file is a sidecar file, it is processed together with its photo/video.
+/// * `Paired` - The file is the RAW file of a RAW+JPEG pair or the video of a Live Photo, it is processed together
+///   with the JPEG file or the photo.
+/// * `UnknownExtension` - The extension is not in the list of extensions and no format for unknown files is set.
+/// * `UnlistedUnknown` - The extension is not in the list of extensions, nor in the list of unknown extensions.
+/// * `SymlinkExists` - The target is already a symlink to the file (see `ExistingSymlink`).
+/// * `TargetExists` - The target file already exists (see `CollisionStrategy::Skip`).
+/// * `IdenticalTarget` - A file with identical content already exists in the target directory.
+/// * `Filtered` - The date of the file is outside of the date range, or it has no date (see `NodateFilter`).
+/// * `PreviouslyProcessed` - The file was processed by a previous run and is unchanged since (see `ProcessedState`).
+/// * `AlreadyNamed` - The file already has its target name, e.g. when renaming files in place.
+/// * `SourceDuplicate` - An identical source file was transferred earlier in the run (see `SourceDuplicates::Skip`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    Sidecar,
+    Paired,
+    UnknownExtension,
+    UnlistedUnknown,
+    SymlinkExists,
+    TargetExists,
+    IdenticalTarget,
+    Filtered,
+    PreviouslyProcessed,
+    SourceDuplicate,
+    AlreadyNamed,
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Sidecar => write!(f, "sidecar file"),
+            SkipReason::Paired => write!(f, "paired file"),
+            SkipReason::UnknownExtension => write!(f, "unknown extension"),
+            SkipReason::UnlistedUnknown => write!(f, "unlisted unknown extension"),
+            SkipReason::SymlinkExists => write!(f, "symlink exists"),
+            SkipReason::TargetExists => write!(f, "target exists"),
+            SkipReason::IdenticalTarget => write!(f, "identical target exists"),
+            SkipReason::Filtered => write!(f, "outside of date range"),
+            SkipReason::PreviouslyProcessed => write!(f, "previously processed"),
+            SkipReason::SourceDuplicate => write!(f, "duplicate of another source file"),
+            SkipReason::AlreadyNamed => write!(f, "already named"),
+        }
+    }
+}
+
+/// `SkippedFile` is a file that is left untouched by a `Plan`.
+///
+/// # Fields
+///
+/// * `source` - The skipped file.
+/// * `target` - The computed target file (or the existing identical file), if the file got that far.
+/// * `reason` - Why the file is skipped.
+/// * `date` - The date derived from the file, if any.
+/// * `date_source` - Where the date was derived from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedFile {
+    pub source: PathBuf,
+    pub target: Option<PathBuf>,
+    pub reason: SkipReason,
+    pub date: Option<NaiveDateTime>,
+    pub date_source: DateSource,
+}
+
+impl SkippedFile {
+    pub(crate) fn new(source: &Path, reason: SkipReason) -> SkippedFile {
+        SkippedFile {
+            source: source.to_path_buf(),
+            target: None,
+            reason,
+            date: None,
+            date_source: DateSource::None,
+        }
+    }
+}
+
+/// The result of planning a single file.
+pub(crate) enum PlanEntry {
+    Action(PlannedAction),
+    Skipped(SkippedFile),
+}
+
+/// `PlanDiagnostic` describes a file that could not be planned, e.g. because the analysis failed or the
+/// computed target is invalid. The file is not part of the planned actions.
+///
+/// # Fields
+///
+/// * `path` - The source file.
+/// * `message` - A description of the problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanDiagnostic {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl Display for PlanDiagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.path, self.message)
+    }
+}
+
+/// `Plan` is the complete set of actions of a run, computed before any file is touched. Targets of the
+/// planned actions are unique: sources mapping to the same target are de-duplicated using the duplicate
+/// counter, in the order the files were planned.
+///
+/// # Fields
+///
+/// * `actions` - The planned actions, in execution order.
+/// * `skipped` - The files that are left untouched.
+/// * `diagnostics` - The files that could not be planned.
+/// * `filtered` - The files left out while scanning the source directories because of their size.
+/// * `near_duplicates` - The report of the near-duplicate photos, if `near_dup_distance` is set (only with the
+///   `perceptual-hash` feature).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    pub actions: Vec<PlannedAction>,
+    pub skipped: Vec<SkippedFile>,
+    pub diagnostics: Vec<PlanDiagnostic>,
+    pub filtered: Vec<PathBuf>,
+    #[cfg(feature = "perceptual-hash")]
+    pub near_duplicates: Option<NearDupReport>,
+}
+
+impl Plan {
+    /// Checks if all files could be planned, i.e. there are no diagnostics.
+    pub fn is_valid(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
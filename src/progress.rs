@@ -0,0 +1,32 @@
+use std::path::Path;
+
+/// `Progress` receives the progress of a run, e.g. to display a progress bar in a user interface. Unlike a
+/// `RunObserver`, it is always called from the thread executing the run, also when files are processed by
+/// multiple threads.
+pub trait Progress {
+    /// Called once before the first file is processed.
+    ///
+    /// # Arguments
+    ///
+    /// * `total` - The number of files of the plan, the performed actions and the skipped files.
+    fn begin(&self, total: u64);
+
+    /// Called once after `begin` if the run copies files, i.e. the action is copy or files are moved to another file
+    /// system, so that the progress can be displayed by the copied bytes. The bytes are reported as they are copied
+    /// by the copy progress callback (see [`Analyzer::set_copy_progress`](crate::Analyzer::set_copy_progress)).
+    ///
+    /// # Arguments
+    ///
+    /// * `total_bytes` - The size of the copied files of the plan (including sidecar files) in bytes.
+    fn begin_bytes(&self, _total_bytes: u64) {}
+
+    /// Called after a file was processed, skipped or failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The source file.
+    fn advance(&self, path: &Path);
+
+    /// Called once after the last file, also if the run was cancelled before all files were processed.
+    fn finish(&self);
+}
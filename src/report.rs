@@ -0,0 +1,490 @@
+use crate::action::{self, ActionMode, ActualAction};
+use crate::analysis::{self, DateSource};
+use crate::outcome::{FileOutcome, OutcomeAction, RunObserver};
+use crate::plan::{Operation, Plan};
+use crate::strip;
+use crate::summary::{RunSummary, SummaryCounts};
+use crate::Error;
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDateTime, TimeDelta};
+use log::error;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// `ReportEntry` is a single action of a `Report`.
+///
+/// # Fields
+///
+/// * `source` - The source file.
+/// * `target` - The target file. For deletions this is the existing identical file.
+/// * `action` - The action, e.g. `Move`, `Copy`, `Delete` or `Link` (a hardlink to an identical file transferred earlier).
+/// * `overwrite` - A boolean that indicates whether an existing target file is replaced.
+/// * `date` - The date derived from the source file, if any.
+/// * `date_source` - Where the date was derived from.
+/// * `mtime` - The modification time the target file is set to (see `set_mtime`), if any.
+/// * `long_path` - A boolean that indicates whether the target path is near the path length limit of older tools
+///   (see [`flag_long_paths`](Report::flag_long_paths)).
+/// * `strip` - A boolean that indicates whether Exif tags are removed from the copied file (see
+///   [`flag_stripped`](Report::flag_stripped)).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReportEntry {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub action: String,
+    pub overwrite: bool,
+    pub date: Option<NaiveDateTime>,
+    pub date_source: DateSource,
+    pub mtime: Option<NaiveDateTime>,
+    pub long_path: bool,
+    pub strip: bool,
+}
+
+/// `Report` is a structured description of what a (dry) run does, built from a `Plan`.
+///
+/// # Fields
+///
+/// * `mkdir` - The target subfolders that are created, parents before their children.
+/// * `actions` - The file actions, including the actions of sidecar files.
+/// * `rmdir` - The source directories that are removed because they are empty afterward, children before their parents.
+/// * `time_offset` - The time offset (in seconds) that was added to the derived dates (see `time_offset`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub mkdir: Vec<PathBuf>,
+    pub actions: Vec<ReportEntry>,
+    pub rmdir: Vec<PathBuf>,
+    pub time_offset: i64,
+}
+
+impl Report {
+    /// Builds the report of a plan.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The plan to describe.
+    /// * `action` - The configured file action.
+    /// * `set_mtime` - A boolean that indicates whether the modification time of target files is set to their date.
+    pub fn from_plan(plan: &Plan, action: ActualAction, set_mtime: bool) -> Report {
+        let set_mtime = set_mtime && action::supports_set_mtime(action);
+        let mut report = Report::default();
+        let mut known_dirs = HashSet::new();
+
+        for planned in &plan.actions {
+            let overwrite = match planned.operation {
+                Operation::Transfer { overwrite } => overwrite,
+                Operation::DeleteDuplicate => {
+                    report.actions.push(ReportEntry {
+                        source: planned.source.clone(),
+                        target: planned.target.clone(),
+                        action: "Delete".to_string(),
+                        overwrite: false,
+                        date: planned.date,
+                        date_source: planned.date_source,
+                        mtime: None,
+                        long_path: false,
+                        strip: false,
+                    });
+                    continue;
+                }
+                // the sidecars are transferred as usual
+                Operation::LinkDuplicate { overwrite, .. } => overwrite,
+            };
+
+            let files = std::iter::once((&planned.source, &planned.target)).chain(
+                planned
+                    .sidecars
+                    .iter()
+                    .map(|(sidecar, target)| (sidecar, target)),
+            );
+            for (source, target) in files {
+                let is_link = source == &planned.source
+                    && matches!(planned.operation, Operation::LinkDuplicate { .. });
+                report.add_missing_dirs(target, &mut known_dirs);
+                report.actions.push(ReportEntry {
+                    source: source.clone(),
+                    target: target.clone(),
+                    action: if is_link {
+                        "Link".to_string()
+                    } else {
+                        action.to_string()
+                    },
+                    overwrite,
+                    date: planned.date,
+                    date_source: planned.date_source,
+                    mtime: planned.date.filter(|_| set_mtime),
+                    long_path: false,
+                    strip: false,
+                });
+            }
+        }
+
+        report
+    }
+
+    /// Flags the actions whose target path is at least `limit` characters long (see
+    /// [`path_length`](action::path_length)), e.g. for tools limited by the `MAX_PATH` of Windows. Deletions are not
+    /// flagged, their target already exists.
+    pub fn flag_long_paths(&mut self, limit: usize) {
+        for entry in &mut self.actions {
+            entry.long_path =
+                entry.action != "Delete" && action::path_length(&entry.target) >= limit;
+        }
+    }
+
+    /// Flags the copies whose Exif tags are removed (see `strip_tags`), i.e. the copies of JPEG files.
+    pub fn flag_stripped(&mut self) {
+        for entry in &mut self.actions {
+            entry.strip =
+                entry.action == ActualAction::Copy.to_string() && strip::can_strip(&entry.source);
+        }
+    }
+
+    /// Adds the missing parent directories of a target, parents first.
+    fn add_missing_dirs(&mut self, target: &Path, known_dirs: &mut HashSet<PathBuf>) {
+        let missing: Vec<_> = target
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .collect();
+        for dir in missing.into_iter().rev() {
+            if known_dirs.insert(dir.to_path_buf()) {
+                self.mkdir.push(dir.to_path_buf());
+            }
+        }
+    }
+
+    /// Writes the report as an aligned table.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing fails.
+    pub fn write_table<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        let mut rows = vec![[
+            "Action".to_string(),
+            "Date".to_string(),
+            "Date source".to_string(),
+            "Source".to_string(),
+            "Target".to_string(),
+            "Mtime".to_string(),
+        ]];
+        for dir in &self.mkdir {
+            rows.push([
+                "Mkdir".to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+                dir.display().to_string(),
+                String::new(),
+            ]);
+        }
+        for entry in &self.actions {
+            let mut action = if entry.overwrite {
+                format!("{} (overwrite)", entry.action)
+            } else {
+                entry.action.clone()
+            };
+            if entry.long_path {
+                action.push_str(" (long path)");
+            }
+            if entry.strip {
+                action.push_str(" (strip)");
+            }
+            rows.push([
+                action,
+                entry
+                    .date
+                    .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default(),
+                entry.date_source.to_string(),
+                entry.source.display().to_string(),
+                entry.target.display().to_string(),
+                entry
+                    .mtime
+                    .map(|mtime| mtime.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default(),
+            ]);
+        }
+        for dir in &self.rmdir {
+            rows.push([
+                "Rmdir".to_string(),
+                String::new(),
+                String::new(),
+                dir.display().to_string(),
+                String::new(),
+                String::new(),
+            ]);
+        }
+
+        let mut widths = [0; 6];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        for row in &rows {
+            let line = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(out, "{}", line.trim_end())?;
+        }
+        if self.time_offset != 0 {
+            writeln!(
+                out,
+                "Dates are shifted by {}",
+                analysis::format_time_offset(TimeDelta::seconds(self.time_offset))
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the report as JSON to a file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be written.
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| anyhow!("Failed to create report file: {:?} - {:?}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// `OutcomeKind` is an enumeration that defines the result of a file in an `OutcomeLog`.
+///
+/// # Variants
+///
+/// * `Done` - The action of the file was performed (or printed, in dry-run mode).
+/// * `Skipped` - The file was left untouched, see `OutcomeEntry::skip_reason`.
+/// * `Failed` - The action of the file failed, see `OutcomeEntry::error`.
+/// * `Invalid` - The file could not be planned, e.g. because its analysis failed, see `OutcomeEntry::error`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutcomeKind {
+    Done,
+    Skipped,
+    Failed,
+    Invalid,
+}
+
+/// `OutcomeEntry` is a single file of an `OutcomeLog`.
+///
+/// # Fields
+///
+/// * `source` - The source file.
+/// * `target` - The target file, if it is known (see `FileOutcome::target`).
+/// * `action` - The action, e.g. `Move`, `Copy`, `Delete`, `Link` or `Skip`, if it is known.
+/// * `dry_run` - A boolean that indicates whether the action was only printed.
+/// * `date` - The date derived from the source file, if any.
+/// * `date_source` - Where the date was derived from.
+/// * `duplicate_counter` - The duplicate counter (`{dup}`) used to make the target unique, if any.
+/// * `outcome` - The result of the file.
+/// * `skip_reason` - Why the file was skipped, if it was skipped.
+/// * `error` - The error message, if the file failed or could not be planned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutcomeEntry {
+    pub source: PathBuf,
+    pub target: Option<PathBuf>,
+    pub action: Option<String>,
+    pub dry_run: bool,
+    pub date: Option<NaiveDateTime>,
+    pub date_source: DateSource,
+    pub duplicate_counter: Option<u32>,
+    pub outcome: OutcomeKind,
+    pub skip_reason: Option<String>,
+    pub error: Option<String>,
+}
+
+impl OutcomeEntry {
+    /// The entry of a performed or skipped file.
+    pub fn from_outcome(outcome: &FileOutcome) -> OutcomeEntry {
+        let action = match outcome.action {
+            OutcomeAction::Transfer(action) => action.to_string(),
+            OutcomeAction::Delete => "Delete".to_string(),
+            OutcomeAction::Link => "Link".to_string(),
+            OutcomeAction::Skip => "Skip".to_string(),
+        };
+        OutcomeEntry {
+            source: outcome.source.clone(),
+            target: outcome.target.clone(),
+            action: Some(action),
+            dry_run: outcome.dry_run,
+            date: outcome.date,
+            date_source: outcome.date_source,
+            duplicate_counter: outcome.duplicate_counter,
+            outcome: match outcome.action {
+                OutcomeAction::Skip => OutcomeKind::Skipped,
+                _ => OutcomeKind::Done,
+            },
+            skip_reason: outcome.skipped_reason.map(|reason| reason.to_string()),
+            error: None,
+        }
+    }
+
+    /// The entry of a failed file or a file that could not be planned.
+    pub fn from_error(
+        path: &Path,
+        outcome: OutcomeKind,
+        error: String,
+        dry_run: bool,
+    ) -> OutcomeEntry {
+        OutcomeEntry {
+            source: path.to_path_buf(),
+            target: None,
+            action: None,
+            dry_run,
+            date: None,
+            date_source: DateSource::None,
+            duplicate_counter: None,
+            outcome,
+            skip_reason: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// The trailing line of an `OutcomeLog`.
+#[derive(Serialize)]
+struct SummaryEntry<'a> {
+    action: String,
+    total: &'a SummaryCounts,
+    success: bool,
+    cancelled: bool,
+    elapsed_secs: f64,
+}
+
+/// A line of an `OutcomeLog`, tagged with its type.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutcomeLine<'a> {
+    File(&'a OutcomeEntry),
+    Summary(SummaryEntry<'a>),
+}
+
+/// `OutcomeLog` writes the outcome of every file of a run as JSON Lines: one `OutcomeEntry` object per file
+/// (`"type": "file"`), followed by a summary object (`"type": "summary"`). Every line is flushed when it is
+/// written, so the log is complete up to the last processed file even if the program is aborted. As a
+/// `RunObserver` it logs the files of a run as they are processed, also from worker threads.
+pub struct OutcomeLog {
+    writer: Mutex<Box<dyn Write + Send>>,
+    dry_run: bool,
+}
+
+impl OutcomeLog {
+    /// Creates the log file, `-` writes the log to stdout.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the log file.
+    /// * `dry_run` - A boolean that indicates whether the logged run is a dry run.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be created.
+    pub fn create<P: AsRef<Path>>(path: P, dry_run: bool) -> Result<OutcomeLog> {
+        let path = path.as_ref();
+        let writer: Box<dyn Write + Send> = if path == Path::new("-") {
+            Box::new(std::io::stdout())
+        } else {
+            let file = File::create(path)
+                .map_err(|e| anyhow!("Failed to create outcome log: {:?} - {:?}", path, e))?;
+            Box::new(BufWriter::new(file))
+        };
+        Ok(OutcomeLog {
+            writer: Mutex::new(writer),
+            dry_run,
+        })
+    }
+
+    /// Writes a file entry.
+    pub fn write_entry(&self, entry: &OutcomeEntry) {
+        self.write_line(&OutcomeLine::File(entry));
+    }
+
+    /// Writes the entries of the files of a plan that could not be planned. The other files are written as
+    /// they are processed, see [`RunObserver`].
+    pub fn write_invalid(&self, plan: &Plan) {
+        for diagnostic in &plan.diagnostics {
+            self.write_entry(&OutcomeEntry::from_error(
+                &diagnostic.path,
+                OutcomeKind::Invalid,
+                diagnostic.message.clone(),
+                self.dry_run,
+            ));
+        }
+    }
+
+    /// Writes the entries of all files of a plan without executing it, e.g. in dry-run mode.
+    pub fn write_plan(&self, plan: &Plan, mode: &ActionMode) {
+        self.write_invalid(plan);
+        for skipped in &plan.skipped {
+            self.write_entry(&OutcomeEntry::from_outcome(&FileOutcome::from_skipped(
+                skipped, mode,
+            )));
+        }
+        for action in &plan.actions {
+            self.write_entry(&OutcomeEntry::from_outcome(&FileOutcome::from_action(
+                action, mode,
+            )));
+        }
+    }
+
+    /// Writes the trailing summary.
+    pub fn write_summary(&self, summary: &RunSummary) {
+        self.write_line(&OutcomeLine::Summary(SummaryEntry {
+            action: summary.action.to_string(),
+            total: &summary.total,
+            success: summary.is_success(),
+            cancelled: summary.cancelled,
+            elapsed_secs: summary.elapsed.as_secs_f64(),
+        }));
+    }
+
+    fn write_line(&self, line: &OutcomeLine) {
+        let Ok(mut writer) = self.writer.lock() else {
+            return;
+        };
+        let result = serde_json::to_writer(&mut *writer, line)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writeln!(writer))
+            .and_then(|_| writer.flush());
+        if let Err(err) = result {
+            error!("Error writing outcome log: {}", err);
+        }
+    }
+}
+
+impl RunObserver for OutcomeLog {
+    fn on_file_done(&self, outcome: &FileOutcome) {
+        self.write_entry(&OutcomeEntry::from_outcome(outcome));
+    }
+
+    fn on_error(&self, path: &Path, err: &Error) {
+        self.write_entry(&OutcomeEntry::from_error(
+            path,
+            OutcomeKind::Failed,
+            err.to_string(),
+            self.dry_run,
+        ));
+    }
+
+    fn on_invalid(&self, path: &Path, err: &Error) {
+        self.write_entry(&OutcomeEntry::from_error(
+            path,
+            OutcomeKind::Invalid,
+            err.to_string(),
+            self.dry_run,
+        ));
+    }
+}
@@ -0,0 +1,438 @@
+use crate::action::{self, ActualAction};
+use crate::plan::{Operation, Plan};
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// `ScriptFormat` is an enumeration that defines the shell of an exported script.
+///
+/// # Variants
+///
+/// * `Sh` - A POSIX shell script (`mkdir -p`, `mv`, `cp`, `ln`, `rm`).
+/// * `Ps1` - A PowerShell script (`New-Item`, `Move-Item`, `Copy-Item`, `Remove-Item`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ScriptFormat {
+    #[default]
+    Sh,
+    Ps1,
+}
+
+impl FromStr for ScriptFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "sh" => Ok(ScriptFormat::Sh),
+            "ps1" => Ok(ScriptFormat::Ps1),
+            _ => Err(anyhow!("Invalid script format")),
+        }
+    }
+}
+
+/// `ScriptCommand` is a single file operation of an exported script.
+enum ScriptCommand<'a> {
+    MakeDir(&'a Path),
+    Transfer {
+        action: ActualAction,
+        source: &'a Path,
+        target: &'a Path,
+        overwrite: bool,
+    },
+    Delete(&'a Path),
+}
+
+impl ScriptFormat {
+    /// Quotes a string as a single literal argument of the shell.
+    pub fn quote(&self, value: &str) -> String {
+        match self {
+            ScriptFormat::Sh => format!("'{}'", value.replace('\'', "'\\''")),
+            // PowerShell also ends single-quoted strings at the typographic single quotes, every quote character
+            // is escaped by doubling it
+            ScriptFormat::Ps1 => {
+                let mut quoted = String::with_capacity(value.len() + 2);
+                quoted.push('\'');
+                for c in value.chars() {
+                    if matches!(c, '\'' | '\u{2018}'..='\u{201B}') {
+                        quoted.push(c);
+                    }
+                    quoted.push(c);
+                }
+                quoted.push('\'');
+                quoted
+            }
+        }
+    }
+
+    /// The lines at the start of a script: the interpreter, the provenance comment and stopping at the first error.
+    fn header(&self, invocation: &[String]) -> Vec<String> {
+        let invocation = invocation
+            .iter()
+            .map(|arg| {
+                let plain = !arg.is_empty()
+                    && arg
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "_-./:=".contains(c));
+                if plain {
+                    arg.clone()
+                } else {
+                    self.quote(arg)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut lines = Vec::new();
+        if *self == ScriptFormat::Sh {
+            lines.push("#!/bin/sh".to_string());
+        }
+        lines.push(format!(
+            "# Generated by PhotoSort {}, review it before running it.",
+            env!("CARGO_PKG_VERSION")
+        ));
+        // a line break in an argument must not end the comment
+        let invocation_lines = invocation
+            .split(['\r', '\n'])
+            .filter(|line| !line.is_empty());
+        for (index, line) in invocation_lines.enumerate() {
+            let prefix = if index == 0 { "Invocation: " } else { "" };
+            lines.push(format!("# {}{}", prefix, line));
+        }
+        lines.push(match self {
+            ScriptFormat::Sh => "set -e".to_string(),
+            ScriptFormat::Ps1 => "$ErrorActionPreference = 'Stop'".to_string(),
+        });
+        lines
+    }
+
+    /// Renders a command as a line of the script.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a path is not valid UTF-8, or if no relative path to the source of a
+    /// relative symlink exists.
+    fn render(&self, command: &ScriptCommand) -> Result<String> {
+        let q = |path: &Path| -> Result<String> {
+            let path = path.to_str().ok_or_else(|| {
+                anyhow!(
+                    "Path is not valid UTF-8, it can not be exported: {:?}",
+                    path
+                )
+            })?;
+            Ok(self.quote(path))
+        };
+        let line = match (self, command) {
+            (ScriptFormat::Sh, ScriptCommand::MakeDir(dir)) => format!("mkdir -p -- {}", q(dir)?),
+            (ScriptFormat::Ps1, ScriptCommand::MakeDir(dir)) => format!(
+                "New-Item -ItemType Directory -Force -Path {} | Out-Null",
+                q(dir)?
+            ),
+            (ScriptFormat::Sh, ScriptCommand::Delete(path)) => format!("rm -- {}", q(path)?),
+            (ScriptFormat::Ps1, ScriptCommand::Delete(path)) => {
+                format!("Remove-Item -LiteralPath {}", q(path)?)
+            }
+            (
+                format,
+                ScriptCommand::Transfer {
+                    action,
+                    source,
+                    target,
+                    overwrite,
+                },
+            ) => {
+                // symlinks point to the source, relative to the directory of the link
+                let link = match action {
+                    ActualAction::RelativeSymlink => q(&relative_link(source, target)?)?,
+                    _ => q(source)?,
+                };
+                let (source, target) = (q(source)?, q(target)?);
+                let force = if *overwrite { " -f" } else { "" };
+                let ps_force = if *overwrite { " -Force" } else { "" };
+                match (format, action) {
+                    (ScriptFormat::Sh, ActualAction::Move) => {
+                        format!("mv{} -- {} {}", force, source, target)
+                    }
+                    (ScriptFormat::Sh, ActualAction::Copy) => {
+                        format!("cp{} -- {} {}", force, source, target)
+                    }
+                    (ScriptFormat::Sh, ActualAction::Reflink) => {
+                        format!("cp --reflink=always{} -- {} {}", force, source, target)
+                    }
+                    (ScriptFormat::Sh, ActualAction::Hardlink) => {
+                        format!("ln{} -- {} {}", force, source, target)
+                    }
+                    (
+                        ScriptFormat::Sh,
+                        ActualAction::RelativeSymlink | ActualAction::AbsoluteSymlink,
+                    ) => format!("ln -s{} -- {} {}", force, link, target),
+                    (ScriptFormat::Ps1, ActualAction::Move) => format!(
+                        "Move-Item{} -LiteralPath {} -Destination {}",
+                        ps_force, source, target
+                    ),
+                    (ScriptFormat::Ps1, ActualAction::Copy | ActualAction::Reflink) => format!(
+                        "Copy-Item{} -LiteralPath {} -Destination {}",
+                        ps_force, source, target
+                    ),
+                    (ScriptFormat::Ps1, ActualAction::Hardlink) => format!(
+                        "New-Item -ItemType HardLink{} -Path {} -Target {} | Out-Null",
+                        ps_force, target, source
+                    ),
+                    (
+                        ScriptFormat::Ps1,
+                        ActualAction::RelativeSymlink | ActualAction::AbsoluteSymlink,
+                    ) => format!(
+                        "New-Item -ItemType SymbolicLink{} -Path {} -Target {} | Out-Null",
+                        ps_force, target, link
+                    ),
+                }
+            }
+        };
+        Ok(line)
+    }
+}
+
+/// The path a relative symlink at the target points to the source with. Unlike a run, the paths are not
+/// canonicalized, as the target directories may not exist yet.
+fn relative_link(source: &Path, target: &Path) -> Result<PathBuf> {
+    let parent = target.parent().unwrap_or(Path::new("."));
+    action::relative_path(parent, source)
+        .ok_or_else(|| anyhow!("No relative path from {:?} to {:?}", parent, source))
+}
+
+/// Writes the planned actions as a script: the parent directory of every target is created, then the configured
+/// file action (move, copy, ...) is performed, followed by the sidecars. Identical source files are deleted or
+/// hardlinked like in a run. Setting the modification time, stripping tags, mirror directories and the journal are
+/// not part of the script.
+///
+/// # Arguments
+///
+/// * `out` - The writer of the script.
+/// * `plan` - The plan, computed like for a run so that the script resolves duplicates the same way.
+/// * `action` - The configured file action.
+/// * `format` - The shell of the script.
+/// * `invocation` - The command line arguments of the PhotoSort invocation, recorded in the header.
+///
+/// # Errors
+///
+/// This function will return an error if writing fails, or if a path can not be exported (it is not valid UTF-8).
+pub fn write_script<W: Write>(
+    out: &mut W,
+    plan: &Plan,
+    action: ActualAction,
+    format: ScriptFormat,
+    invocation: &[String],
+) -> Result<()> {
+    for line in format.header(invocation) {
+        writeln!(out, "{}", line)?;
+    }
+
+    // the paths are made absolute, so that the script can be run from any directory
+    let mut dirs: HashSet<PathBuf> = HashSet::new();
+    for planned in &plan.actions {
+        let source = std::path::absolute(&planned.source)?;
+        let target = std::path::absolute(&planned.target)?;
+        let original = match &planned.operation {
+            Operation::LinkDuplicate { original, .. } => Some(std::path::absolute(original)?),
+            _ => None,
+        };
+        let sidecars = planned
+            .sidecars
+            .iter()
+            .map(|(source, target)| {
+                Ok((std::path::absolute(source)?, std::path::absolute(target)?))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        writeln!(out)?;
+        let mut commands = Vec::new();
+        let overwrite = match (&planned.operation, &original) {
+            (Operation::DeleteDuplicate, _) => {
+                writeln!(out, "# identical to {}", target.display())?;
+                commands.push(ScriptCommand::Delete(&source));
+                false
+            }
+            // like in a run, the target is a hardlink to the target of the identical file, a moved source is deleted
+            (Operation::LinkDuplicate { overwrite, .. }, Some(original)) => {
+                writeln!(out, "# identical to {}", original.display())?;
+                commands.push(ScriptCommand::Transfer {
+                    action: ActualAction::Hardlink,
+                    source: original,
+                    target: &target,
+                    overwrite: *overwrite,
+                });
+                if action == ActualAction::Move {
+                    commands.push(ScriptCommand::Delete(&source));
+                }
+                *overwrite
+            }
+            (Operation::Transfer { overwrite } | Operation::LinkDuplicate { overwrite, .. }, _) => {
+                commands.push(ScriptCommand::Transfer {
+                    action,
+                    source: &source,
+                    target: &target,
+                    overwrite: *overwrite,
+                });
+                *overwrite
+            }
+        };
+        if planned.operation != Operation::DeleteDuplicate {
+            for (source, target) in &sidecars {
+                commands.push(ScriptCommand::Transfer {
+                    action,
+                    source,
+                    target,
+                    overwrite,
+                });
+            }
+        }
+
+        for command in &commands {
+            if let ScriptCommand::Transfer { target, .. } = command {
+                if let Some(dir) = target.parent().filter(|dir| dirs.insert(dir.to_path_buf())) {
+                    writeln!(out, "{}", format.render(&ScriptCommand::MakeDir(dir))?)?;
+                }
+            }
+            writeln!(out, "{}", format.render(command)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the planned actions as a script to a file (see [`write_script`]). Shell scripts are made executable.
+///
+/// # Errors
+///
+/// This function will return an error if the file can not be written, or if a path can not be exported.
+pub fn export_script<P: AsRef<Path>>(
+    path: P,
+    plan: &Plan,
+    action: ActualAction,
+    format: ScriptFormat,
+    invocation: &[String],
+) -> Result<()> {
+    let path = path.as_ref();
+    let file =
+        File::create(path).map_err(|e| anyhow!("Failed to create script: {:?} - {:?}", path, e))?;
+    let mut writer = BufWriter::new(file);
+    write_script(&mut writer, plan, action, format, invocation)?;
+    writer.flush()?;
+
+    #[cfg(unix)]
+    if format == ScriptFormat::Sh {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        permissions.set_mode(permissions.mode() | 0o111);
+        std::fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [&str; 8] = [
+        "it's",
+        "$HOME $(id) ${x}",
+        "`date` and `n",
+        "first\nsecond",
+        "-rf",
+        "\u{2018}smart\u{2019} \u{201A}low\u{201B}",
+        "",
+        "plain",
+    ];
+
+    #[test]
+    fn sh_quoting() {
+        let expected = [
+            r"'it'\''s'",
+            "'$HOME $(id) ${x}'",
+            "'`date` and `n'",
+            "'first\nsecond'",
+            "'-rf'",
+            "'\u{2018}smart\u{2019} \u{201A}low\u{201B}'",
+            "''",
+            "'plain'",
+        ];
+        for (value, expected) in VALUES.iter().zip(expected) {
+            assert_eq!(ScriptFormat::Sh.quote(value), expected);
+        }
+
+        // the shell reads every quoted value back unchanged
+        #[cfg(unix)]
+        for value in VALUES {
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(format!("printf '%s' {}", ScriptFormat::Sh.quote(value)))
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn ps1_quoting() {
+        let expected = [
+            "'it''s'",
+            "'$HOME $(id) ${x}'",
+            "'`date` and `n'",
+            "'first\nsecond'",
+            "'-rf'",
+            "'\u{2018}\u{2018}smart\u{2019}\u{2019} \u{201A}\u{201A}low\u{201B}\u{201B}'",
+            "''",
+            "'plain'",
+        ];
+        for (value, expected) in VALUES.iter().zip(expected) {
+            assert_eq!(ScriptFormat::Ps1.quote(value), expected);
+        }
+    }
+
+    #[test]
+    fn leading_dashes_are_not_options() {
+        let path = Path::new("-rf");
+        assert_eq!(
+            ScriptFormat::Sh
+                .render(&ScriptCommand::Delete(path))
+                .unwrap(),
+            "rm -- '-rf'"
+        );
+        assert_eq!(
+            ScriptFormat::Ps1
+                .render(&ScriptCommand::Delete(path))
+                .unwrap(),
+            "Remove-Item -LiteralPath '-rf'"
+        );
+    }
+
+    #[test]
+    fn header_comment_survives_line_breaks() {
+        let invocation = [
+            "photo_sort".to_string(),
+            "--format".to_string(),
+            "{name}\nrm -rf ~\r\nrm -rf /\rexit".to_string(),
+        ];
+        for format in [ScriptFormat::Sh, ScriptFormat::Ps1] {
+            let header = format.header(&invocation);
+            let comments: Vec<_> = header
+                .iter()
+                .filter(|line| !line.starts_with("#!"))
+                .take_while(|line| line.starts_with('#'))
+                .collect();
+            assert_eq!(comments.len(), 5, "{:?}", header);
+            assert_eq!(comments[1], "# Invocation: photo_sort --format '{name}");
+            assert_eq!(comments[4], "# exit'");
+            // every line of the header is a comment or a statement of the header
+            for line in &header {
+                assert!(!line.contains(['\r', '\n']), "{:?}", line);
+                assert!(
+                    line.starts_with('#')
+                        || line == "set -e"
+                        || line.starts_with("$ErrorActionPreference"),
+                    "{:?}",
+                    line
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use anyhow::{anyhow, Result};
+use filetime::FileTime;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The version of the state file format, state files of other versions are ignored
+pub const STATE_VERSION: u32 = 1;
+
+/// The first line of a state file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StateHeader {
+    version: u32,
+}
+
+/// `StateEntry` is a processed source file, one line of the state file.
+///
+/// # Fields
+///
+/// * `source` - The source file.
+/// * `size` - The size of the source file when it was processed.
+/// * `modified` - The modification time of the source file when it was processed (nanoseconds since the unix epoch).
+/// * `target` - The resulting target file (or the existing identical file).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateEntry {
+    pub source: PathBuf,
+    pub size: u64,
+    pub modified: i64,
+    pub target: PathBuf,
+}
+
+/// `ProcessedState` records the source files processed by previous runs (JSON lines: a header with the format
+/// version, followed by one `StateEntry` per file), so that later runs skip files that are unchanged since (same
+/// path, size and modification time). Entries are appended and flushed as files are processed, so the state is
+/// complete up to the last processed file even if the program is aborted.
+pub struct ProcessedState {
+    path: PathBuf,
+    entries: HashMap<PathBuf, StateEntry>,
+    writer: Option<Mutex<File>>,
+}
+
+impl ProcessedState {
+    /// Opens a state file. A missing, corrupt or outdated state file is (re)started empty, a corrupt one with
+    /// a warning. Otherwise, the file is rewritten with the latest entry of every source file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the state file.
+    /// * `reset` - If set, the recorded files are discarded.
+    /// * `update` - If not set, the state file is only read (e.g. in dry-run mode).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the state file can not be written.
+    pub fn open<P: AsRef<Path>>(path: P, reset: bool, update: bool) -> Result<ProcessedState> {
+        let path = path.as_ref().to_path_buf();
+        let entries = if reset {
+            info!("Resetting state file {:?}", path);
+            HashMap::new()
+        } else if path.exists() {
+            read_state(&path).unwrap_or_else(|err| {
+                warn!("Ignoring state file {:?}: {}", path, err);
+                HashMap::new()
+            })
+        } else {
+            HashMap::new()
+        };
+        debug!(
+            "Loaded {} previously processed files from {:?}",
+            entries.len(),
+            path
+        );
+
+        let writer = if update {
+            write_state(&path, entries.values())?;
+            let file = OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .map_err(|e| anyhow!("Failed to open state file: {:?} - {:?}", path, e))?;
+            Some(Mutex::new(file))
+        } else {
+            None
+        };
+
+        Ok(ProcessedState {
+            path,
+            entries,
+            writer,
+        })
+    }
+
+    /// Checks if a file was processed by a previous run and is unchanged since.
+    pub fn is_processed(&self, source: &Path) -> bool {
+        let Some(entry) = self.entries.get(source) else {
+            return false;
+        };
+        match fs::metadata(source) {
+            Ok(metadata) => {
+                metadata.len() == entry.size && modification_time(&metadata) == entry.modified
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Records a processed file. Errors are logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source file.
+    /// * `target` - The resulting target file.
+    pub fn record(&self, source: &Path, target: &Path) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+        let metadata = match fs::metadata(source) {
+            Ok(metadata) => metadata,
+            // the source was moved or deleted, a later run will not find it again
+            Err(_) => return,
+        };
+        let entry = StateEntry {
+            source: source.to_path_buf(),
+            size: metadata.len(),
+            modified: modification_time(&metadata),
+            target: target.to_path_buf(),
+        };
+
+        let result = serde_json::to_string(&entry)
+            .map_err(std::io::Error::from)
+            .and_then(|mut line| {
+                line.push('\n');
+                let mut file = writer
+                    .lock()
+                    .map_err(|_| std::io::Error::other("State file lock poisoned"))?;
+                file.write_all(line.as_bytes()).and_then(|_| file.flush())
+            });
+        if let Err(err) = result {
+            warn!("Failed to write state file {:?}: {}", self.path, err);
+        }
+    }
+}
+
+fn modification_time(metadata: &fs::Metadata) -> i64 {
+    let modified = FileTime::from_last_modification_time(metadata);
+    modified.unix_seconds() * 1_000_000_000 + modified.nanoseconds() as i64
+}
+
+/// Reads a state file, later entries of a source file replace earlier ones. Invalid lines (e.g. a line cut off
+/// by an interrupted run) are ignored with a warning.
+///
+/// # Errors
+///
+/// This function will return an error if the file can not be read, or if the header is missing or has another
+/// version.
+fn read_state(path: &Path) -> Result<HashMap<PathBuf, StateEntry>> {
+    let file = File::open(path)?;
+    // split into raw lines, so that a line that is not valid UTF-8 does not fail the whole file
+    let mut lines = BufReader::new(file).split(b'\n');
+
+    let header = lines.next().ok_or(anyhow!("the file is empty"))??;
+    let header: StateHeader =
+        serde_json::from_slice(&header).map_err(|_| anyhow!("the header is missing or corrupt"))?;
+    if header.version != STATE_VERSION {
+        return Err(anyhow!(
+            "unsupported version {} (expected {})",
+            header.version,
+            STATE_VERSION
+        ));
+    }
+
+    let mut entries = HashMap::new();
+    let mut invalid = 0;
+    for line in lines {
+        match serde_json::from_slice::<StateEntry>(&line?) {
+            Ok(entry) => {
+                entries.insert(entry.source.clone(), entry);
+            }
+            Err(_) => invalid += 1,
+        }
+    }
+    if invalid > 0 {
+        warn!(
+            "Ignoring {} invalid entries of state file {:?}",
+            invalid, path
+        );
+    }
+    Ok(entries)
+}
+
+/// Rewrites the state file with the header and the given entries.
+fn write_state<'a, I: Iterator<Item = &'a StateEntry>>(path: &Path, entries: I) -> Result<()> {
+    let mut temporary = path.as_os_str().to_os_string();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut writer = BufWriter::new(
+        File::create(&temporary)
+            .map_err(|e| anyhow!("Failed to write state file: {:?} - {:?}", temporary, e))?,
+    );
+    serde_json::to_writer(
+        &mut writer,
+        &StateHeader {
+            version: STATE_VERSION,
+        },
+    )?;
+    writer.write_all(b"\n")?;
+    for entry in entries {
+        serde_json::to_writer(&mut writer, entry)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    drop(writer);
+
+    fs::rename(&temporary, path)
+        .map_err(|e| anyhow!("Failed to write state file: {:?} - {:?}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{ActionMode, ActualAction};
+    use crate::plan::SkipReason;
+    use crate::{AnalysisType, Analyzer, AnalyzerSettings};
+
+    /// A state file recording the given file as processed, with the given version in its header.
+    fn state_file(dir: &Path, version: u32, source: &Path) -> PathBuf {
+        let metadata = fs::metadata(source).unwrap();
+        let entry = StateEntry {
+            source: source.to_path_buf(),
+            size: metadata.len(),
+            modified: modification_time(&metadata),
+            target: dir.join("target.jpg"),
+        };
+        let path = dir.join("state.jsonl");
+        let content = format!(
+            "{}\n{}\n",
+            serde_json::to_string(&StateHeader { version }).unwrap(),
+            serde_json::to_string(&entry).unwrap()
+        );
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn state_of_another_version_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("photo.jpg");
+        fs::write(&source, b"photo").unwrap();
+
+        let path = state_file(dir.path(), STATE_VERSION, &source);
+        assert!(ProcessedState::open(&path, false, false)
+            .unwrap()
+            .is_processed(&source));
+
+        let path = state_file(dir.path(), STATE_VERSION + 1, &source);
+        let state = ProcessedState::open(&path, false, true).unwrap();
+        assert!(!state.is_processed(&source));
+        // the state file is restarted with the current version
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{{\"version\":{}}}\n", STATE_VERSION)
+        );
+    }
+
+    #[test]
+    fn garbage_state_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("photo.jpg");
+        fs::write(&source, b"photo").unwrap();
+        let path = dir.path().join("state.jsonl");
+
+        for content in [
+            &b""[..],
+            b"\n",
+            b"not json at all",
+            b"{\"version\":\"1\"}\n",
+            b"\xff\xfe\x00garbage\n\x01",
+        ] {
+            fs::write(&path, content).unwrap();
+            let state = ProcessedState::open(&path, false, false).unwrap();
+            assert!(!state.is_processed(&source), "{:?}", content);
+        }
+
+        // invalid entries are skipped, the valid ones are kept
+        let path = state_file(dir.path(), STATE_VERSION, &source);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"source\": 42}\n\xff\xff\n{\"sou")
+            .unwrap();
+        drop(file);
+        assert!(ProcessedState::open(&path, false, true)
+            .unwrap()
+            .is_processed(&source));
+    }
+
+    fn analyzer(source: &Path, target: &Path, state: &Path, reset: bool) -> Analyzer {
+        AnalyzerSettings::builder()
+            .source_dir(source)
+            .target_dir(target)
+            .analysis_type(AnalysisType::OnlyName)
+            .nodate_file_format("{name}{-:dup}.{ext}")
+            .action_type(ActionMode::Execute(ActualAction::Copy))
+            .state_file(Some(state))
+            .reset_state(reset)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn unchanged_files_are_skipped_by_later_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let [source, target] = ["source", "target"].map(|name| {
+            let path = dir.path().join(name);
+            fs::create_dir(&path).unwrap();
+            path
+        });
+        let state = dir.path().join("state.jsonl");
+        let photo = source.join("photo.jpg");
+        fs::write(&photo, b"photo").unwrap();
+        let skipped_reason = |reset: bool| {
+            let analyzer = analyzer(&source, &target, &state, reset);
+            analyzer.run_file(&photo).unwrap().skipped_reason
+        };
+
+        assert_eq!(skipped_reason(false), None);
+        assert_eq!(skipped_reason(false), Some(SkipReason::PreviouslyProcessed));
+
+        // a changed size
+        fs::write(&photo, b"edited photo").unwrap();
+        assert_eq!(skipped_reason(false), None);
+        assert_eq!(skipped_reason(false), Some(SkipReason::PreviouslyProcessed));
+
+        // a changed modification time, the file is processed again and found at the target
+        filetime::set_file_mtime(&photo, FileTime::from_unix_time(1_000_000_000, 0)).unwrap();
+        assert_eq!(skipped_reason(false), Some(SkipReason::IdenticalTarget));
+        assert_eq!(skipped_reason(false), Some(SkipReason::PreviouslyProcessed));
+
+        // a reset forgets all processed files
+        fs::remove_dir_all(&target).unwrap();
+        fs::create_dir(&target).unwrap();
+        assert_eq!(skipped_reason(true), None);
+        assert_eq!(fs::read(target.join("photo.jpg")).unwrap(), b"edited photo");
+        assert_eq!(skipped_reason(false), Some(SkipReason::PreviouslyProcessed));
+    }
+}
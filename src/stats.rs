@@ -0,0 +1,233 @@
+use crate::action::ActionMode;
+use crate::outcome::{FileOutcome, OutcomeAction};
+use crate::plan::Plan;
+use anyhow::{anyhow, Result};
+use chrono::Datelike;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// `DateStats` holds the number of files a run placed at their target (transferred or linked, not counting
+/// sidecar files) per year and per month of their date, e.g. to spot implausible dates like 1970 at a glance.
+///
+/// # Fields
+///
+/// * `years` - The number of files per year.
+/// * `months` - The number of files per month (`YYYY-MM`).
+/// * `no_date` - Placed files without a date (not counting `unknown` files, they are not analyzed).
+/// * `unknown` - Placed files with none of the configured extensions, processed using the unknown file format.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DateStats {
+    pub years: BTreeMap<i32, usize>,
+    pub months: BTreeMap<String, usize>,
+    pub no_date: usize,
+    pub unknown: usize,
+}
+
+impl DateStats {
+    /// The statistics a plan would have if all its actions succeed, e.g. for a dry run.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The plan.
+    /// * `mode` - The action mode of the plan.
+    pub fn from_plan(plan: &Plan, mode: &ActionMode) -> DateStats {
+        let mut stats = DateStats::default();
+        for action in &plan.actions {
+            stats.record(&FileOutcome::from_action(action, mode));
+        }
+        stats
+    }
+
+    /// Counts the outcome of a file, files that were not placed at a target are ignored.
+    pub fn record(&mut self, outcome: &FileOutcome) {
+        if !matches!(
+            outcome.action,
+            OutcomeAction::Transfer(_) | OutcomeAction::Link
+        ) {
+            return;
+        }
+        if outcome.unknown_extension {
+            self.unknown += 1;
+            return;
+        }
+        let Some(date) = outcome.date else {
+            self.no_date += 1;
+            return;
+        };
+        *self.years.entry(date.year()).or_default() += 1;
+        *self
+            .months
+            .entry(format!("{:04}-{:02}", date.year(), date.month()))
+            .or_default() += 1;
+    }
+
+    /// Writes the statistics as aligned tables: the files per year, the files per month and the files without date
+    /// or with unknown extension.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing fails.
+    pub fn write_table<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        let years = self
+            .years
+            .iter()
+            .map(|(year, count)| (year.to_string(), *count));
+        write_counts(out, "Year", years)?;
+        writeln!(out)?;
+        let months = self
+            .months
+            .iter()
+            .map(|(month, count)| (month.clone(), *count));
+        write_counts(out, "Month", months)?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{} files without date, {} files with unknown extension",
+            self.no_date, self.unknown
+        )
+    }
+
+    /// Writes the statistics as JSON to a file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be written.
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| anyhow!("Failed to create statistics file: {:?} - {:?}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes a two-column table of labels and counts.
+fn write_counts<W: Write, I: Iterator<Item = (String, usize)>>(
+    out: &mut W,
+    header: &str,
+    rows: I,
+) -> std::io::Result<()> {
+    let rows: Vec<(String, String)> = rows
+        .map(|(label, count)| (label, count.to_string()))
+        .collect();
+    let label_width = rows
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .chain(std::iter::once(header.len()))
+        .max()
+        .unwrap_or_default();
+    let count_width = rows
+        .iter()
+        .map(|(_, count)| count.len())
+        .chain(std::iter::once("Files".len()))
+        .max()
+        .unwrap_or_default();
+
+    writeln!(out, "{:<label_width$}  {:>count_width$}", header, "Files")?;
+    for (label, count) in &rows {
+        writeln!(out, "{:<label_width$}  {:>count_width$}", label, count)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::ActualAction;
+    use crate::analysis::DateSource;
+    use chrono::NaiveDateTime;
+    use std::path::PathBuf;
+
+    fn outcome(action: OutcomeAction, date: Option<&str>, unknown_extension: bool) -> FileOutcome {
+        FileOutcome {
+            source: PathBuf::from("source.jpg"),
+            target: Some(PathBuf::from("target.jpg")),
+            action,
+            dry_run: false,
+            date: date
+                .map(|date| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap()),
+            date_source: DateSource::Exif,
+            duplicate_counter: None,
+            skipped_reason: None,
+            unknown_extension,
+            sidecars: Vec::new(),
+            mirror_failures: Vec::new(),
+            hardlink_fallbacks: 0,
+            symlink_fallbacks: 0,
+            undeleted_sources: Vec::new(),
+        }
+    }
+
+    fn stats() -> DateStats {
+        let copy = OutcomeAction::Transfer(ActualAction::Copy);
+        let mut stats = DateStats::default();
+        for outcome in [
+            outcome(copy, Some("2019-12-31 23:59:59"), false),
+            outcome(copy, Some("2020-01-01 00:00:00"), false),
+            outcome(copy, Some("2020-01-15 12:00:00"), false),
+            outcome(OutcomeAction::Link, Some("2020-07-04 12:00:00"), false),
+            outcome(copy, None, false),
+            outcome(copy, None, true),
+            // not placed at a target
+            outcome(OutcomeAction::Skip, Some("2021-01-01 00:00:00"), false),
+            outcome(OutcomeAction::Delete, Some("2021-01-01 00:00:00"), false),
+        ] {
+            stats.record(&outcome);
+        }
+        stats
+    }
+
+    #[test]
+    fn counts_placed_files_per_year_and_month() {
+        let stats = stats();
+        assert_eq!(stats.years, BTreeMap::from([(2019, 1), (2020, 3)]));
+        assert_eq!(
+            stats.months,
+            BTreeMap::from([
+                ("2019-12".to_string(), 1),
+                ("2020-01".to_string(), 2),
+                ("2020-07".to_string(), 1),
+            ])
+        );
+        assert_eq!(stats.no_date, 1);
+        assert_eq!(stats.unknown, 1);
+    }
+
+    #[test]
+    fn writes_aligned_tables() {
+        let mut out = Vec::new();
+        stats().write_table(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Year  Files\n\
+             2019      1\n\
+             2020      3\n\
+             \n\
+             Month    Files\n\
+             2019-12      1\n\
+             2020-01      2\n\
+             2020-07      1\n\
+             \n\
+             1 files without date, 1 files with unknown extension\n"
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.json");
+        stats().write_json(&path).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["years"]["2020"], 3);
+        assert_eq!(json["months"]["2019-12"], 1);
+        assert_eq!(json["no_date"], 1);
+        assert_eq!(json["unknown"], 1);
+    }
+}
@@ -0,0 +1,501 @@
+use anyhow::{anyhow, Result};
+use filetime::FileTime;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// `StripTag` is an enumeration that defines the groups of Exif tags that can be removed from copied JPEG files.
+///
+/// # Variants
+///
+/// * `Gps` - The GPS IFD (location, altitude, GPS time, ...).
+/// * `Serial` - The serial numbers of the camera body and lens.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StripTag {
+    Gps,
+    Serial,
+}
+
+impl FromStr for StripTag {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gps" | "location" => Ok(StripTag::Gps),
+            "serial" | "serials" => Ok(StripTag::Serial),
+            _ => Err(anyhow!("Invalid tag group to strip: {}", s)),
+        }
+    }
+}
+
+impl Display for StripTag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StripTag::Gps => write!(f, "gps"),
+            StripTag::Serial => write!(f, "serial"),
+        }
+    }
+}
+
+/// The tag of the pointer to the Exif IFD
+const TAG_EXIF_IFD: u16 = 0x8769;
+/// The tag of the pointer to the GPS IFD
+const TAG_GPS_IFD: u16 = 0x8825;
+/// The serial number tags of IFD0: `CameraSerialNumber` (DNG)
+const IFD0_SERIAL_TAGS: &[u16] = &[0xC62F];
+/// The serial number tags of the Exif IFD: `BodySerialNumber` and `LensSerialNumber`
+const EXIF_SERIAL_TAGS: &[u16] = &[0xA431, 0xA435];
+
+/// Checks if the tags of a file can be stripped, i.e. it is a JPEG file. Other formats (HEIC, RAW, videos) are
+/// not rewritten.
+pub fn can_strip(path: &Path) -> bool {
+    let mut header = [0u8; 3];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|_| header == [0xFF, 0xD8, 0xFF])
+}
+
+/// Removes the Exif tags of the given groups from a JPEG file. Only the Exif (APP1) segment is rewritten in place:
+/// the removed entries are taken out of their IFD and their values are overwritten with zeros, so that the segment
+/// keeps its length and all other bytes of the file stay identical. The modification time of the file is kept.
+/// Maker notes are not rewritten, they might still contain serial numbers. XMP metadata is left untouched.
+///
+/// # Arguments
+///
+/// * `path` - The JPEG file.
+/// * `tags` - The groups of tags to remove.
+///
+/// # Returns
+///
+/// * `Result<bool>` - `true` if tags were removed, `false` if the file has none of the tags.
+///
+/// # Errors
+///
+/// This function will return an error if the file is not a JPEG file, its Exif data is malformed (the file is left
+/// unmodified) or it can not be written.
+pub fn strip_tags(path: &Path, tags: &[StripTag]) -> Result<bool> {
+    let metadata = fs::metadata(path)?;
+    let content = fs::read(path)?;
+    let Some(segment) = exif_segment(&content)? else {
+        return Ok(false);
+    };
+
+    let mut tiff = content[segment.clone()].to_vec();
+    if !strip_tiff(&mut tiff, tags)? {
+        return Ok(false);
+    }
+
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(segment.start as u64))?;
+    file.write_all(&tiff)?;
+    file.sync_all()?;
+    drop(file);
+    filetime::set_file_mtime(path, FileTime::from_last_modification_time(&metadata))?;
+    Ok(true)
+}
+
+/// Finds the TIFF data of the Exif (APP1) segment of a JPEG file.
+///
+/// # Returns
+///
+/// * `Result<Option<std::ops::Range<usize>>>` - The range of the TIFF data in the file, `None` if the file has no
+///   Exif segment.
+fn exif_segment(content: &[u8]) -> Result<Option<std::ops::Range<usize>>> {
+    if !content.starts_with(&[0xFF, 0xD8]) {
+        return Err(anyhow!("Not a JPEG file"));
+    }
+    let mut position = 2;
+    while position + 4 <= content.len() {
+        if content[position] != 0xFF {
+            return Err(anyhow!("Malformed JPEG segment at byte {}", position));
+        }
+        let marker = content[position + 1];
+        match marker {
+            // fill bytes
+            0xFF => {
+                position += 1;
+                continue;
+            }
+            // standalone markers without length
+            0x01 | 0xD0..=0xD7 => {
+                position += 2;
+                continue;
+            }
+            // the image data follows the start of scan, the metadata segments come before it
+            0xD9 | 0xDA => return Ok(None),
+            _ => {}
+        }
+        let length = usize::from(u16::from_be_bytes([
+            content[position + 2],
+            content[position + 3],
+        ]));
+        let end = position + 2 + length;
+        if length < 2 || end > content.len() {
+            return Err(anyhow!("Truncated JPEG segment at byte {}", position));
+        }
+        let data = position + 4;
+        if marker == 0xE1 && content[data..end].starts_with(b"Exif\0\0") {
+            return Ok(Some(data + 6..end));
+        }
+        position = end;
+    }
+    Ok(None)
+}
+
+/// The byte order of TIFF data.
+#[derive(Copy, Clone)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// An IFD entry: its tag, type, number of values and its value (or the offset of its values).
+#[derive(Copy, Clone)]
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    value: u32,
+    raw: [u8; 12],
+}
+
+/// Accesses TIFF data with bounds checks.
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    order: ByteOrder,
+}
+
+impl Tiff<'_> {
+    fn bytes(&self, offset: usize, length: usize) -> Result<&[u8]> {
+        offset
+            .checked_add(length)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| anyhow!("Exif offset {} is out of bounds", offset))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        let mut bytes = [0u8; 2];
+        bytes.copy_from_slice(self.bytes(offset, 2)?);
+        Ok(match self.order {
+            ByteOrder::Little => u16::from_le_bytes(bytes),
+            ByteOrder::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.bytes(offset, 4)?);
+        Ok(match self.order {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) -> Result<()> {
+        let bytes = match self.order {
+            ByteOrder::Little => value.to_le_bytes(),
+            ByteOrder::Big => value.to_be_bytes(),
+        };
+        self.bytes(offset, 2)?;
+        self.data[offset..offset + 2].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    fn zero(&mut self, offset: usize, length: usize) -> Result<()> {
+        self.bytes(offset, length)?;
+        self.data[offset..offset + length].fill(0);
+        Ok(())
+    }
+
+    fn entries(&self, ifd: usize) -> Result<Vec<Entry>> {
+        let count = usize::from(self.u16(ifd)?);
+        (0..count)
+            .map(|index| {
+                let offset = ifd + 2 + index * 12;
+                let mut raw = [0u8; 12];
+                raw.copy_from_slice(self.bytes(offset, 12)?);
+                Ok(Entry {
+                    tag: self.u16(offset)?,
+                    kind: self.u16(offset + 2)?,
+                    count: self.u32(offset + 4)?,
+                    value: self.u32(offset + 8)?,
+                    raw,
+                })
+            })
+            .collect()
+    }
+
+    /// The offset and length of the values of an entry stored outside of the IFD, `None` if they fit into the entry.
+    fn external_values(&self, entry: &Entry) -> Result<Option<(usize, usize)>> {
+        let size = match entry.kind {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 | 13 => 4,
+            5 | 10 | 12 => 8,
+            kind => return Err(anyhow!("Unknown Exif value type {}", kind)),
+        };
+        let length = (entry.count as usize)
+            .checked_mul(size)
+            .ok_or_else(|| anyhow!("Exif value is too long"))?;
+        if length <= 4 {
+            return Ok(None);
+        }
+        self.bytes(entry.value as usize, length)?;
+        Ok(Some((entry.value as usize, length)))
+    }
+
+    /// Removes the entries with the given tags from an IFD, the remaining entries and the offset of the next IFD
+    /// move up and the freed bytes are overwritten with zeros. The values of the removed entries are not touched.
+    fn remove_entries(&mut self, ifd: usize, tags: &[u16]) -> Result<Vec<Entry>> {
+        let entries = self.entries(ifd)?;
+        let (removed, kept): (Vec<Entry>, Vec<Entry>) =
+            entries.iter().partition(|entry| tags.contains(&entry.tag));
+        if removed.is_empty() {
+            return Ok(removed);
+        }
+        let next_ifd = ifd + 2 + entries.len() * 12;
+        let mut next = [0u8; 4];
+        next.copy_from_slice(self.bytes(next_ifd, 4)?);
+
+        self.write_u16(ifd, kept.len() as u16)?;
+        for (index, entry) in kept.iter().enumerate() {
+            let offset = ifd + 2 + index * 12;
+            self.data[offset..offset + 12].copy_from_slice(&entry.raw);
+        }
+        let end = ifd + 2 + kept.len() * 12;
+        self.data[end..end + 4].copy_from_slice(&next);
+        self.zero(end + 4, removed.len() * 12)?;
+        Ok(removed)
+    }
+
+    /// Overwrites the values of removed entries stored outside of their IFD with zeros.
+    fn zero_values(&mut self, entries: &[Entry]) -> Result<()> {
+        for entry in entries {
+            if let Some((offset, length)) = self.external_values(entry)? {
+                self.zero(offset, length)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Removes the tags of the given groups from TIFF data (the content of an Exif segment).
+///
+/// # Returns
+///
+/// * `Result<bool>` - `true` if tags were removed.
+fn strip_tiff(data: &mut [u8], tags: &[StripTag]) -> Result<bool> {
+    let order = match data.get(0..2) {
+        Some(b"II") => ByteOrder::Little,
+        Some(b"MM") => ByteOrder::Big,
+        _ => return Err(anyhow!("Invalid Exif byte order")),
+    };
+    let mut tiff = Tiff { data, order };
+    if tiff.u16(2)? != 42 {
+        return Err(anyhow!("Invalid Exif header"));
+    }
+    let ifd0 = tiff.u32(4)? as usize;
+
+    // all offsets are checked before anything is changed, so malformed data is left untouched
+    let ifd0_entries = tiff.entries(ifd0)?;
+    let gps_ifd = ifd0_entries
+        .iter()
+        .find(|entry| entry.tag == TAG_GPS_IFD)
+        .map(|entry| entry.value as usize);
+    let exif_ifd = ifd0_entries
+        .iter()
+        .find(|entry| entry.tag == TAG_EXIF_IFD)
+        .map(|entry| entry.value as usize);
+    let gps_entries = match gps_ifd {
+        Some(gps_ifd) => tiff.entries(gps_ifd)?,
+        None => Vec::new(),
+    };
+    let exif_entries = match exif_ifd {
+        Some(exif_ifd) => tiff.entries(exif_ifd)?,
+        None => Vec::new(),
+    };
+    for entry in ifd0_entries.iter().chain(&gps_entries).chain(&exif_entries) {
+        tiff.external_values(entry)?;
+    }
+    let ifds = [
+        (Some(ifd0), ifd0_entries.len()),
+        (gps_ifd, gps_entries.len()),
+        (exif_ifd, exif_entries.len()),
+    ];
+    for (ifd, count) in ifds {
+        if let Some(ifd) = ifd {
+            // the entries are followed by the offset of the next IFD
+            tiff.bytes(ifd, 2 + count * 12 + 4)?;
+        }
+    }
+
+    let mut stripped = false;
+    if tags.contains(&StripTag::Gps) {
+        if let Some(gps_ifd) = gps_ifd {
+            tiff.remove_entries(ifd0, &[TAG_GPS_IFD])?;
+            tiff.zero_values(&gps_entries)?;
+            tiff.zero(gps_ifd, 2 + gps_entries.len() * 12 + 4)?;
+            stripped = true;
+        }
+    }
+    if tags.contains(&StripTag::Serial) {
+        let removed = tiff.remove_entries(ifd0, IFD0_SERIAL_TAGS)?;
+        tiff.zero_values(&removed)?;
+        stripped |= !removed.is_empty();
+        if let Some(exif_ifd) = exif_ifd {
+            let removed = tiff.remove_entries(exif_ifd, EXIF_SERIAL_TAGS)?;
+            tiff.zero_values(&removed)?;
+            stripped |= !removed.is_empty();
+        }
+    }
+    Ok(stripped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::{In, Tag};
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{ExtendedColorType, ImageEncoder};
+    use std::io::Cursor;
+
+    /// A little endian IFD entry.
+    fn entry(tag: u16, kind: u16, count: u32, value: [u8; 4]) -> Vec<u8> {
+        [
+            &tag.to_le_bytes()[..],
+            &kind.to_le_bytes(),
+            &count.to_le_bytes(),
+            &value,
+        ]
+        .concat()
+    }
+
+    /// A little endian IFD with its entries and without a next IFD.
+    fn ifd(entries: &[Vec<u8>]) -> Vec<u8> {
+        [
+            &(entries.len() as u16).to_le_bytes()[..],
+            &entries.concat(),
+            &0u32.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    /// An Exif segment with the camera make in IFD0, a GPS IFD with a location and an Exif IFD with a date and
+    /// the body serial number.
+    fn exif_segment_data() -> Vec<u8> {
+        const GPS_IFD: u32 = 50;
+        const GPS_LATITUDE: u32 = 80;
+        const EXIF_IFD: u32 = 104;
+        const DATE: u32 = 134;
+        const SERIAL: u32 = 154;
+
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        tiff.extend(ifd(&[
+            entry(0x010F, 2, 4, *b"Cam\0"),
+            entry(TAG_EXIF_IFD, 4, 1, EXIF_IFD.to_le_bytes()),
+            entry(TAG_GPS_IFD, 4, 1, GPS_IFD.to_le_bytes()),
+        ]));
+        assert_eq!(tiff.len(), GPS_IFD as usize);
+        tiff.extend(ifd(&[
+            entry(0x0001, 2, 2, *b"N\0\0\0"),
+            entry(0x0002, 5, 3, GPS_LATITUDE.to_le_bytes()),
+        ]));
+        for value in [48u32, 1, 8, 1, 30, 1] {
+            tiff.extend_from_slice(&value.to_le_bytes());
+        }
+        assert_eq!(tiff.len(), EXIF_IFD as usize);
+        tiff.extend(ifd(&[
+            entry(0x9003, 2, 20, DATE.to_le_bytes()),
+            entry(0xA431, 2, 8, SERIAL.to_le_bytes()),
+        ]));
+        assert_eq!(tiff.len(), DATE as usize);
+        tiff.extend_from_slice(b"2023:07:14 10:20:30\0");
+        tiff.extend_from_slice(b"SN12345\0");
+
+        let data = [&b"Exif\0\0"[..], &tiff].concat();
+        [
+            &[0xFF, 0xE1][..],
+            &(data.len() as u16 + 2).to_be_bytes(),
+            &data,
+        ]
+        .concat()
+    }
+
+    /// A decodable JPEG file with the Exif segment of [`exif_segment_data`] after the start of image marker.
+    fn jpeg() -> (Vec<u8>, std::ops::Range<usize>) {
+        let pixels: Vec<u8> = (0..16 * 16 * 3).map(|i| (i * 7 % 256) as u8).collect();
+        let mut encoded = Vec::new();
+        JpegEncoder::new(&mut encoded)
+            .write_image(&pixels, 16, 16, ExtendedColorType::Rgb8)
+            .unwrap();
+        let segment = exif_segment_data();
+        let range = 2..2 + segment.len();
+        ([&encoded[..2], &segment, &encoded[2..]].concat(), range)
+    }
+
+    fn read_exif(content: &[u8]) -> exif::Exif {
+        exif::Reader::new()
+            .read_from_container(&mut Cursor::new(content))
+            .unwrap()
+    }
+
+    #[test]
+    fn stripped_jpeg_still_decodes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.jpg");
+        let (content, segment) = jpeg();
+        fs::write(&path, &content).unwrap();
+        let exif = read_exif(&content);
+        assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some());
+        assert!(exif.get_field(Tag::BodySerialNumber, In::PRIMARY).is_some());
+
+        assert!(can_strip(&path));
+        assert!(strip_tags(&path, &[StripTag::Gps, StripTag::Serial]).unwrap());
+
+        let stripped = fs::read(&path).unwrap();
+        assert_eq!(stripped.len(), content.len());
+        // only the Exif segment is rewritten, all other segments and the image data are identical
+        assert_eq!(stripped[..segment.start], content[..segment.start]);
+        assert_eq!(stripped[segment.end..], content[segment.end..]);
+        let image = image::load_from_memory(&stripped).unwrap();
+        assert_eq!((image.width(), image.height()), (16, 16));
+
+        let exif = read_exif(&stripped);
+        assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_none());
+        assert!(exif.get_field(Tag::GPSLatitudeRef, In::PRIMARY).is_none());
+        assert!(exif.get_field(Tag::BodySerialNumber, In::PRIMARY).is_none());
+        assert!(exif.get_field(Tag::Make, In::PRIMARY).is_some());
+        let date = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY).unwrap();
+        assert_eq!(date.display_value().to_string(), "2023-07-14 10:20:30");
+        // nothing left to strip
+        assert!(!strip_tags(&path, &[StripTag::Gps, StripTag::Serial]).unwrap());
+    }
+
+    #[test]
+    fn only_selected_tags_are_stripped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.jpg");
+        fs::write(&path, jpeg().0).unwrap();
+
+        assert!(strip_tags(&path, &[StripTag::Serial]).unwrap());
+        let exif = read_exif(&fs::read(&path).unwrap());
+        assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some());
+        assert!(exif.get_field(Tag::BodySerialNumber, In::PRIMARY).is_none());
+    }
+
+    #[test]
+    fn malformed_exif_is_left_unmodified() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.jpg");
+        let (mut content, segment) = jpeg();
+        // the offset of IFD0 points past the end of the segment
+        content[segment.start + 14..segment.start + 18].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &content).unwrap();
+
+        assert!(strip_tags(&path, &[StripTag::Gps]).is_err());
+        assert_eq!(fs::read(&path).unwrap(), content);
+    }
+}
@@ -0,0 +1,432 @@
+use crate::action::{ActionMode, ActualAction};
+use crate::outcome::{FileOutcome, OutcomeAction};
+use crate::plan::{Plan, SkipReason};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Exit code of a run in which actions failed
+pub const EXIT_ACTION_FAILED: i32 = 1;
+/// Exit code of invalid arguments or settings, the same code the argument parser exits with on usage errors
+pub const EXIT_INVALID_ARGUMENTS: i32 = 2;
+/// Exit code of a run in which files could not be planned (e.g. the analysis failed), but all actions succeeded
+pub const EXIT_PLANNING_FAILED: i32 = 3;
+/// Exit code of a verification (see `--verify-tree`) that found files not matching the format
+pub const EXIT_INCONSISTENT: i32 = 4;
+/// Exit code of a cancelled run, the code shells report for programs interrupted by Ctrl-C
+pub const EXIT_CANCELLED: i32 = 130;
+
+/// `SummaryCounts` holds the number of files per result.
+///
+/// # Fields
+///
+/// * `transferred` - Files the action (move, copy, ...) was performed on.
+/// * `unknown` - Transferred files with none of the configured extensions, processed using the unknown file format.
+/// * `deleted` - Source files deleted because an identical file already exists at the target.
+/// * `linked` - Files whose target was created as a hardlink to an identical source file transferred earlier in the run.
+/// * `skipped_extension` - Files skipped because their extension is not in the list of extensions.
+/// * `skipped_unlisted` - Unknown files skipped because their extension is not in the list of unknown extensions.
+/// * `skipped_sidecar` - Sidecar files, they are processed together with their photo/video (also the RAW files of
+///   RAW+JPEG pairs and the videos of Live Photos, see `pair_raw_jpeg` and `live_photos`).
+/// * `skipped_existing` - Files skipped because they are already sorted (identical target or symlink exists, or the file
+///   already has its target name).
+/// * `skipped_collision` - Files skipped because the target file already exists (see `CollisionStrategy::Skip`).
+/// * `skipped_processed` - Files skipped because they were processed by a previous run (see `state_file`).
+/// * `skipped_duplicate` - Files skipped because an identical source file was transferred earlier in the run (see `source_duplicates`).
+/// * `filtered` - Files skipped because their date is outside of the date range (see `since`, `until` and `nodate_filter`)
+///   or because of their size (see `min_size` and `max_size`).
+/// * `no_date` - Transferred files without a date (not counting `unknown` files, they are not analyzed).
+/// * `invalid` - Files that could not be planned, e.g. because the analysis failed.
+/// * `failed` - Files whose action failed.
+/// * `mirror_failed` - Transferred files that could not be replicated to all mirror directories (see `mirror_dirs`).
+/// * `hardlink_fallbacks` - Hardlinks that were substituted by a copy or symlink (see `hardlink_fallback`).
+/// * `symlink_fallbacks` - Symlinks that were substituted by a hardlink or copy (see `symlink_fallback`).
+/// * `undeleted_sources` - Sources of moves across file systems that were copied but could not be deleted.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SummaryCounts {
+    pub transferred: usize,
+    pub unknown: usize,
+    pub deleted: usize,
+    pub linked: usize,
+    pub skipped_extension: usize,
+    pub skipped_unlisted: usize,
+    pub skipped_sidecar: usize,
+    pub skipped_existing: usize,
+    pub skipped_collision: usize,
+    pub skipped_processed: usize,
+    pub skipped_duplicate: usize,
+    pub filtered: usize,
+    pub no_date: usize,
+    pub invalid: usize,
+    pub failed: usize,
+    pub mirror_failed: usize,
+    pub hardlink_fallbacks: usize,
+    pub symlink_fallbacks: usize,
+    pub undeleted_sources: usize,
+}
+
+impl SummaryCounts {
+    /// The number of skipped files.
+    pub fn skipped(&self) -> usize {
+        self.skipped_extension
+            + self.skipped_unlisted
+            + self.skipped_sidecar
+            + self.skipped_existing
+            + self.skipped_collision
+            + self.skipped_processed
+            + self.skipped_duplicate
+    }
+
+    /// The number of files found in the source directories.
+    pub fn scanned(&self) -> usize {
+        self.transferred
+            + self.deleted
+            + self.linked
+            + self.skipped()
+            + self.filtered
+            + self.errors()
+    }
+
+    /// The number of files with errors.
+    pub fn errors(&self) -> usize {
+        self.invalid + self.failed
+    }
+
+    fn record(&mut self, outcome: &FileOutcome) {
+        if !outcome.mirror_failures.is_empty() {
+            self.mirror_failed += 1;
+        }
+        self.hardlink_fallbacks += outcome.hardlink_fallbacks;
+        self.symlink_fallbacks += outcome.symlink_fallbacks;
+        self.undeleted_sources += outcome.undeleted_sources.len();
+        match outcome.action {
+            OutcomeAction::Transfer(_) => {
+                self.transferred += 1;
+                if outcome.unknown_extension {
+                    self.unknown += 1;
+                } else if outcome.date.is_none() {
+                    self.no_date += 1;
+                }
+            }
+            OutcomeAction::Delete => self.deleted += 1,
+            OutcomeAction::Link => self.linked += 1,
+            OutcomeAction::Skip => match outcome.skipped_reason {
+                Some(SkipReason::Sidecar | SkipReason::Paired) => self.skipped_sidecar += 1,
+                Some(SkipReason::UnknownExtension) => self.skipped_extension += 1,
+                Some(SkipReason::UnlistedUnknown) => self.skipped_unlisted += 1,
+                Some(SkipReason::TargetExists) => self.skipped_collision += 1,
+                Some(SkipReason::Filtered) => self.filtered += 1,
+                Some(SkipReason::PreviouslyProcessed) => self.skipped_processed += 1,
+                Some(SkipReason::SourceDuplicate) => self.skipped_duplicate += 1,
+                Some(
+                    SkipReason::SymlinkExists
+                    | SkipReason::IdenticalTarget
+                    | SkipReason::AlreadyNamed,
+                )
+                | None => self.skipped_existing += 1,
+            },
+        }
+    }
+}
+
+/// `RunSummary` holds the statistics of a run, in total and per source directory.
+///
+/// # Fields
+///
+/// * `action` - The performed action.
+/// * `total` - The counts of all files.
+/// * `per_source` - The counts per source directory.
+/// * `elapsed` - The duration of the run.
+/// * `cancelled` - The run was cancelled before all files were processed, the counts cover the processed files.
+/// * `undeleted_sources` - The sources (paired with their targets) of moves across file systems that were copied but
+///   could not be deleted, they exist twice and have to be cleaned up manually.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunSummary {
+    pub action: ActualAction,
+    pub total: SummaryCounts,
+    pub per_source: Vec<(PathBuf, SummaryCounts)>,
+    pub elapsed: Duration,
+    pub cancelled: bool,
+    pub undeleted_sources: Vec<(PathBuf, PathBuf)>,
+}
+
+impl RunSummary {
+    /// Creates an empty summary.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The performed action.
+    /// * `source_dirs` - The source directories, files are attributed to the source directory containing them.
+    pub fn new(action: ActualAction, source_dirs: &[PathBuf]) -> RunSummary {
+        RunSummary {
+            action,
+            total: SummaryCounts::default(),
+            per_source: source_dirs
+                .iter()
+                .map(|source| (source.clone(), SummaryCounts::default()))
+                .collect(),
+            elapsed: Duration::ZERO,
+            cancelled: false,
+            undeleted_sources: Vec::new(),
+        }
+    }
+
+    /// The summary a plan would have if all its actions succeed, e.g. for a dry run.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The plan.
+    /// * `mode` - The action mode of the plan.
+    /// * `source_dirs` - The source directories, see [`new`](#method.new).
+    pub fn from_plan(plan: &Plan, mode: &ActionMode, source_dirs: &[PathBuf]) -> RunSummary {
+        let action = match mode {
+            ActionMode::Execute(action) | ActionMode::DryRun(action) => *action,
+        };
+        let mut summary = RunSummary::new(action, source_dirs);
+        for diagnostic in &plan.diagnostics {
+            summary.record_invalid(&diagnostic.path);
+        }
+        for path in &plan.filtered {
+            summary.record_filtered(path);
+        }
+        for skipped in &plan.skipped {
+            summary.record(&FileOutcome::from_skipped(skipped, mode));
+        }
+        for action in &plan.actions {
+            summary.record(&FileOutcome::from_action(action, mode));
+        }
+        summary
+    }
+
+    /// Counts the outcome of a file.
+    pub fn record(&mut self, outcome: &FileOutcome) {
+        self.total.record(outcome);
+        self.undeleted_sources
+            .extend(outcome.undeleted_sources.iter().cloned());
+        if let Some(counts) = self.source_counts(&outcome.source) {
+            counts.record(outcome);
+        }
+    }
+
+    /// Counts a file that could not be planned.
+    pub fn record_invalid(&mut self, path: &Path) {
+        self.total.invalid += 1;
+        if let Some(counts) = self.source_counts(path) {
+            counts.invalid += 1;
+        }
+    }
+
+    /// Counts a file left out while scanning because of its size.
+    pub fn record_filtered(&mut self, path: &Path) {
+        self.total.filtered += 1;
+        if let Some(counts) = self.source_counts(path) {
+            counts.filtered += 1;
+        }
+    }
+
+    /// Counts a file whose action failed.
+    pub fn record_failed(&mut self, path: &Path) {
+        self.total.failed += 1;
+        if let Some(counts) = self.source_counts(path) {
+            counts.failed += 1;
+        }
+    }
+
+    /// The counts of the source directory containing the path, the innermost one if source directories are nested.
+    fn source_counts(&mut self, path: &Path) -> Option<&mut SummaryCounts> {
+        self.per_source
+            .iter_mut()
+            .filter(|(source, _)| path.starts_with(source))
+            .max_by_key(|(source, _)| source.components().count())
+            .map(|(_, counts)| counts)
+    }
+
+    /// Checks if all files were processed without errors.
+    pub fn is_success(&self) -> bool {
+        !self.cancelled && self.total.errors() == 0 && self.total.mirror_failed == 0
+    }
+
+    /// The exit code of the run: 0 on success, [`EXIT_CANCELLED`] if the run was cancelled, [`EXIT_ACTION_FAILED`]
+    /// if actions (or replications to mirror directories) failed, otherwise [`EXIT_PLANNING_FAILED`] if files could
+    /// not be planned.
+    pub fn exit_code(&self) -> i32 {
+        if self.cancelled {
+            EXIT_CANCELLED
+        } else if self.total.failed > 0 || self.total.mirror_failed > 0 {
+            EXIT_ACTION_FAILED
+        } else if self.total.invalid > 0 {
+            EXIT_PLANNING_FAILED
+        } else {
+            0
+        }
+    }
+
+    /// A single line with the totals of the run.
+    pub fn totals_line(&self) -> String {
+        let total = &self.total;
+        format!(
+            "{} files scanned: {} transferred ({}, {} with unknown extension), {} deleted duplicates, {} linked duplicates, \
+             {} skipped ({} extension, {} unlisted unknown, {} sidecar, {} existing, {} collision, {} previously processed, {} source duplicate), {} filtered, {} without date, \
+             {} errors ({} invalid, {} failed){} ({:.1}s){}",
+            total.scanned(),
+            total.transferred,
+            self.action,
+            total.unknown,
+            total.deleted,
+            total.linked,
+            total.skipped(),
+            total.skipped_extension,
+            total.skipped_unlisted,
+            total.skipped_sidecar,
+            total.skipped_existing,
+            total.skipped_collision,
+            total.skipped_processed,
+            total.skipped_duplicate,
+            total.filtered,
+            total.no_date,
+            total.errors(),
+            total.invalid,
+            total.failed,
+            if total.mirror_failed > 0 {
+                format!(", {} not replicated to all mirrors", total.mirror_failed)
+            } else {
+                String::new()
+            },
+            self.elapsed.as_secs_f64(),
+            if self.cancelled { ", cancelled" } else { "" }
+        )
+    }
+
+    /// Writes the summary as an aligned table, one row per source directory plus the totals.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing fails.
+    pub fn write_table<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        let header = [
+            "Source".to_string(),
+            self.action.to_string(),
+            "Unknown".to_string(),
+            "Deleted".to_string(),
+            "Linked".to_string(),
+            "Skip ext".to_string(),
+            "Skip unlisted".to_string(),
+            "Skip sidecar".to_string(),
+            "Skip existing".to_string(),
+            "Skip collision".to_string(),
+            "Skip processed".to_string(),
+            "Skip duplicate".to_string(),
+            "Filtered".to_string(),
+            "No date".to_string(),
+            "Invalid".to_string(),
+            "Failed".to_string(),
+        ];
+        let row = |name: String, counts: &SummaryCounts| {
+            [
+                name,
+                counts.transferred.to_string(),
+                counts.unknown.to_string(),
+                counts.deleted.to_string(),
+                counts.linked.to_string(),
+                counts.skipped_extension.to_string(),
+                counts.skipped_unlisted.to_string(),
+                counts.skipped_sidecar.to_string(),
+                counts.skipped_existing.to_string(),
+                counts.skipped_collision.to_string(),
+                counts.skipped_processed.to_string(),
+                counts.skipped_duplicate.to_string(),
+                counts.filtered.to_string(),
+                counts.no_date.to_string(),
+                counts.invalid.to_string(),
+                counts.failed.to_string(),
+            ]
+        };
+
+        let mut rows = vec![header];
+        for (source, counts) in &self.per_source {
+            rows.push(row(source.display().to_string(), counts));
+        }
+        rows.push(row("Total".to_string(), &self.total));
+
+        let mut widths = [0; 16];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        for row in &rows {
+            let line = row
+                .iter()
+                .zip(widths)
+                .enumerate()
+                .map(|(index, (cell, width))| match index {
+                    0 => format!("{:<width$}", cell, width = width),
+                    _ => format!("{:>width$}", cell, width = width),
+                })
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(out, "{}", line.trim_end())?;
+        }
+        writeln!(out, "{}", self.totals_line())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::DateSource;
+    use crate::plan::{Operation, PlannedAction};
+
+    fn outcome(source: &str, hardlink_fallbacks: usize, undeleted: bool) -> FileOutcome {
+        let plan = PlannedAction {
+            source: PathBuf::from(source),
+            target: PathBuf::from("target").join(source),
+            operation: Operation::Transfer { overwrite: false },
+            date: None,
+            date_source: DateSource::None,
+            sidecars: Vec::new(),
+            duplicate_counter: None,
+            format: String::new(),
+            unknown_extension: false,
+        };
+        FileOutcome {
+            hardlink_fallbacks,
+            symlink_fallbacks: 1,
+            undeleted_sources: match undeleted {
+                true => vec![(plan.source.clone(), plan.target.clone())],
+                false => Vec::new(),
+            },
+            ..FileOutcome::from_action(&plan, &ActionMode::Execute(ActualAction::Move))
+        }
+    }
+
+    #[test]
+    fn substituted_links_and_undeleted_sources_are_counted_per_run() {
+        let source_dirs = [PathBuf::from("a"), PathBuf::from("b")];
+        let mut summary = RunSummary::new(ActualAction::Move, &source_dirs);
+        summary.record(&outcome("a/1.jpg", 2, true));
+        summary.record(&outcome("b/2.jpg", 0, false));
+        summary.record(&outcome("b/3.jpg", 1, true));
+
+        assert_eq!(summary.total.transferred, 3);
+        assert_eq!(summary.total.hardlink_fallbacks, 3);
+        assert_eq!(summary.total.symlink_fallbacks, 3);
+        assert_eq!(summary.total.undeleted_sources, 2);
+        assert_eq!(summary.per_source[0].1.hardlink_fallbacks, 2);
+        assert_eq!(summary.per_source[1].1.undeleted_sources, 1);
+        assert_eq!(
+            summary.undeleted_sources,
+            vec![
+                (PathBuf::from("a/1.jpg"), PathBuf::from("target/a/1.jpg")),
+                (PathBuf::from("b/3.jpg"), PathBuf::from("target/b/3.jpg")),
+            ]
+        );
+
+        // the counts belong to the run, a new run starts at zero
+        let summary = RunSummary::new(ActualAction::Move, &source_dirs);
+        assert_eq!(summary.total, SummaryCounts::default());
+        assert!(summary.undeleted_sources.is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::plan::{Operation, Plan, SkipReason};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// `VerifyIssue` is an enumeration that defines why a file of a sorted tree does not match the format.
+///
+/// # Variants
+///
+/// * `WrongFolder` - The file is in another folder than its date and metadata would place it in, e.g. a wrong year.
+/// * `WrongName` - The file is in the right folder, but its name differs from the computed name, e.g. a wrong date.
+/// * `Duplicate` - The file is identical to the file at its computed path.
+/// * `Stray` - The file has none of the configured extensions, it would not have been sorted.
+/// * `Invalid` - The file could not be analyzed, e.g. its Exif data is corrupt.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyIssue {
+    WrongFolder,
+    WrongName,
+    Duplicate,
+    Stray,
+    Invalid,
+}
+
+impl Display for VerifyIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyIssue::WrongFolder => write!(f, "wrong folder"),
+            VerifyIssue::WrongName => write!(f, "wrong name"),
+            VerifyIssue::Duplicate => write!(f, "duplicate"),
+            VerifyIssue::Stray => write!(f, "stray file"),
+            VerifyIssue::Invalid => write!(f, "invalid"),
+        }
+    }
+}
+
+/// `VerifyEntry` is a file of a sorted tree that does not match the format.
+///
+/// # Fields
+///
+/// * `path` - The file.
+/// * `expected` - The path the file would be sorted to, if any.
+/// * `issue` - Why the file does not match.
+/// * `message` - The error of invalid files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerifyEntry {
+    pub path: PathBuf,
+    pub expected: Option<PathBuf>,
+    pub issue: VerifyIssue,
+    pub message: Option<String>,
+}
+
+/// `VerifyReport` is the result of verifying a sorted tree: the target directory is planned as the source of a move
+/// into itself, files that already have their computed path are consistent, all other files are reported.
+///
+/// # Fields
+///
+/// * `checked` - The number of checked files (not counting sidecar files).
+/// * `mismatches` - The files that do not match the format.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub mismatches: Vec<VerifyEntry>,
+}
+
+impl VerifyReport {
+    /// Builds the report of a plan of the sorted tree into itself.
+    pub fn from_plan(plan: &Plan) -> VerifyReport {
+        let mut report = VerifyReport::default();
+
+        for action in &plan.actions {
+            let issue = match action.operation {
+                Operation::DeleteDuplicate | Operation::LinkDuplicate { .. } => {
+                    VerifyIssue::Duplicate
+                }
+                Operation::Transfer { .. } if action.source.parent() == action.target.parent() => {
+                    VerifyIssue::WrongName
+                }
+                Operation::Transfer { .. } => VerifyIssue::WrongFolder,
+            };
+            report.mismatches.push(VerifyEntry {
+                path: action.source.clone(),
+                expected: Some(action.target.clone()),
+                issue,
+                message: None,
+            });
+        }
+        for skipped in &plan.skipped {
+            let issue = match skipped.reason {
+                SkipReason::UnknownExtension | SkipReason::UnlistedUnknown => VerifyIssue::Stray,
+                SkipReason::IdenticalTarget | SkipReason::SourceDuplicate => VerifyIssue::Duplicate,
+                SkipReason::Sidecar | SkipReason::Paired => continue,
+                _ => {
+                    report.checked += 1;
+                    continue;
+                }
+            };
+            report.mismatches.push(VerifyEntry {
+                path: skipped.source.clone(),
+                expected: skipped.target.clone(),
+                issue,
+                message: None,
+            });
+        }
+        for diagnostic in &plan.diagnostics {
+            report.mismatches.push(VerifyEntry {
+                path: diagnostic.path.clone(),
+                expected: None,
+                issue: VerifyIssue::Invalid,
+                message: Some(diagnostic.message.clone()),
+            });
+        }
+
+        report.checked += report.mismatches.len();
+        report.mismatches.sort_by(|a, b| a.path.cmp(&b.path));
+        report
+    }
+
+    /// Checks if all files match the format.
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    /// Writes the mismatches, one per line, followed by the totals.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing fails.
+    pub fn write_table<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        let issue_width = self
+            .mismatches
+            .iter()
+            .map(|entry| entry.issue.to_string().len())
+            .max()
+            .unwrap_or_default();
+        for entry in &self.mismatches {
+            let detail = match (&entry.expected, &entry.message) {
+                (_, Some(message)) => message.clone(),
+                (Some(expected), None) => format!("expected {}", expected.display()),
+                (None, None) => String::new(),
+            };
+            let line = format!(
+                "{:<issue_width$}  {}  {}",
+                entry.issue.to_string(),
+                entry.path.display(),
+                detail
+            );
+            writeln!(out, "{}", line.trim_end())?;
+        }
+        if !self.mismatches.is_empty() {
+            writeln!(out)?;
+        }
+        writeln!(
+            out,
+            "{} files checked, {} do not match the format",
+            self.checked,
+            self.mismatches.len()
+        )
+    }
+
+    /// Writes the report as JSON to a file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can not be written.
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| anyhow!("Failed to create verify report: {:?} - {:?}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Reduces the plan of a verification to the actions that fix misplaced and misnamed files, duplicates are left for
+/// the user to review.
+pub fn fixes(mut plan: Plan) -> Plan {
+    plan.actions
+        .retain(|action| matches!(action.operation, Operation::Transfer { .. }));
+    plan.skipped.clear();
+    plan
+}